@@ -1,5 +1,6 @@
 export interface ServiceInfo {
   name: string;
+  display_name?: string | null;
   endpoints?: string[];
   endpoint?: string | null;
   exposed?: boolean;
@@ -40,10 +41,30 @@ export interface Peer {
   raw?: FlowKey | null;
 }
 
+export type BodyEncoding = "base64" | "form" | "multipart";
+
+export interface BodyEncodings {
+  request?: BodyEncoding | null;
+  response?: BodyEncoding | null;
+}
+
+export interface ContractViolation {
+  kind:
+    | "unknown_route"
+    | "wrong_method"
+    | "undocumented_status"
+    | "request_schema"
+    | "response_schema";
+  message: string;
+}
+
 export interface Correlation {
   request_id?: string | null;
   trace_id?: string | null;
   span_id?: string | null;
+  parent_span_id?: string | null;
+  trace_state?: string | null;
+  sampled?: boolean | null;
 }
 
 export interface ObservationAttrs {
@@ -73,6 +94,14 @@ export type EdgeKey =
       to: EntityId;
       service: string;
       method: string;
+    }
+  | {
+      kind: "db";
+      from: EntityId;
+      to: EntityId;
+      system: "postgres" | "mysql" | "redis";
+      operation: string;
+      table?: string | null;
     };
 
 export interface EdgeStats {
@@ -80,6 +109,7 @@ export interface EdgeStats {
   bytes_in: number;
   bytes_out: number;
   errors: number;
+  violations?: number;
   p50_ms?: number | null;
   p95_ms?: number | null;
   visibility: "l4_flow" | "l7_envelope" | "l7_semantics";
@@ -88,6 +118,7 @@ export interface EdgeStats {
 export interface TrafficEdge {
   key: EdgeKey;
   stats: EdgeStats;
+  instances?: Record<string, EdgeStats>;
   last_seen_ms: number;
 }
 
@@ -97,6 +128,7 @@ export interface TrafficCall {
   peer: Peer;
   method?: string | null;
   path?: string | null;
+  route?: string | null;
   status?: number | null;
   duration_ms?: number | null;
   bytes_in?: number | null;
@@ -105,8 +137,17 @@ export interface TrafficCall {
   response_headers: Record<string, string>;
   request_body?: string | null;
   response_body?: string | null;
+  body_encoding?: BodyEncodings;
   correlation: Correlation;
   attrs: ObservationAttrs;
+  violations?: ContractViolation[];
+}
+
+export interface ReplayResponse {
+  status: number;
+  headers: Record<string, string>;
+  body: string;
+  warnings?: string[];
 }
 
 export interface PanelState {
@@ -126,3 +167,18 @@ export interface PanelConfig {
   exclude: string[];
   follow: boolean;
 }
+
+export type ChaosAction = { kind: "block" } | { kind: "delay"; ms: number };
+
+export interface ChaosRule {
+  from: string;
+  to: string;
+  port?: number | null;
+  action: ChaosAction;
+}
+
+export interface TimelineEvent {
+  at_ms: number;
+  kind: string;
+  message: string;
+}
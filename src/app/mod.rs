@@ -3,10 +3,11 @@ mod watchdog;
 
 use signal_hook::consts::signal::{SIGINT, SIGTERM};
 use signal_hook::iterator::Signals;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::net::SocketAddr;
 use std::path::Path;
 use std::process::ExitCode;
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
@@ -17,20 +18,32 @@ use std::time::Duration;
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
+use crate::domain::traffic::{EntityId, TrafficCall};
+use crate::infra::bench::{self, parse_bench_args, BenchCommand};
+use crate::infra::chaos::{parse_chaos_args, ChaosControl};
 use crate::infra::compose::detect_compose_cmd;
 use crate::infra::engine::{CleanupContext, ContainerInfo, Engine};
-use crate::infra::ui::{open_browser, UiServer};
+use crate::infra::fault::{parse_fault_args, FaultControl};
+use crate::infra::replay::ReplayControl;
+use crate::infra::ui::{open_browser, UiControls, UiServer};
 use crate::support::args::{
     extract_compose_file_arg, extract_engine_arg, extract_subcommand, extract_traffic_arg,
     first_compose_file, strip_project_name_args,
 };
+use crate::support::bench::format_edge_stats;
+use crate::support::chaos::ChaosRule;
 use crate::support::constants::{
-    COMPOSE_FILE_LABEL, DERIVED_COMPOSE_LABEL, PROJECT_NAME_LABEL, PROXY_EGRESS_LABEL, PROXY_LABEL,
-    RUN_ID_LABEL, SERVICE_LABEL, STARTED_AT_LABEL,
+    BENCH_HEADER, BENCH_KIND, BENCH_TAG, COMPOSE_FILE_LABEL, DERIVED_COMPOSE_LABEL,
+    PROJECT_NAME_LABEL, PROXY_EGRESS_LABEL, PROXY_LABEL, PROXY_NAME_LABEL, RUN_ID_LABEL,
+    SERVICE_LABEL, STARTED_AT_LABEL,
 };
+use crate::support::har;
 use crate::support::logging::LogHub;
+use crate::support::openapi;
+use crate::support::replay::{self, ReplayRequest};
 use crate::support::run::{new_run_id, project_name_from_run_id, run_started_at};
-use crate::support::services::build_service_info;
+use crate::support::services::{build_service_info, published_ports};
+use crate::support::timeline::{self, TimelineEvent};
 use crate::support::traffic::TrafficHub;
 
 pub fn run() -> ExitCode {
@@ -59,9 +72,44 @@ impl AppError {
 
 enum SessionCommand {
     List,
-    Logs { run_id: Option<String> },
-    Traffic { run_id: Option<String> },
-    Down { run_id: Option<String> },
+    Logs {
+        run_id: Option<String>,
+    },
+    Traffic {
+        run_id: Option<String>,
+    },
+    Down {
+        run_id: Option<String>,
+    },
+    Fault {
+        run_id: Option<String>,
+        service: Option<String>,
+        args: Vec<String>,
+    },
+    Chaos {
+        run_id: Option<String>,
+        args: Vec<String>,
+    },
+    Openapi {
+        run_id: Option<String>,
+        service: Option<String>,
+    },
+    Har {
+        run_id: Option<String>,
+    },
+    Replay {
+        run_id: Option<String>,
+        call: Option<String>,
+        args: Vec<String>,
+    },
+    Bench {
+        run_id: Option<String>,
+        service: Option<String>,
+        args: Vec<String>,
+    },
+    View {
+        path: Option<String>,
+    },
 }
 
 fn run_inner() -> Result<i32, AppError> {
@@ -75,25 +123,15 @@ fn run_inner() -> Result<i32, AppError> {
     let (args, traffic_override) = extract_traffic_arg(&args);
     let args = strip_project_name_args(&args);
     if let Some(command) = extract_session_command(&args) {
+        // Viewing a HAR file needs no container engine.
+        if let SessionCommand::View { path } = command {
+            return run_view(path).map_err(|err| AppError::new(err, 2));
+        }
         let selection =
             detect_compose_cmd(engine_preference).map_err(|err| AppError::new(err, 1))?;
         let engine = Engine::new(selection.engine, &selection.compose_cmd);
-        let exit_code = match command {
-            SessionCommand::List => Ok(run_list(&engine)),
-            SessionCommand::Logs { run_id } => match require_run_id("logs", run_id) {
-                Ok(run_id) => run_logs(&engine, &run_id),
-                Err(err) => Err(err),
-            },
-            SessionCommand::Traffic { run_id } => match require_run_id("traffic", run_id) {
-                Ok(run_id) => run_traffic(&engine, &run_id),
-                Err(err) => Err(err),
-            },
-            SessionCommand::Down { run_id } => match require_run_id("down", run_id) {
-                Ok(run_id) => run_down(&engine, &selection.compose_cmd, &run_id),
-                Err(err) => Err(err),
-            },
-        }
-        .map_err(|err| AppError::new(err, 2))?;
+        let exit_code = run_session_command(command, engine, &selection.compose_cmd)
+            .map_err(|err| AppError::new(err, 2))?;
         return Ok(exit_code);
     }
 
@@ -172,6 +210,71 @@ fn traffic_enabled(traffic_override: Option<bool>) -> bool {
     traffic_override.unwrap_or(true)
 }
 
+fn run_session_command(
+    command: SessionCommand,
+    engine: Engine,
+    compose_cmd: &[String],
+) -> Result<i32, String> {
+    match command {
+        SessionCommand::List => Ok(run_list(&engine)),
+        SessionCommand::Logs { run_id } => match require_run_id("logs", run_id) {
+            Ok(run_id) => run_logs(&engine, &run_id),
+            Err(err) => Err(err),
+        },
+        SessionCommand::Traffic { run_id } => match require_run_id("traffic", run_id) {
+            Ok(run_id) => run_traffic(&engine, &run_id),
+            Err(err) => Err(err),
+        },
+        SessionCommand::Down { run_id } => match require_run_id("down", run_id) {
+            Ok(run_id) => run_down(&engine, compose_cmd, &run_id),
+            Err(err) => Err(err),
+        },
+        SessionCommand::Fault {
+            run_id,
+            service,
+            args,
+        } => run_fault(engine, run_id, service, &args),
+        SessionCommand::Chaos { run_id, args } => match require_run_id("chaos", run_id) {
+            Ok(run_id) => run_chaos(&engine, &run_id, &args),
+            Err(err) => Err(err),
+        },
+        SessionCommand::Openapi { run_id, service } => match (run_id, service) {
+            (Some(run_id), Some(service)) => run_openapi(&engine, &run_id, &service),
+            _ => Err("Usage: sanelens openapi <run_id> <service>".to_string()),
+        },
+        SessionCommand::Har { run_id } => match require_run_id("har", run_id) {
+            Ok(run_id) => run_har(&engine, &run_id),
+            Err(err) => Err(err),
+        },
+        SessionCommand::Replay { run_id, call, args } => match (run_id, call) {
+            (Some(run_id), Some(call)) => run_replay(engine, &run_id, &call, &args),
+            _ => Err(format!(
+                "Usage: sanelens replay <run_id> <seq|request_id> {REPLAY_USAGE}"
+            )),
+        },
+        SessionCommand::Bench {
+            run_id,
+            service,
+            args,
+        } => match (run_id, service) {
+            (Some(run_id), Some(service)) => run_bench(&engine, &run_id, &service, &args),
+            _ => Err(format!(
+                "Usage: sanelens bench <run_id> <service> {BENCH_USAGE}"
+            )),
+        },
+        SessionCommand::View { path } => run_view(path),
+    }
+}
+
+const FAULT_USAGE: &str =
+    "[--path <prefix>] [--delay <duration>] [--abort <status>] [--reset] [--pct <percent>] | --clear";
+const CHAOS_USAGE: &str =
+    "[<from> <to> (--block | --delay <duration> | --clear) [--port <port>] [--for <duration>]]";
+const REPLAY_USAGE: &str =
+    "[--method <method>] [--path <path>] [-H '<name>: <value>'] [--data <body>] [--curl]";
+const BENCH_USAGE: &str = "[--rate <rps> | --concurrency <n>] [--duration <duration>] [--call <seq>] [--port <port>] [--method <method>] [--path <path>] [-H '<name>: <value>'] [--data <body>]";
+const SETTLE_TICKS: u32 = 4;
+
 fn require_run_id(command: &str, run_id: Option<String>) -> Result<String, String> {
     run_id.ok_or_else(|| format!("Usage: sanelens {command} <run_id>"))
 }
@@ -214,6 +317,35 @@ fn parse_session_command<'a>(
         "down" => Some(SessionCommand::Down {
             run_id: iter.next().cloned(),
         }),
+        "fault" => Some(SessionCommand::Fault {
+            run_id: iter.next().cloned(),
+            service: iter.next().cloned(),
+            args: iter.cloned().collect(),
+        }),
+        "chaos" => Some(SessionCommand::Chaos {
+            run_id: iter.next().cloned(),
+            args: iter.cloned().collect(),
+        }),
+        "openapi" => Some(SessionCommand::Openapi {
+            run_id: iter.next().cloned(),
+            service: iter.next().cloned(),
+        }),
+        "har" => Some(SessionCommand::Har {
+            run_id: iter.next().cloned(),
+        }),
+        "replay" => Some(SessionCommand::Replay {
+            run_id: iter.next().cloned(),
+            call: iter.next().cloned(),
+            args: iter.cloned().collect(),
+        }),
+        "bench" => Some(SessionCommand::Bench {
+            run_id: iter.next().cloned(),
+            service: iter.next().cloned(),
+            args: iter.cloned().collect(),
+        }),
+        "view" => Some(SessionCommand::View {
+            path: iter.next().cloned(),
+        }),
         _ => None,
     }
 }
@@ -286,6 +418,31 @@ fn run_list(engine: &Engine) -> i32 {
     0
 }
 
+fn start_log_ui(
+    log_hub: Arc<LogHub>,
+    service_info: Vec<crate::domain::ServiceInfo>,
+    stop_event: Arc<AtomicBool>,
+) -> Option<UiServer> {
+    match UiServer::start(
+        log_hub,
+        service_info,
+        None,
+        UiControls::default(),
+        stop_event,
+    ) {
+        Ok(server) => {
+            let url = format!("http://127.0.0.1:{}/", server.port());
+            let _ = writeln!(std::io::stdout(), "[compose] log UI: {url}");
+            open_browser(&url);
+            Some(server)
+        }
+        Err(err) => {
+            eprintln!("[compose] log UI failed: {err}");
+            None
+        }
+    }
+}
+
 fn run_logs(engine: &Engine, run_id: &str) -> Result<i32, String> {
     let containers = load_run_containers(engine, run_id, crate::domain::Scope::Running)?;
     let metadata = run_metadata_from_containers(run_id, &containers);
@@ -311,19 +468,7 @@ fn run_logs(engine: &Engine, run_id: &str) -> Result<i32, String> {
         .map(build_service_info)
         .unwrap_or_default();
 
-    let mut ui_server = None;
-    match UiServer::start(log_hub.clone(), service_info, None, stop_event.clone()) {
-        Ok(server) => {
-            let port = server.port();
-            let url = format!("http://127.0.0.1:{port}/");
-            let _ = writeln!(std::io::stdout(), "[compose] log UI: {url}");
-            open_browser(&url);
-            ui_server = Some(server);
-        }
-        Err(err) => {
-            eprintln!("[compose] log UI failed: {err}");
-        }
-    }
+    let mut ui_server = start_log_ui(log_hub.clone(), service_info, stop_event.clone());
 
     let follower = runner::LogFollower::new(
         engine.clone(),
@@ -349,7 +494,28 @@ fn run_logs(engine: &Engine, run_id: &str) -> Result<i32, String> {
     Ok(exit)
 }
 
-fn run_traffic(engine: &Engine, run_id: &str) -> Result<i32, String> {
+struct TrafficSession {
+    hub: Arc<TrafficHub>,
+    stop_event: Arc<AtomicBool>,
+    exit_code: Arc<AtomicI32>,
+    handles: Arc<runner::ProcessHandles>,
+    follower: thread::JoinHandle<i32>,
+}
+
+impl TrafficSession {
+    fn finish(self) -> i32 {
+        self.stop_event.store(true, Ordering::SeqCst);
+        self.handles.stop_log_procs();
+        let follower_exit = self.follower.join().map_or(1, |code| code);
+        let signal_exit = self.exit_code.load(Ordering::SeqCst);
+        if signal_exit != 0 {
+            return signal_exit;
+        }
+        follower_exit
+    }
+}
+
+fn follow_run_traffic(engine: &Engine, run_id: &str) -> Result<TrafficSession, String> {
     let containers = load_run_containers(engine, run_id, crate::domain::Scope::Running)?;
     let metadata = run_metadata_from_containers(run_id, &containers);
     let services = run_services_from_containers(&containers);
@@ -386,16 +552,25 @@ fn run_traffic(engine: &Engine, run_id: &str) -> Result<i32, String> {
         services.egress_proxy,
         tap_dir,
     );
+    Ok(TrafficSession {
+        hub,
+        stop_event,
+        exit_code,
+        handles,
+        follower: thread::spawn(move || follower.follow()),
+    })
+}
 
-    let handle = thread::spawn(move || follower.follow());
-    let (receiver, snapshot) = hub.register_call_client();
+fn run_traffic(engine: &Engine, run_id: &str) -> Result<i32, String> {
+    let session = follow_run_traffic(engine, run_id)?;
+    let (receiver, snapshot) = session.hub.register_call_client();
     let mut stdout = io::stdout();
     for call in snapshot {
         let line = serde_json::to_string(&call).unwrap_or_default();
         let _ = writeln!(stdout, "{line}");
         let _ = stdout.flush();
     }
-    while !stop_event.load(Ordering::SeqCst) {
+    while !session.stop_event.load(Ordering::SeqCst) {
         match receiver.recv_timeout(Duration::from_secs(1)) {
             Ok(call) => {
                 let line = serde_json::to_string(&call).unwrap_or_default();
@@ -406,14 +581,389 @@ fn run_traffic(engine: &Engine, run_id: &str) -> Result<i32, String> {
             Err(crossbeam_channel::RecvTimeoutError::Disconnected) => break,
         }
     }
+    Ok(session.finish())
+}
 
-    handles.stop_log_procs();
-    let follower_exit = handle.join().map_or(1, |code| code);
-    let signal_exit = exit_code.load(Ordering::SeqCst);
-    if signal_exit != 0 {
-        return Ok(signal_exit);
+// Sidecar logs are replayed from the start of the run and kept tap files are read without
+// being removed, so commands that need the whole capture wait until no new calls arrive.
+fn settled_calls(engine: &Engine, run_id: &str) -> Result<(Vec<TrafficCall>, i32), String> {
+    let session = follow_run_traffic(engine, run_id)?;
+    settle(&session);
+    let calls = session.hub.calls();
+    Ok((calls, session.finish()))
+}
+
+fn settle(session: &TrafficSession) {
+    let mut seen = 0;
+    let mut quiet_ticks = 0;
+    while !session.stop_event.load(Ordering::SeqCst) && quiet_ticks < SETTLE_TICKS {
+        thread::sleep(Duration::from_millis(500));
+        let count = session.hub.calls().len();
+        quiet_ticks = if count == seen { quiet_ticks + 1 } else { 0 };
+        seen = count;
     }
-    Ok(follower_exit)
+}
+
+fn run_openapi(engine: &Engine, run_id: &str, service: &str) -> Result<i32, String> {
+    let (calls, code) = settled_calls(engine, run_id)?;
+    if code != 0 {
+        return Ok(code);
+    }
+    let document = openapi::infer(service, &calls);
+    if document
+        .get("paths")
+        .and_then(serde_json::Value::as_object)
+        .is_none_or(serde_json::Map::is_empty)
+    {
+        eprintln!("[compose] no captured HTTP calls into {service}");
+    }
+    let payload = serde_json::to_string_pretty(&document).map_err(|err| err.to_string())?;
+    let _ = writeln!(io::stdout(), "{payload}");
+    Ok(0)
+}
+
+// Calls are numbered oldest first from the kept capture, as in `sanelens traffic` and
+// `sanelens har`; their request id stays valid while the numbering shifts.
+fn run_replay(engine: Engine, run_id: &str, key: &str, args: &[String]) -> Result<i32, String> {
+    let (edits, curl) = replay::parse_replay_args(args)?;
+    let (calls, code) = settled_calls(&engine, run_id)?;
+    if code != 0 {
+        return Ok(code);
+    }
+    let call = replay::find_call(calls, key)
+        .ok_or_else(|| format!("no captured call {key} in run {run_id}"))?;
+    let mut stdout = io::stdout();
+    if curl {
+        let request = ReplayRequest::from_call(&call, &edits)?;
+        let _ = writeln!(stdout, "{}", request.curl());
+        return Ok(0);
+    }
+    let response = ReplayControl::new(engine, run_id.to_string()).send(&call, &edits)?;
+    for warning in &response.warnings {
+        eprintln!("[compose] {warning}");
+    }
+    let _ = writeln!(stdout, "HTTP {}", response.status);
+    for (key, value) in &response.headers {
+        let _ = writeln!(stdout, "{key}: {value}");
+    }
+    let _ = writeln!(stdout, "\n{}", response.body);
+    Ok(0)
+}
+
+// Bench requests are tagged with a per-run id, so the sidecar's view of exactly those calls
+// can be rebuilt as edges once the capture has caught up.
+fn run_bench(engine: &Engine, run_id: &str, service: &str, args: &[String]) -> Result<i32, String> {
+    let command = parse_bench_args(args)
+        .map_err(|err| format!("{err}\nUsage: sanelens bench <run_id> <service> {BENCH_USAGE}"))?;
+    let containers = load_run_containers(engine, run_id, crate::domain::Scope::Running)?;
+    let metadata = run_metadata_from_containers(run_id, &containers);
+    let session = follow_run_traffic(engine, run_id)?;
+    let target = bench_request(&session, service, &command).and_then(|request| {
+        bench_target(&containers, &metadata, &request).map(|addr| (request, addr))
+    });
+    let (request, addr) = match target {
+        Ok(target) => target,
+        Err(err) => {
+            session.finish();
+            return Err(err);
+        }
+    };
+    let run_dir = metadata
+        .derived_compose
+        .as_ref()
+        .and_then(|path| Path::new(path).parent().map(Path::to_path_buf));
+    let bench_id = now_ms().to_string();
+    let label = format!(
+        "bench {service} {} {} at {} for {}s",
+        request.method,
+        request.path,
+        command.mode.describe(),
+        command.duration.as_secs()
+    );
+    let mut stdout = io::stdout();
+    let _ = writeln!(stdout, "[compose] {label} via {addr}");
+    record_bench(run_dir.as_deref(), &format!("{label} started"));
+    let (stats, elapsed) = bench::run(
+        addr,
+        request.to_http(BENCH_HEADER, &bench_id),
+        &command,
+        &session.stop_event,
+    );
+    record_bench(
+        run_dir.as_deref(),
+        &format!("{label} done: {} requests", stats.completed()),
+    );
+    for line in stats.report(elapsed) {
+        let _ = writeln!(stdout, "{line}");
+    }
+    settle(&session);
+    let observed = TrafficHub::new();
+    for call in session.hub.calls() {
+        if call.attrs.tags.get(BENCH_TAG) == Some(&bench_id) {
+            observed.import_call(call);
+        }
+    }
+    let edges = observed.edges();
+    if edges.is_empty() {
+        let _ = writeln!(stdout, "sidecar: no bench calls were captured");
+    }
+    for line in format_edge_stats(&edges) {
+        let _ = writeln!(stdout, "{line}");
+    }
+    Ok(session.finish())
+}
+
+fn bench_request(
+    session: &TrafficSession,
+    service: &str,
+    command: &BenchCommand,
+) -> Result<ReplayRequest, String> {
+    let Some(seq) = command.call else {
+        let edits = &command.edits;
+        return Ok(ReplayRequest {
+            service: service.to_string(),
+            port: command.port,
+            method: edits.method.as_deref().unwrap_or("GET").to_uppercase(),
+            path: edits.path.clone().unwrap_or_else(|| "/".to_string()),
+            headers: edits
+                .headers
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), value.clone()?)))
+                .collect(),
+            body: edits.body.clone().unwrap_or_default().into_bytes(),
+            warnings: Vec::new(),
+        });
+    };
+    settle(session);
+    let call = session
+        .hub
+        .calls()
+        .into_iter()
+        .find(|call| call.seq == seq)
+        .ok_or_else(|| format!("no captured call {seq}"))?;
+    let mut request = ReplayRequest::from_call(&call, &command.edits)?;
+    if request.service != service {
+        return Err(format!(
+            "call {seq} went to {}, not {service}",
+            request.service
+        ));
+    }
+    for warning in &request.warnings {
+        eprintln!("[compose] {warning}");
+    }
+    request.port = command.port.or(request.port);
+    Ok(request)
+}
+
+// A published port works from any host. The request's port (captured or `--port`) is looked
+// up by container port; without a mapping the sidecar is reached on the internal network.
+fn bench_target(
+    containers: &[ContainerInfo],
+    metadata: &RunMetadata,
+    request: &ReplayRequest,
+) -> Result<SocketAddr, String> {
+    let published = metadata
+        .compose_file
+        .as_deref()
+        .map(|compose_file| published_ports(compose_file, &request.service))
+        .unwrap_or_default();
+    let host_port = published
+        .iter()
+        .find(|(_, container)| request.port.is_none_or(|port| port == *container))
+        .map(|(host, _)| *host);
+    if let Some(port) = host_port {
+        return Ok(SocketAddr::from(([127, 0, 0, 1], port)));
+    }
+    let port = request
+        .port
+        .ok_or_else(|| format!("service {} publishes no port; pass --port", request.service))?;
+    containers
+        .iter()
+        .filter(|info| {
+            info.labels.get(PROXY_NAME_LABEL).map(String::as_str) == Some(request.service.as_str())
+        })
+        .flat_map(|info| info.ips.iter())
+        .find(|ip| ip.is_ipv4())
+        .map(|ip| SocketAddr::new(*ip, port))
+        .ok_or_else(|| format!("no running proxy for service {}", request.service))
+}
+
+fn record_bench(run_dir: Option<&Path>, message: &str) {
+    let Some(run_dir) = run_dir else {
+        return;
+    };
+    let event = TimelineEvent {
+        at_ms: now_ms(),
+        kind: BENCH_KIND.to_string(),
+        message: message.to_string(),
+    };
+    if let Err(err) = timeline::append(run_dir, &event) {
+        eprintln!("[compose] failed to record bench timeline: {err}");
+    }
+}
+
+fn now_ms() -> u64 {
+    u64::try_from(OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000).unwrap_or_default()
+}
+
+fn run_har(engine: &Engine, run_id: &str) -> Result<i32, String> {
+    let (calls, code) = settled_calls(engine, run_id)?;
+    if code != 0 {
+        return Ok(code);
+    }
+    let payload =
+        serde_json::to_string_pretty(&har::export(&calls)).map_err(|err| err.to_string())?;
+    let _ = writeln!(io::stdout(), "{payload}");
+    Ok(0)
+}
+
+// Serves the calls of a HAR file in the UI until interrupted. Nothing is running, so the
+// fault and chaos controls are left out.
+fn run_view(path: Option<String>) -> Result<i32, String> {
+    let path = path.ok_or_else(|| "Usage: sanelens view <file.har>".to_string())?;
+    let raw = fs::read_to_string(&path).map_err(|err| format!("failed to read {path}: {err}"))?;
+    let calls = har::import(&raw).map_err(|err| format!("{path}: {err}"))?;
+    let count = calls.len();
+    let mut services = BTreeSet::new();
+    let hub = Arc::new(TrafficHub::new());
+    for call in calls {
+        for entity in [&call.peer.src, &call.peer.dst].into_iter().flatten() {
+            if let EntityId::Workload { name, .. } = entity {
+                services.insert(name.clone());
+            }
+        }
+        hub.import_call(call);
+    }
+    let service_info = services
+        .into_iter()
+        .map(|name| crate::domain::ServiceInfo {
+            name,
+            display_name: None,
+            endpoints: Vec::new(),
+            endpoint: None,
+            exposed: false,
+        })
+        .collect();
+
+    let stop_event = Arc::new(AtomicBool::new(false));
+    setup_signals(runner::SignalContext::new(
+        stop_event.clone(),
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicI32::new(0)),
+        Arc::new(runner::ProcessHandles::new()),
+    ));
+    let log_hub = Arc::new(LogHub::new(crate::support::constants::HISTORY_LIMIT));
+    let mut server = UiServer::start(
+        log_hub,
+        service_info,
+        Some(hub),
+        UiControls::default(),
+        stop_event.clone(),
+    )
+    .map_err(|err| format!("log UI failed: {err}"))?;
+    let url = format!("http://127.0.0.1:{}/", server.port());
+    let _ = writeln!(
+        io::stdout(),
+        "[compose] viewing {count} calls from {path}: {url} (Ctrl-C to exit)"
+    );
+    open_browser(&url);
+    while !stop_event.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(200));
+    }
+    server.stop();
+    Ok(0)
+}
+
+fn run_fault(
+    engine: Engine,
+    run_id: Option<String>,
+    service: Option<String>,
+    args: &[String],
+) -> Result<i32, String> {
+    let (Some(run_id), Some(service)) = (run_id, service) else {
+        return Err(format!(
+            "Usage: sanelens fault <run_id> <service> {FAULT_USAGE}"
+        ));
+    };
+    let command = parse_fault_args(args)
+        .map_err(|err| format!("{err}\nUsage: sanelens fault <run_id> <service> {FAULT_USAGE}"))?;
+    let summary = FaultControl::new(engine, run_id).apply(
+        &service,
+        command.path.as_deref(),
+        &command.spec,
+    )?;
+    let _ = writeln!(std::io::stdout(), "[compose] {summary}");
+    Ok(0)
+}
+
+fn run_chaos(engine: &Engine, run_id: &str, args: &[String]) -> Result<i32, String> {
+    let usage = || format!("Usage: sanelens chaos <run_id> {CHAOS_USAGE}");
+    let containers = load_run_containers(engine, run_id, crate::domain::Scope::Running)?;
+    let metadata = run_metadata_from_containers(run_id, &containers);
+    let services = run_services_from_containers(&containers);
+    let run_dir = metadata
+        .derived_compose
+        .as_ref()
+        .and_then(|path| Path::new(path).parent().map(Path::to_path_buf))
+        .ok_or_else(|| format!("run {run_id} has no derived compose directory"))?;
+    let control = ChaosControl::new(
+        engine.clone(),
+        run_id.to_string(),
+        run_dir,
+        services.service_aliases,
+    );
+    let mut stdout = io::stdout();
+    let [from, to, options @ ..] = args else {
+        if !args.is_empty() {
+            return Err(usage());
+        }
+        for rule in control.rules() {
+            let _ = writeln!(stdout, "{rule}");
+        }
+        for event in control.timeline() {
+            let _ = writeln!(
+                stdout,
+                "{}",
+                serde_json::to_string(&event).unwrap_or_default()
+            );
+        }
+        return Ok(0);
+    };
+    let command = parse_chaos_args(options).map_err(|err| format!("{err}\n{}", usage()))?;
+    let Some(action) = command.action else {
+        let summary = control.clear(from, to, command.port)?;
+        let _ = writeln!(stdout, "[compose] {summary}");
+        return Ok(0);
+    };
+    let rule = ChaosRule {
+        from: from.clone(),
+        to: to.clone(),
+        port: command.port,
+        action,
+    };
+    let summary = control.apply(&rule, command.duration_ms)?;
+    let _ = writeln!(stdout, "[compose] {summary}");
+    if let Some(ms) = command.duration_ms {
+        wait_for_interrupt(Duration::from_millis(ms))?;
+        if let Some(summary) = control.expire(&rule)? {
+            let _ = writeln!(stdout, "[compose] {summary}");
+        }
+    }
+    Ok(0)
+}
+
+// Timed rules are lifted early on Ctrl+C so an interrupted command never leaves them behind.
+fn wait_for_interrupt(duration: Duration) -> Result<(), String> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, interrupted.clone())
+            .map_err(|err| format!("failed to register signal handler: {err}"))?;
+    }
+    let deadline = std::time::Instant::now() + duration;
+    while !interrupted.load(Ordering::SeqCst) && std::time::Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(200));
+    }
+    Ok(())
 }
 
 fn run_down(engine: &Engine, compose_cmd: &[String], run_id: &str) -> Result<i32, String> {
@@ -610,6 +1160,9 @@ fn setup_signals(context: runner::SignalContext) {
 fn run_with_cleanup(runner: &mut runner::ComposeRunner) -> i32 {
     let mut exit_code = runner.run();
     runner.cleanup_once();
+    if exit_code == 0 && runner.contract_failed() {
+        exit_code = 1;
+    }
     let signal_exit = runner.signal_exit_code();
     if signal_exit != 0 {
         exit_code = signal_exit;
@@ -1,30 +1,49 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::env;
+use std::ffi::OsString;
 use std::fs;
 use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::domain::traffic::ObservationSink;
+use crate::domain::traffic::{DbSystem, ObservationSink};
 use crate::domain::{Scope, ServiceInfo};
+use crate::infra::chaos::ChaosControl;
 use crate::infra::derive::{derive_compose, DeriveConfig, DerivedCompose};
 use crate::infra::engine::{CleanupContext, Engine};
+use crate::infra::fault::FaultControl;
+use crate::infra::otlp::{OtlpExporter, OtlpReceiver};
 use crate::infra::process::{spawn_process_group, terminate_process};
+use crate::infra::replay::ReplayControl;
 use crate::infra::resolver::RuntimeResolver;
-use crate::infra::traffic::{observation_from_envoy, observation_from_tap, parse_envoy_log_line};
-use crate::infra::ui::{open_browser, UiServer};
+use crate::infra::traffic::{
+    db_tap_system, interaction_from_tap, observation_from_envoy, observation_from_tap,
+    parse_envoy_log_line, DbTapStream, TapSource,
+};
+use crate::infra::ui::{open_browser, UiControls, UiServer};
+use crate::infra::vcr::ReplayServer;
 use crate::support::args::{
-    extract_subcommand, has_flag, insert_after, is_env_false, is_env_truthy,
-    strip_compose_file_args, take_flag,
+    env_list, extract_subcommand, has_flag, insert_after, is_env_false, is_env_truthy,
+    net_init_image, rewrite_scale_args, strip_compose_file_args, take_flag,
+};
+use crate::support::capture::CapturePolicy;
+use crate::support::constants::{
+    BIN_NAME, HISTORY_LIMIT, TAP_ARCHIVE_DIR, TRAFFIC_CALL_HISTORY_LIMIT,
 };
-use crate::support::constants::{BIN_NAME, HISTORY_LIMIT};
+use crate::support::contract::Contract;
 use crate::support::logging::{log_worker, LogHub, LogWorkerConfig};
+use crate::support::otlp_export::RunResource;
 use crate::support::services::build_service_info;
-use crate::support::traffic::TrafficHub;
+use crate::support::traffic::{
+    format_contract_summary, format_policy_summary, FanoutSink, TrafficHub,
+};
+use crate::support::vcr::{format_misses, write_unmatched, Player, Recorder, VcrConfig, VcrMode};
+
+const RESOLVER_REFRESH_TICKS: u32 = 8;
 
 pub struct ProcessHandles {
     compose_proc: Mutex<Option<Child>>,
@@ -110,6 +129,13 @@ pub struct ComposeRunner {
     watchdog_proc: Option<Child>,
     derived_dir: Option<PathBuf>,
     retain_run_dir: bool,
+    vcr: Option<VcrConfig>,
+    vcr_recorder: Option<Arc<Recorder>>,
+    replay_server: Option<ReplayServer>,
+    otlp_receiver: Option<OtlpReceiver>,
+    otlp_exporter: Option<Arc<OtlpExporter>>,
+    contracts: HashMap<String, PathBuf>,
+    contract_failed: bool,
 }
 
 #[allow(clippy::struct_excessive_bools)]
@@ -167,6 +193,13 @@ impl ComposeRunner {
             watchdog_proc: None,
             derived_dir: None,
             retain_run_dir: false,
+            vcr: None,
+            vcr_recorder: None,
+            replay_server: None,
+            otlp_receiver: None,
+            otlp_exporter: None,
+            contracts: HashMap::new(),
+            contract_failed: false,
         }
     }
 
@@ -193,12 +226,24 @@ impl ComposeRunner {
         } else {
             "envoyproxy/envoy:v1.30-latest".to_string()
         };
+        let transparent_egress = env::var("SANELENS_EGRESS_PROXY")
+            .is_ok_and(|value| value.eq_ignore_ascii_case("transparent"));
+        let vcr_mode = self.vcr.as_ref().map(|vcr| vcr.mode);
         let mut config = DeriveConfig {
             run_id: self.run_id.clone(),
             run_started_at: self.run_started_at.clone(),
             envoy_image,
             enable_traffic: self.traffic_enabled,
-            enable_egress: self.traffic_enabled && is_env_truthy("SANELENS_EGRESS_PROXY"),
+            enable_egress: self.traffic_enabled
+                && (is_env_truthy("SANELENS_EGRESS_PROXY")
+                    || transparent_egress
+                    || vcr_mode.is_some()),
+            egress_tls_hosts: env_list("SANELENS_EGRESS_TLS"),
+            transparent_egress,
+            net_init_image: net_init_image(),
+            vcr_mode,
+            vcr_replay_port: self.replay_server.as_ref().map(ReplayServer::port),
+            otlp_port: self.otlp_receiver.as_ref().map(OtlpReceiver::port),
             compose_cmd: self.compose_cmd.clone(),
             compose_args: self.compose_args.clone(),
             compose_file_from_args: self.compose_file_from_args,
@@ -217,6 +262,9 @@ impl ComposeRunner {
                 self.traffic_enabled = false;
                 config.enable_traffic = false;
                 config.enable_egress = false;
+                config.transparent_egress = false;
+                config.vcr_mode = None;
+                config.otlp_port = None;
                 let derived =
                     derive_compose(&self.original_compose_file, &self.project_name, &config)?;
                 self.apply_derived_compose(derived);
@@ -229,9 +277,16 @@ impl ComposeRunner {
         self.compose_file = derived.path.to_string_lossy().into_owned();
         self.derived_dir = Some(derived.run_dir);
         self.proxy_services = derived.proxy_services;
+        let scale_targets: HashMap<String, String> = derived
+            .app_service_map
+            .iter()
+            .map(|(app, service)| (service.clone(), app.clone()))
+            .collect();
         self.service_aliases = derived.app_service_map;
         self.egress_proxy = derived.egress_proxy;
-        self.compose_args = strip_compose_file_args(&self.compose_args);
+        self.contracts = derived.contracts;
+        self.compose_args =
+            rewrite_scale_args(&strip_compose_file_args(&self.compose_args), &scale_targets);
         self.compose_file_from_args = false;
     }
 
@@ -258,6 +313,10 @@ impl ComposeRunner {
         self.exit_code.load(Ordering::SeqCst)
     }
 
+    pub const fn contract_failed(&self) -> bool {
+        self.contract_failed
+    }
+
     pub fn cleanup_once(&mut self) {
         if self.cleanup_done {
             return;
@@ -275,6 +334,24 @@ impl ComposeRunner {
         for handle in self.traffic_threads.drain(..) {
             let _ = handle.join();
         }
+        if let Some(hub) = &self.traffic_hub {
+            for line in format_policy_summary(&hub.policy_summary()) {
+                eprintln!("[compose] {line}");
+            }
+            let contract_hits = hub.contract_summary();
+            for line in format_contract_summary(&contract_hits) {
+                eprintln!("[compose] {line}");
+            }
+            self.contract_failed =
+                !contract_hits.is_empty() && is_env_truthy("SANELENS_CONTRACT_STRICT");
+        }
+        self.stop_replay_server();
+        if let Some(mut receiver) = self.otlp_receiver.take() {
+            receiver.stop();
+        }
+        if let Some(exporter) = self.otlp_exporter.take() {
+            exporter.stop();
+        }
         if let Some(server) = self.ui_server.as_mut() {
             server.stop();
         }
@@ -294,16 +371,160 @@ impl ComposeRunner {
         }
     }
 
+    fn stop_replay_server(&mut self) {
+        let Some(mut server) = self.replay_server.take() else {
+            return;
+        };
+        server.stop();
+        let misses = server.player().misses();
+        for line in format_misses(&misses) {
+            eprintln!("[compose] {line}");
+        }
+        if let Some(vcr) = &self.vcr {
+            if let Err(err) = write_unmatched(&vcr.dir, &misses) {
+                eprintln!("[compose] vcr report failed: {err}");
+            }
+        }
+    }
+
+    // Record and replay only apply to an attached `up`: the replay server and the
+    // recorder live in this process.
+    fn start_vcr(&mut self, subcommand: &str) {
+        let Ok(mode) = env::var("SANELENS_VCR") else {
+            return;
+        };
+        if subcommand != "up" || !self.traffic_enabled {
+            return;
+        }
+        if has_flag(&self.compose_args, &["-d", "--detach"]) {
+            eprintln!("[compose] vcr disabled: not available with a detached up");
+            return;
+        }
+        let dir = env::var("SANELENS_VCR_DIR").map_or_else(
+            |_| {
+                Path::new(&self.original_compose_file)
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .join("cassettes")
+            },
+            PathBuf::from,
+        );
+        let config = match VcrConfig::parse(&mode, dir, &env_list("SANELENS_VCR_MATCH")) {
+            Ok(config) => config,
+            Err(err) => {
+                eprintln!("[compose] vcr disabled: {err}");
+                return;
+            }
+        };
+        let started = match config.mode {
+            VcrMode::Record => Recorder::new(&config.dir)
+                .map(|recorder| self.vcr_recorder = Some(Arc::new(recorder)))
+                .map_err(|err| err.to_string()),
+            VcrMode::Replay => Player::load(&config)
+                .and_then(|player| {
+                    eprintln!(
+                        "[compose] vcr: replaying {} recorded interactions",
+                        player.interaction_count()
+                    );
+                    ReplayServer::start(player, self.engine.host_gateway_ip())
+                        .map_err(|err| err.to_string())
+                })
+                .map(|server| self.replay_server = Some(server)),
+        };
+        match started {
+            Ok(()) => self.vcr = Some(config),
+            Err(err) => eprintln!("[compose] vcr disabled: {err}"),
+        }
+    }
+
+    // Like VCR, the receiver lives in this process, so apps only get an OTLP endpoint
+    // during an attached `up`.
+    fn start_otlp(&mut self, subcommand: &str) {
+        if !is_env_truthy("SANELENS_OTLP") || subcommand != "up" {
+            return;
+        }
+        if has_flag(&self.compose_args, &["-d", "--detach"]) {
+            eprintln!("[compose] otlp disabled: not available with a detached up");
+            return;
+        }
+        let Some(traffic_hub) = self.ensure_traffic_hub() else {
+            return;
+        };
+        let log_hub = self
+            .log_hub
+            .get_or_insert_with(|| Arc::new(LogHub::new(HISTORY_LIMIT)))
+            .clone();
+        match OtlpReceiver::start(traffic_hub, log_hub, self.engine.host_gateway_ip()) {
+            Ok(receiver) => self.otlp_receiver = Some(receiver),
+            Err(err) => eprintln!("[compose] otlp disabled: {err}"),
+        }
+    }
+
+    // Exports what this process observes, so it needs an attached `up` as well.
+    fn start_otlp_export(&mut self, subcommand: &str) {
+        let Ok(endpoint) = env::var("SANELENS_OTLP_EXPORT") else {
+            return;
+        };
+        if endpoint.trim().is_empty() || subcommand != "up" {
+            return;
+        }
+        if has_flag(&self.compose_args, &["-d", "--detach"]) {
+            eprintln!("[compose] otlp export disabled: not available with a detached up");
+            return;
+        }
+        let log_hub = self
+            .log_hub
+            .get_or_insert_with(|| Arc::new(LogHub::new(HISTORY_LIMIT)));
+        let run = RunResource {
+            run_id: self.run_id.clone(),
+            project_name: self.project_name.clone(),
+            started_at: self.run_started_at.clone(),
+        };
+        match OtlpExporter::start(&endpoint, run, Some(log_hub)) {
+            Ok(exporter) => self.otlp_exporter = Some(Arc::new(exporter)),
+            Err(err) => eprintln!("[compose] otlp export disabled: {err}"),
+        }
+    }
+
+    // Contracts are checked by the traffic hub, so they only matter when this process
+    // follows the run's traffic. In strict mode a contract that cannot be loaded fails the
+    // run instead of silently checking nothing.
+    fn load_contracts(&mut self, subcommand: &str) -> Result<(), String> {
+        if self.contracts.is_empty() || subcommand != "up" {
+            return Ok(());
+        }
+        let Some(hub) = self.ensure_traffic_hub() else {
+            return Ok(());
+        };
+        let strict = is_env_truthy("SANELENS_CONTRACT_STRICT");
+        for (service, path) in &self.contracts {
+            match Contract::load(path) {
+                Ok(contract) => hub.set_contract(service, contract),
+                Err(err) if strict => return Err(format!("contract for {service}: {err}")),
+                Err(err) => eprintln!("[compose] contract for {service} ignored: {err}"),
+            }
+        }
+        Ok(())
+    }
+
     pub fn run(&mut self) -> i32 {
         let subcommand_plan = match self.prepare_subcommand() {
             Ok(values) => values,
             Err(code) => return code,
         };
 
+        self.start_vcr(&subcommand_plan.name);
+        self.start_otlp(&subcommand_plan.name);
+        self.start_otlp_export(&subcommand_plan.name);
+
         if let Err(err) = self.prepare_derived_compose() {
             eprintln!("[compose] derive failed: {err}");
             return 1;
         }
+        if let Err(err) = self.load_contracts(&subcommand_plan.name) {
+            eprintln!("[compose] {err}");
+            return 1;
+        }
         self.apply_defaults(&subcommand_plan);
         let follow_plan = self.prepare_follow_plan(&subcommand_plan.name);
         self.maybe_cleanup_before_up(&subcommand_plan.name);
@@ -546,10 +767,23 @@ impl ComposeRunner {
         let log_hub = self
             .log_hub
             .get_or_insert_with(|| Arc::new(LogHub::new(HISTORY_LIMIT)));
+        let controls = UiControls {
+            faults: Some(FaultControl::new(self.engine.clone(), self.run_id.clone())),
+            chaos: self.derived_dir.clone().map(|run_dir| {
+                ChaosControl::new(
+                    self.engine.clone(),
+                    self.run_id.clone(),
+                    run_dir,
+                    self.service_aliases.clone(),
+                )
+            }),
+            replay: Some(ReplayControl::new(self.engine.clone(), self.run_id.clone())),
+        };
         match UiServer::start(
             log_hub.clone(),
             self.service_info.clone(),
             traffic_hub,
+            controls,
             self.stop_event.clone(),
         ) {
             Ok(server) => {
@@ -608,6 +842,10 @@ impl ComposeRunner {
             return None;
         }
         let hub = self.ensure_traffic_hub()?;
+        let sink: Arc<dyn ObservationSink> = match &self.otlp_exporter {
+            Some(exporter) => Arc::new(FanoutSink::new(vec![hub, exporter.clone()])),
+            None => hub,
+        };
         let tap_dir = self
             .derived_dir
             .as_ref()
@@ -619,11 +857,13 @@ impl ComposeRunner {
             project_name: self.project_name.clone(),
             stop_event: self.stop_event.clone(),
             handles: self.handles.clone(),
-            hub,
+            sink,
             proxy_services: self.proxy_services.clone(),
             service_aliases: self.service_aliases.clone(),
             egress_proxy: self.egress_proxy.clone(),
             tap_dir,
+            consume_taps: true,
+            recorder: self.vcr_recorder.clone(),
         })
     }
 
@@ -842,31 +1082,37 @@ pub struct TrafficFollower {
     project_name: String,
     stop_event: Arc<AtomicBool>,
     handles: Arc<ProcessHandles>,
-    hub: Arc<TrafficHub>,
+    sink: Arc<dyn ObservationSink>,
     proxy_services: HashSet<String>,
     service_aliases: HashMap<String, String>,
     egress_proxy: Option<String>,
     tap_dir: Option<PathBuf>,
+    consume_taps: bool,
+    recorder: Option<Arc<Recorder>>,
 }
 
 #[derive(Clone)]
 struct TrafficWorkerContext {
-    hub: Arc<TrafficHub>,
+    sink: Arc<dyn ObservationSink>,
     resolver: Arc<RuntimeResolver>,
     stop_event: Arc<AtomicBool>,
     service_name: String,
     is_egress: bool,
     tap_enabled: bool,
+    policy: Arc<CapturePolicy>,
 }
 
 #[derive(Clone)]
 struct TapWorkerContext {
-    hub: Arc<TrafficHub>,
+    sink: Arc<dyn ObservationSink>,
     resolver: Arc<RuntimeResolver>,
     stop_event: Arc<AtomicBool>,
     service_name: String,
     is_egress: bool,
     tap_dir: PathBuf,
+    consume: bool,
+    policy: Arc<CapturePolicy>,
+    recorder: Option<Arc<Recorder>>,
 }
 
 impl TrafficFollower {
@@ -877,7 +1123,7 @@ impl TrafficFollower {
         project_name: String,
         stop_event: Arc<AtomicBool>,
         handles: Arc<ProcessHandles>,
-        hub: Arc<TrafficHub>,
+        sink: Arc<dyn ObservationSink>,
         proxy_services: HashSet<String>,
         service_aliases: HashMap<String, String>,
         egress_proxy: Option<String>,
@@ -889,11 +1135,13 @@ impl TrafficFollower {
             project_name,
             stop_event,
             handles,
-            hub,
+            sink,
             proxy_services,
             service_aliases,
             egress_proxy,
             tap_dir,
+            consume_taps: false,
+            recorder: None,
         }
     }
 
@@ -904,6 +1152,8 @@ impl TrafficFollower {
         let mut workers = Vec::new();
         let mut seen = HashSet::new();
         let mut tap_seen = HashSet::new();
+        let mut resolver: Option<Arc<RuntimeResolver>> = None;
+        let mut ticks: u32 = 0;
 
         while !self.stop_event.load(Ordering::SeqCst) {
             let ids = self
@@ -913,13 +1163,11 @@ impl TrafficFollower {
                 .into_iter()
                 .filter(|id| seen.insert(id.clone()))
                 .collect();
-            if !new_ids.is_empty() {
-                let resolver = Arc::new(RuntimeResolver::from_engine(
-                    &self.engine,
-                    &self.run_id,
-                    &self.service_aliases,
-                ));
-                workers.extend(self.spawn_workers(&new_ids, &resolver, &mut tap_seen));
+            ticks = ticks.wrapping_add(1);
+            let refresh_due = ticks.is_multiple_of(RESOLVER_REFRESH_TICKS) && resolver.is_some();
+            if !new_ids.is_empty() || refresh_due {
+                let current = self.refresh_resolver(&mut resolver);
+                workers.extend(self.spawn_workers(&new_ids, &current, &mut tap_seen));
             }
             self.prune_finished_log_procs();
             Self::prune_finished_workers(&mut workers);
@@ -933,6 +1181,23 @@ impl TrafficFollower {
         0
     }
 
+    fn refresh_resolver(
+        &self,
+        resolver: &mut Option<Arc<RuntimeResolver>>,
+    ) -> Arc<RuntimeResolver> {
+        if let Some(existing) = resolver.as_ref() {
+            existing.refresh(&self.engine, &self.run_id, &self.service_aliases);
+            return existing.clone();
+        }
+        let created = Arc::new(RuntimeResolver::from_engine(
+            &self.engine,
+            &self.run_id,
+            &self.service_aliases,
+        ));
+        *resolver = Some(created.clone());
+        created
+    }
+
     fn prune_finished_log_procs(&self) {
         let mut procs = self.handles.log_procs();
         procs.retain_mut(|child| child.try_wait().ok().flatten().is_none());
@@ -961,6 +1226,7 @@ impl TrafficFollower {
         for cid in ids {
             let service = self.engine.resolve_service_name(&self.project_name, cid);
             let is_egress = self.egress_proxy.as_deref() == Some(&service);
+            let policy = Arc::new(self.capture_policy(cid));
             let log_cmd = self.engine.logs_cmd(cid, false);
             let Some((log_bin, log_args)) = log_cmd.split_first() else {
                 continue;
@@ -977,12 +1243,13 @@ impl TrafficFollower {
             let stderr = child.stderr.take();
             self.handles.log_procs().push(child);
             let context = TrafficWorkerContext {
-                hub: self.hub.clone(),
+                sink: self.sink.clone(),
                 resolver: resolver.clone(),
                 stop_event: self.stop_event.clone(),
                 service_name: service.clone(),
                 is_egress,
                 tap_enabled: self.tap_dir.is_some(),
+                policy: policy.clone(),
             };
 
             if let Some(stdout) = stdout {
@@ -994,12 +1261,15 @@ impl TrafficFollower {
 
             if let Some(tap_dir) = self.tap_dir_for_service(&service, tap_seen) {
                 let tap_context = TapWorkerContext {
-                    hub: self.hub.clone(),
+                    sink: self.sink.clone(),
                     resolver: resolver.clone(),
                     stop_event: self.stop_event.clone(),
                     service_name: service.clone(),
                     is_egress,
                     tap_dir,
+                    consume: self.consume_taps,
+                    policy,
+                    recorder: self.recorder.clone().filter(|_| is_egress),
                 };
                 Self::spawn_tap_worker(tap_context, &mut workers);
             }
@@ -1007,6 +1277,14 @@ impl TrafficFollower {
         workers
     }
 
+    fn capture_policy(&self, cid: &str) -> CapturePolicy {
+        self.engine
+            .inspect_containers(&[cid.to_string()])
+            .first()
+            .map(|container| CapturePolicy::from_labels(&container.labels))
+            .unwrap_or_default()
+    }
+
     fn spawn_traffic_worker<R: Read + Send + 'static>(
         reader: R,
         context: TrafficWorkerContext,
@@ -1040,12 +1318,13 @@ impl TrafficFollower {
 
 fn traffic_log_worker<R: Read>(reader: R, context: TrafficWorkerContext) {
     let TrafficWorkerContext {
-        hub,
+        sink,
         resolver,
         stop_event,
         service_name,
         is_egress,
         tap_enabled,
+        policy,
     } = context;
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
@@ -1070,51 +1349,176 @@ fn traffic_log_worker<R: Read>(reader: R, context: TrafficWorkerContext) {
         let now_ms = current_time_ms();
         if let Some(obs) =
             observation_from_envoy(log, &service_name, resolver.as_ref(), is_egress, now_ms)
+                .and_then(|obs| policy.apply(obs))
         {
-            hub.emit(obs);
+            sink.emit(obs);
         }
     }
 }
 
+// The attached `up` moves every tap file it has read into `seen/`, keeping the latest ones, so
+// other commands can rebuild the capture from both directories without removing anything.
 fn tap_file_worker(context: TapWorkerContext) {
     let TapWorkerContext {
-        hub,
+        sink,
         resolver,
         stop_event,
         service_name,
         is_egress,
         tap_dir,
+        consume,
+        policy,
+        recorder,
     } = context;
-    let _ = fs::create_dir_all(&tap_dir);
+    let archive_dir = tap_dir.join(TAP_ARCHIVE_DIR);
+    let _ = fs::create_dir_all(&archive_dir);
+    let mut archive = consume.then(|| TapArchive::open(archive_dir.clone()));
+    let mut read = HashSet::new();
+    let mut db_streams = HashMap::new();
+    let source = TapFileSource {
+        service_name: &service_name,
+        is_egress,
+        resolver: resolver.as_ref(),
+        policy: &policy,
+        sink: sink.as_ref(),
+        recorder: recorder.as_deref(),
+    };
     while !stop_event.load(Ordering::SeqCst) {
-        let Ok(entries) = fs::read_dir(&tap_dir) else {
-            thread::sleep(Duration::from_millis(250));
-            continue;
+        let dirs = if consume {
+            vec![tap_dir.as_path()]
+        } else {
+            vec![archive_dir.as_path(), tap_dir.as_path()]
         };
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if !path.is_file() {
+        for path in sorted_tap_files(&dirs, &read) {
+            let system = db_tap_system(&path);
+            if let Some(system) = system.filter(|_| consume) {
+                follow_db_tap(&mut db_streams, &path, system, &source);
                 continue;
             }
-            let Ok(payload) = fs::read_to_string(&path) else {
+            if system.is_some() || !emit_http_tap(&path, &source) {
                 continue;
-            };
-            let now_ms = current_time_ms();
-            if let Some(obs) = observation_from_tap(
-                &payload,
-                &service_name,
-                resolver.as_ref(),
-                is_egress,
-                now_ms,
-            ) {
-                hub.emit(obs);
-                let _ = fs::remove_file(&path);
+            }
+            match archive.as_mut() {
+                Some(archive) => archive.keep(&path),
+                None => read.extend(path.file_name().map(ToOwned::to_owned)),
             }
         }
         thread::sleep(Duration::from_millis(250));
     }
 }
 
+struct TapFileSource<'a> {
+    service_name: &'a str,
+    is_egress: bool,
+    resolver: &'a RuntimeResolver,
+    policy: &'a CapturePolicy,
+    sink: &'a dyn ObservationSink,
+    recorder: Option<&'a Recorder>,
+}
+
+// Files that do not parse yet are still being written and are retried on the next pass.
+fn emit_http_tap(path: &Path, source: &TapFileSource<'_>) -> bool {
+    let Ok(payload) = fs::read_to_string(path) else {
+        return false;
+    };
+    let now_ms = current_time_ms();
+    if let Some(recorder) = source.recorder {
+        record_tap(recorder, &payload, now_ms);
+    }
+    let tap = TapSource {
+        service_name: source.service_name,
+        is_egress: source.is_egress,
+        bodies: source.policy.bodies(),
+    };
+    let Some(obs) = observation_from_tap(&payload, &tap, source.resolver, now_ms) else {
+        return false;
+    };
+    if let Some(obs) = source.policy.apply(obs) {
+        source.sink.emit(obs);
+    }
+    true
+}
+
+// Oldest first, so every command numbers the same capture the same way.
+fn sorted_tap_files(dirs: &[&Path], read: &HashSet<OsString>) -> Vec<PathBuf> {
+    let mut files: Vec<(SystemTime, PathBuf)> = dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(Iterator::flatten)
+        .filter(|entry| !read.contains(&entry.file_name()))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(fs::Metadata::is_file)?;
+            Some((metadata.modified().unwrap_or(UNIX_EPOCH), entry.path()))
+        })
+        .collect();
+    files.sort_by(|left, right| {
+        left.0
+            .cmp(&right.0)
+            .then_with(|| left.1.file_name().cmp(&right.1.file_name()))
+    });
+    files.into_iter().map(|(_, path)| path).collect()
+}
+
+struct TapArchive {
+    dir: PathBuf,
+    files: VecDeque<PathBuf>,
+}
+
+impl TapArchive {
+    fn open(dir: PathBuf) -> Self {
+        let files = sorted_tap_files(&[dir.as_path()], &HashSet::new()).into();
+        Self { dir, files }
+    }
+
+    fn keep(&mut self, path: &Path) {
+        let Some(name) = path.file_name() else {
+            return;
+        };
+        let target = self.dir.join(name);
+        if fs::rename(path, &target).is_err() {
+            let _ = fs::remove_file(path);
+            return;
+        }
+        self.files.push_back(target);
+        while self.files.len() > TRAFFIC_CALL_HISTORY_LIMIT {
+            if let Some(oldest) = self.files.pop_front() {
+                let _ = fs::remove_file(oldest);
+            }
+        }
+    }
+}
+
+// Database tap files stay open for the life of the connection and are removed once it closes.
+fn follow_db_tap(
+    streams: &mut HashMap<PathBuf, DbTapStream>,
+    path: &Path,
+    system: DbSystem,
+    source: &TapFileSource<'_>,
+) {
+    let stream = streams
+        .entry(path.to_path_buf())
+        .or_insert_with(|| DbTapStream::new(system));
+    let (observations, closed) = stream.read(path, source.service_name, source.resolver);
+    for obs in observations {
+        if let Some(obs) = source.policy.apply(obs) {
+            source.sink.emit(obs);
+        }
+    }
+    if closed {
+        streams.remove(path);
+        let _ = fs::remove_file(path);
+    }
+}
+
+fn record_tap(recorder: &Recorder, payload: &str, now_ms: u64) {
+    let Some(interaction) = interaction_from_tap(payload, now_ms) else {
+        return;
+    };
+    if let Err(err) = recorder.record(&interaction) {
+        eprintln!("[compose] vcr record failed: {err}");
+    }
+}
+
 fn current_time_ms() -> u64 {
     let millis = SystemTime::now()
         .duration_since(UNIX_EPOCH)
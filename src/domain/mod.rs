@@ -5,17 +5,21 @@ pub mod traffic;
 #[derive(Clone, Serialize)]
 pub struct ServiceInfo {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
     pub endpoints: Vec<String>,
     pub endpoint: Option<String>,
     pub exposed: bool,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct LogEvent {
     pub seq: u64,
     pub service: String,
     pub container_ts: Option<String>,
     pub line: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
 }
 
 #[derive(Clone, Copy)]
@@ -1,8 +1,10 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::net::IpAddr;
 
-#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize)]
+use crate::domain::LogEvent;
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum EntityId {
     Workload {
@@ -13,13 +15,31 @@ pub enum EntityId {
         ip: IpAddr,
         dns_name: Option<String>,
     },
-    #[allow(dead_code)]
     Host {
         name: String,
     },
     Unknown,
 }
 
+impl EntityId {
+    pub fn instance(&self) -> Option<&str> {
+        match self {
+            Self::Workload { instance, .. } => instance.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn without_instance(&self) -> Self {
+        match self {
+            Self::Workload { name, .. } => Self::Workload {
+                name: name.clone(),
+                instance: None,
+            },
+            other => other.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash, Eq, PartialEq, Serialize)]
 pub struct Socket {
     pub ip: IpAddr,
@@ -60,14 +80,14 @@ pub struct Peer {
     pub raw: Option<FlowKey>,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ObservationAttrs {
     pub visibility: Visibility,
     pub confidence: Confidence,
     pub tags: BTreeMap<String, String>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Visibility {
     L4Flow,
@@ -85,7 +105,7 @@ impl Visibility {
     }
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Confidence {
     Exact,
@@ -95,11 +115,28 @@ pub enum Confidence {
 }
 
 #[allow(clippy::struct_field_names)]
-#[derive(Clone, Debug, Default, Serialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Correlation {
     pub request_id: Option<String>,
     pub trace_id: Option<String>,
     pub span_id: Option<String>,
+    pub parent_span_id: Option<String>,
+    pub trace_state: Option<String>,
+    pub sampled: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BodyEncoding {
+    Base64,
+    Form,
+    Multipart,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BodyEncodings {
+    pub request: Option<BodyEncoding>,
+    pub response: Option<BodyEncoding>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -108,6 +145,7 @@ pub struct HttpObservation {
     pub peer: Peer,
     pub method: Option<String>,
     pub path: Option<String>,
+    pub route: Option<String>,
     pub status: Option<u16>,
     pub duration_ms: Option<u64>,
     pub bytes_in: Option<u64>,
@@ -116,6 +154,7 @@ pub struct HttpObservation {
     pub response_headers: BTreeMap<String, String>,
     pub request_body: Option<String>,
     pub response_body: Option<String>,
+    pub body_encoding: BodyEncodings,
     pub correlation: Correlation,
     pub attrs: ObservationAttrs,
 }
@@ -129,11 +168,54 @@ pub struct FlowObservation {
     pub attrs: ObservationAttrs,
 }
 
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbSystem {
+    Postgres,
+    Mysql,
+    Redis,
+}
+
+impl DbSystem {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Postgres => "postgres",
+            Self::Mysql => "mysql",
+            Self::Redis => "redis",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "postgres" => Some(Self::Postgres),
+            "mysql" => Some(Self::Mysql),
+            "redis" => Some(Self::Redis),
+            _ => None,
+        }
+    }
+}
+
+// One statement or command decoded from a database connection. `operation` is the SQL
+// statement type or the Redis command; `error` is the SQLSTATE, MySQL error number or Redis
+// error prefix.
+#[derive(Clone, Debug, Serialize)]
+pub struct DbObservation {
+    pub at_ms: u64,
+    pub peer: Peer,
+    pub system: DbSystem,
+    pub operation: String,
+    pub table: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: Option<u64>,
+    pub attrs: ObservationAttrs,
+}
+
 #[derive(Clone, Debug, Serialize)]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Observation {
     Flow(FlowObservation),
-    Http(HttpObservation),
+    Http(Box<HttpObservation>),
+    Db(DbObservation),
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -143,6 +225,7 @@ pub struct TrafficCall {
     pub peer: Peer,
     pub method: Option<String>,
     pub path: Option<String>,
+    pub route: Option<String>,
     pub status: Option<u16>,
     pub duration_ms: Option<u64>,
     pub bytes_in: Option<u64>,
@@ -151,8 +234,105 @@ pub struct TrafficCall {
     pub response_headers: BTreeMap<String, String>,
     pub request_body: Option<String>,
     pub response_body: Option<String>,
+    pub body_encoding: BodyEncodings,
     pub correlation: Correlation,
     pub attrs: ObservationAttrs,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<ContractViolation>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    UnknownRoute,
+    WrongMethod,
+    UndocumentedStatus,
+    RequestSchema,
+    ResponseSchema,
+}
+
+impl ViolationKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::UnknownRoute => "unknown_route",
+            Self::WrongMethod => "wrong_method",
+            Self::UndocumentedStatus => "undocumented_status",
+            Self::RequestSchema => "request_schema",
+            Self::ResponseSchema => "response_schema",
+        }
+    }
+}
+
+// A way a captured call disagrees with the OpenAPI contract of the service it reached.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractViolation {
+    pub kind: ViolationKind,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+    Internal,
+    Server,
+    Client,
+    Producer,
+    Consumer,
+}
+
+// A span reported by an application over OTLP rather than observed by a sidecar.
+#[derive(Clone, Debug, Serialize)]
+pub struct AppSpan {
+    pub service: String,
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: Option<SpanKind>,
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    pub error: bool,
+    pub status_message: Option<String>,
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    pub started_at_ms: u64,
+    pub duration_ms: u64,
+    pub calls: usize,
+    pub spans: usize,
+    pub errors: usize,
+    pub services: Vec<String>,
+    pub root: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SpanNode {
+    pub offset_ms: u64,
+    pub span: AppSpan,
+    pub children: Vec<Self>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TraceNode {
+    pub offset_ms: u64,
+    pub call: TrafficCall,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<SpanNode>,
+    pub children: Vec<Self>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TraceTree {
+    #[serde(flatten)]
+    pub summary: TraceSummary,
+    pub roots: Vec<TraceNode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub spans: Vec<SpanNode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logs: Vec<LogEvent>,
 }
 
 #[allow(clippy::struct_excessive_bools)]
@@ -195,6 +375,14 @@ pub enum EdgeKey {
         service: String,
         method: String,
     },
+    Db {
+        from: EntityId,
+        to: EntityId,
+        system: DbSystem,
+        operation: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        table: Option<String>,
+    },
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -203,6 +391,7 @@ pub struct EdgeStats {
     pub bytes_in: u64,
     pub bytes_out: u64,
     pub errors: u64,
+    pub violations: u64,
     pub p50_ms: Option<u64>,
     pub p95_ms: Option<u64>,
     pub visibility: Visibility,
@@ -212,5 +401,7 @@ pub struct EdgeStats {
 pub struct TrafficEdge {
     pub key: EdgeKey,
     pub stats: EdgeStats,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub instances: BTreeMap<String, EdgeStats>,
     pub last_seen_ms: u64,
 }
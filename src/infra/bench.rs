@@ -0,0 +1,260 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::bounded;
+
+use crate::infra::envoy::parse_duration_ms;
+use crate::support::bench::{BenchMode, BenchStats};
+use crate::support::replay::{parse_replay_args, parse_response, ReplayEdits};
+
+const DEFAULT_DURATION_MS: u64 = 10_000;
+const DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_RATE_WORKERS: usize = 64;
+const REQUEST_OPTIONS: [&str; 5] = ["--method", "--path", "-H", "--header", "--data"];
+
+#[derive(Debug)]
+pub struct BenchCommand {
+    pub mode: BenchMode,
+    pub duration: Duration,
+    pub timeout: Duration,
+    pub port: Option<u16>,
+    pub call: Option<u64>,
+    pub edits: ReplayEdits,
+}
+
+// Request options are shared with `replay`; `--call` starts from a captured request instead
+// of an empty `GET /`.
+pub fn parse_bench_args(args: &[String]) -> Result<BenchCommand, String> {
+    let mut rate = None;
+    let mut workers = None;
+    let mut duration = None;
+    let mut timeout = None;
+    let mut port = None;
+    let mut call = None;
+    let mut request = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = arg
+            .split_once('=')
+            .filter(|_| arg.starts_with("--"))
+            .map_or((arg.as_str(), None), |(flag, value)| (flag, Some(value)));
+        let slot = match flag {
+            "--rate" => &mut rate,
+            "--concurrency" => &mut workers,
+            "--duration" => &mut duration,
+            "--timeout" => &mut timeout,
+            "--port" => &mut port,
+            "--call" => &mut call,
+            _ if REQUEST_OPTIONS.contains(&flag) => {
+                request.push(arg.clone());
+                if inline.is_none() {
+                    request.extend(iter.next().cloned());
+                }
+                continue;
+            }
+            _ => return Err(format!("unknown bench option '{arg}'")),
+        };
+        let value = inline
+            .map(str::to_string)
+            .or_else(|| iter.next().cloned())
+            .ok_or_else(|| format!("{flag} needs a value"))?;
+        *slot = Some(value);
+    }
+    let workers = workers
+        .as_deref()
+        .map(|raw| positive(raw, "--concurrency"))
+        .transpose()?;
+    let mode = match rate.as_deref() {
+        Some(raw) => BenchMode::Rate {
+            per_second: u32::try_from(positive(raw, "--rate")?)
+                .map_err(|_| format!("invalid --rate '{raw}'"))?,
+            workers: workers.unwrap_or(DEFAULT_RATE_WORKERS),
+        },
+        None => BenchMode::Concurrency(workers.unwrap_or(1)),
+    };
+    let (edits, _) = parse_replay_args(&request)?;
+    Ok(BenchCommand {
+        mode,
+        duration: duration_or(duration.as_deref(), DEFAULT_DURATION_MS)?,
+        timeout: duration_or(timeout.as_deref(), DEFAULT_TIMEOUT_MS)?,
+        port: number(port.as_deref(), "--port")?,
+        call: number(call.as_deref(), "--call")?,
+        edits,
+    })
+}
+
+fn duration_or(raw: Option<&str>, default_ms: u64) -> Result<Duration, String> {
+    Ok(Duration::from_millis(
+        raw.map(parse_duration_ms)
+            .transpose()?
+            .unwrap_or(default_ms),
+    ))
+}
+
+fn number<T: std::str::FromStr>(raw: Option<&str>, flag: &str) -> Result<Option<T>, String> {
+    raw.map(|raw| {
+        raw.trim()
+            .parse()
+            .map_err(|_| format!("invalid {flag} '{raw}'"))
+    })
+    .transpose()
+}
+
+fn positive(raw: &str, flag: &str) -> Result<usize, String> {
+    raw.trim()
+        .parse::<usize>()
+        .ok()
+        .filter(|value| *value > 0)
+        .ok_or_else(|| format!("invalid {flag} '{raw}'"))
+}
+
+struct Worker {
+    addr: SocketAddr,
+    request: Arc<Vec<u8>>,
+    timeout: Duration,
+    stats: Arc<Mutex<BenchStats>>,
+}
+
+impl Worker {
+    fn send(&self) {
+        let started = Instant::now();
+        let outcome = self.exchange();
+        lock(&self.stats).record(started.elapsed(), outcome);
+    }
+
+    // One connection per request: the request asks the server to close it, which also
+    // delimits the response.
+    fn exchange(&self) -> Result<u16, String> {
+        let mut stream = TcpStream::connect_timeout(&self.addr, self.timeout)
+            .map_err(|err| format!("connect: {}", err.kind()))?;
+        stream
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|()| stream.set_write_timeout(Some(self.timeout)))
+            .and_then(|()| stream.write_all(&self.request))
+            .map_err(|err| format!("send: {}", err.kind()))?;
+        let mut raw = Vec::new();
+        stream
+            .read_to_end(&mut raw)
+            .map_err(|err| format!("receive: {}", err.kind()))?;
+        parse_response(&raw).map(|response| response.status)
+    }
+}
+
+fn lock(stats: &Mutex<BenchStats>) -> MutexGuard<'_, BenchStats> {
+    stats
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+// Runs until the duration elapses or `stop_event` is set, then waits for requests in flight.
+pub fn run(
+    addr: SocketAddr,
+    request: Vec<u8>,
+    command: &BenchCommand,
+    stop_event: &Arc<AtomicBool>,
+) -> (BenchStats, Duration) {
+    let stats = Arc::new(Mutex::new(BenchStats::default()));
+    let worker = Arc::new(Worker {
+        addr,
+        request: Arc::new(request),
+        timeout: command.timeout,
+        stats: stats.clone(),
+    });
+    let started = Instant::now();
+    let deadline = started + command.duration;
+    let running =
+        move |stop: &AtomicBool| Instant::now() < deadline && !stop.load(Ordering::SeqCst);
+    let (sender, receiver) = bounded::<()>(command.mode.workers());
+    let handles: Vec<_> = (0..command.mode.workers())
+        .map(|_| {
+            let worker = worker.clone();
+            let receiver = receiver.clone();
+            let stop = stop_event.clone();
+            let mode = command.mode;
+            thread::spawn(move || match mode {
+                BenchMode::Rate { .. } => {
+                    while receiver.recv().is_ok() {
+                        worker.send();
+                    }
+                }
+                BenchMode::Concurrency(_) => {
+                    while running(&stop) {
+                        worker.send();
+                    }
+                }
+            })
+        })
+        .collect();
+    if let BenchMode::Rate { per_second, .. } = command.mode {
+        let interval = Duration::from_secs(1) / per_second;
+        let mut next = started;
+        while running(stop_event) {
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+            if sender.try_send(()).is_err() {
+                lock(&stats).record_dropped();
+            }
+            next += interval;
+        }
+    }
+    drop(sender);
+    for handle in handles {
+        let _ = handle.join();
+    }
+    let elapsed = started.elapsed();
+    let stats = std::mem::take(&mut *lock(&stats));
+    (stats, elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_bench_args;
+    use crate::support::bench::BenchMode;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn bench_args_select_a_mode_and_keep_request_edits() {
+        let command = parse_bench_args(&args(&[
+            "--rate=200",
+            "--duration",
+            "30s",
+            "--path",
+            "/users",
+            "-H",
+            "x-debug: 1",
+        ]));
+        assert!(command.is_ok());
+        let Ok(command) = command else {
+            return;
+        };
+        assert_eq!(
+            command.mode,
+            BenchMode::Rate {
+                per_second: 200,
+                workers: 64
+            }
+        );
+        assert_eq!(command.duration, Duration::from_secs(30));
+        assert_eq!(command.edits.path.as_deref(), Some("/users"));
+        assert_eq!(command.edits.headers.len(), 1);
+
+        let closed = parse_bench_args(&args(&["--concurrency", "8", "--call", "12"]));
+        assert!(closed.is_ok());
+        let Ok(closed) = closed else {
+            return;
+        };
+        assert_eq!(closed.mode, BenchMode::Concurrency(8));
+        assert_eq!(closed.call, Some(12));
+
+        assert!(parse_bench_args(&args(&["--rate", "0"])).is_err());
+        assert!(parse_bench_args(&args(&["--curl"])).is_err());
+    }
+}
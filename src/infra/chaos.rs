@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::domain::Scope;
+use crate::infra::engine::Engine;
+use crate::infra::envoy::parse_duration_ms;
+use crate::infra::process::run_output;
+use crate::infra::resolver::RuntimeResolver;
+use crate::support::args::net_init_image;
+use crate::support::chaos::{self, edge_label, ChaosAction, ChaosRule};
+use crate::support::constants::{CHAOS_KIND, PROXY_NAME_LABEL};
+use crate::support::timeline::{self, TimelineEvent};
+
+const CHAIN: &str = "SANELENS_CHAOS";
+// `prio` supports 16 bands and the first three keep their default traffic classes.
+const MAX_DELAY_RULES: usize = 13;
+
+// Rules are enforced in the network namespace of the destination's sidecar, which is where
+// every caller's connections to that service arrive, whatever the protocol. Callers are
+// identified by their container addresses; the full rule set of a destination is reapplied
+// on every change so rules from earlier commands survive.
+#[derive(Clone)]
+pub struct ChaosControl {
+    engine: Engine,
+    run_id: String,
+    run_dir: PathBuf,
+    service_aliases: HashMap<String, String>,
+}
+
+impl ChaosControl {
+    pub const fn new(
+        engine: Engine,
+        run_id: String,
+        run_dir: PathBuf,
+        service_aliases: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            engine,
+            run_id,
+            run_dir,
+            service_aliases,
+        }
+    }
+
+    pub fn rules(&self) -> Vec<ChaosRule> {
+        chaos::load(&self.run_dir).unwrap_or_default()
+    }
+
+    pub fn timeline(&self) -> Vec<TimelineEvent> {
+        timeline::read(&self.run_dir).unwrap_or_default()
+    }
+
+    pub fn apply(&self, rule: &ChaosRule, duration_ms: Option<u64>) -> Result<String, String> {
+        let mut rules = self.load()?;
+        chaos::upsert(&mut rules, rule.clone());
+        self.enforce(&rules, &rule.to)?;
+        self.save(&rules)?;
+        let message =
+            duration_ms.map_or_else(|| rule.to_string(), |ms| format!("{rule} for {ms}ms"));
+        self.record(&message);
+        Ok(message)
+    }
+
+    pub fn clear(&self, from: &str, to: &str, port: Option<u16>) -> Result<String, String> {
+        let mut rules = self.load()?;
+        let edge = edge_label(from, to, port);
+        if !chaos::remove(&mut rules, from, to, port) {
+            return Err(format!("no chaos rule for {edge}"));
+        }
+        self.enforce(&rules, to)?;
+        self.save(&rules)?;
+        let message = format!("{edge} restored");
+        self.record(&message);
+        Ok(message)
+    }
+
+    // Timed rules are only lifted if nobody replaced them in the meantime.
+    pub fn expire(&self, rule: &ChaosRule) -> Result<Option<String>, String> {
+        if !self.load()?.contains(rule) {
+            return Ok(None);
+        }
+        self.clear(&rule.from, &rule.to, rule.port).map(Some)
+    }
+
+    fn enforce(&self, rules: &[ChaosRule], to: &str) -> Result<(), String> {
+        let ids = self
+            .engine
+            .collect_run_proxy_container_ids(&self.run_id, Scope::Running);
+        let sidecars: Vec<String> = self
+            .engine
+            .inspect_containers(&ids)
+            .into_iter()
+            .filter(|info| info.labels.get(PROXY_NAME_LABEL).map(String::as_str) == Some(to))
+            .map(|info| info.id)
+            .collect();
+        if sidecars.is_empty() {
+            return Err(format!(
+                "no running proxy for service {to} in run {}",
+                self.run_id
+            ));
+        }
+        let resolver =
+            RuntimeResolver::from_engine(&self.engine, &self.run_id, &self.service_aliases);
+        let mut targets = Vec::new();
+        for rule in rules.iter().filter(|rule| rule.to == to) {
+            let ips: Vec<IpAddr> = resolver
+                .ips_of(&rule.from)
+                .into_iter()
+                .filter(IpAddr::is_ipv4)
+                .collect();
+            if ips.is_empty() {
+                return Err(format!("no running containers for service {}", rule.from));
+            }
+            targets.push((ips, rule));
+        }
+        let script = chaos_script(&targets)?;
+        let image = net_init_image();
+        for sidecar in sidecars {
+            let output = run_output(&self.engine.net_admin_cmd(&sidecar, &image, &script))
+                .map_err(|err| format!("failed to start {image}: {err}"))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "failed to apply chaos rules to {to}: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<ChaosRule>, String> {
+        chaos::load(&self.run_dir).map_err(|err| format!("failed to read chaos rules: {err}"))
+    }
+
+    fn save(&self, rules: &[ChaosRule]) -> Result<(), String> {
+        chaos::save(&self.run_dir, rules)
+            .map_err(|err| format!("failed to save chaos rules: {err}"))
+    }
+
+    fn record(&self, message: &str) {
+        let event = TimelineEvent {
+            at_ms: now_ms(),
+            kind: CHAOS_KIND.to_string(),
+            message: message.to_string(),
+        };
+        if let Err(err) = timeline::append(&self.run_dir, &event) {
+            eprintln!("[compose] failed to record chaos timeline: {err}");
+        }
+    }
+}
+
+// Blocked callers have their packets to the sidecar dropped; delayed callers get every
+// packet the sidecar sends them held back, which adds the delay to each round trip.
+fn chaos_script(targets: &[(Vec<IpAddr>, &ChaosRule)]) -> Result<String, String> {
+    let delays: Vec<_> = targets
+        .iter()
+        .filter_map(|(ips, rule)| match rule.action {
+            ChaosAction::Delay { ms } => Some((ips, rule.port, ms)),
+            ChaosAction::Block => None,
+        })
+        .collect();
+    if delays.len() > MAX_DELAY_RULES {
+        return Err(format!(
+            "at most {MAX_DELAY_RULES} delay rules are supported per service"
+        ));
+    }
+    let mut script = format!(
+        "set -e; iptables -N {CHAIN} 2>/dev/null || true; iptables -F {CHAIN}; {{ iptables -C INPUT -j {CHAIN} 2>/dev/null || iptables -I INPUT -j {CHAIN}; }}"
+    );
+    for (ips, rule) in targets {
+        if rule.action != ChaosAction::Block {
+            continue;
+        }
+        let port = rule
+            .port
+            .map(|port| format!(" --dport {port}"))
+            .unwrap_or_default();
+        for ip in ips {
+            let _ = write!(script, "; iptables -A {CHAIN} -s {ip} -p tcp{port} -j DROP");
+        }
+    }
+    script.push_str(
+        "; for dev in $(ls /sys/class/net); do [ \"$dev\" = lo ] && continue; tc qdisc del dev \"$dev\" root 2>/dev/null || true",
+    );
+    if !delays.is_empty() {
+        let _ = write!(
+            script,
+            "; tc qdisc add dev \"$dev\" root handle 1: prio bands {}",
+            delays.len() + 3
+        );
+    }
+    for (index, (ips, port, ms)) in delays.iter().enumerate() {
+        let band = index + 4;
+        let _ = write!(
+            script,
+            "; tc qdisc add dev \"$dev\" parent 1:{band} handle {}: netem delay {ms}ms",
+            band * 10
+        );
+        let sport = port
+            .map(|port| format!(" match ip sport {port} 0xffff"))
+            .unwrap_or_default();
+        for ip in *ips {
+            let _ = write!(
+                script,
+                "; tc filter add dev \"$dev\" parent 1: protocol ip prio 1 u32 match ip dst {ip}/32{sport} flowid 1:{band}"
+            );
+        }
+    }
+    script.push_str("; done");
+    Ok(script)
+}
+
+pub struct ChaosCommand {
+    pub port: Option<u16>,
+    pub action: Option<ChaosAction>,
+    pub duration_ms: Option<u64>,
+}
+
+pub fn parse_chaos_args(args: &[String]) -> Result<ChaosCommand, String> {
+    let mut command = ChaosCommand {
+        port: None,
+        action: None,
+        duration_ms: None,
+    };
+    let mut clear = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = arg
+            .split_once('=')
+            .map_or((arg.as_str(), None), |(flag, value)| (flag, Some(value)));
+        if flag == "--block" {
+            command.action = Some(ChaosAction::Block);
+            continue;
+        }
+        if flag == "--clear" {
+            clear = true;
+            continue;
+        }
+        let value = inline
+            .map(str::to_string)
+            .or_else(|| iter.next().cloned())
+            .ok_or_else(|| format!("{flag} needs a value"))?;
+        match flag {
+            "--delay" => {
+                command.action = Some(ChaosAction::Delay {
+                    ms: parse_duration_ms(&value)?,
+                });
+            }
+            "--port" => {
+                let port = value
+                    .parse()
+                    .map_err(|_| format!("invalid port '{value}'"))?;
+                command.port = Some(port);
+            }
+            "--for" => command.duration_ms = Some(parse_duration_ms(&value)?),
+            _ => return Err(format!("unknown chaos option '{arg}'")),
+        }
+    }
+    if clear == command.action.is_some() {
+        return Err("pass exactly one of --block, --delay or --clear".to_string());
+    }
+    Ok(command)
+}
+
+fn now_ms() -> u64 {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    u64::try_from(millis).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::{chaos_script, parse_chaos_args};
+    use crate::support::chaos::{ChaosAction, ChaosRule};
+
+    fn rule(from: &str, port: Option<u16>, action: ChaosAction) -> ChaosRule {
+        ChaosRule {
+            from: from.to_string(),
+            to: "db".to_string(),
+            port,
+            action,
+        }
+    }
+
+    #[test]
+    fn script_blocks_and_delays_callers() {
+        let web = rule("web", Some(5432), ChaosAction::Block);
+        let api = rule("api", None, ChaosAction::Delay { ms: 300 });
+        let targets = [
+            (vec![IpAddr::from([10, 0, 0, 2])], &web),
+            (vec![IpAddr::from([10, 0, 0, 3])], &api),
+        ];
+        let script = chaos_script(&targets).unwrap_or_default();
+        assert!(
+            script.contains("iptables -A SANELENS_CHAOS -s 10.0.0.2 -p tcp --dport 5432 -j DROP")
+        );
+        assert!(script.contains("prio bands 4"));
+        assert!(script.contains("parent 1:4 handle 40: netem delay 300ms"));
+        assert!(script.contains("match ip dst 10.0.0.3/32 flowid 1:4"));
+        let cleared = chaos_script(&[]).unwrap_or_default();
+        assert!(!cleared.contains("netem"));
+        assert!(cleared.contains("tc qdisc del"));
+    }
+
+    #[test]
+    fn chaos_args_need_one_action() {
+        let args = |raw: &str| {
+            raw.split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let command = parse_chaos_args(&args("--delay 300ms --port 5432 --for 1m"));
+        assert_eq!(
+            command.as_ref().ok().and_then(|command| command.action),
+            Some(ChaosAction::Delay { ms: 300 })
+        );
+        assert_eq!(
+            command
+                .as_ref()
+                .ok()
+                .and_then(|command| command.duration_ms),
+            Some(60_000)
+        );
+        assert!(parse_chaos_args(&args("--port 5432")).is_err());
+        assert!(parse_chaos_args(&args("--block --clear")).is_err());
+    }
+}
@@ -6,11 +6,29 @@ use std::process::{Command, Stdio};
 
 use serde_yaml::{Mapping, Value};
 
-use crate::support::args::extract_compose_global_args;
+use crate::infra::envoy::{
+    self, CanaryRoute, CanarySplit, EgressOptions, IngressOptions, InterceptHost, PortConfig,
+    PortTuning, ProxyProtocol, VcrProxy, ADMIN_PORT, TUNING_KEYS,
+};
+use crate::infra::extension::{
+    take_service_extension, take_top_level_extension, CanaryExtension, ProxyMode, ServiceExtension,
+    EXTENSION_KEY,
+};
+use crate::infra::fault::{self, FaultRule, FaultSpec};
+use crate::infra::tls::{self, InterceptCa};
+use crate::support::args::{extract_compose_global_args, scale_overrides};
+use crate::support::capture::body_patterns_label;
 use crate::support::constants::{
-    COMPOSE_FILE_LABEL, DERIVED_COMPOSE_LABEL, PROJECT_NAME_LABEL, RUN_ID_LABEL, SERVICE_LABEL,
-    STARTED_AT_LABEL,
+    CANARY_SUFFIX, CAPTURE_CONTENT_TYPES_LABEL, CAPTURE_IGNORE_PATHS_LABEL,
+    CAPTURE_PREVIEW_BYTES_LABEL, CAPTURE_REDACT_BODY_LABEL, CAPTURE_REDACT_HEADERS_LABEL,
+    CAPTURE_REDACT_JSON_LABEL, CAPTURE_ROUTES_LABEL, CAPTURE_SKIP_CONTENT_TYPES_LABEL,
+    COMPOSE_FILE_LABEL, DERIVED_COMPOSE_LABEL, EGRESS_POLICY_HOSTS_LABEL, EGRESS_POLICY_MODE_LABEL,
+    FAULT_ABORT_LABEL, FAULT_DELAY_LABEL, FAULT_PERCENT_LABEL, FAULT_RESET_LABEL,
+    FAULT_ROUTES_LABEL, PROJECT_NAME_LABEL, PROXY_LABEL, PROXY_NAME_LABEL, RUN_ID_LABEL,
+    SERVICE_LABEL, STARTED_AT_LABEL,
 };
+use crate::support::egress_policy::EgressPolicy;
+use crate::support::vcr::VcrMode;
 
 #[derive(Clone)]
 pub struct DerivedCompose {
@@ -19,6 +37,7 @@ pub struct DerivedCompose {
     pub proxy_services: HashSet<String>,
     pub app_service_map: HashMap<String, String>,
     pub egress_proxy: Option<String>,
+    pub contracts: HashMap<String, PathBuf>,
 }
 
 #[derive(Clone)]
@@ -29,18 +48,18 @@ pub struct DeriveConfig {
     pub envoy_image: String,
     pub enable_traffic: bool,
     pub enable_egress: bool,
+    pub egress_tls_hosts: Vec<String>,
+    pub transparent_egress: bool,
+    pub net_init_image: String,
+    pub vcr_mode: Option<VcrMode>,
+    pub vcr_replay_port: Option<u16>,
+    pub otlp_port: Option<u16>,
     pub compose_cmd: Vec<String>,
     pub compose_args: Vec<String>,
     pub compose_file_from_args: bool,
     pub disable_pods: bool,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-enum ProxyProtocol {
-    Http,
-    Tcp,
-}
-
 struct RunLabelContext<'a> {
     run_id: &'a str,
     compose_file: &'a str,
@@ -73,6 +92,7 @@ pub fn derive_compose(
         project_name,
     };
 
+    let extension_defaults = take_top_level_extension(&mut doc)?;
     rewrite_top_level_paths(&mut doc, compose_dir);
     if config.disable_pods {
         disable_podman_pods(&mut doc);
@@ -101,6 +121,8 @@ pub fn derive_compose(
             let Value::Mapping(service) = service_value else {
                 continue;
             };
+            service.remove(Value::String(EXTENSION_KEY.to_string()));
+            strip_config_labels(service);
             rewrite_service_paths(service, compose_dir);
             add_run_labels(service, service_name, &run_labels);
         }
@@ -114,6 +136,7 @@ pub fn derive_compose(
             proxy_services: HashSet::new(),
             app_service_map: HashMap::new(),
             egress_proxy: None,
+            contracts: HashMap::new(),
         });
     }
 
@@ -126,14 +149,27 @@ pub fn derive_compose(
     let mut proxy_services = HashSet::new();
     let mut app_service_map = HashMap::new();
     let mut proxy_app_map = HashMap::new();
+    let mut contracts = HashMap::new();
     let mut no_proxy_hosts = Vec::new();
     for name in &service_names {
         no_proxy_hosts.push(name.clone());
     }
+    let mut extra_hosts = collect_extra_hosts(services);
     no_proxy_hosts.push("localhost".to_string());
     no_proxy_hosts.push("127.0.0.1".to_string());
-    let no_proxy_value = no_proxy_hosts.join(",");
+    let mut egress = EgressRouting {
+        no_proxy_value: no_proxy_hosts.join(","),
+        intercept: if config.enable_egress {
+            prepare_tls_intercept(&out_dir, config, &extension_defaults)?
+        } else {
+            None
+        },
+        transparent: config.transparent_egress,
+        clients: Vec::new(),
+        ca_inits: Vec::new(),
+    };
 
+    let scale_overrides = scale_overrides(&config.compose_args);
     for name in service_names {
         let key = Value::String(name.clone());
         let service_value = services.get(&key).cloned().unwrap_or(Value::Null);
@@ -144,47 +180,38 @@ pub fn derive_compose(
                 continue;
             }
         };
+        let extension =
+            take_service_extension(&mut service, &name)?.with_defaults(&extension_defaults);
+        let replicas = service_replicas(&service, scale_override(&scale_overrides, &name));
         rewrite_service_paths(&mut service, compose_dir);
         let network_mode = get_string(&service, "network_mode");
         if network_mode.as_deref() == Some("host") || network_mode.as_deref() == Some("none") {
+            strip_config_labels(&mut service);
             add_run_labels(&mut service, &name, &run_labels);
             new_services.insert(key, Value::Mapping(service));
             continue;
         }
-        let ports = extract_ports(&service);
-        if ports.is_empty() {
-            if config.enable_egress {
-                ensure_env_var(
-                    &mut service,
-                    "HTTP_PROXY",
-                    "http://sanelens-egress-proxy:15001",
-                );
-                ensure_env_var(
-                    &mut service,
-                    "HTTPS_PROXY",
-                    "http://sanelens-egress-proxy:15001",
-                );
-                merge_env_var(&mut service, "NO_PROXY", &no_proxy_value);
+        let ports = network_mode
+            .as_deref()
+            .and_then(shared_namespace_owner)
+            .map_or_else(
+                || extract_ports(&service),
+                |owner| {
+                    warn_unproxied_ports(&name, owner, &service);
+                    Vec::new()
+                },
+            );
+        let egress_enabled = config.enable_egress && extension.participates_in_egress();
+        let service_mode = extension.proxy.or_else(|| read_proxy_mode(&service, &name));
+        if ports.is_empty() || service_mode == Some(ProxyMode::Off) {
+            if extension.contract.is_some() {
+                eprintln!("[compose] contract for {name} ignored: the service is not proxied");
             }
-            add_run_labels(&mut service, &name, &run_labels);
-            new_services.insert(key, Value::Mapping(service));
-            continue;
-        }
-        let protocol_override = read_proxy_protocol(&service);
-        if protocol_override == Some("off".to_string()) {
-            if config.enable_egress {
-                ensure_env_var(
-                    &mut service,
-                    "HTTP_PROXY",
-                    "http://sanelens-egress-proxy:15001",
-                );
-                ensure_env_var(
-                    &mut service,
-                    "HTTPS_PROXY",
-                    "http://sanelens-egress-proxy:15001",
-                );
-                merge_env_var(&mut service, "NO_PROXY", &no_proxy_value);
+            if egress_enabled {
+                egress.attach(&mut service, &name, replicas)?;
             }
+            apply_otlp_env(&mut service, &name, config);
+            strip_config_labels(&mut service);
             add_run_labels(&mut service, &name, &run_labels);
             new_services.insert(key, Value::Mapping(service));
             continue;
@@ -192,16 +219,24 @@ pub fn derive_compose(
 
         let mut port_modes = Vec::new();
         for port in &ports {
-            let mode = match protocol_override.as_deref() {
-                Some("http") => ProxyProtocol::Http,
-                Some("tcp") => ProxyProtocol::Tcp,
-                Some("auto" | "true") | None => guess_protocol(*port),
-                Some(other) => {
-                    eprintln!("[compose] unknown sanelens.proxy value '{other}' on {name}");
-                    guess_protocol(*port)
-                }
+            let protocol = match extension.port_mode(*port).or(service_mode) {
+                Some(ProxyMode::Http) => ProxyProtocol::Http,
+                Some(ProxyMode::Tcp) => ProxyProtocol::Tcp,
+                Some(ProxyMode::Postgres) => ProxyProtocol::Postgres,
+                Some(ProxyMode::Mysql) => ProxyProtocol::Mysql,
+                Some(ProxyMode::Redis) => ProxyProtocol::Redis,
+                Some(ProxyMode::Auto | ProxyMode::Off) | None => guess_protocol(*port),
             };
-            port_modes.push((*port, mode));
+            port_modes.push(PortConfig {
+                port: *port,
+                protocol,
+                tuning: read_port_tuning(&service, &name, *port, &extension, &extension_defaults)?,
+            });
+        }
+        let faults = read_fault_rules(&service, &name, &extension)?;
+        strip_config_labels(&mut service);
+        if let Some(contract) = &extension.contract {
+            contracts.insert(name.clone(), compose_dir.join(contract));
         }
 
         let app_name = format!("{name}-app");
@@ -217,19 +252,10 @@ pub fn derive_compose(
         add_label(&mut app_service, "sanelens.app", "true");
         add_label(&mut app_service, "sanelens.app.name", &name);
         add_run_labels(&mut app_service, &name, &run_labels);
-        if config.enable_egress {
-            ensure_env_var(
-                &mut app_service,
-                "HTTP_PROXY",
-                "http://sanelens-egress-proxy:15001",
-            );
-            ensure_env_var(
-                &mut app_service,
-                "HTTPS_PROXY",
-                "http://sanelens-egress-proxy:15001",
-            );
-            merge_env_var(&mut app_service, "NO_PROXY", &no_proxy_value);
+        if egress_enabled {
+            egress.attach(&mut app_service, &app_name, replicas)?;
         }
+        apply_otlp_env(&mut app_service, &name, config);
 
         let mut proxy_service = Mapping::new();
         proxy_service.insert(
@@ -244,9 +270,76 @@ pub fn derive_compose(
         }
         if let Some(networks) = service.get(Value::String("networks".to_string())) {
             proxy_service.insert(Value::String("networks".to_string()), networks.clone());
+            app_service.insert(
+                Value::String("networks".to_string()),
+                app_network_attachments(networks),
+            );
+        }
+        if let Some(mode) = network_mode.as_deref() {
+            proxy_service.insert(
+                Value::String("network_mode".to_string()),
+                Value::String(mode.to_string()),
+            );
+            if mode == "bridge" {
+                proxy_service.insert(
+                    Value::String("links".to_string()),
+                    Value::Sequence(vec![Value::String(app_name.clone())]),
+                );
+            }
+        }
+        if let Some(extra_hosts) = service.get(Value::String("extra_hosts".to_string())) {
+            proxy_service.insert(
+                Value::String("extra_hosts".to_string()),
+                extra_hosts.clone(),
+            );
+        }
+        let app_healthcheck = enabled_healthcheck(&service);
+        let mut depends = build_proxy_depends_on(&app_name, app_healthcheck.is_some());
+        let canary_name = format!("{name}{CANARY_SUFFIX}");
+        let canary = match extension.canary.as_ref() {
+            Some(canary) => Some((canary, canary.split()?)),
+            None => None,
+        };
+        if let Some((canary, split)) = canary {
+            if services.contains_key(Value::String(canary_name.clone())) {
+                return Err(format!(
+                    "service {name} declares a canary but {canary_name} already exists"
+                ));
+            }
+            if let CanarySplit::Mirror(_) = split {
+                warn_tcp_mirror(&name, &port_modes);
+            }
+            let mut canary_service = build_canary_service(
+                &service,
+                canary,
+                compose_dir,
+                &ports,
+                original_expose.as_ref(),
+            );
+            add_label(&mut canary_service, "sanelens.app", "true");
+            add_label(&mut canary_service, "sanelens.app.name", &canary_name);
+            add_run_labels(&mut canary_service, &canary_name, &run_labels);
+            if egress_enabled {
+                let canary_replicas = service_replicas(&canary_service, None);
+                egress.attach(&mut canary_service, &canary_name, canary_replicas)?;
+            }
+            apply_otlp_env(&mut canary_service, &canary_name, config);
+            if let Value::Mapping(map) = &mut depends {
+                map.insert(
+                    Value::String(canary_name.clone()),
+                    Value::Mapping(Mapping::new()),
+                );
+            }
+            new_services.insert(
+                Value::String(canary_name.clone()),
+                Value::Mapping(canary_service),
+            );
         }
-        let depends = build_proxy_depends_on(&app_name);
         proxy_service.insert(Value::String("depends_on".to_string()), depends);
+        proxy_service.insert(
+            Value::String("healthcheck".to_string()),
+            build_proxy_healthcheck(app_healthcheck, &app_name, &ports),
+        );
         if let Some(ports_value) = original_ports.clone() {
             proxy_service.insert(Value::String("ports".to_string()), ports_value);
         }
@@ -269,10 +362,23 @@ pub fn derive_compose(
         ]);
         proxy_service.insert(Value::String("volumes".to_string()), volumes_value);
         add_label(&mut proxy_service, "sanelens.proxy", "true");
-        add_label(&mut proxy_service, "sanelens.proxy.name", &name);
+        add_label(&mut proxy_service, PROXY_NAME_LABEL, &name);
+        if let Some(routes) = fault::routes_label(&faults) {
+            add_label(&mut proxy_service, FAULT_ROUTES_LABEL, &routes);
+        }
+        add_capture_labels(&mut proxy_service, &extension);
         add_run_labels(&mut proxy_service, &name, &run_labels);
 
-        write_envoy_config(&envoy_dir, &name, &app_name, &port_modes)
+        let options = IngressOptions {
+            scaled: replicas > 1,
+            faults: &faults,
+            canary: canary.map(|(_, split)| CanaryRoute {
+                app_name: &canary_name,
+                split,
+            }),
+        };
+        let bootstrap = envoy::ingress_bootstrap(&name, &app_name, &port_modes, options);
+        write_envoy_config(&envoy_dir, &name, &bootstrap)
             .map_err(|err| format!("failed to write envoy config: {err}"))?;
 
         new_services.insert(Value::String(name.clone()), Value::Mapping(proxy_service));
@@ -281,6 +387,10 @@ pub fn derive_compose(
     }
 
     if config.enable_egress {
+        let egress_policy = extension_defaults.egress_policy()?;
+        if vcr_proxy(config).is_some() && !config.disable_pods {
+            extra_hosts.push(Value::String(format!("{DOCKER_HOST_GATEWAY}:host-gateway")));
+        }
         let egress_name = "sanelens-egress-proxy".to_string();
         let tap_service_dir = tap_dir.join(&egress_name);
         fs::create_dir_all(&tap_service_dir)
@@ -293,20 +403,75 @@ pub fn derive_compose(
             Some(&tap_service_dir),
         );
         if let Value::Mapping(map) = &mut egress_config {
+            if egress.transparent {
+                let mut sysctls = Mapping::new();
+                sysctls.insert(
+                    Value::String("net.ipv4.ip_forward".to_string()),
+                    Value::String("1".to_string()),
+                );
+                map.insert(
+                    Value::String("sysctls".to_string()),
+                    Value::Mapping(sysctls),
+                );
+            }
+            if let Some(intercept) = &egress.intercept {
+                // Leaf keys are only readable by the host user, which is root in the
+                // container; the image's entrypoint drops to its own user otherwise.
+                ensure_env_var(map, "ENVOY_UID", "0");
+                add_volume(
+                    map,
+                    format!(
+                        "{}:{}:ro",
+                        intercept.dir.to_string_lossy(),
+                        tls::CONTAINER_TLS_DIR
+                    ),
+                );
+            }
+            if !extra_hosts.is_empty() {
+                map.insert(
+                    Value::String("extra_hosts".to_string()),
+                    Value::Sequence(extra_hosts),
+                );
+            }
+            add_capture_labels(map, &extension_defaults);
+            if let Some(policy) = &egress_policy {
+                add_label(map, EGRESS_POLICY_MODE_LABEL, policy.mode.as_str());
+                add_label(map, EGRESS_POLICY_HOSTS_LABEL, &policy.patterns_label());
+            }
             add_run_labels(map, &egress_name, &run_labels);
         }
         let egress_envoy = envoy_dir.join("egress.yaml");
-        write_egress_envoy_config(&egress_envoy)
-            .map_err(|err| format!("failed to write egress envoy config: {err}"))?;
+        write_egress_envoy_config(
+            &egress_envoy,
+            egress.intercept.as_ref(),
+            egress.transparent,
+            egress_policy.as_ref(),
+            vcr_proxy(config),
+        )
+        .map_err(|err| format!("failed to write egress envoy config: {err}"))?;
         new_services.insert(Value::String(egress_name.clone()), egress_config);
         proxy_services.insert(egress_name);
+        for (init_name, mut init_service) in std::mem::take(&mut egress.ca_inits) {
+            add_run_labels(&mut init_service, &init_name, &run_labels);
+            new_services.insert(Value::String(init_name), Value::Mapping(init_service));
+        }
+        if egress.transparent {
+            for (net_name, mut net_service) in
+                build_transparent_net_services(&config.net_init_image, &egress.clients)
+            {
+                add_run_labels(&mut net_service, &net_name, &run_labels);
+                new_services.insert(Value::String(net_name), Value::Mapping(net_service));
+            }
+        }
     }
 
-    for (_, value) in &mut new_services {
+    for (name, value) in &mut new_services {
         let Value::Mapping(service) = value else {
             continue;
         };
         rewrite_depends_on_for_proxies(service, &proxy_app_map);
+        rewrite_network_mode_for_proxies(service, &proxy_app_map);
+        warn_aliased_links(name.as_str().unwrap_or_default(), service, &proxy_app_map);
     }
 
     *services = new_services;
@@ -326,6 +491,7 @@ pub fn derive_compose(
         } else {
             None
         },
+        contracts,
     })
 }
 
@@ -381,15 +547,170 @@ fn run_compose_output(cmd: &[String]) -> std::io::Result<std::process::Output> {
     command.output()
 }
 
-fn build_proxy_depends_on(app_name: &str) -> Value {
+fn build_proxy_depends_on(app_name: &str, wait_healthy: bool) -> Value {
+    let mut condition = Mapping::new();
+    if wait_healthy {
+        condition.insert(
+            Value::String("condition".to_string()),
+            Value::String("service_healthy".to_string()),
+        );
+    }
     let mut map = Mapping::new();
     map.insert(
         Value::String(app_name.to_string()),
-        Value::Mapping(Mapping::new()),
+        Value::Mapping(condition),
     );
     Value::Mapping(map)
 }
 
+// The canary starts from the app's definition; `image`, `build`, `command` and `environment`
+// from the extension replace the original values.
+fn build_canary_service(
+    service: &Mapping,
+    canary: &CanaryExtension,
+    compose_dir: &Path,
+    ports: &[u16],
+    original_expose: Option<&Value>,
+) -> Mapping {
+    let mut canary_service = service.clone();
+    if let Some(image) = &canary.image {
+        canary_service.insert(
+            Value::String("image".to_string()),
+            Value::String(image.clone()),
+        );
+        if canary.build.is_none() {
+            canary_service.remove(Value::String("build".to_string()));
+        }
+    }
+    if let Some(build) = &canary.build {
+        canary_service.insert(Value::String("build".to_string()), build.clone());
+        rewrite_build(&mut canary_service, compose_dir);
+        if canary.image.is_none() {
+            canary_service.remove(Value::String("image".to_string()));
+        }
+    }
+    if let Some(command) = &canary.command {
+        canary_service.insert(Value::String("command".to_string()), command.clone());
+    }
+    for (key, value) in canary.environment.iter().flatten() {
+        set_env_var(&mut canary_service, key, &value.to_string());
+    }
+    ensure_expose_ports(&mut canary_service, ports, original_expose);
+    if let Some(networks) = service.get(Value::String("networks".to_string())) {
+        canary_service.insert(
+            Value::String("networks".to_string()),
+            app_network_attachments(networks),
+        );
+    }
+    canary_service
+}
+
+fn warn_tcp_mirror(name: &str, ports: &[PortConfig]) {
+    for config in ports
+        .iter()
+        .filter(|config| config.protocol != ProxyProtocol::Http)
+    {
+        eprintln!(
+            "[compose] {name} port {} is proxied as TCP; traffic on it is not mirrored to the canary",
+            config.port
+        );
+    }
+}
+
+fn enabled_healthcheck(service: &Mapping) -> Option<&Mapping> {
+    let Some(Value::Mapping(healthcheck)) = service.get(Value::String("healthcheck".to_string()))
+    else {
+        return None;
+    };
+    let disabled = healthcheck
+        .get(Value::String("disable".to_string()))
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let test_none = match healthcheck.get(Value::String("test".to_string())) {
+        Some(Value::Sequence(items)) => items.first().and_then(Value::as_str) == Some("NONE"),
+        Some(Value::String(value)) => value == "NONE",
+        _ => false,
+    };
+    if disabled || test_none {
+        None
+    } else {
+        Some(healthcheck)
+    }
+}
+
+const HEALTHCHECK_TIMING_KEYS: [&str; 5] = [
+    "interval",
+    "timeout",
+    "retries",
+    "start_period",
+    "start_interval",
+];
+// Used when the app has no check to copy: probe often and give slow apps a minute to listen.
+const UPSTREAM_HEALTHCHECK_INTERVAL: &str = "2s";
+const UPSTREAM_HEALTHCHECK_RETRIES: u64 = 30;
+
+// When the app has a healthcheck the proxy only starts once it passes, so Envoy's readiness is
+// enough. Otherwise the proxy also has to reach every proxied port of the app.
+fn build_proxy_healthcheck(
+    app_healthcheck: Option<&Mapping>,
+    app_name: &str,
+    ports: &[u16],
+) -> Value {
+    let mut script = proxy_healthcheck_script();
+    if app_healthcheck.is_none() && !ports.is_empty() {
+        script = format!("{script} && {}", upstream_probe_script(app_name, ports));
+    }
+    let mut map = Mapping::new();
+    map.insert(
+        Value::String("test".to_string()),
+        Value::Sequence(vec![
+            Value::String("CMD-SHELL".to_string()),
+            Value::String(script),
+        ]),
+    );
+    if let Some(app_healthcheck) = app_healthcheck {
+        for key in HEALTHCHECK_TIMING_KEYS {
+            let key = Value::String(key.to_string());
+            if let Some(value) = app_healthcheck.get(&key) {
+                map.insert(key, value.clone());
+            }
+        }
+    } else {
+        for key in ["interval", "timeout"] {
+            map.insert(
+                Value::String(key.to_string()),
+                Value::String(UPSTREAM_HEALTHCHECK_INTERVAL.to_string()),
+            );
+        }
+        map.insert(
+            Value::String("retries".to_string()),
+            Value::Number(UPSTREAM_HEALTHCHECK_RETRIES.into()),
+        );
+    }
+    Value::Mapping(map)
+}
+
+// The admin endpoint is probed with whichever client the Envoy image ships.
+fn proxy_healthcheck_script() -> String {
+    let url = format!("http://127.0.0.1:{ADMIN_PORT}/ready");
+    format!(
+        "if command -v curl >/dev/null; then curl -fsS -o /dev/null {url}; \
+elif command -v wget >/dev/null; then wget -q -O /dev/null {url}; \
+else bash -c 'exec 3<>/dev/tcp/127.0.0.1/{ADMIN_PORT} \
+&& printf \"GET /ready HTTP/1.0\\r\\n\\r\\n\" >&3 && read -r status <&3 \
+&& [[ $$status == *\" 200 \"* ]]'; fi"
+    )
+}
+
+fn upstream_probe_script(app_name: &str, ports: &[u16]) -> String {
+    let probes = ports
+        .iter()
+        .map(|port| format!("exec 3<>/dev/tcp/{app_name}/{port}"))
+        .collect::<Vec<_>>()
+        .join(" && ");
+    format!("bash -c '{probes}'")
+}
+
 fn rewrite_depends_on_for_proxies(service: &mut Mapping, proxy_app_map: &HashMap<String, String>) {
     let depends_key = Value::String("depends_on".to_string());
     let Some(depends) = service.get_mut(&depends_key) else {
@@ -406,7 +727,8 @@ fn rewrite_depends_on_for_proxies(service: &mut Mapping, proxy_app_map: &HashMap
         let Some(app_name) = proxy_app_map.get(service_name) else {
             continue;
         };
-        if is_service_healthy_condition(value) {
+        // The proxy has its own healthcheck and never exits, so only completion waits move.
+        if is_completed_successfully_condition(value) {
             replacements.push((service_name.to_string(), app_name.clone(), value.clone()));
         }
     }
@@ -416,13 +738,128 @@ fn rewrite_depends_on_for_proxies(service: &mut Mapping, proxy_app_map: &HashMap
     }
 }
 
-fn is_service_healthy_condition(value: &Value) -> bool {
+fn is_completed_successfully_condition(value: &Value) -> bool {
     let Value::Mapping(map) = value else {
         return false;
     };
     map.get(Value::String("condition".to_string()))
         .and_then(|value| value.as_str())
-        .is_some_and(|condition| condition == "service_healthy")
+        .is_some_and(|condition| condition == "service_completed_successfully")
+}
+
+fn shared_namespace_owner(network_mode: &str) -> Option<&str> {
+    network_mode
+        .strip_prefix("service:")
+        .or_else(|| network_mode.strip_prefix("container:"))
+}
+
+fn warn_unproxied_ports(name: &str, owner: &str, service: &Mapping) {
+    if !extract_ports(service).is_empty() {
+        eprintln!(
+            "[compose] {name} shares the network namespace of {owner}; its ports are not proxied"
+        );
+    }
+}
+
+fn rewrite_network_mode_for_proxies(
+    service: &mut Mapping,
+    proxy_app_map: &HashMap<String, String>,
+) {
+    let mode_key = Value::String("network_mode".to_string());
+    let Some(Value::String(mode)) = service.get_mut(&mode_key) else {
+        return;
+    };
+    let Some(app_name) = mode
+        .strip_prefix("service:")
+        .and_then(|owner| proxy_app_map.get(owner))
+    else {
+        return;
+    };
+    *mode = format!("service:{app_name}");
+}
+
+// `links: [api:backend]` keeps resolving `backend` to whatever answers as `api`, which is
+// now the proxy. The alias is left alone so traffic is still captured, but the proxy only
+// forwards the proxied ports and intercepted TLS certificates do not name the alias.
+fn aliased_proxy_links(
+    service: &Mapping,
+    proxy_app_map: &HashMap<String, String>,
+) -> Vec<(String, String)> {
+    let Some(Value::Sequence(links)) = service.get(Value::String("links".to_string())) else {
+        return Vec::new();
+    };
+    links
+        .iter()
+        .filter_map(Value::as_str)
+        .filter_map(|link| link.split_once(':'))
+        .filter(|(target, alias)| target != alias && proxy_app_map.contains_key(*target))
+        .map(|(target, alias)| (target.to_string(), alias.to_string()))
+        .collect()
+}
+
+fn warn_aliased_links(name: &str, service: &Mapping, proxy_app_map: &HashMap<String, String>) {
+    for (target, alias) in aliased_proxy_links(service, proxy_app_map) {
+        eprintln!(
+            "[compose] {name} links {target} as {alias}; {alias} reaches the {target} proxy, \
+which only forwards its proxied ports"
+        );
+    }
+}
+
+const PROXY_ONLY_NETWORK_KEYS: [&str; 5] = [
+    "aliases",
+    "ipv4_address",
+    "ipv6_address",
+    "link_local_ips",
+    "mac_address",
+];
+
+fn app_network_attachments(networks: &Value) -> Value {
+    let Value::Mapping(map) = networks else {
+        return networks.clone();
+    };
+    let mut updated = Mapping::new();
+    for (name, config) in map {
+        let config = match config {
+            Value::Mapping(config) => {
+                let mut config = config.clone();
+                for key in PROXY_ONLY_NETWORK_KEYS {
+                    config.remove(Value::String(key.to_string()));
+                }
+                if config.is_empty() {
+                    Value::Null
+                } else {
+                    Value::Mapping(config)
+                }
+            }
+            other => other.clone(),
+        };
+        updated.insert(name.clone(), config);
+    }
+    Value::Mapping(updated)
+}
+
+fn collect_extra_hosts(services: &Mapping) -> Vec<Value> {
+    let mut hosts: Vec<Value> = Vec::new();
+    for service in services.values() {
+        let entries: Vec<Value> = match service.get("extra_hosts") {
+            Some(Value::Sequence(list)) => list.clone(),
+            Some(Value::Mapping(map)) => map
+                .iter()
+                .filter_map(|(host, ip)| {
+                    let host = host.as_str()?;
+                    Some(Value::String(format!("{host}:{}", label_value_string(ip))))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        for entry in entries {
+            if !hosts.contains(&entry) {
+                hosts.push(entry);
+            }
+        }
+    }
+    hosts
 }
 
 fn build_expose_value(ports: &[u16], original: Option<&Value>) -> Option<Value> {
@@ -449,27 +886,470 @@ fn ensure_expose_ports(service: &mut Mapping, ports: &[u16], original_expose: Op
     }
 }
 
-fn read_proxy_protocol(service: &Mapping) -> Option<String> {
-    let labels = service.get(Value::String("labels".to_string()));
-    let key = "sanelens.proxy";
-    match labels {
+fn read_proxy_mode(service: &Mapping, name: &str) -> Option<ProxyMode> {
+    let value = read_label(service, PROXY_LABEL)?;
+    let mode = ProxyMode::parse(&value);
+    if mode.is_none() {
+        eprintln!("[compose] unknown {PROXY_LABEL} value '{value}' on {name}");
+    }
+    mode
+}
+
+fn read_label(service: &Mapping, key: &str) -> Option<String> {
+    match service.get(Value::String("labels".to_string())) {
         Some(Value::Sequence(list)) => {
             list.iter()
                 .filter_map(|entry| entry.as_str())
                 .find_map(|entry| {
                     entry
-                        .strip_prefix(&format!("{key}="))
-                        .map(str::to_lowercase)
+                        .strip_prefix(key)
+                        .and_then(|rest| rest.strip_prefix('='))
+                        .map(str::to_string)
                 })
         }
         Some(Value::Mapping(map)) => map
             .get(Value::String(key.to_string()))
-            .and_then(|value| value.as_str())
-            .map(str::to_lowercase),
+            .map(label_value_string),
         _ => None,
     }
 }
 
+fn read_port_tuning(
+    service: &Mapping,
+    name: &str,
+    port: u16,
+    extension: &ServiceExtension,
+    defaults: &ServiceExtension,
+) -> Result<PortTuning, String> {
+    let mut tuning = PortTuning::default();
+    defaults
+        .apply_tuning(&mut tuning)
+        .map_err(|err| format!("invalid top-level {EXTENSION_KEY}: {err}"))?;
+    apply_tuning_labels(&mut tuning, service, name, "sanelens");
+    extension
+        .apply_tuning(&mut tuning)
+        .map_err(|err| format!("invalid {EXTENSION_KEY} on service {name}: {err}"))?;
+    apply_tuning_labels(&mut tuning, service, name, &format!("sanelens.port.{port}"));
+    if let Some(settings) = extension.ports.get(&port) {
+        settings.apply_tuning(&mut tuning).map_err(|err| {
+            format!("invalid {EXTENSION_KEY} on service {name} port {port}: {err}")
+        })?;
+    }
+    if !extension.captures_bodies(port) {
+        tuning.tap_max_bytes = 0;
+    }
+    tuning.tap_ignore_paths = extension
+        .ignore_paths
+        .clone()
+        .unwrap_or_else(|| read_list_label(service, CAPTURE_IGNORE_PATHS_LABEL));
+    tuning.tap_content_types = extension
+        .content_types
+        .clone()
+        .unwrap_or_else(|| read_list_label(service, CAPTURE_CONTENT_TYPES_LABEL));
+    tuning.tap_skip_content_types = extension
+        .skip_content_types
+        .clone()
+        .unwrap_or_else(|| read_list_label(service, CAPTURE_SKIP_CONTENT_TYPES_LABEL));
+    Ok(tuning)
+}
+
+fn read_list_label(service: &Mapping, key: &str) -> Vec<String> {
+    read_label(service, key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn apply_tuning_labels(tuning: &mut PortTuning, service: &Mapping, name: &str, prefix: &str) {
+    for key in TUNING_KEYS {
+        let label = format!("{prefix}.{key}");
+        let Some(value) = read_label(service, &label) else {
+            continue;
+        };
+        if let Err(err) = tuning.set(key, &value) {
+            eprintln!("[compose] ignoring {label} on {name}: {err}");
+        }
+    }
+}
+
+// Startup faults from labels apply to the whole service; path rules come from x-sanelens,
+// which also wins when both configure the whole service.
+fn read_fault_rules(
+    service: &Mapping,
+    name: &str,
+    extension: &ServiceExtension,
+) -> Result<Vec<FaultRule>, String> {
+    let mut rules = extension
+        .fault_rules()
+        .map_err(|err| format!("invalid {EXTENSION_KEY} on service {name}: {err}"))?;
+    let delay = read_label(service, FAULT_DELAY_LABEL);
+    let abort = read_label(service, FAULT_ABORT_LABEL);
+    let reset = read_label(service, FAULT_RESET_LABEL)
+        .is_some_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"));
+    if (delay.is_none() && abort.is_none() && !reset)
+        || rules.iter().any(|rule| rule.path.is_none())
+    {
+        return Ok(rules);
+    }
+    let percent = read_label(service, FAULT_PERCENT_LABEL);
+    match FaultSpec::parse(
+        delay.as_deref(),
+        abort.as_deref(),
+        reset,
+        percent.as_deref(),
+    ) {
+        Ok(spec) => rules.push(FaultRule { path: None, spec }),
+        Err(err) => eprintln!("[compose] ignoring fault labels on {name}: {err}"),
+    }
+    Ok(rules)
+}
+
+fn is_config_label(key: &str) -> bool {
+    key == PROXY_LABEL
+        || key.starts_with("sanelens.port.")
+        || key.starts_with("sanelens.fault.")
+        || key
+            .strip_prefix("sanelens.")
+            .is_some_and(|rest| TUNING_KEYS.contains(&rest))
+}
+
+fn strip_config_labels(service: &mut Mapping) {
+    match service.get_mut(Value::String("labels".to_string())) {
+        Some(Value::Mapping(map)) => {
+            map.retain(|key, _| !key.as_str().is_some_and(is_config_label));
+        }
+        Some(Value::Sequence(list)) => {
+            list.retain(|entry| {
+                !entry
+                    .as_str()
+                    .map(|entry| entry.split_once('=').map_or(entry, |(key, _)| key))
+                    .is_some_and(is_config_label)
+            });
+        }
+        _ => {}
+    }
+}
+
+fn add_capture_labels(service: &mut Mapping, extension: &ServiceExtension) {
+    let lists = [
+        (CAPTURE_REDACT_HEADERS_LABEL, &extension.redact_headers),
+        (CAPTURE_REDACT_JSON_LABEL, &extension.redact_json),
+        (CAPTURE_IGNORE_PATHS_LABEL, &extension.ignore_paths),
+        (CAPTURE_CONTENT_TYPES_LABEL, &extension.content_types),
+        (
+            CAPTURE_SKIP_CONTENT_TYPES_LABEL,
+            &extension.skip_content_types,
+        ),
+        (CAPTURE_ROUTES_LABEL, &extension.routes),
+    ];
+    for (label, values) in lists {
+        if let Some(values) = values.as_ref().filter(|values| !values.is_empty()) {
+            add_label(service, label, &values.join(","));
+        }
+    }
+    if let Some(patterns) = extension
+        .redact_body
+        .as_ref()
+        .filter(|patterns| !patterns.is_empty())
+    {
+        add_label(
+            service,
+            CAPTURE_REDACT_BODY_LABEL,
+            &body_patterns_label(patterns),
+        );
+    }
+    if let Ok(Some(bytes)) = extension.preview_bytes() {
+        add_label(service, CAPTURE_PREVIEW_BYTES_LABEL, &bytes.to_string());
+    }
+}
+
+struct EgressRouting {
+    no_proxy_value: String,
+    intercept: Option<InterceptCa>,
+    transparent: bool,
+    clients: Vec<String>,
+    ca_inits: Vec<(String, Mapping)>,
+}
+
+impl EgressRouting {
+    fn attach(
+        &mut self,
+        service: &mut Mapping,
+        netns_service: &str,
+        replicas: u64,
+    ) -> Result<(), String> {
+        if !self.transparent {
+            self.trust_intercept_ca(service, netns_service)?;
+            apply_egress_env(service, &self.no_proxy_value, self.intercept.as_ref());
+            return Ok(());
+        }
+        if get_string(service, "network_mode")
+            .as_deref()
+            .and_then(shared_namespace_owner)
+            .is_some()
+        {
+            return Ok(());
+        }
+        // The sidecar can only join one replica's namespace; the others would bypass the proxy.
+        if replicas > 1 {
+            return Err(format!(
+                "transparent egress cannot cover the {replicas} replicas of {netns_service}; \
+scale it to 1 or use SANELENS_EGRESS_PROXY=1"
+            ));
+        }
+        self.clients.push(netns_service.to_string());
+        Ok(())
+    }
+
+    // The bundle is built from the service's own image as it starts. Services that set
+    // `SSL_CERT_FILE` themselves keep their trust store.
+    fn trust_intercept_ca(
+        &mut self,
+        service: &mut Mapping,
+        netns_service: &str,
+    ) -> Result<(), String> {
+        let Some(intercept) = &self.intercept else {
+            return Ok(());
+        };
+        let bundle_dir = intercept.app_bundle_dir(netns_service)?;
+        add_volume(
+            service,
+            format!(
+                "{}:{}:ro",
+                bundle_dir.join(tls::BUNDLE_FILE).to_string_lossy(),
+                tls::container_path(tls::BUNDLE_FILE)
+            ),
+        );
+        if env_var_defined(service, "SSL_CERT_FILE") {
+            return Ok(());
+        }
+        let init_name = format!("{netns_service}-ca-init");
+        let init = build_ca_init_service(service, intercept, &bundle_dir);
+        add_optional_dependency(service, &init_name);
+        self.ca_inits.push((init_name, init));
+        Ok(())
+    }
+}
+
+fn build_ca_init_service(service: &Mapping, intercept: &InterceptCa, bundle_dir: &Path) -> Mapping {
+    let mut map = Mapping::new();
+    for key in ["image", "build", "platform"] {
+        if let Some(value) = service.get(Value::String(key.to_string())) {
+            map.insert(Value::String(key.to_string()), value.clone());
+        }
+    }
+    for (key, value) in [("user", "0:0"), ("network_mode", "none"), ("restart", "no")] {
+        map.insert(
+            Value::String(key.to_string()),
+            Value::String(value.to_string()),
+        );
+    }
+    map.insert(
+        Value::String("entrypoint".to_string()),
+        Value::Sequence(vec![
+            Value::String("sh".to_string()),
+            Value::String("-c".to_string()),
+            Value::String(tls::bundle_script()),
+        ]),
+    );
+    map.insert(
+        Value::String("volumes".to_string()),
+        Value::Sequence(vec![
+            Value::String(format!(
+                "{}:{}:ro",
+                intercept.host_path(tls::CA_FILE),
+                tls::container_path(tls::CA_FILE)
+            )),
+            Value::String(format!(
+                "{}:{}",
+                bundle_dir.to_string_lossy(),
+                tls::BUNDLE_OUT_DIR
+            )),
+        ]),
+    );
+    map
+}
+
+// The app only waits for the init to start and does not need it to succeed, so images
+// without `sh` still start; they keep the CA-only bundle.
+fn add_optional_dependency(service: &mut Mapping, name: &str) {
+    let mut condition = Mapping::new();
+    condition.insert(
+        Value::String("condition".to_string()),
+        Value::String("service_started".to_string()),
+    );
+    condition.insert(Value::String("required".to_string()), Value::Bool(false));
+    let depends_key = Value::String("depends_on".to_string());
+    let mut depends = match service.remove(&depends_key) {
+        Some(Value::Mapping(map)) => map,
+        Some(Value::Sequence(list)) => list
+            .into_iter()
+            .map(|entry| (entry, Value::Mapping(Mapping::new())))
+            .collect(),
+        _ => Mapping::new(),
+    };
+    depends.insert(Value::String(name.to_string()), Value::Mapping(condition));
+    service.insert(depends_key, Value::Mapping(depends));
+}
+
+// The egress proxy becomes the default gateway of each client; forwarded TCP is
+// redirected to its original-destination listener and the rest is masqueraded.
+const EGRESS_REDIRECT_RULES: [&str; 2] = [
+    "PREROUTING -p tcp -m addrtype ! --dst-type LOCAL -j REDIRECT --to-ports {port}",
+    "POSTROUTING -m addrtype ! --src-type LOCAL -j MASQUERADE",
+];
+const CLIENT_ROUTE_SCRIPT: &str = "gw=$(getent hosts {egress} | awk '{print $1; exit}') && [ -n \"$gw\" ] && ip route replace default via \"$gw\"";
+
+fn egress_redirect_script() -> String {
+    EGRESS_REDIRECT_RULES
+        .iter()
+        .map(|rule| {
+            let rule = rule.replace("{port}", &envoy::TRANSPARENT_PORT.to_string());
+            format!("{{ iptables -t nat -C {rule} 2>/dev/null || iptables -t nat -A {rule}; }}")
+        })
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
+
+fn build_transparent_net_services(image: &str, clients: &[String]) -> Vec<(String, Mapping)> {
+    let egress_name = "sanelens-egress-proxy";
+    let client_script = CLIENT_ROUTE_SCRIPT.replace("{egress}", egress_name);
+    let mut services = vec![(
+        "sanelens-egress-net".to_string(),
+        build_net_sidecar_service(image, egress_name, &[], &egress_redirect_script()),
+    )];
+    for client in clients {
+        services.push((
+            format!("{client}-egress-net"),
+            build_net_sidecar_service(image, client, &[egress_name], &client_script),
+        ));
+    }
+    services
+}
+
+// The sidecar shares the namespace of `netns_service` and is restarted with it, so the rules
+// follow the container instead of being applied once.
+fn build_net_sidecar_service(
+    image: &str,
+    netns_service: &str,
+    after: &[&str],
+    script: &str,
+) -> Mapping {
+    let mut map = Mapping::new();
+    map.insert(
+        Value::String("image".to_string()),
+        Value::String(image.to_string()),
+    );
+    map.insert(
+        Value::String("network_mode".to_string()),
+        Value::String(format!("service:{netns_service}")),
+    );
+    map.insert(
+        Value::String("cap_add".to_string()),
+        Value::Sequence(vec![Value::String("NET_ADMIN".to_string())]),
+    );
+    let mut follow = Mapping::new();
+    follow.insert(
+        Value::String("condition".to_string()),
+        Value::String("service_started".to_string()),
+    );
+    follow.insert(Value::String("restart".to_string()), Value::Bool(true));
+    let mut depends = Mapping::new();
+    depends.insert(
+        Value::String(netns_service.to_string()),
+        Value::Mapping(follow),
+    );
+    for name in after {
+        depends.insert(
+            Value::String((*name).to_string()),
+            Value::Mapping(Mapping::new()),
+        );
+    }
+    map.insert(
+        Value::String("depends_on".to_string()),
+        Value::Mapping(depends),
+    );
+    map.insert(
+        Value::String("restart".to_string()),
+        Value::String("unless-stopped".to_string()),
+    );
+    map.insert(
+        Value::String("entrypoint".to_string()),
+        Value::Sequence(vec![
+            Value::String("sh".to_string()),
+            Value::String("-c".to_string()),
+            // Rules are re-applied so a restarted egress proxy (with a new address) is picked up.
+            Value::String(format!(
+                "trap 'exit 0' TERM; while :; do {script}; sleep 5 & wait $!; done"
+            )),
+        ]),
+    );
+    add_label(&mut map, "sanelens.egress.net", "true");
+    map
+}
+
+fn apply_egress_env(service: &mut Mapping, no_proxy_value: &str, intercept: Option<&InterceptCa>) {
+    ensure_env_var(service, "HTTP_PROXY", "http://sanelens-egress-proxy:15001");
+    ensure_env_var(service, "HTTPS_PROXY", "http://sanelens-egress-proxy:15001");
+    merge_env_var(service, "NO_PROXY", no_proxy_value);
+    let Some(intercept) = intercept else {
+        return;
+    };
+    add_volume(
+        service,
+        format!(
+            "{}:{}:ro",
+            intercept.host_path(tls::CA_FILE),
+            tls::container_path(tls::CA_FILE)
+        ),
+    );
+    let bundle = tls::container_path(tls::BUNDLE_FILE);
+    ensure_env_var(service, "SSL_CERT_FILE", &bundle);
+    ensure_env_var(service, "REQUESTS_CA_BUNDLE", &bundle);
+    ensure_env_var(
+        service,
+        "NODE_EXTRA_CA_CERTS",
+        &tls::container_path(tls::CA_FILE),
+    );
+}
+
+fn prepare_tls_intercept(
+    out_dir: &Path,
+    config: &DeriveConfig,
+    defaults: &ServiceExtension,
+) -> Result<Option<InterceptCa>, String> {
+    let mut hosts = config.egress_tls_hosts.clone();
+    for host in defaults.intercept_tls.iter().flatten() {
+        if !hosts.contains(host) {
+            hosts.push(host.clone());
+        }
+    }
+    if hosts.is_empty() {
+        return Ok(None);
+    }
+    if config.transparent_egress {
+        eprintln!("[compose] TLS interception is not available with transparent egress; ignoring");
+        return Ok(None);
+    }
+    tls::generate_intercept_ca(&out_dir.join("tls"), &hosts).map(Some)
+}
+
+fn add_volume(service: &mut Mapping, entry: String) {
+    let volumes_key = Value::String("volumes".to_string());
+    match service.get_mut(&volumes_key) {
+        Some(Value::Sequence(list)) => list.push(Value::String(entry)),
+        _ => {
+            service.insert(volumes_key, Value::Sequence(vec![Value::String(entry)]));
+        }
+    }
+}
+
 fn add_label(service: &mut Mapping, key: &str, value: &str) {
     let labels_key = Value::String("labels".to_string());
     if key == STARTED_AT_LABEL {
@@ -539,6 +1419,53 @@ fn add_run_labels(service: &mut Mapping, service_name: &str, labels: &RunLabelCo
     add_label(service, PROJECT_NAME_LABEL, labels.project_name);
 }
 
+// Apps export to the receiver in this process through the host gateway. Services sharing
+// another's network namespace inherit its hosts file and cannot declare extra_hosts.
+fn apply_otlp_env(service: &mut Mapping, name: &str, config: &DeriveConfig) {
+    let Some(port) = config.otlp_port else {
+        return;
+    };
+    let host = host_gateway(config);
+    ensure_env_var(
+        service,
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        &format!("http://{host}:{port}"),
+    );
+    ensure_env_var(service, "OTEL_EXPORTER_OTLP_PROTOCOL", "http/protobuf");
+    ensure_env_var(service, "OTEL_SERVICE_NAME", name);
+    let shares_namespace = get_string(service, "network_mode")
+        .as_deref()
+        .and_then(shared_namespace_owner)
+        .is_some();
+    if !config.disable_pods && !shares_namespace {
+        add_extra_host(service, &format!("{DOCKER_HOST_GATEWAY}:host-gateway"));
+    }
+}
+
+fn add_extra_host(service: &mut Mapping, entry: &str) {
+    let key = Value::String("extra_hosts".to_string());
+    let host = entry.split(':').next().unwrap_or(entry);
+    match service.get_mut(&key) {
+        Some(Value::Sequence(list)) => {
+            if !list
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|item| item.split(':').next() == Some(host))
+            {
+                list.push(Value::String(entry.to_string()));
+            }
+        }
+        Some(Value::Mapping(map)) => {
+            let target = entry.split_once(':').map_or("", |(_, target)| target);
+            map.entry(Value::String(host.to_string()))
+                .or_insert(Value::String(target.to_string()));
+        }
+        _ => {
+            service.insert(key, Value::Sequence(vec![Value::String(entry.to_string())]));
+        }
+    }
+}
+
 fn ensure_env_var(service: &mut Mapping, key: &str, value: &str) {
     let env_key = Value::String("environment".to_string());
     match service.get_mut(&env_key) {
@@ -567,6 +1494,36 @@ fn ensure_env_var(service: &mut Mapping, key: &str, value: &str) {
     }
 }
 
+fn env_var_defined(service: &Mapping, key: &str) -> bool {
+    match service.get(Value::String("environment".to_string())) {
+        Some(Value::Mapping(map)) => map.contains_key(Value::String(key.to_string())),
+        Some(Value::Sequence(list)) => list.iter().any(|entry| {
+            entry
+                .as_str()
+                .is_some_and(|item| item == key || item.starts_with(&format!("{key}=")))
+        }),
+        _ => false,
+    }
+}
+
+fn set_env_var(service: &mut Mapping, key: &str, value: &str) {
+    match service.get_mut(Value::String("environment".to_string())) {
+        Some(Value::Mapping(map)) => {
+            map.remove(Value::String(key.to_string()));
+        }
+        Some(Value::Sequence(list)) => {
+            let prefix = format!("{key}=");
+            list.retain(|entry| {
+                entry
+                    .as_str()
+                    .is_none_or(|item| item != key && !item.starts_with(&prefix))
+            });
+        }
+        _ => {}
+    }
+    ensure_env_var(service, key, value);
+}
+
 fn merge_env_var(service: &mut Mapping, key: &str, value: &str) {
     let env_key = Value::String("environment".to_string());
     match service.get_mut(&env_key) {
@@ -722,6 +1679,33 @@ fn parse_port_token(token: &str) -> Option<u16> {
     parse_port_token(default)
 }
 
+// A `--scale` flag wins over `scale`, which wins over `deploy.replicas`. Counts may come
+// through as strings when they are interpolated from the environment.
+fn service_replicas(service: &Mapping, scale_override: Option<u64>) -> u64 {
+    let scale = service
+        .get(Value::String("scale".to_string()))
+        .and_then(replica_count);
+    let replicas = service
+        .get(Value::String("deploy".to_string()))
+        .and_then(|deploy| deploy.get("replicas"))
+        .and_then(replica_count);
+    scale_override.or(scale).or(replicas).unwrap_or(1)
+}
+
+fn replica_count(value: &Value) -> Option<u64> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str().and_then(|raw| raw.trim().parse().ok()))
+}
+
+// Flags name the original service until the run retargets them at its app container.
+fn scale_override(overrides: &HashMap<String, u64>, name: &str) -> Option<u64> {
+    overrides
+        .get(name)
+        .or_else(|| overrides.get(&format!("{name}-app")))
+        .copied()
+}
+
 fn guess_protocol(port: u16) -> ProxyProtocol {
     const HTTP_PORTS: [u16; 12] = [
         80, 443, 3000, 3001, 3002, 5173, 8000, 8080, 8100, 9000, 10000, 15672,
@@ -736,7 +1720,66 @@ fn guess_protocol(port: u16) -> ProxyProtocol {
 #[cfg(test)]
 #[allow(clippy::literal_string_with_formatting_args)]
 mod tests {
-    use super::parse_container_port;
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::{env, fs};
+
+    use serde_yaml::{Mapping, Value};
+
+    use super::{
+        add_optional_dependency, aliased_proxy_links, app_network_attachments,
+        build_ca_init_service, build_canary_service, build_proxy_healthcheck,
+        build_transparent_net_services, collect_extra_hosts, derive_compose, enabled_healthcheck,
+        guess_protocol, parse_container_port, rewrite_depends_on_for_proxies,
+        rewrite_network_mode_for_proxies, scale_override, service_replicas, strip_config_labels,
+        DeriveConfig, EgressRouting,
+    };
+    use crate::infra::envoy::{ProxyProtocol, TRANSPARENT_PORT};
+    use crate::infra::extension::CanaryExtension;
+    use crate::infra::tls::InterceptCa;
+
+    fn yaml_mapping(raw: &str) -> Mapping {
+        serde_yaml::from_str(raw).unwrap_or_default()
+    }
+
+    // `compose config` is replaced by printing the compose file (`$4` after `-p <name> -f`).
+    fn derive_config() -> DeriveConfig {
+        DeriveConfig {
+            run_id: "run_test".to_string(),
+            run_started_at: "2026-01-01T00:00:00Z".to_string(),
+            envoy_image: "envoy".to_string(),
+            enable_traffic: true,
+            enable_egress: false,
+            egress_tls_hosts: Vec::new(),
+            transparent_egress: false,
+            net_init_image: "netshoot".to_string(),
+            vcr_mode: None,
+            vcr_replay_port: None,
+            otlp_port: None,
+            compose_cmd: ["sh", "-c", "cat \"$4\"", "sh"]
+                .map(str::to_string)
+                .to_vec(),
+            compose_args: Vec::new(),
+            compose_file_from_args: false,
+            disable_pods: false,
+        }
+    }
+
+    fn derive(name: &str, compose: &str) -> Result<Mapping, String> {
+        let dir = env::temp_dir().join(format!("sanelens-derive-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+        let compose_path = dir.join("compose.yaml");
+        fs::write(&compose_path, compose).map_err(|err| err.to_string())?;
+        let derived = derive_compose(&compose_path.to_string_lossy(), name, &derive_config());
+        let raw = derived
+            .and_then(|derived| fs::read_to_string(derived.path).map_err(|err| err.to_string()));
+        let _ = fs::remove_dir_all(&dir);
+        let doc: Mapping = serde_yaml::from_str(&raw?).map_err(|err| err.to_string())?;
+        match doc.get("services") {
+            Some(Value::Mapping(services)) => Ok(services.clone()),
+            _ => Err("derived compose has no services".to_string()),
+        }
+    }
 
     #[test]
     fn parse_container_port_plain() {
@@ -768,6 +1811,409 @@ mod tests {
             Some(80)
         );
     }
+
+    #[test]
+    fn depends_on_keeps_healthy_and_moves_completion_to_app() {
+        let mut service = yaml_mapping(
+            "depends_on:\n  api:\n    condition: service_healthy\n  migrate:\n    condition: service_completed_successfully\n",
+        );
+        let proxy_app_map = HashMap::from([
+            ("api".to_string(), "api-app".to_string()),
+            ("migrate".to_string(), "migrate-app".to_string()),
+        ]);
+        rewrite_depends_on_for_proxies(&mut service, &proxy_app_map);
+        let depends = service
+            .get(Value::String("depends_on".to_string()))
+            .and_then(Value::as_mapping)
+            .cloned()
+            .unwrap_or_default();
+        assert!(depends.contains_key(Value::String("api".to_string())));
+        assert!(depends.contains_key(Value::String("migrate-app".to_string())));
+        assert!(!depends.contains_key(Value::String("migrate".to_string())));
+    }
+
+    #[test]
+    fn proxy_healthcheck_copies_timing_and_probes_envoy() {
+        let service = yaml_mapping(
+            "healthcheck:\n  test: [\"CMD\", \"test\", \"-f\", \"/tmp/ready\"]\n  interval: 5s\n  retries: 3\n",
+        );
+        let app_healthcheck = enabled_healthcheck(&service);
+        assert!(app_healthcheck.is_some());
+        let healthcheck = build_proxy_healthcheck(app_healthcheck, "api-app", &[8080]);
+        assert_eq!(
+            healthcheck.get("interval").and_then(Value::as_str),
+            Some("5s")
+        );
+        assert_eq!(healthcheck.get("retries").and_then(Value::as_u64), Some(3));
+        let test = healthcheck.get("test");
+        assert_eq!(
+            test.and_then(|test| test.get(0)).and_then(Value::as_str),
+            Some("CMD-SHELL")
+        );
+        let script = test
+            .and_then(|test| test.get(1))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        assert!(script.contains("http://127.0.0.1:9901/ready"));
+        assert!(script.contains("$$status"));
+        assert!(!script.contains("/tmp/ready"));
+        assert!(!script.contains("/dev/tcp/api-app"));
+    }
+
+    #[test]
+    fn proxy_without_app_healthcheck_probes_the_app_ports() {
+        let services = derive(
+            "no-healthcheck",
+            "services:\n  api:\n    image: api\n    expose: [\"8080\", \"9000\"]\n",
+        );
+        assert!(services.is_ok(), "{services:?}");
+        let services = services.ok();
+        let proxy = services.as_ref().and_then(|services| services.get("api"));
+        assert_eq!(
+            proxy
+                .and_then(|proxy| proxy.get("depends_on"))
+                .and_then(|depends| depends.get("api-app"))
+                .and_then(|app| app.get("condition")),
+            None
+        );
+        let healthcheck = proxy.and_then(|proxy| proxy.get("healthcheck"));
+        let script = healthcheck
+            .and_then(|check| check.get("test"))
+            .and_then(|test| test.get(1))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        assert!(script.contains("http://127.0.0.1:9901/ready"));
+        assert!(script
+            .ends_with("bash -c 'exec 3<>/dev/tcp/api-app/8080 && exec 3<>/dev/tcp/api-app/9000'"));
+        assert_eq!(
+            healthcheck
+                .and_then(|check| check.get("interval"))
+                .and_then(Value::as_str),
+            Some("2s")
+        );
+    }
+
+    #[test]
+    fn proxy_waits_for_healthy_app_which_keeps_its_check() {
+        let services = derive(
+            "healthcheck",
+            "services:\n  api:\n    image: api\n    expose: [\"8080\"]\n    healthcheck:\n      test: [\"CMD-SHELL\", \"pg_isready\"]\n",
+        );
+        assert!(services.is_ok(), "{services:?}");
+        let services = services.ok();
+        let services = services.as_ref();
+        let app = services.and_then(|services| services.get("api-app"));
+        assert_eq!(
+            app.and_then(|app| app.get("healthcheck"))
+                .and_then(|check| check.get("test"))
+                .and_then(|test| test.get(1))
+                .and_then(Value::as_str),
+            Some("pg_isready")
+        );
+        assert_eq!(
+            services
+                .and_then(|services| services.get("api"))
+                .and_then(|proxy| proxy.get("depends_on"))
+                .and_then(|depends| depends.get("api-app"))
+                .and_then(|app| app.get("condition"))
+                .and_then(Value::as_str),
+            Some("service_healthy")
+        );
+    }
+
+    #[test]
+    fn disabled_healthcheck_is_ignored() {
+        let service = yaml_mapping("healthcheck:\n  disable: true\n");
+        assert!(enabled_healthcheck(&service).is_none());
+        let service = yaml_mapping("healthcheck:\n  test: [\"NONE\"]\n");
+        assert!(enabled_healthcheck(&service).is_none());
+    }
+
+    #[test]
+    fn network_mode_service_follows_app_container() {
+        let mut service = yaml_mapping("network_mode: service:api\n");
+        let proxy_app_map = HashMap::from([("api".to_string(), "api-app".to_string())]);
+        rewrite_network_mode_for_proxies(&mut service, &proxy_app_map);
+        assert_eq!(
+            service
+                .get(Value::String("network_mode".to_string()))
+                .and_then(Value::as_str),
+            Some("service:api-app")
+        );
+    }
+
+    #[test]
+    fn aliased_links_to_proxied_services_are_reported() {
+        let service =
+            yaml_mapping("links:\n  - api:backend\n  - api\n  - db:database\n  - api:api\n");
+        let proxy_app_map = HashMap::from([("api".to_string(), "api-app".to_string())]);
+        assert_eq!(
+            aliased_proxy_links(&service, &proxy_app_map),
+            vec![("api".to_string(), "backend".to_string())]
+        );
+    }
+
+    #[test]
+    fn app_networks_drop_aliases_and_static_addresses() {
+        let networks = Value::Mapping(yaml_mapping(
+            "front:\n  aliases: [api.local]\n  ipv4_address: 10.0.0.5\n  priority: 10\nback:\n  aliases: [api.back]\n",
+        ));
+        let expected = Value::Mapping(yaml_mapping("front:\n  priority: 10\nback:\n"));
+        assert_eq!(app_network_attachments(&networks), expected);
+    }
+
+    #[test]
+    fn extra_hosts_merge_list_and_mapping_forms() {
+        let services = yaml_mapping(
+            "api:\n  extra_hosts: [\"db.internal:10.0.0.9\"]\nweb:\n  extra_hosts:\n    db.internal: 10.0.0.9\n    cache.internal: 10.0.0.10\n",
+        );
+        let hosts = collect_extra_hosts(&services);
+        assert_eq!(
+            hosts,
+            vec![
+                Value::String("db.internal:10.0.0.9".to_string()),
+                Value::String("cache.internal:10.0.0.10".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn config_labels_do_not_leak_to_app() {
+        let mut listed = yaml_mapping(
+            "labels:\n  - sanelens.proxy=true\n  - sanelens.port.8080.timeout=5s\n  - sanelens.retries=2\n  - team=core\n",
+        );
+        strip_config_labels(&mut listed);
+        let expected = yaml_mapping("labels:\n  - team=core\n");
+        assert_eq!(listed, expected);
+
+        let mut mapped = yaml_mapping("labels:\n  sanelens.proxy: http\n  team: core\n");
+        strip_config_labels(&mut mapped);
+        assert_eq!(mapped, yaml_mapping("labels:\n  team: core\n"));
+    }
+
+    #[test]
+    fn database_ports_are_not_decoded_unless_asked() {
+        assert_eq!(guess_protocol(8080), ProxyProtocol::Http);
+        for port in [5432, 3306, 6379] {
+            assert_eq!(guess_protocol(port), ProxyProtocol::Tcp);
+        }
+    }
+
+    #[test]
+    fn replicas_come_from_scale_then_deploy() {
+        assert_eq!(service_replicas(&yaml_mapping("image: api"), None), 1);
+        assert_eq!(
+            service_replicas(&yaml_mapping("deploy:\n  replicas: 3"), None),
+            3
+        );
+        assert_eq!(
+            service_replicas(&yaml_mapping("scale: 2\ndeploy:\n  replicas: 3"), None),
+            2
+        );
+        assert_eq!(
+            service_replicas(&yaml_mapping("deploy:\n  replicas: \"3\""), None),
+            3
+        );
+        assert_eq!(
+            service_replicas(&yaml_mapping("deploy:\n  replicas: \"x\""), None),
+            1
+        );
+    }
+
+    #[test]
+    fn scale_flag_overrides_the_compose_file() {
+        let overrides = HashMap::from([("api".to_string(), 4), ("worker-app".to_string(), 2)]);
+        let service = yaml_mapping("deploy:\n  replicas: 1");
+        assert_eq!(
+            service_replicas(&service, scale_override(&overrides, "api")),
+            4
+        );
+        assert_eq!(
+            service_replicas(&service, scale_override(&overrides, "worker")),
+            2
+        );
+        assert_eq!(
+            service_replicas(&service, scale_override(&overrides, "db")),
+            1
+        );
+    }
+
+    #[test]
+    fn transparent_egress_refuses_scaled_services() {
+        let mut egress = EgressRouting {
+            no_proxy_value: String::new(),
+            intercept: None,
+            transparent: true,
+            clients: Vec::new(),
+            ca_inits: Vec::new(),
+        };
+        let mut service = yaml_mapping("image: api");
+        assert!(egress.attach(&mut service, "api-app", 1).is_ok());
+        let err = egress.attach(&mut service, "worker-app", 3).err();
+        assert!(err.is_some_and(|err| err.contains("3 replicas of worker-app")));
+        assert_eq!(egress.clients, ["api-app"]);
+    }
+
+    #[test]
+    fn transparent_sidecars_follow_their_namespace() {
+        let services = build_transparent_net_services("netshoot", &["api-app".to_string()]);
+        let names: Vec<&str> = services.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["sanelens-egress-net", "api-app-egress-net"]);
+        let field = |index: usize, key: &str| {
+            services
+                .get(index)
+                .and_then(|(_, service)| service.get(Value::String(key.to_string())))
+                .cloned()
+                .unwrap_or(Value::Null)
+        };
+        let script = |index: usize| {
+            field(index, "entrypoint")
+                .get(2)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        assert_eq!(
+            field(0, "network_mode"),
+            Value::String("service:sanelens-egress-proxy".to_string())
+        );
+        let redirect = format!(
+            "iptables -t nat -C PREROUTING -p tcp -m addrtype ! --dst-type LOCAL -j REDIRECT \
+--to-ports {TRANSPARENT_PORT} 2>/dev/null || iptables -t nat -A PREROUTING"
+        );
+        assert!(script(0).contains(&redirect));
+        assert!(script(0).contains("iptables -t nat -A POSTROUTING"));
+
+        assert_eq!(
+            field(1, "network_mode"),
+            Value::String("service:api-app".to_string())
+        );
+        assert_eq!(
+            field(1, "depends_on"),
+            Value::Mapping(yaml_mapping(
+                "api-app:\n  condition: service_started\n  restart: true\nsanelens-egress-proxy: {}\n"
+            ))
+        );
+        assert_eq!(
+            field(1, "restart"),
+            Value::String("unless-stopped".to_string())
+        );
+        assert!(script(1).starts_with(
+            "trap 'exit 0' TERM; while :; do gw=$(getent hosts sanelens-egress-proxy"
+        ));
+        assert!(script(1).contains("ip route replace default via \"$gw\"; sleep 5"));
+        let labels = serde_yaml::to_string(&field(1, "labels")).unwrap_or_default();
+        assert!(labels.contains("sanelens.egress.net"));
+        assert!(!labels.contains("sanelens.proxy"));
+    }
+
+    #[test]
+    fn canary_copies_the_app_with_its_overrides() {
+        let service = yaml_mapping(
+            "image: api:1\nenvironment:\n  MODE: stable\n  LOG: info\nexpose: [\"9000\"]\n\
+networks:\n  front:\n    aliases: [public]\n",
+        );
+        let canary = serde_yaml::from_str::<CanaryExtension>(
+            "build: ./api-next\nenvironment: { MODE: canary }\nweight: 20\n",
+        );
+        assert!(canary.is_ok());
+        let Ok(canary) = canary else {
+            return;
+        };
+        let built = build_canary_service(
+            &service,
+            &canary,
+            Path::new("/src"),
+            &[8080],
+            service.get("expose"),
+        );
+        assert_eq!(built.get("image"), None);
+        assert_eq!(
+            built.get("build").and_then(Value::as_str),
+            Some("/src/./api-next")
+        );
+        assert_eq!(
+            built.get("environment"),
+            Some(&Value::Mapping(yaml_mapping("MODE: canary\nLOG: info\n")))
+        );
+        let expose = serde_yaml::to_string(&built.get("expose")).unwrap_or_default();
+        assert!(expose.contains("9000") && expose.contains("8080"));
+        assert_eq!(
+            built.get("networks"),
+            Some(&Value::Mapping(yaml_mapping("front: null\n")))
+        );
+
+        let with_build = yaml_mapping("build: ./api\n");
+        let Ok(canary) = serde_yaml::from_str::<CanaryExtension>("image: api:2\nmirror: 10\n")
+        else {
+            return;
+        };
+        let built = build_canary_service(&with_build, &canary, Path::new("/src"), &[8080], None);
+        assert_eq!(built.get("image").and_then(Value::as_str), Some("api:2"));
+        assert_eq!(built.get("build"), None);
+    }
+
+    #[test]
+    fn proxy_waits_for_the_canary_and_names_must_be_free() {
+        let compose = concat!(
+            "services:\n",
+            "  api:\n",
+            "    image: api:1\n",
+            "    ports: [\"8080:8080\"]\n",
+            "    x-sanelens:\n",
+            "      canary: { image: api:2, weight: 20 }\n",
+        );
+        let services = derive("canary", compose);
+        assert!(services.is_ok(), "{services:?}");
+        let services = services.unwrap_or_default();
+        let depends = services
+            .get("api")
+            .and_then(|proxy| proxy.get("depends_on"))
+            .and_then(Value::as_mapping)
+            .map(|depends| depends.keys().filter_map(Value::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+        assert_eq!(depends, ["api-app", "api-canary"]);
+        assert_eq!(
+            services
+                .get("api-canary")
+                .and_then(|canary| canary.get("image"))
+                .and_then(Value::as_str),
+            Some("api:2")
+        );
+
+        let taken = format!("{compose}  api-canary:\n    image: other\n");
+        let err = derive("canary-taken", &taken).err().unwrap_or_default();
+        assert!(err.contains("api-canary already exists"), "{err}");
+    }
+
+    #[test]
+    fn ca_init_runs_the_app_image_without_blocking_the_app() {
+        let mut service =
+            yaml_mapping("build: ./api\nimage: api:dev\nuser: app\ndepends_on: [db]\n");
+        let intercept = InterceptCa {
+            dir: "/run/tls".into(),
+            ca_pem: String::new(),
+            leaves: Vec::new(),
+        };
+        let init = build_ca_init_service(&service, &intercept, "/run/tls/apps/api-app".as_ref());
+        let field = |key: &str| init.get(Value::String(key.to_string())).cloned();
+        assert_eq!(field("build"), Some(Value::String("./api".to_string())));
+        assert_eq!(field("image"), Some(Value::String("api:dev".to_string())));
+        assert_eq!(field("user"), Some(Value::String("0:0".to_string())));
+        let volumes = serde_yaml::to_string(&field("volumes")).unwrap_or_default();
+        assert!(volumes.contains("/run/tls/ca.pem:/sanelens/tls/ca.pem:ro"));
+        assert!(volumes.contains("/run/tls/apps/api-app:/sanelens/bundle"));
+
+        add_optional_dependency(&mut service, "api-app-ca-init");
+        let expected = yaml_mapping(
+            "db: {}\napi-app-ca-init:\n  condition: service_started\n  required: false\n",
+        );
+        assert_eq!(
+            service.get(Value::String("depends_on".to_string())),
+            Some(&Value::Mapping(expected))
+        );
+    }
 }
 
 fn build_egress_service(
@@ -1147,208 +2593,61 @@ fn is_uri_like(value: &str) -> bool {
 fn write_envoy_config(
     envoy_dir: &Path,
     service_name: &str,
-    app_name: &str,
-    ports: &[(u16, ProxyProtocol)],
+    bootstrap: &envoy::Bootstrap,
 ) -> Result<(), String> {
-    let mut body = String::new();
-    body.push_str("static_resources:\n  listeners:\n");
-    for (port, mode) in ports {
-        match mode {
-            ProxyProtocol::Http => {
-                body.push_str(&http_listener_block(service_name, app_name, *port));
-            }
-            ProxyProtocol::Tcp => {
-                body.push_str(&tcp_listener_block(service_name, app_name, *port));
-            }
-        }
-    }
-    body.push_str("  clusters:\n");
-    for (port, _) in ports {
-        body.push_str(&cluster_block(app_name, *port));
-    }
-    body.push_str("admin:\n  access_log_path: /tmp/envoy_admin.log\n  address:\n    socket_address:\n      address: 0.0.0.0\n      port_value: 9901\n");
-
+    let body = bootstrap.to_yaml()?;
     let path = envoy_dir.join(format!("{service_name}.yaml"));
     fs::write(path, body).map_err(|err| err.to_string())
 }
 
-const EGRESS_ENVOY_CONFIG: &str = r#"static_resources:
-  listeners:
-  - name: egress_listener
-    address:
-      socket_address:
-        address: 0.0.0.0
-        port_value: 15001
-    filter_chains:
-    - filters:
-      - name: envoy.filters.network.http_connection_manager
-        typed_config:
-          "@type": type.googleapis.com/envoy.extensions.filters.network.http_connection_manager.v3.HttpConnectionManager
-          stat_prefix: egress_http
-          route_config:
-            name: egress_route
-            virtual_hosts:
-            - name: default
-              domains: ["*"]
-              routes:
-              - match:
-                  prefix: "/"
-                route:
-                  cluster: egress_cluster
-                  timeout: 0s
-          http_filters:
-          - name: envoy.filters.http.dynamic_forward_proxy
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.filters.http.dynamic_forward_proxy.v3.FilterConfig
-              dns_cache_config:
-                name: egress_cache
-                dns_lookup_family: V4_ONLY
-          - name: envoy.filters.http.tap
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.filters.http.tap.v3.Tap
-              common_config:
-                static_config:
-                  match_config:
-                    any_match: true
-                  output_config:
-                    max_buffered_rx_bytes: 10485760
-                    max_buffered_tx_bytes: 10485760
-                    sinks:
-                    - format: JSON_BODY_AS_STRING
-                      file_per_tap:
-                        path_prefix: /sanelens/tap/trace
-          - name: envoy.filters.http.router
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.filters.http.router.v3.Router
-          access_log:
-          - name: envoy.access_loggers.stdout
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.access_loggers.stream.v3.StdoutAccessLog
-              log_format:
-                json_format:
-                  timestamp: "%START_TIME%"
-                  method: "%REQ(:METHOD)%"
-                  path: "%REQ(X-ENVOY-ORIGINAL-PATH?:PATH)%"
-                  authority: "%REQ(:AUTHORITY)%"
-                  request_id: "%REQ(X-REQUEST-ID)%"
-                  request_user_agent: "%REQ(USER-AGENT)%"
-                  request_content_type: "%REQ(CONTENT-TYPE)%"
-                  request_accept: "%REQ(ACCEPT)%"
-                  request_body: "%DYNAMIC_METADATA(sanelens:request_body)%"
-                  request_forwarded_for: "%REQ(X-FORWARDED-FOR)%"
-                  request_forwarded_proto: "%REQ(X-FORWARDED-PROTO)%"
-                  response_content_type: "%RESP(CONTENT-TYPE)%"
-                  response_content_length: "%RESP(CONTENT-LENGTH)%"
-                  response_body: "%DYNAMIC_METADATA(sanelens:response_body)%"
-                  response_code: "%RESPONSE_CODE%"
-                  duration_ms: "%DURATION%"
-                  downstream_remote_address: "%DOWNSTREAM_REMOTE_ADDRESS%"
-                  upstream_host: "%UPSTREAM_HOST%"
-                  bytes_received: "%BYTES_RECEIVED%"
-                  bytes_sent: "%BYTES_SENT%"
-  clusters:
-  - name: egress_cluster
-    connect_timeout: 5s
-    lb_policy: CLUSTER_PROVIDED
-    cluster_type:
-      name: envoy.clusters.dynamic_forward_proxy
-      typed_config:
-        "@type": type.googleapis.com/envoy.extensions.clusters.dynamic_forward_proxy.v3.ClusterConfig
-        dns_cache_config:
-          name: egress_cache
-          dns_lookup_family: V4_ONLY
-admin:
-  access_log_path: /tmp/envoy_admin.log
-  address:
-    socket_address:
-      address: 0.0.0.0
-      port_value: 9901
-"#;
-fn write_egress_envoy_config(path: &Path) -> Result<(), String> {
-    fs::write(path, EGRESS_ENVOY_CONFIG).map_err(|err| err.to_string())
-}
-
-#[allow(clippy::too_many_lines)]
-fn http_listener_block(service_name: &str, app_name: &str, port: u16) -> String {
-    format!(
-        r#"  - name: {service_name}_listener_{port}
-    address:
-      socket_address:
-        address: 0.0.0.0
-        port_value: {port}
-    filter_chains:
-    - filters:
-      - name: envoy.filters.network.http_connection_manager
-        typed_config:
-          "@type": type.googleapis.com/envoy.extensions.filters.network.http_connection_manager.v3.HttpConnectionManager
-          stat_prefix: ingress_http_{port}
-          codec_type: AUTO
-          route_config:
-            name: route_{port}
-            virtual_hosts:
-            - name: backend
-              domains: ["*"]
-              routes:
-              - match:
-                  prefix: "/"
-                route:
-                  cluster: {app_name}_{port}
-          http_filters:
-          - name: envoy.filters.http.tap
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.filters.http.tap.v3.Tap
-              common_config:
-                static_config:
-                  match_config:
-                    any_match: true
-                  output_config:
-                    max_buffered_rx_bytes: 10485760
-                    max_buffered_tx_bytes: 10485760
-                    sinks:
-                    - format: JSON_BODY_AS_STRING
-                      file_per_tap:
-                        path_prefix: /sanelens/tap/trace
-          - name: envoy.filters.http.router
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.filters.http.router.v3.Router
-          access_log:
-          - name: envoy.access_loggers.stdout
-            typed_config:
-              "@type": type.googleapis.com/envoy.extensions.access_loggers.stream.v3.StdoutAccessLog
-              log_format:
-                json_format:
-                  timestamp: "%START_TIME%"
-                  method: "%REQ(:METHOD)%"
-                  path: "%REQ(X-ENVOY-ORIGINAL-PATH?:PATH)%"
-                  protocol: "%PROTOCOL%"
-                  response_code: "%RESPONSE_CODE%"
-                  duration_ms: "%DURATION%"
-                  downstream_remote_address: "%DOWNSTREAM_REMOTE_ADDRESS%"
-                  upstream_host: "%UPSTREAM_HOST%"
-                  bytes_received: "%BYTES_RECEIVED%"
-                  bytes_sent: "%BYTES_SENT%"
-                  request_id: "%REQ(X-REQUEST-ID)%"
-                  request_user_agent: "%REQ(USER-AGENT)%"
-                  request_content_type: "%REQ(CONTENT-TYPE)%"
-                  request_accept: "%REQ(ACCEPT)%"
-                  request_body: "%DYNAMIC_METADATA(sanelens:request_body)%"
-                  request_forwarded_for: "%REQ(X-FORWARDED-FOR)%"
-                  request_forwarded_proto: "%REQ(X-FORWARDED-PROTO)%"
-                  response_content_type: "%RESP(CONTENT-TYPE)%"
-                  response_content_length: "%RESP(CONTENT-LENGTH)%"
-                  response_body: "%DYNAMIC_METADATA(sanelens:response_body)%"
-"#,
-    )
+const DOCKER_HOST_GATEWAY: &str = "host.docker.internal";
+const PODMAN_HOST_GATEWAY: &str = "host.containers.internal";
+
+const fn host_gateway(config: &DeriveConfig) -> &'static str {
+    if config.disable_pods {
+        PODMAN_HOST_GATEWAY
+    } else {
+        DOCKER_HOST_GATEWAY
+    }
 }
 
-fn tcp_listener_block(service_name: &str, app_name: &str, port: u16) -> String {
-    format!(
-        "  - name: {service_name}_tcp_listener_{port}\n    address:\n      socket_address:\n        address: 0.0.0.0\n        port_value: {port}\n    filter_chains:\n    - filters:\n      - name: envoy.filters.network.tcp_proxy\n        typed_config:\n          \"@type\": type.googleapis.com/envoy.extensions.filters.network.tcp_proxy.v3.TcpProxy\n          stat_prefix: tcp_{port}\n          cluster: {app_name}_{port}\n          access_log:\n          - name: envoy.access_loggers.stdout\n            typed_config:\n              \"@type\": type.googleapis.com/envoy.extensions.access_loggers.stream.v3.StdoutAccessLog\n              log_format:\n                json_format:\n                  timestamp: \"%START_TIME%\"\n                  duration_ms: \"%DURATION%\"\n                  downstream_remote_address: \"%DOWNSTREAM_REMOTE_ADDRESS%\"\n                  upstream_host: \"%UPSTREAM_HOST%\"\n                  bytes_received: \"%BYTES_RECEIVED%\"\n                  bytes_sent: \"%BYTES_SENT%\"\n",
-    )
+fn vcr_proxy(config: &DeriveConfig) -> Option<VcrProxy<'static>> {
+    match (config.vcr_mode?, config.vcr_replay_port) {
+        (VcrMode::Record, _) => Some(VcrProxy::Record),
+        (VcrMode::Replay, Some(port)) => Some(VcrProxy::Replay {
+            host: host_gateway(config),
+            port,
+        }),
+        (VcrMode::Replay, None) => None,
+    }
 }
 
-fn cluster_block(app_name: &str, port: u16) -> String {
-    format!(
-        "  - name: {app_name}_{port}\n    connect_timeout: 2s\n    type: STRICT_DNS\n    lb_policy: ROUND_ROBIN\n    load_assignment:\n      cluster_name: {app_name}_{port}\n      endpoints:\n      - lb_endpoints:\n        - endpoint:\n            address:\n              socket_address:\n                address: {app_name}\n                port_value: {port}\n",
-    )
+fn write_egress_envoy_config(
+    path: &Path,
+    intercept: Option<&InterceptCa>,
+    transparent: bool,
+    policy: Option<&EgressPolicy>,
+    vcr: Option<VcrProxy<'_>>,
+) -> Result<(), String> {
+    let hosts: Vec<InterceptHost> = intercept
+        .map(|intercept| {
+            intercept
+                .leaves
+                .iter()
+                .map(|leaf| InterceptHost {
+                    host: leaf.host.clone(),
+                    cert_path: tls::container_path(&leaf.cert_file),
+                    key_path: tls::container_path(&leaf.key_file),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let body = envoy::egress_bootstrap(&EgressOptions {
+        intercept: &hosts,
+        transparent,
+        policy,
+        vcr,
+    })
+    .to_yaml()?;
+    fs::write(path, body).map_err(|err| err.to_string())
 }
@@ -146,6 +146,53 @@ impl Engine {
         command
     }
 
+    pub fn exec_cmd(&self, cid: &str, args: &[&str]) -> Vec<String> {
+        let mut command = match self.kind {
+            EngineKind::Podman => self.podman_cmd.clone(),
+            EngineKind::Docker => self.docker_cmd.clone(),
+        };
+        command.push("exec".to_string());
+        command.push(cid.to_string());
+        command.extend(args.iter().map(|arg| (*arg).to_string()));
+        command
+    }
+
+    pub fn exec_input_cmd(&self, cid: &str, args: &[&str]) -> Vec<String> {
+        let mut command = match self.kind {
+            EngineKind::Podman => self.podman_cmd.clone(),
+            EngineKind::Docker => self.docker_cmd.clone(),
+        };
+        command.push("exec".to_string());
+        command.push("-i".to_string());
+        command.push(cid.to_string());
+        command.extend(args.iter().map(|arg| (*arg).to_string()));
+        command
+    }
+
+    pub fn net_admin_cmd(&self, cid: &str, image: &str, script: &str) -> Vec<String> {
+        let mut command = match self.kind {
+            EngineKind::Podman => self.podman_cmd.clone(),
+            EngineKind::Docker => self.docker_cmd.clone(),
+        };
+        command.extend(
+            [
+                "run",
+                "--rm",
+                "--network",
+                &format!("container:{cid}"),
+                "--cap-add",
+                "NET_ADMIN",
+                "--entrypoint",
+                "sh",
+                image,
+                "-c",
+                script,
+            ]
+            .map(str::to_string),
+        );
+        command
+    }
+
     pub fn cleanup_project(&self, context: &CleanupContext<'_>) {
         Self::compose_down(
             context.compose_cmd,
@@ -192,6 +239,28 @@ impl Engine {
         let _ = command.output();
     }
 
+    // Gateway of the engine's default bridge, which `host-gateway` resolves to.
+    pub fn host_gateway_ip(&self) -> Option<IpAddr> {
+        let (mut cmd, network, format) = match self.kind {
+            EngineKind::Podman => (
+                self.podman_cmd.clone(),
+                "podman",
+                "{{range .Subnets}}{{.Gateway}} {{end}}",
+            ),
+            EngineKind::Docker => (
+                self.docker_cmd.clone(),
+                "bridge",
+                "{{range .IPAM.Config}}{{.Gateway}} {{end}}",
+            ),
+        };
+        cmd.extend(["network", "inspect", "--format", format, network].map(str::to_string));
+        let output = run_output(&cmd).ok()?;
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .find(IpAddr::is_ipv4)
+    }
+
     pub fn inspect_containers(&self, ids: &[String]) -> Vec<ContainerInfo> {
         if ids.is_empty() {
             return Vec::new();
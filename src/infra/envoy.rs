@@ -0,0 +1,2958 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::domain::traffic::DbSystem;
+use crate::infra::fault::{route_rule, runtime_key, FaultRule, FaultSpec, SERVICE_RULE};
+use crate::support::constants::{
+    CALLER_HEADER, DB_TAP_PREFIX, FAULT_HEADER, MIRROR_HEADER, POLICY_BLOCKED, POLICY_HEADER,
+};
+use crate::support::egress_policy::{EgressPolicy, PolicyMode};
+
+pub const ADMIN_PORT: u16 = 9901;
+pub const EGRESS_PORT: u16 = 15001;
+pub const EGRESS_TLS_PORT: u16 = 15002;
+pub const TRANSPARENT_PORT: u16 = 15006;
+pub const MIRROR_PORT_BASE: u16 = 15100;
+pub const TUNING_KEYS: [&str; 6] = [
+    "connect_timeout",
+    "timeout",
+    "idle_timeout",
+    "retries",
+    "tap_max_bytes",
+    "tap_sample",
+];
+
+const TAP_DIR: &str = "/sanelens/tap";
+const TAP_PATH_PREFIX: &str = "/sanelens/tap/trace";
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 2_000;
+const EGRESS_CONNECT_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_TAP_MAX_BYTES: u64 = 10 * 1024 * 1024;
+const RETRY_ON: &str = "connect-failure,refused-stream,reset";
+const SYSTEM_TRUSTED_CA: &str = "/etc/ssl/certs/ca-certificates.crt";
+const BLOCKED_BODY: &str = "egress blocked by sanelens policy\n";
+const VCR_REPLAY_CLUSTER: &str = "vcr_replay";
+const FAULT_FILTER: &str = "envoy.filters.http.fault";
+const FAULT_RESET_CLUSTER: &str = "fault_reset";
+const FAULT_RESET_PORT: u16 = 15007;
+// Promises a body and then closes, so the proxy has already answered when the upstream goes
+// away and can only reset the caller.
+const FAULT_RESET_RESPONSE: &str =
+    "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 1024\r\n\r\n";
+const MIRROR_RUNTIME_KEY: &str = "sanelens.canary.mirror_percent";
+const CALLER_ADDRESS: &str = "%DOWNSTREAM_REMOTE_ADDRESS_WITHOUT_PORT%";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyProtocol {
+    Http,
+    Tcp,
+    Postgres,
+    Mysql,
+    Redis,
+}
+
+impl ProxyProtocol {
+    // Database ports are relayed like TCP, with the socket tapped for the sanelens decoder.
+    pub const fn db_system(self) -> Option<DbSystem> {
+        match self {
+            Self::Http | Self::Tcp => None,
+            Self::Postgres => Some(DbSystem::Postgres),
+            Self::Mysql => Some(DbSystem::Mysql),
+            Self::Redis => Some(DbSystem::Redis),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortTuning {
+    pub connect_timeout_ms: u64,
+    pub timeout_ms: Option<u64>,
+    pub idle_timeout_ms: Option<u64>,
+    pub retries: Option<u32>,
+    pub tap_max_bytes: u64,
+    pub tap_sample_percent: u32,
+    pub tap_ignore_paths: Vec<String>,
+    pub tap_content_types: Vec<String>,
+    pub tap_skip_content_types: Vec<String>,
+}
+
+impl Default for PortTuning {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT_MS,
+            timeout_ms: None,
+            idle_timeout_ms: None,
+            retries: None,
+            tap_max_bytes: DEFAULT_TAP_MAX_BYTES,
+            tap_sample_percent: 100,
+            tap_ignore_paths: Vec::new(),
+            tap_content_types: Vec::new(),
+            tap_skip_content_types: Vec::new(),
+        }
+    }
+}
+
+impl PortTuning {
+    pub fn set(&mut self, key: &str, raw: &str) -> Result<(), String> {
+        match key {
+            "connect_timeout" => self.connect_timeout_ms = parse_duration_ms(raw)?,
+            "timeout" => self.timeout_ms = Some(parse_duration_ms(raw)?),
+            "idle_timeout" => self.idle_timeout_ms = Some(parse_duration_ms(raw)?),
+            "retries" => {
+                self.retries = Some(
+                    raw.trim()
+                        .parse()
+                        .map_err(|_| format!("invalid retry count '{raw}'"))?,
+                );
+            }
+            "tap_max_bytes" => self.tap_max_bytes = parse_tap_max_bytes(raw)?,
+            "tap_sample" => self.tap_sample_percent = parse_sample_percent(raw)?,
+            other => return Err(format!("unknown tuning key '{other}'")),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InterceptHost {
+    pub host: String,
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct PortConfig {
+    pub port: u16,
+    pub protocol: ProxyProtocol,
+    pub tuning: PortTuning,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CanarySplit {
+    Mirror(u32),
+    Weight(u32),
+}
+
+#[derive(Clone, Copy)]
+pub struct CanaryRoute<'a> {
+    pub app_name: &'a str,
+    pub split: CanarySplit,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct IngressOptions<'a> {
+    pub scaled: bool,
+    pub faults: &'a [FaultRule],
+    pub canary: Option<CanaryRoute<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct Bootstrap {
+    static_resources: StaticResources,
+    admin: Admin,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layered_runtime: Option<LayeredRuntime>,
+}
+
+impl Bootstrap {
+    pub fn to_yaml(&self) -> Result<String, String> {
+        serde_yaml::to_string(self).map_err(|err| format!("serialize envoy config failed: {err}"))
+    }
+}
+
+// Startup faults live in the static layer; the admin layer is what `/runtime_modify` writes.
+#[derive(Serialize)]
+struct LayeredRuntime {
+    layers: Vec<RuntimeLayer>,
+}
+
+#[derive(Serialize)]
+struct RuntimeLayer {
+    name: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    static_layer: Option<BTreeMap<String, u64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    admin_layer: Option<Empty>,
+}
+
+#[derive(Serialize)]
+struct StaticResources {
+    listeners: Vec<Listener>,
+    clusters: Vec<Cluster>,
+}
+
+#[derive(Serialize)]
+#[allow(clippy::struct_field_names)]
+struct Listener {
+    name: String,
+    address: Address,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    listener_filters: Vec<ListenerFilter>,
+    filter_chains: Vec<FilterChain>,
+}
+
+#[derive(Serialize)]
+struct ListenerFilter {
+    name: &'static str,
+    typed_config: ListenerFilterConfig,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "@type")]
+enum ListenerFilterConfig {
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.listener.proxy_protocol.v3.ProxyProtocol"
+    )]
+    ProxyProtocol,
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.listener.tls_inspector.v3.TlsInspector"
+    )]
+    TlsInspector,
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.listener.original_dst.v3.OriginalDst"
+    )]
+    OriginalDst,
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.listener.http_inspector.v3.HttpInspector"
+    )]
+    HttpInspector,
+}
+
+#[derive(Serialize)]
+struct Address {
+    socket_address: SocketAddress,
+}
+
+#[derive(Serialize)]
+struct SocketAddress {
+    address: String,
+    port_value: u16,
+}
+
+#[derive(Serialize)]
+#[allow(clippy::struct_field_names)]
+struct FilterChain {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter_chain_match: Option<FilterChainMatch>,
+    filters: Vec<NetworkFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transport_socket: Option<TransportSocket>,
+}
+
+impl FilterChain {
+    fn plain(filter: NetworkFilter) -> Self {
+        Self {
+            filter_chain_match: None,
+            filters: vec![filter],
+            transport_socket: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FilterChainMatch {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    server_names: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    application_protocols: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prefix_ranges: Vec<CidrRange>,
+}
+
+#[derive(Serialize, Clone)]
+struct CidrRange {
+    address_prefix: String,
+    prefix_len: u8,
+}
+
+#[derive(Serialize)]
+struct TransportSocket {
+    name: &'static str,
+    typed_config: TransportSocketConfig,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "@type")]
+enum TransportSocketConfig {
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.transport_sockets.tls.v3.DownstreamTlsContext"
+    )]
+    DownstreamTls {
+        common_tls_context: CommonTlsContext,
+    },
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.transport_sockets.tls.v3.UpstreamTlsContext"
+    )]
+    UpstreamTls {
+        common_tls_context: CommonTlsContext,
+    },
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.transport_sockets.proxy_protocol.v3.ProxyProtocolUpstreamTransport"
+    )]
+    UpstreamProxyProtocol {
+        config: ProxyProtocolConfig,
+        transport_socket: Box<TransportSocket>,
+    },
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.transport_sockets.raw_buffer.v3.RawBuffer"
+    )]
+    RawBuffer,
+    #[serde(rename = "type.googleapis.com/envoy.extensions.transport_sockets.tap.v3.Tap")]
+    Tap {
+        common_config: TapCommonConfig,
+        transport_socket: Box<TransportSocket>,
+    },
+}
+
+#[derive(Serialize)]
+struct ProxyProtocolConfig {
+    version: &'static str,
+}
+
+#[derive(Serialize, Default)]
+struct CommonTlsContext {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tls_certificates: Vec<TlsCertificate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    validation_context: Option<ValidationContext>,
+}
+
+#[derive(Serialize)]
+struct TlsCertificate {
+    certificate_chain: DataSource,
+    private_key: DataSource,
+}
+
+#[derive(Serialize)]
+struct ValidationContext {
+    trusted_ca: DataSource,
+}
+
+#[derive(Serialize)]
+struct DataSource {
+    filename: String,
+}
+
+#[derive(Serialize)]
+struct NetworkFilter {
+    name: &'static str,
+    typed_config: NetworkFilterConfig,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "@type")]
+enum NetworkFilterConfig {
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.network.http_connection_manager.v3.HttpConnectionManager"
+    )]
+    HttpConnectionManager(Box<HttpConnectionManager>),
+    #[serde(rename = "type.googleapis.com/envoy.extensions.filters.network.tcp_proxy.v3.TcpProxy")]
+    TcpProxy(TcpProxy),
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.network.direct_response.v3.Config"
+    )]
+    DirectResponse { response: InlineString },
+}
+
+#[derive(Serialize)]
+struct HttpConnectionManager {
+    stat_prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codec_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    common_http_protocol_options: Option<HttpProtocolOptions>,
+    route_config: RouteConfiguration,
+    http_filters: Vec<HttpFilter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_reply_config: Option<LocalReplyConfig>,
+    access_log: Vec<AccessLog>,
+}
+
+#[derive(Serialize)]
+struct LocalReplyConfig {
+    mappers: Vec<ResponseMapper>,
+}
+
+#[derive(Serialize)]
+struct ResponseMapper {
+    filter: ResponseFilter,
+    headers_to_add: Vec<HeaderValueOption>,
+}
+
+#[derive(Serialize)]
+struct ResponseFilter {
+    response_flag_filter: ResponseFlagFilter,
+}
+
+#[derive(Serialize)]
+struct ResponseFlagFilter {
+    flags: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct HttpProtocolOptions {
+    idle_timeout: String,
+}
+
+#[derive(Serialize)]
+struct RouteConfiguration {
+    name: String,
+    virtual_hosts: Vec<VirtualHost>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    request_headers_to_add: Vec<HeaderValueOption>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    request_headers_to_remove: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct VirtualHost {
+    name: &'static str,
+    domains: Vec<String>,
+    routes: Vec<Route>,
+}
+
+#[derive(Serialize)]
+#[allow(clippy::struct_field_names)]
+struct Route {
+    #[serde(rename = "match")]
+    route_match: RouteMatch,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    route: Option<RouteAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    direct_response: Option<DirectResponse>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    response_headers_to_add: Vec<HeaderValueOption>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    typed_per_filter_config: BTreeMap<&'static str, HttpFilterConfig>,
+}
+
+impl Route {
+    const fn forward(route_match: RouteMatch, action: RouteAction) -> Self {
+        Self {
+            route_match,
+            route: Some(action),
+            direct_response: None,
+            response_headers_to_add: Vec::new(),
+            typed_per_filter_config: BTreeMap::new(),
+        }
+    }
+
+    // The cluster is the proxy's own reset listener, which cuts the response short after its
+    // headers, so the caller's stream is reset rather than answered.
+    fn reset(route_match: RouteMatch) -> Self {
+        Self {
+            route_match,
+            route: Some(RouteAction::cluster(FAULT_RESET_CLUSTER)),
+            direct_response: None,
+            response_headers_to_add: vec![HeaderValueOption::new(FAULT_HEADER, "reset")],
+            typed_per_filter_config: BTreeMap::new(),
+        }
+    }
+
+    fn blocked(route_match: RouteMatch) -> Self {
+        Self {
+            route_match,
+            route: None,
+            direct_response: Some(DirectResponse {
+                status: 403,
+                body: InlineString {
+                    inline_string: BLOCKED_BODY,
+                },
+            }),
+            response_headers_to_add: vec![HeaderValueOption::new(POLICY_HEADER, POLICY_BLOCKED)],
+            typed_per_filter_config: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RouteMatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_matcher: Option<Empty>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<HeaderMatcher>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtime_fraction: Option<RuntimeFraction>,
+}
+
+impl RouteMatch {
+    fn prefix(prefix: &str) -> Self {
+        Self {
+            prefix: Some(prefix.to_string()),
+            connect_matcher: None,
+            headers: Vec::new(),
+            runtime_fraction: None,
+        }
+    }
+
+    const fn connect() -> Self {
+        Self {
+            prefix: None,
+            connect_matcher: Some(Empty {}),
+            headers: Vec::new(),
+            runtime_fraction: None,
+        }
+    }
+
+    fn sampled(mut self, runtime_key: String) -> Self {
+        self.runtime_fraction = Some(RuntimeFraction {
+            default_value: FractionalPercent::percent(100),
+            runtime_key,
+        });
+        self
+    }
+
+    fn authority(mut self, regex: &str, invert_match: bool) -> Self {
+        self.headers.push(HeaderMatcher {
+            name: ":authority",
+            string_match: StringMatch {
+                safe_regex: RegexMatcher {
+                    regex: regex.to_string(),
+                },
+            },
+            invert_match,
+            treat_missing_header_as_empty: false,
+        });
+        self
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct RuntimeFraction {
+    default_value: FractionalPercent,
+    runtime_key: String,
+}
+
+#[derive(Clone, Serialize)]
+struct FractionalPercent {
+    numerator: u32,
+    denominator: &'static str,
+}
+
+impl FractionalPercent {
+    const fn percent(numerator: u32) -> Self {
+        Self {
+            numerator,
+            denominator: "HUNDRED",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HeaderMatcher {
+    name: &'static str,
+    string_match: StringMatch,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    invert_match: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    treat_missing_header_as_empty: bool,
+}
+
+#[derive(Serialize)]
+struct StringMatch {
+    safe_regex: RegexMatcher,
+}
+
+#[derive(Serialize)]
+struct RegexMatcher {
+    regex: String,
+}
+
+#[derive(Serialize)]
+struct DirectResponse {
+    status: u16,
+    body: InlineString,
+}
+
+#[derive(Serialize)]
+struct InlineString {
+    inline_string: &'static str,
+}
+
+#[derive(Serialize)]
+struct HeaderValueOption {
+    header: HeaderValue,
+}
+
+impl HeaderValueOption {
+    const fn new(key: &'static str, value: &'static str) -> Self {
+        Self {
+            header: HeaderValue { key, value },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HeaderValue {
+    key: &'static str,
+    value: &'static str,
+}
+
+#[derive(Clone, Serialize)]
+struct Empty {}
+
+#[derive(Clone, Serialize)]
+struct RouteAction {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weighted_clusters: Option<WeightedClusters>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_policy: Option<RetryPolicy>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    request_mirror_policies: Vec<MirrorPolicy>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    upgrade_configs: Vec<UpgradeConfig>,
+}
+
+impl RouteAction {
+    fn cluster(cluster: &str) -> Self {
+        Self {
+            cluster: Some(cluster.to_string()),
+            weighted_clusters: None,
+            timeout: None,
+            retry_policy: None,
+            request_mirror_policies: Vec::new(),
+            upgrade_configs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct WeightedClusters {
+    clusters: Vec<ClusterWeight>,
+}
+
+impl WeightedClusters {
+    fn split(primary: String, canary: String, weight: u32) -> Self {
+        Self {
+            clusters: vec![
+                ClusterWeight {
+                    name: primary,
+                    weight: 100 - weight,
+                },
+                ClusterWeight {
+                    name: canary,
+                    weight,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+struct ClusterWeight {
+    name: String,
+    weight: u32,
+}
+
+#[derive(Clone, Serialize)]
+struct MirrorPolicy {
+    cluster: String,
+    runtime_fraction: RuntimeFraction,
+}
+
+#[derive(Clone, Serialize)]
+struct UpgradeConfig {
+    upgrade_type: &'static str,
+    connect_config: Empty,
+}
+
+#[derive(Clone, Serialize)]
+struct RetryPolicy {
+    retry_on: &'static str,
+    num_retries: u32,
+}
+
+#[derive(Serialize)]
+struct HttpFilter {
+    name: &'static str,
+    typed_config: HttpFilterConfig,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "@type")]
+enum HttpFilterConfig {
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.filters.http.dynamic_forward_proxy.v3.FilterConfig"
+    )]
+    DynamicForwardProxy { dns_cache_config: DnsCacheConfig },
+    #[serde(rename = "type.googleapis.com/envoy.extensions.filters.http.tap.v3.Tap")]
+    Tap { common_config: TapCommonConfig },
+    #[serde(rename = "type.googleapis.com/envoy.extensions.filters.http.fault.v3.HTTPFault")]
+    Fault(Box<FaultConfig>),
+    #[serde(rename = "type.googleapis.com/envoy.extensions.filters.http.router.v3.Router")]
+    Router,
+}
+
+// Faults start disabled and are switched on through the runtime keys, which lets the
+// static layer carry startup rules and the admin layer carry live toggles.
+#[derive(Serialize)]
+struct FaultConfig {
+    delay: FaultDelay,
+    abort: FaultAbort,
+    delay_percent_runtime: String,
+    delay_duration_runtime: String,
+    abort_percent_runtime: String,
+    abort_http_status_runtime: String,
+}
+
+#[derive(Serialize)]
+struct FaultDelay {
+    fixed_delay: String,
+    percentage: FractionalPercent,
+}
+
+#[derive(Serialize)]
+struct FaultAbort {
+    http_status: u16,
+    percentage: FractionalPercent,
+}
+
+#[derive(Serialize)]
+struct TapCommonConfig {
+    static_config: TapStaticConfig,
+}
+
+#[derive(Serialize)]
+struct TapStaticConfig {
+    match_config: TapMatch,
+    output_config: TapOutput,
+}
+
+#[derive(Default, Serialize)]
+struct TapMatch {
+    #[serde(rename = "any_match", skip_serializing_if = "Option::is_none")]
+    any: Option<bool>,
+    #[serde(rename = "and_match", skip_serializing_if = "Option::is_none")]
+    all: Option<TapMatchSet>,
+    #[serde(rename = "not_match", skip_serializing_if = "Option::is_none")]
+    not: Option<Box<Self>>,
+    #[serde(
+        rename = "http_request_headers_match",
+        skip_serializing_if = "Option::is_none"
+    )]
+    request_headers: Option<TapHeadersMatch>,
+}
+
+#[derive(Serialize)]
+struct TapMatchSet {
+    rules: Vec<TapMatch>,
+}
+
+#[derive(Serialize)]
+struct TapHeadersMatch {
+    headers: Vec<HeaderMatcher>,
+}
+
+impl TapMatch {
+    fn request_header(name: &'static str, regex: String) -> Self {
+        Self {
+            request_headers: Some(TapHeadersMatch {
+                headers: vec![HeaderMatcher {
+                    name,
+                    string_match: StringMatch {
+                        safe_regex: RegexMatcher { regex },
+                    },
+                    invert_match: false,
+                    treat_missing_header_as_empty: false,
+                }],
+            }),
+            ..Self::default()
+        }
+    }
+
+    fn missing_header_as_empty(mut self) -> Self {
+        for header in self
+            .request_headers
+            .iter_mut()
+            .flat_map(|matcher| matcher.headers.iter_mut())
+        {
+            header.treat_missing_header_as_empty = true;
+        }
+        self
+    }
+
+    fn not(self) -> Self {
+        Self {
+            not: Some(Box::new(self)),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TapOutput {
+    max_buffered_rx_bytes: u64,
+    max_buffered_tx_bytes: u64,
+    sinks: Vec<TapSink>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    streaming: bool,
+}
+
+#[derive(Serialize)]
+struct TapSink {
+    format: &'static str,
+    file_per_tap: FilePerTap,
+}
+
+#[derive(Serialize)]
+struct FilePerTap {
+    path_prefix: String,
+}
+
+#[derive(Serialize)]
+struct DnsCacheConfig {
+    name: &'static str,
+    dns_lookup_family: &'static str,
+}
+
+#[derive(Serialize)]
+struct AccessLog {
+    name: &'static str,
+    typed_config: AccessLogConfig,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "@type")]
+enum AccessLogConfig {
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.access_loggers.stream.v3.StdoutAccessLog"
+    )]
+    Stdout { log_format: LogFormat },
+}
+
+#[derive(Serialize)]
+struct LogFormat {
+    json_format: BTreeMap<&'static str, &'static str>,
+}
+
+#[derive(Serialize)]
+struct TcpProxy {
+    stat_prefix: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    weighted_clusters: Option<WeightedClusters>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_timeout: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_connect_attempts: Option<u32>,
+    access_log: Vec<AccessLog>,
+}
+
+#[derive(Serialize)]
+#[allow(clippy::struct_field_names)]
+struct Cluster {
+    name: String,
+    connect_timeout: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    discovery_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dns_refresh_rate: Option<String>,
+    lb_policy: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    load_assignment: Option<LoadAssignment>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster_type: Option<CustomClusterType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    typed_extension_protocol_options: Option<ExtensionProtocolOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transport_socket: Option<TransportSocket>,
+}
+
+#[derive(Serialize)]
+struct ExtensionProtocolOptions {
+    #[serde(rename = "envoy.extensions.upstreams.http.v3.HttpProtocolOptions")]
+    http: HttpUpstreamOptions,
+}
+
+#[derive(Serialize)]
+struct HttpUpstreamOptions {
+    #[serde(rename = "@type")]
+    type_url: &'static str,
+    upstream_http_protocol_options: UpstreamHttpProtocolOptions,
+    auto_config: AutoHttpConfig,
+}
+
+#[derive(Serialize)]
+struct UpstreamHttpProtocolOptions {
+    auto_sni: bool,
+    auto_san_validation: bool,
+}
+
+#[derive(Serialize)]
+struct AutoHttpConfig {
+    http_protocol_options: Empty,
+    http2_protocol_options: Empty,
+}
+
+#[derive(Serialize)]
+struct LoadAssignment {
+    cluster_name: String,
+    endpoints: Vec<LocalityEndpoints>,
+}
+
+#[derive(Serialize)]
+struct LocalityEndpoints {
+    lb_endpoints: Vec<LbEndpoint>,
+}
+
+#[derive(Serialize)]
+struct LbEndpoint {
+    endpoint: Endpoint,
+}
+
+#[derive(Serialize)]
+struct Endpoint {
+    address: Address,
+}
+
+#[derive(Serialize)]
+struct CustomClusterType {
+    name: &'static str,
+    typed_config: ClusterTypeConfig,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "@type")]
+enum ClusterTypeConfig {
+    #[serde(
+        rename = "type.googleapis.com/envoy.extensions.clusters.dynamic_forward_proxy.v3.ClusterConfig"
+    )]
+    DynamicForwardProxy { dns_cache_config: DnsCacheConfig },
+}
+
+#[derive(Serialize)]
+struct Admin {
+    access_log_path: &'static str,
+    address: Address,
+}
+
+pub fn ingress_bootstrap(
+    service_name: &str,
+    app_name: &str,
+    ports: &[PortConfig],
+    options: IngressOptions<'_>,
+) -> Bootstrap {
+    let mut listeners = Vec::new();
+    let mut clusters = Vec::new();
+    for (index, config) in ports.iter().enumerate() {
+        let port = config.port;
+        let primary = format!("{app_name}_{port}");
+        clusters.push(app_cluster(app_name, config, options.scaled));
+        let mut action = RouteAction::cluster(&primary);
+        if let Some(canary) = options.canary {
+            let canary_cluster = format!("{}_{port}", canary.app_name);
+            clusters.push(app_cluster(canary.app_name, config, options.scaled));
+            match canary.split {
+                CanarySplit::Weight(weight) => {
+                    action.cluster = None;
+                    action.weighted_clusters =
+                        Some(WeightedClusters::split(primary, canary_cluster, weight));
+                }
+                CanarySplit::Mirror(percent) if config.protocol == ProxyProtocol::Http => {
+                    let mirror_port = MIRROR_PORT_BASE.saturating_add(port_index(index));
+                    let mirror_cluster = format!("{service_name}_mirror_{port}");
+                    listeners.push(mirror_listener(
+                        service_name,
+                        &canary_cluster,
+                        config,
+                        mirror_port,
+                    ));
+                    clusters.push(static_cluster(&mirror_cluster, mirror_port));
+                    action.request_mirror_policies.push(MirrorPolicy {
+                        cluster: mirror_cluster,
+                        runtime_fraction: RuntimeFraction {
+                            default_value: FractionalPercent::percent(percent),
+                            runtime_key: MIRROR_RUNTIME_KEY.to_string(),
+                        },
+                    });
+                }
+                CanarySplit::Mirror(_) => {}
+            }
+        }
+        listeners.push(match config.protocol {
+            ProxyProtocol::Http => http_listener(service_name, action, config, options.faults),
+            ProxyProtocol::Tcp
+            | ProxyProtocol::Postgres
+            | ProxyProtocol::Mysql
+            | ProxyProtocol::Redis => tcp_listener(service_name, action, config),
+        });
+    }
+    let http = ports
+        .iter()
+        .any(|config| config.protocol == ProxyProtocol::Http);
+    if http {
+        listeners.push(fault_reset_listener());
+        clusters.push(fault_reset_cluster());
+    }
+    Bootstrap {
+        static_resources: StaticResources {
+            listeners,
+            clusters,
+        },
+        admin: admin(),
+        layered_runtime: http.then(|| fault_runtime(options.faults)),
+    }
+}
+
+fn port_index(index: usize) -> u16 {
+    u16::try_from(index).unwrap_or(u16::MAX)
+}
+
+fn fault_runtime(faults: &[FaultRule]) -> LayeredRuntime {
+    let service = faults
+        .iter()
+        .rev()
+        .find(|rule| rule.path.is_none())
+        .map(|rule| rule.spec)
+        .unwrap_or_default();
+    let mut values = service.runtime_values(SERVICE_RULE);
+    for (index, rule) in faults.iter().filter(|rule| rule.path.is_some()).enumerate() {
+        values.extend(rule.spec.runtime_values(&route_rule(index)));
+    }
+    LayeredRuntime {
+        layers: vec![
+            RuntimeLayer {
+                name: "static",
+                static_layer: Some(values),
+                admin_layer: None,
+            },
+            RuntimeLayer {
+                name: "admin",
+                static_layer: None,
+                admin_layer: Some(Empty {}),
+            },
+        ],
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum VcrProxy<'a> {
+    Record,
+    Replay { host: &'a str, port: u16 },
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct EgressOptions<'a> {
+    pub intercept: &'a [InterceptHost],
+    pub transparent: bool,
+    pub policy: Option<&'a EgressPolicy>,
+    pub vcr: Option<VcrProxy<'a>>,
+}
+
+#[derive(Clone, Copy)]
+struct EgressRoutes<'a> {
+    policy: Option<&'a EgressPolicy>,
+    vcr: Option<VcrProxy<'a>>,
+}
+
+impl EgressRoutes<'_> {
+    const fn cluster<'c>(&self, upstream: &'c str) -> &'c str {
+        match self.vcr {
+            Some(VcrProxy::Replay { .. }) => VCR_REPLAY_CLUSTER,
+            _ => upstream,
+        }
+    }
+
+    // Recorded bodies are stored as text, so upstreams are asked not to compress them.
+    fn request_headers_to_remove(&self) -> Vec<&'static str> {
+        match self.vcr {
+            Some(VcrProxy::Record) => vec!["accept-encoding"],
+            _ => Vec::new(),
+        }
+    }
+}
+
+pub fn egress_bootstrap(options: &EgressOptions<'_>) -> Bootstrap {
+    let intercept = options.intercept;
+    let routes = EgressRoutes {
+        policy: options
+            .policy
+            .filter(|policy| policy.mode != PolicyMode::Report),
+        vcr: options.vcr,
+    };
+    let mut route_config = egress_route_config("egress_route", "egress_cluster", routes);
+    if !intercept.is_empty() {
+        route_config
+            .virtual_hosts
+            .insert(0, intercept_connect_host(intercept));
+    }
+    let listener = Listener {
+        name: "egress_listener".to_string(),
+        address: socket_address("0.0.0.0", EGRESS_PORT),
+        listener_filters: Vec::new(),
+        filter_chains: vec![FilterChain::plain(http_connection_manager(
+            egress_connection_manager("egress_http", route_config, true),
+        ))],
+    };
+    let mut listeners = vec![listener];
+    let mut clusters = vec![egress_cluster("egress_cluster", None)];
+    if !intercept.is_empty() {
+        listeners.push(intercept_listener(intercept, routes));
+        clusters.push(intercept_loopback_cluster());
+        clusters.push(egress_cluster(
+            "egress_tls_cluster",
+            Some(upstream_tls_socket()),
+        ));
+    }
+    if options.transparent {
+        listeners.push(transparent_listener(routes));
+        clusters.push(passthrough_cluster());
+        if routes.policy.is_some() {
+            clusters.push(blocked_cluster());
+        }
+    }
+    if let Some(VcrProxy::Replay { host, port }) = options.vcr {
+        clusters.push(replay_cluster(host, port));
+    }
+    Bootstrap {
+        static_resources: StaticResources {
+            listeners,
+            clusters,
+        },
+        admin: admin(),
+        layered_runtime: None,
+    }
+}
+
+fn egress_action(cluster: &str) -> RouteAction {
+    RouteAction {
+        timeout: Some(format_duration(0)),
+        ..RouteAction::cluster(cluster)
+    }
+}
+
+// Blocked requests get a 403 from Envoy itself; CONNECT tunnels are refused up front.
+fn egress_route_config(name: &str, upstream: &str, egress: EgressRoutes<'_>) -> RouteConfiguration {
+    let policy = egress.policy;
+    let action = egress_action(egress.cluster(upstream));
+    let regex = policy.and_then(EgressPolicy::authority_regex);
+    let routes = match (policy.map(|policy| policy.mode), regex) {
+        (Some(PolicyMode::Allow), Some(regex)) => vec![
+            Route::forward(RouteMatch::prefix("/").authority(&regex, false), action),
+            Route::blocked(RouteMatch::prefix("/")),
+            Route::blocked(RouteMatch::connect().authority(&regex, true)),
+        ],
+        (Some(PolicyMode::Allow), None) => vec![
+            Route::blocked(RouteMatch::prefix("/")),
+            Route::blocked(RouteMatch::connect()),
+        ],
+        (Some(PolicyMode::Deny), Some(regex)) => vec![
+            Route::blocked(RouteMatch::prefix("/").authority(&regex, false)),
+            Route::blocked(RouteMatch::connect().authority(&regex, false)),
+            Route::forward(RouteMatch::prefix("/"), action),
+        ],
+        _ => vec![Route::forward(RouteMatch::prefix("/"), action)],
+    };
+    RouteConfiguration {
+        name: name.to_string(),
+        virtual_hosts: vec![VirtualHost {
+            name: "default",
+            domains: vec!["*".to_string()],
+            routes,
+        }],
+        request_headers_to_add: Vec::new(),
+        request_headers_to_remove: egress.request_headers_to_remove(),
+    }
+}
+
+fn egress_connection_manager(
+    stat_prefix: &str,
+    route_config: RouteConfiguration,
+    forward_proxy: bool,
+) -> HttpConnectionManager {
+    let mut http_filters = vec![tap_filter(&PortTuning::default()), router_filter()];
+    if forward_proxy {
+        http_filters.insert(
+            0,
+            HttpFilter {
+                name: "envoy.filters.http.dynamic_forward_proxy",
+                typed_config: HttpFilterConfig::DynamicForwardProxy {
+                    dns_cache_config: egress_dns_cache(),
+                },
+            },
+        );
+    }
+    HttpConnectionManager {
+        stat_prefix: stat_prefix.to_string(),
+        codec_type: None,
+        common_http_protocol_options: None,
+        route_config,
+        http_filters,
+        local_reply_config: None,
+        access_log: vec![stdout_access_log(&http_log_fields(&[
+            ("authority", "%REQ(:AUTHORITY)%"),
+            ("policy", "%RESP(X-SANELENS-POLICY)%"),
+        ]))],
+    }
+}
+
+// CONNECT requests for intercepted hosts are terminated here and the TLS stream is
+// handed to the loopback listener, which decrypts it with the per-run leaf certs.
+fn intercept_connect_host(intercept: &[InterceptHost]) -> VirtualHost {
+    let domains = intercept
+        .iter()
+        .flat_map(|entry| [entry.host.clone(), format!("{}:443", entry.host)])
+        .collect();
+    let mut action = egress_action("egress_tls_loopback");
+    action.upgrade_configs = vec![UpgradeConfig {
+        upgrade_type: "CONNECT",
+        connect_config: Empty {},
+    }];
+    VirtualHost {
+        name: "tls_intercept",
+        domains,
+        routes: vec![Route::forward(RouteMatch::connect(), action)],
+    }
+}
+
+fn intercept_listener(intercept: &[InterceptHost], routes: EgressRoutes<'_>) -> Listener {
+    let filter_chains = intercept
+        .iter()
+        .map(|entry| FilterChain {
+            filter_chain_match: Some(FilterChainMatch {
+                server_names: vec![entry.host.clone()],
+                application_protocols: Vec::new(),
+                prefix_ranges: Vec::new(),
+            }),
+            filters: vec![http_connection_manager(egress_connection_manager(
+                "egress_https",
+                egress_route_config("egress_tls_route", "egress_tls_cluster", routes),
+                true,
+            ))],
+            transport_socket: Some(downstream_tls_socket(entry)),
+        })
+        .collect();
+    Listener {
+        name: "egress_tls_listener".to_string(),
+        address: socket_address("127.0.0.1", EGRESS_TLS_PORT),
+        listener_filters: vec![
+            ListenerFilter {
+                name: "envoy.filters.listener.proxy_protocol",
+                typed_config: ListenerFilterConfig::ProxyProtocol,
+            },
+            ListenerFilter {
+                name: "envoy.filters.listener.tls_inspector",
+                typed_config: ListenerFilterConfig::TlsInspector,
+            },
+        ],
+        filter_chains,
+    }
+}
+
+// Traffic redirected at the network level keeps its original destination, so plain
+// HTTP is parsed and everything else is relayed as a TCP flow. Policy ranges match on
+// that destination and host patterns on the TLS SNI, read by the TLS inspector, or the HTTP
+// authority.
+fn transparent_listener(routes: EgressRoutes<'_>) -> Listener {
+    let policy = routes.policy;
+    let mut filter_chains = Vec::new();
+    let ranges = policy.map(cidr_ranges).unwrap_or_default();
+    let server_names = policy.map(EgressPolicy::hosts).unwrap_or_default();
+    let allow = policy.is_some_and(|policy| policy.mode == PolicyMode::Allow);
+    if !ranges.is_empty() {
+        if allow {
+            let unfiltered = EgressRoutes {
+                policy: None,
+                ..routes
+            };
+            filter_chains.push(transparent_http_chain(ranges.clone(), unfiltered));
+        }
+        filter_chains.push(transparent_tcp_chain(
+            Some(chain_match(Vec::new(), ranges)),
+            !allow,
+        ));
+    }
+    if !server_names.is_empty() {
+        filter_chains.push(transparent_tcp_chain(
+            Some(chain_match(server_names, Vec::new())),
+            !allow,
+        ));
+    }
+    filter_chains.push(transparent_http_chain(Vec::new(), routes));
+    filter_chains.push(transparent_tcp_chain(None, allow));
+    Listener {
+        name: "egress_transparent_listener".to_string(),
+        address: socket_address("0.0.0.0", TRANSPARENT_PORT),
+        listener_filters: vec![
+            ListenerFilter {
+                name: "envoy.filters.listener.original_dst",
+                typed_config: ListenerFilterConfig::OriginalDst,
+            },
+            ListenerFilter {
+                name: "envoy.filters.listener.tls_inspector",
+                typed_config: ListenerFilterConfig::TlsInspector,
+            },
+            ListenerFilter {
+                name: "envoy.filters.listener.http_inspector",
+                typed_config: ListenerFilterConfig::HttpInspector,
+            },
+        ],
+        filter_chains,
+    }
+}
+
+const fn chain_match(server_names: Vec<String>, prefix_ranges: Vec<CidrRange>) -> FilterChainMatch {
+    FilterChainMatch {
+        server_names,
+        application_protocols: Vec::new(),
+        prefix_ranges,
+    }
+}
+
+fn cidr_ranges(policy: &EgressPolicy) -> Vec<CidrRange> {
+    policy
+        .cidrs()
+        .into_iter()
+        .map(|(ip, prefix_len)| CidrRange {
+            address_prefix: ip.to_string(),
+            prefix_len,
+        })
+        .collect()
+}
+
+fn transparent_http_chain(prefix_ranges: Vec<CidrRange>, routes: EgressRoutes<'_>) -> FilterChain {
+    FilterChain {
+        filter_chain_match: Some(FilterChainMatch {
+            server_names: Vec::new(),
+            application_protocols: vec!["http/1.0", "http/1.1", "h2c"],
+            prefix_ranges,
+        }),
+        filters: vec![http_connection_manager(egress_connection_manager(
+            "egress_transparent_http",
+            egress_route_config("egress_transparent_route", "egress_passthrough", routes),
+            false,
+        ))],
+        transport_socket: None,
+    }
+}
+
+fn transparent_tcp_chain(
+    filter_chain_match: Option<FilterChainMatch>,
+    blocked: bool,
+) -> FilterChain {
+    let (cluster, fields) = if blocked {
+        let mut fields = TCP_LOG_FIELDS.to_vec();
+        fields.push(("policy", POLICY_BLOCKED));
+        ("egress_blocked", fields)
+    } else {
+        ("egress_passthrough", TCP_LOG_FIELDS.to_vec())
+    };
+    FilterChain {
+        filter_chain_match,
+        filters: vec![NetworkFilter {
+            name: "envoy.filters.network.tcp_proxy",
+            typed_config: NetworkFilterConfig::TcpProxy(TcpProxy {
+                stat_prefix: cluster.to_string(),
+                cluster: Some(cluster.to_string()),
+                weighted_clusters: None,
+                idle_timeout: None,
+                max_connect_attempts: None,
+                access_log: vec![stdout_access_log(&fields)],
+            }),
+        }],
+        transport_socket: None,
+    }
+}
+
+fn passthrough_cluster() -> Cluster {
+    Cluster {
+        name: "egress_passthrough".to_string(),
+        connect_timeout: format_duration(EGRESS_CONNECT_TIMEOUT_MS),
+        discovery_type: Some("ORIGINAL_DST"),
+        dns_refresh_rate: None,
+        lb_policy: "CLUSTER_PROVIDED",
+        load_assignment: None,
+        cluster_type: None,
+        typed_extension_protocol_options: None,
+        transport_socket: None,
+    }
+}
+
+// No endpoints: connections routed here are closed as soon as they arrive.
+fn blocked_cluster() -> Cluster {
+    Cluster {
+        name: "egress_blocked".to_string(),
+        connect_timeout: format_duration(EGRESS_CONNECT_TIMEOUT_MS),
+        discovery_type: Some("STATIC"),
+        dns_refresh_rate: None,
+        lb_policy: "ROUND_ROBIN",
+        load_assignment: Some(LoadAssignment {
+            cluster_name: "egress_blocked".to_string(),
+            endpoints: Vec::new(),
+        }),
+        cluster_type: None,
+        typed_extension_protocol_options: None,
+        transport_socket: None,
+    }
+}
+
+// Replay responses come from the sanelens process on the host, reached through the
+// engine's host gateway name.
+fn replay_cluster(host: &str, port: u16) -> Cluster {
+    Cluster {
+        name: VCR_REPLAY_CLUSTER.to_string(),
+        connect_timeout: format_duration(DEFAULT_CONNECT_TIMEOUT_MS),
+        discovery_type: Some("STRICT_DNS"),
+        dns_refresh_rate: None,
+        lb_policy: "ROUND_ROBIN",
+        load_assignment: Some(LoadAssignment {
+            cluster_name: VCR_REPLAY_CLUSTER.to_string(),
+            endpoints: vec![LocalityEndpoints {
+                lb_endpoints: vec![LbEndpoint {
+                    endpoint: Endpoint {
+                        address: socket_address(host, port),
+                    },
+                }],
+            }],
+        }),
+        cluster_type: None,
+        typed_extension_protocol_options: None,
+        transport_socket: None,
+    }
+}
+
+fn downstream_tls_socket(entry: &InterceptHost) -> TransportSocket {
+    TransportSocket {
+        name: "envoy.transport_sockets.tls",
+        typed_config: TransportSocketConfig::DownstreamTls {
+            common_tls_context: CommonTlsContext {
+                tls_certificates: vec![TlsCertificate {
+                    certificate_chain: DataSource {
+                        filename: entry.cert_path.clone(),
+                    },
+                    private_key: DataSource {
+                        filename: entry.key_path.clone(),
+                    },
+                }],
+                validation_context: None,
+            },
+        },
+    }
+}
+
+fn upstream_tls_socket() -> TransportSocket {
+    TransportSocket {
+        name: "envoy.transport_sockets.tls",
+        typed_config: TransportSocketConfig::UpstreamTls {
+            common_tls_context: CommonTlsContext {
+                tls_certificates: Vec::new(),
+                validation_context: Some(ValidationContext {
+                    trusted_ca: DataSource {
+                        filename: SYSTEM_TRUSTED_CA.to_string(),
+                    },
+                }),
+            },
+        },
+    }
+}
+
+fn egress_cluster(name: &str, transport_socket: Option<TransportSocket>) -> Cluster {
+    let typed_extension_protocol_options =
+        transport_socket.as_ref().map(|_| ExtensionProtocolOptions {
+            http: HttpUpstreamOptions {
+                type_url:
+                    "type.googleapis.com/envoy.extensions.upstreams.http.v3.HttpProtocolOptions",
+                upstream_http_protocol_options: UpstreamHttpProtocolOptions {
+                    auto_sni: true,
+                    auto_san_validation: true,
+                },
+                auto_config: AutoHttpConfig {
+                    http_protocol_options: Empty {},
+                    http2_protocol_options: Empty {},
+                },
+            },
+        });
+    Cluster {
+        name: name.to_string(),
+        connect_timeout: format_duration(EGRESS_CONNECT_TIMEOUT_MS),
+        discovery_type: None,
+        dns_refresh_rate: None,
+        lb_policy: "CLUSTER_PROVIDED",
+        load_assignment: None,
+        cluster_type: Some(CustomClusterType {
+            name: "envoy.clusters.dynamic_forward_proxy",
+            typed_config: ClusterTypeConfig::DynamicForwardProxy {
+                dns_cache_config: egress_dns_cache(),
+            },
+        }),
+        typed_extension_protocol_options,
+        transport_socket,
+    }
+}
+
+// The PROXY header keeps the app's address visible to the loopback listener's access log.
+fn intercept_loopback_cluster() -> Cluster {
+    let name = "egress_tls_loopback".to_string();
+    Cluster {
+        name: name.clone(),
+        connect_timeout: format_duration(DEFAULT_CONNECT_TIMEOUT_MS),
+        discovery_type: Some("STATIC"),
+        dns_refresh_rate: None,
+        lb_policy: "ROUND_ROBIN",
+        load_assignment: Some(LoadAssignment {
+            cluster_name: name,
+            endpoints: vec![LocalityEndpoints {
+                lb_endpoints: vec![LbEndpoint {
+                    endpoint: Endpoint {
+                        address: socket_address("127.0.0.1", EGRESS_TLS_PORT),
+                    },
+                }],
+            }],
+        }),
+        cluster_type: None,
+        typed_extension_protocol_options: None,
+        transport_socket: Some(TransportSocket {
+            name: "envoy.transport_sockets.upstream_proxy_protocol",
+            typed_config: TransportSocketConfig::UpstreamProxyProtocol {
+                config: ProxyProtocolConfig { version: "V1" },
+                transport_socket: Box::new(TransportSocket {
+                    name: "envoy.transport_sockets.raw_buffer",
+                    typed_config: TransportSocketConfig::RawBuffer,
+                }),
+            },
+        }),
+    }
+}
+
+fn http_listener(
+    service_name: &str,
+    action: RouteAction,
+    config: &PortConfig,
+    faults: &[FaultRule],
+) -> Listener {
+    let port = config.port;
+    let tuning = &config.tuning;
+    let mirrored = !action.request_mirror_policies.is_empty();
+    let action = RouteAction {
+        timeout: tuning.timeout_ms.map(format_duration),
+        retry_policy: tuning.retries.map(|num_retries| RetryPolicy {
+            retry_on: RETRY_ON,
+            num_retries,
+        }),
+        ..action
+    };
+    let manager = HttpConnectionManager {
+        stat_prefix: format!("ingress_http_{port}"),
+        codec_type: Some("AUTO"),
+        common_http_protocol_options: tuning.idle_timeout_ms.map(|ms| HttpProtocolOptions {
+            idle_timeout: format_duration(ms),
+        }),
+        route_config: RouteConfiguration {
+            name: format!("route_{port}"),
+            virtual_hosts: vec![VirtualHost {
+                name: "backend",
+                domains: vec!["*".to_string()],
+                routes: fault_routes(&action, faults),
+            }],
+            request_headers_to_add: if mirrored {
+                vec![HeaderValueOption::new(CALLER_HEADER, CALLER_ADDRESS)]
+            } else {
+                Vec::new()
+            },
+            request_headers_to_remove: Vec::new(),
+        },
+        http_filters: vec![
+            tap_filter(tuning),
+            fault_filter(SERVICE_RULE),
+            router_filter(),
+        ],
+        local_reply_config: Some(LocalReplyConfig {
+            mappers: vec![ResponseMapper {
+                filter: ResponseFilter {
+                    response_flag_filter: ResponseFlagFilter { flags: vec!["FI"] },
+                },
+                headers_to_add: vec![HeaderValueOption::new(FAULT_HEADER, "abort")],
+            }],
+        }),
+        access_log: vec![stdout_access_log(&http_log_fields(&[
+            ("protocol", "%PROTOCOL%"),
+            ("fault", "%RESP(X-SANELENS-FAULT)%"),
+            ("response_flags", "%RESPONSE_FLAGS%"),
+        ]))],
+    };
+    Listener {
+        name: format!("{service_name}_listener_{port}"),
+        address: socket_address("0.0.0.0", port),
+        listener_filters: Vec::new(),
+        filter_chains: vec![FilterChain::plain(http_connection_manager(manager))],
+    }
+}
+
+// Each fault rule gets a regular route that only admits the runtime share of requests not
+// being reset, followed by a reset route for the rest. Path rules replace the service rule.
+fn fault_routes(action: &RouteAction, faults: &[FaultRule]) -> Vec<Route> {
+    let paths = faults.iter().filter_map(|rule| rule.path.as_deref());
+    let mut routes = Vec::new();
+    for (index, path) in paths.enumerate() {
+        let rule = route_rule(index);
+        let mut forward = fault_forward(path, &rule, action.clone());
+        forward
+            .typed_per_filter_config
+            .insert(FAULT_FILTER, fault_filter(&rule).typed_config);
+        routes.push(forward);
+        routes.push(Route::reset(RouteMatch::prefix(path)));
+    }
+    routes.push(fault_forward("/", SERVICE_RULE, action.clone()));
+    routes.push(Route::reset(RouteMatch::prefix("/")));
+    routes
+}
+
+fn fault_forward(prefix: &str, rule: &str, action: RouteAction) -> Route {
+    Route::forward(
+        RouteMatch::prefix(prefix).sampled(runtime_key(rule, "pass_percent")),
+        action,
+    )
+}
+
+// Mirrored copies are sent to a loopback listener that forwards them to the canary, so they
+// are tapped like regular calls. Their responses are dropped by the mirroring listener.
+fn mirror_listener(
+    service_name: &str,
+    canary_cluster: &str,
+    config: &PortConfig,
+    listen_port: u16,
+) -> Listener {
+    let port = config.port;
+    let mut route = Route::forward(
+        RouteMatch::prefix("/"),
+        RouteAction {
+            timeout: config.tuning.timeout_ms.map(format_duration),
+            ..RouteAction::cluster(canary_cluster)
+        },
+    );
+    route
+        .response_headers_to_add
+        .push(HeaderValueOption::new(MIRROR_HEADER, "shadow"));
+    let manager = HttpConnectionManager {
+        stat_prefix: format!("mirror_http_{port}"),
+        codec_type: Some("AUTO"),
+        common_http_protocol_options: None,
+        route_config: RouteConfiguration {
+            name: format!("mirror_route_{port}"),
+            virtual_hosts: vec![VirtualHost {
+                name: "canary",
+                domains: vec!["*".to_string()],
+                routes: vec![route],
+            }],
+            request_headers_to_add: Vec::new(),
+            request_headers_to_remove: vec![CALLER_HEADER],
+        },
+        http_filters: vec![tap_filter(&config.tuning), router_filter()],
+        local_reply_config: None,
+        access_log: vec![stdout_access_log(&http_log_fields(&[(
+            "protocol",
+            "%PROTOCOL%",
+        )]))],
+    };
+    Listener {
+        name: format!("{service_name}_mirror_listener_{port}"),
+        address: socket_address("127.0.0.1", listen_port),
+        listener_filters: Vec::new(),
+        filter_chains: vec![FilterChain::plain(http_connection_manager(manager))],
+    }
+}
+
+fn tcp_listener(service_name: &str, action: RouteAction, config: &PortConfig) -> Listener {
+    let port = config.port;
+    let tuning = &config.tuning;
+    let proxy = TcpProxy {
+        stat_prefix: format!("tcp_{port}"),
+        cluster: action.cluster,
+        weighted_clusters: action.weighted_clusters,
+        idle_timeout: tuning.idle_timeout_ms.map(format_duration),
+        max_connect_attempts: tuning.retries.map(|retries| retries.saturating_add(1)),
+        access_log: vec![stdout_access_log(&TCP_LOG_FIELDS)],
+    };
+    Listener {
+        name: format!("{service_name}_tcp_listener_{port}"),
+        address: socket_address("0.0.0.0", port),
+        listener_filters: Vec::new(),
+        filter_chains: vec![FilterChain {
+            filter_chain_match: None,
+            filters: vec![NetworkFilter {
+                name: "envoy.filters.network.tcp_proxy",
+                typed_config: NetworkFilterConfig::TcpProxy(proxy),
+            }],
+            transport_socket: config.protocol.db_system().map(db_tap_socket),
+        }],
+    }
+}
+
+// Each connection is streamed to its own file as it happens, so statements on long-lived
+// pooled connections are decoded without waiting for the connection to close. Events are
+// never cropped: the decoder cannot resynchronise after a cut.
+fn db_tap_socket(system: DbSystem) -> TransportSocket {
+    TransportSocket {
+        name: "envoy.transport_sockets.tap",
+        typed_config: TransportSocketConfig::Tap {
+            common_config: TapCommonConfig {
+                static_config: TapStaticConfig {
+                    match_config: TapMatch {
+                        any: Some(true),
+                        ..TapMatch::default()
+                    },
+                    output_config: TapOutput {
+                        max_buffered_rx_bytes: DEFAULT_TAP_MAX_BYTES,
+                        max_buffered_tx_bytes: DEFAULT_TAP_MAX_BYTES,
+                        sinks: vec![TapSink {
+                            format: "JSON_BODY_AS_BYTES",
+                            file_per_tap: FilePerTap {
+                                path_prefix: format!(
+                                    "{TAP_DIR}/{DB_TAP_PREFIX}{}",
+                                    system.as_str()
+                                ),
+                            },
+                        }],
+                        streaming: true,
+                    },
+                },
+            },
+            transport_socket: Box::new(TransportSocket {
+                name: "envoy.transport_sockets.raw_buffer",
+                typed_config: TransportSocketConfig::RawBuffer,
+            }),
+        },
+    }
+}
+
+fn app_cluster(app_name: &str, config: &PortConfig, scaled: bool) -> Cluster {
+    let port = config.port;
+    let name = format!("{app_name}_{port}");
+    Cluster {
+        name: name.clone(),
+        connect_timeout: format_duration(config.tuning.connect_timeout_ms),
+        discovery_type: Some("STRICT_DNS"),
+        dns_refresh_rate: scaled.then(|| format_duration(1_000)),
+        lb_policy: "ROUND_ROBIN",
+        load_assignment: Some(LoadAssignment {
+            cluster_name: name,
+            endpoints: vec![LocalityEndpoints {
+                lb_endpoints: vec![LbEndpoint {
+                    endpoint: Endpoint {
+                        address: socket_address(app_name, port),
+                    },
+                }],
+            }],
+        }),
+        cluster_type: None,
+        typed_extension_protocol_options: None,
+        transport_socket: None,
+    }
+}
+
+fn http_connection_manager(manager: HttpConnectionManager) -> NetworkFilter {
+    NetworkFilter {
+        name: "envoy.filters.network.http_connection_manager",
+        typed_config: NetworkFilterConfig::HttpConnectionManager(Box::new(manager)),
+    }
+}
+
+fn tap_filter(tuning: &PortTuning) -> HttpFilter {
+    HttpFilter {
+        name: "envoy.filters.http.tap",
+        typed_config: HttpFilterConfig::Tap {
+            common_config: TapCommonConfig {
+                static_config: TapStaticConfig {
+                    match_config: tap_match(tuning),
+                    output_config: TapOutput {
+                        max_buffered_rx_bytes: tuning.tap_max_bytes,
+                        max_buffered_tx_bytes: tuning.tap_max_bytes,
+                        sinks: vec![TapSink {
+                            format: "JSON_BODY_AS_BYTES",
+                            file_per_tap: FilePerTap {
+                                path_prefix: TAP_PATH_PREFIX.to_string(),
+                            },
+                        }],
+                        streaming: false,
+                    },
+                },
+            },
+        },
+    }
+}
+
+// Sampling keys off the request id, which Envoy generates and forwards, so a sampled call
+// stays sampled on every hop it takes through the run.
+fn tap_match(tuning: &PortTuning) -> TapMatch {
+    let mut rules: Vec<TapMatch> = tuning
+        .tap_ignore_paths
+        .iter()
+        .map(|pattern| TapMatch::request_header(":path", ignored_path_regex(pattern)).not())
+        .collect();
+    if let Some(regex) = content_type_regex(&tuning.tap_skip_content_types, false) {
+        rules.push(TapMatch::request_header("content-type", regex).not());
+    }
+    // Requests without a body (and so without a content type) are still tapped for their
+    // response.
+    if let Some(regex) = content_type_regex(&tuning.tap_content_types, true) {
+        rules.push(TapMatch::request_header("content-type", regex).missing_header_as_empty());
+    }
+    if let Some(regex) = sample_regex(tuning.tap_sample_percent) {
+        rules.push(TapMatch::request_header("x-request-id", regex));
+    }
+    if rules.is_empty() {
+        TapMatch {
+            any: Some(true),
+            ..TapMatch::default()
+        }
+    } else {
+        TapMatch {
+            all: Some(TapMatchSet { rules }),
+            ..TapMatch::default()
+        }
+    }
+}
+
+fn ignored_path_regex(pattern: &str) -> String {
+    pattern.strip_suffix('*').map_or_else(
+        || format!("^{}([?#].*)?$", regex_lite::escape(pattern)),
+        |prefix| format!("^{}.*", regex_lite::escape(prefix)),
+    )
+}
+
+// Patterns are media types with `*` wildcards, matched case-insensitively before any parameters.
+fn content_type_regex(patterns: &[String], allow_empty: bool) -> Option<String> {
+    if patterns.is_empty() {
+        return None;
+    }
+    let mut options: Vec<String> = patterns
+        .iter()
+        .map(|pattern| {
+            pattern
+                .trim()
+                .split('*')
+                .map(regex_lite::escape)
+                .collect::<Vec<_>>()
+                .join(".*")
+        })
+        .collect();
+    if allow_empty {
+        options.push(String::new());
+    }
+    Some(format!("(?i)^\\s*({})\\s*(;.*)?$", options.join("|")))
+}
+
+fn sample_regex(percent: u32) -> Option<String> {
+    const HEX: &str = "0123456789abcdef";
+    if percent >= 100 {
+        return None;
+    }
+    // The last two hex digits of the id pick one of 256 buckets.
+    let threshold = (percent * 256).div_ceil(100);
+    let full = usize::try_from(threshold / 16).unwrap_or_default();
+    let partial = usize::try_from(threshold % 16).unwrap_or_default();
+    let mut options = Vec::new();
+    if full > 0 {
+        options.push(format!("[{}][0-9a-f]", HEX.get(..full).unwrap_or_default()));
+    }
+    if partial > 0 {
+        options.push(format!(
+            "{}[{}]",
+            HEX.get(full..=full).unwrap_or_default(),
+            HEX.get(..partial).unwrap_or_default()
+        ));
+    }
+    Some(format!("(?i)^.*({})$", options.join("|")))
+}
+
+fn fault_filter(rule: &str) -> HttpFilter {
+    let defaults = FaultSpec::default();
+    HttpFilter {
+        name: FAULT_FILTER,
+        typed_config: HttpFilterConfig::Fault(Box::new(FaultConfig {
+            delay: FaultDelay {
+                fixed_delay: format_duration(defaults.delay_ms.unwrap_or(1)),
+                percentage: FractionalPercent::percent(defaults.percent),
+            },
+            abort: FaultAbort {
+                http_status: defaults.abort_status.unwrap_or(503),
+                percentage: FractionalPercent::percent(defaults.percent),
+            },
+            delay_percent_runtime: runtime_key(rule, "delay_percent"),
+            delay_duration_runtime: runtime_key(rule, "delay_ms"),
+            abort_percent_runtime: runtime_key(rule, "abort_percent"),
+            abort_http_status_runtime: runtime_key(rule, "abort_status"),
+        })),
+    }
+}
+
+fn fault_reset_listener() -> Listener {
+    Listener {
+        name: "fault_reset_listener".to_string(),
+        address: socket_address("127.0.0.1", FAULT_RESET_PORT),
+        listener_filters: Vec::new(),
+        filter_chains: vec![FilterChain::plain(NetworkFilter {
+            name: "envoy.filters.network.direct_response",
+            typed_config: NetworkFilterConfig::DirectResponse {
+                response: InlineString {
+                    inline_string: FAULT_RESET_RESPONSE,
+                },
+            },
+        })],
+    }
+}
+
+fn fault_reset_cluster() -> Cluster {
+    static_cluster(FAULT_RESET_CLUSTER, FAULT_RESET_PORT)
+}
+
+fn static_cluster(name: &str, port: u16) -> Cluster {
+    Cluster {
+        name: name.to_string(),
+        connect_timeout: format_duration(DEFAULT_CONNECT_TIMEOUT_MS),
+        discovery_type: Some("STATIC"),
+        dns_refresh_rate: None,
+        lb_policy: "ROUND_ROBIN",
+        load_assignment: Some(LoadAssignment {
+            cluster_name: name.to_string(),
+            endpoints: vec![LocalityEndpoints {
+                lb_endpoints: vec![LbEndpoint {
+                    endpoint: Endpoint {
+                        address: socket_address("127.0.0.1", port),
+                    },
+                }],
+            }],
+        }),
+        cluster_type: None,
+        typed_extension_protocol_options: None,
+        transport_socket: None,
+    }
+}
+
+const fn router_filter() -> HttpFilter {
+    HttpFilter {
+        name: "envoy.filters.http.router",
+        typed_config: HttpFilterConfig::Router,
+    }
+}
+
+const fn egress_dns_cache() -> DnsCacheConfig {
+    DnsCacheConfig {
+        name: "egress_cache",
+        dns_lookup_family: "V4_ONLY",
+    }
+}
+
+const TCP_LOG_FIELDS: [(&str, &str); 6] = [
+    ("timestamp", "%START_TIME%"),
+    ("duration_ms", "%DURATION%"),
+    ("downstream_remote_address", "%DOWNSTREAM_REMOTE_ADDRESS%"),
+    ("upstream_host", "%UPSTREAM_HOST%"),
+    ("bytes_received", "%BYTES_RECEIVED%"),
+    ("bytes_sent", "%BYTES_SENT%"),
+];
+
+const HTTP_LOG_FIELDS: [(&str, &str); 20] = [
+    ("method", "%REQ(:METHOD)%"),
+    ("path", "%REQ(X-ENVOY-ORIGINAL-PATH?:PATH)%"),
+    ("response_code", "%RESPONSE_CODE%"),
+    ("request_id", "%REQ(X-REQUEST-ID)%"),
+    ("request_user_agent", "%REQ(USER-AGENT)%"),
+    ("request_content_type", "%REQ(CONTENT-TYPE)%"),
+    ("request_accept", "%REQ(ACCEPT)%"),
+    ("request_body", "%DYNAMIC_METADATA(sanelens:request_body)%"),
+    ("request_forwarded_for", "%REQ(X-FORWARDED-FOR)%"),
+    ("request_forwarded_proto", "%REQ(X-FORWARDED-PROTO)%"),
+    ("response_content_type", "%RESP(CONTENT-TYPE)%"),
+    ("response_content_length", "%RESP(CONTENT-LENGTH)%"),
+    (
+        "response_body",
+        "%DYNAMIC_METADATA(sanelens:response_body)%",
+    ),
+    ("traceparent", "%REQ(TRACEPARENT)%"),
+    ("tracestate", "%REQ(TRACESTATE)%"),
+    ("b3", "%REQ(B3)%"),
+    ("x-b3-traceid", "%REQ(X-B3-TRACEID)%"),
+    ("x-b3-spanid", "%REQ(X-B3-SPANID)%"),
+    ("x-b3-parentspanid", "%REQ(X-B3-PARENTSPANID)%"),
+    ("x-b3-sampled", "%REQ(X-B3-SAMPLED)%"),
+];
+
+fn http_log_fields(extra: &[(&'static str, &'static str)]) -> Vec<(&'static str, &'static str)> {
+    let mut fields: Vec<_> = TCP_LOG_FIELDS.to_vec();
+    fields.extend(HTTP_LOG_FIELDS);
+    fields.extend_from_slice(extra);
+    fields
+}
+
+fn stdout_access_log(fields: &[(&'static str, &'static str)]) -> AccessLog {
+    AccessLog {
+        name: "envoy.access_loggers.stdout",
+        typed_config: AccessLogConfig::Stdout {
+            log_format: LogFormat {
+                json_format: fields.iter().copied().collect(),
+            },
+        },
+    }
+}
+
+fn socket_address(address: &str, port: u16) -> Address {
+    Address {
+        socket_address: SocketAddress {
+            address: address.to_string(),
+            port_value: port,
+        },
+    }
+}
+
+fn admin() -> Admin {
+    Admin {
+        access_log_path: "/tmp/envoy_admin.log",
+        address: socket_address("0.0.0.0", ADMIN_PORT),
+    }
+}
+
+fn format_duration(ms: u64) -> String {
+    if ms.is_multiple_of(1_000) {
+        format!("{}s", ms / 1_000)
+    } else {
+        format!("{}.{:03}s", ms / 1_000, ms % 1_000)
+    }
+}
+
+pub fn parse_duration_ms(raw: &str) -> Result<u64, String> {
+    const UNITS: [(&str, u64); 3] = [("ms", 1), ("s", 1_000), ("m", 60_000)];
+    scaled_number(raw.trim(), &UNITS, 1_000).ok_or_else(|| format!("invalid duration '{raw}'"))
+}
+
+fn parse_sample_percent(raw: &str) -> Result<u32, String> {
+    raw.trim()
+        .trim_end_matches('%')
+        .trim()
+        .parse()
+        .ok()
+        .filter(|percent| (1..=100).contains(percent))
+        .ok_or_else(|| format!("invalid sample percentage '{raw}'"))
+}
+
+pub fn parse_byte_size(raw: &str) -> Result<u64, String> {
+    const UNITS: [(&str, u64); 3] = [("kb", 1024), ("mb", 1024 * 1024), ("b", 1)];
+    scaled_number(&raw.trim().to_lowercase(), &UNITS, 1)
+        .ok_or_else(|| format!("invalid byte size '{raw}'"))
+}
+
+// Envoy keeps the tap buffer limits as 32-bit values and refuses larger ones at startup.
+fn parse_tap_max_bytes(raw: &str) -> Result<u64, String> {
+    let bytes = parse_byte_size(raw)?;
+    if bytes > u64::from(u32::MAX) {
+        return Err(format!(
+            "tap_max_bytes '{raw}' is above the {} byte limit",
+            u32::MAX
+        ));
+    }
+    Ok(bytes)
+}
+
+fn scaled_number(value: &str, units: &[(&str, u64)], default_scale: u64) -> Option<u64> {
+    let (number, scale) = units
+        .iter()
+        .find_map(|(suffix, scale)| value.strip_suffix(suffix).map(|number| (number, *scale)))
+        .unwrap_or((value, default_scale));
+    number.trim().parse::<u64>().ok()?.checked_mul(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::Value;
+
+    use super::{
+        egress_bootstrap, ingress_bootstrap, tap_match, Bootstrap, CanaryRoute, CanarySplit,
+        EgressOptions, IngressOptions, InterceptHost, PortConfig, PortTuning, ProxyProtocol,
+        VcrProxy, FAULT_RESET_PORT,
+    };
+    use crate::infra::fault::{FaultRule, FaultSpec};
+    use crate::support::egress_policy::EgressPolicy;
+
+    fn to_value(bootstrap: &Bootstrap) -> Value {
+        serde_yaml::to_value(bootstrap).unwrap_or_default()
+    }
+
+    fn lookup<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+        path.iter().try_fold(value, |current, segment| {
+            segment
+                .parse::<usize>()
+                .map_or_else(|_| current.get(*segment), |index| current.get(index))
+        })
+    }
+
+    fn port(port: u16, protocol: ProxyProtocol, tuning: PortTuning) -> PortConfig {
+        PortConfig {
+            port,
+            protocol,
+            tuning,
+        }
+    }
+
+    const HCM: [&str; 7] = [
+        "static_resources",
+        "listeners",
+        "0",
+        "filter_chains",
+        "0",
+        "filters",
+        "0",
+    ];
+
+    #[test]
+    fn http_port_defaults() {
+        let config = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &[port(8080, ProxyProtocol::Http, PortTuning::default())],
+            IngressOptions::default(),
+        ));
+        let manager = lookup(&config, &HCM).and_then(|filter| filter.get("typed_config"));
+        assert_eq!(
+            manager
+                .and_then(|value| value.get("@type"))
+                .and_then(Value::as_str),
+            Some("type.googleapis.com/envoy.extensions.filters.network.http_connection_manager.v3.HttpConnectionManager")
+        );
+        let route = manager.and_then(|value| {
+            lookup(
+                value,
+                &["route_config", "virtual_hosts", "0", "routes", "0", "route"],
+            )
+        });
+        assert_eq!(
+            route
+                .and_then(|value| value.get("cluster"))
+                .and_then(Value::as_str),
+            Some("api-app_8080")
+        );
+        assert!(route.and_then(|value| value.get("timeout")).is_none());
+        let tap_bytes = manager.and_then(|value| {
+            lookup(
+                value,
+                &[
+                    "http_filters",
+                    "0",
+                    "typed_config",
+                    "common_config",
+                    "static_config",
+                    "output_config",
+                    "max_buffered_rx_bytes",
+                ],
+            )
+        });
+        assert_eq!(tap_bytes.and_then(Value::as_u64), Some(10_485_760));
+        let cluster = lookup(&config, &["static_resources", "clusters", "0"]);
+        assert_eq!(
+            cluster
+                .and_then(|value| value.get("connect_timeout"))
+                .and_then(Value::as_str),
+            Some("2s")
+        );
+        assert!(cluster
+            .and_then(|value| value.get("dns_refresh_rate"))
+            .is_none());
+    }
+
+    #[test]
+    fn tap_max_bytes_fits_envoy_buffer_limits() {
+        let mut tuning = PortTuning::default();
+        assert_eq!(tuning.set("tap_max_bytes", "4294967295"), Ok(()));
+        assert_eq!(tuning.tap_max_bytes, u64::from(u32::MAX));
+        assert!(tuning.set("tap_max_bytes", "4096mb").is_err());
+        assert!(tuning.set("tap_max_bytes", "4294967296").is_err());
+        assert_eq!(tuning.tap_max_bytes, u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn http_port_tuning() {
+        let mut tuning = PortTuning::default();
+        for (key, value) in [
+            ("connect_timeout", "250ms"),
+            ("timeout", "30s"),
+            ("idle_timeout", "5m"),
+            ("retries", "2"),
+            ("tap_max_bytes", "64kb"),
+        ] {
+            assert_eq!(tuning.set(key, value), Ok(()));
+        }
+        let config = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &[port(3000, ProxyProtocol::Http, tuning)],
+            IngressOptions {
+                scaled: true,
+                ..IngressOptions::default()
+            },
+        ));
+        let manager = lookup(&config, &HCM).and_then(|filter| filter.get("typed_config"));
+        let route = manager.and_then(|value| {
+            lookup(
+                value,
+                &["route_config", "virtual_hosts", "0", "routes", "0", "route"],
+            )
+        });
+        assert_eq!(
+            route
+                .and_then(|value| value.get("timeout"))
+                .and_then(Value::as_str),
+            Some("30s")
+        );
+        assert_eq!(
+            route
+                .and_then(|value| lookup(value, &["retry_policy", "num_retries"]))
+                .and_then(Value::as_u64),
+            Some(2)
+        );
+        assert_eq!(
+            manager
+                .and_then(|value| lookup(value, &["common_http_protocol_options", "idle_timeout"]))
+                .and_then(Value::as_str),
+            Some("300s")
+        );
+        let cluster = lookup(&config, &["static_resources", "clusters", "0"]);
+        assert_eq!(
+            cluster
+                .and_then(|value| value.get("connect_timeout"))
+                .and_then(Value::as_str),
+            Some("0.250s")
+        );
+        assert_eq!(
+            cluster
+                .and_then(|value| value.get("dns_refresh_rate"))
+                .and_then(Value::as_str),
+            Some("1s")
+        );
+    }
+
+    #[test]
+    fn reset_fault_cuts_the_response_after_its_headers() {
+        let config = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &[port(8080, ProxyProtocol::Http, PortTuning::default())],
+            IngressOptions::default(),
+        ));
+        let listener = lookup(&config, &["static_resources", "listeners", "1"]);
+        assert_eq!(
+            listener
+                .and_then(|value| lookup(value, &["address", "socket_address", "port_value"]))
+                .and_then(Value::as_u64),
+            Some(u64::from(FAULT_RESET_PORT))
+        );
+        let response = listener
+            .and_then(|value| {
+                lookup(
+                    value,
+                    &[
+                        "filter_chains",
+                        "0",
+                        "filters",
+                        "0",
+                        "typed_config",
+                        "response",
+                    ],
+                )
+            })
+            .and_then(|value| value.get("inline_string"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        assert!(response.starts_with("HTTP/1.1 503"));
+        assert!(response.ends_with("\r\n\r\n"));
+        let clusters = lookup(&config, &["static_resources", "clusters"])
+            .and_then(Value::as_sequence)
+            .cloned()
+            .unwrap_or_default();
+        assert!(clusters
+            .iter()
+            .any(|cluster| cluster.get("name").and_then(Value::as_str) == Some("fault_reset")));
+    }
+
+    #[test]
+    fn http_faults_use_runtime_keys() {
+        let spec = FaultSpec::parse(Some("500ms"), None, false, Some("20")).unwrap_or_default();
+        let faults = [FaultRule {
+            path: Some("/pay".to_string()),
+            spec,
+        }];
+        let config = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &[port(8080, ProxyProtocol::Http, PortTuning::default())],
+            IngressOptions {
+                faults: &faults,
+                ..IngressOptions::default()
+            },
+        ));
+        let manager = lookup(&config, &HCM).and_then(|filter| filter.get("typed_config"));
+        let routes = manager
+            .and_then(|value| lookup(value, &["route_config", "virtual_hosts", "0", "routes"]));
+        let route_value = |index: &str, path: &[&str]| {
+            routes
+                .and_then(|routes| lookup(routes, &[index]))
+                .and_then(|route| lookup(route, path))
+                .and_then(|value| value.as_str().map(str::to_string))
+        };
+        assert_eq!(
+            route_value("0", &["match", "prefix"]).as_deref(),
+            Some("/pay")
+        );
+        assert_eq!(
+            route_value(
+                "0",
+                &[
+                    "typed_per_filter_config",
+                    "envoy.filters.http.fault",
+                    "delay_duration_runtime"
+                ]
+            )
+            .as_deref(),
+            Some("sanelens.fault.route0.delay_ms")
+        );
+        assert_eq!(
+            route_value("1", &["route", "cluster"]).as_deref(),
+            Some("fault_reset")
+        );
+        assert_eq!(
+            route_value("2", &["match", "runtime_fraction", "runtime_key"]).as_deref(),
+            Some("sanelens.fault.all.pass_percent")
+        );
+        let layer = lookup(&config, &["layered_runtime", "layers", "0", "static_layer"]);
+        assert_eq!(
+            layer
+                .and_then(|layer| layer.get("sanelens.fault.route0.delay_percent"))
+                .and_then(Value::as_u64),
+            Some(20)
+        );
+        assert_eq!(
+            layer
+                .and_then(|layer| layer.get("sanelens.fault.all.delay_percent"))
+                .and_then(Value::as_u64),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn fault_kind_comes_from_what_fired() {
+        let config = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &[port(8080, ProxyProtocol::Http, PortTuning::default())],
+            IngressOptions::default(),
+        ));
+        let manager = lookup(&config, &HCM).and_then(|filter| filter.get("typed_config"));
+        let fault = manager.and_then(|value| lookup(value, &["http_filters", "1", "typed_config"]));
+        assert!(fault.is_some());
+        assert!(fault
+            .and_then(|value| value.get("filter_metadata"))
+            .is_none());
+        let format = manager.and_then(|value| {
+            lookup(
+                value,
+                &[
+                    "access_log",
+                    "0",
+                    "typed_config",
+                    "log_format",
+                    "json_format",
+                ],
+            )
+        });
+        assert_eq!(
+            format
+                .and_then(|value| value.get("response_flags"))
+                .and_then(Value::as_str),
+            Some("%RESPONSE_FLAGS%")
+        );
+    }
+
+    #[test]
+    fn tcp_port_tuning() {
+        let mut tuning = PortTuning::default();
+        assert_eq!(tuning.set("idle_timeout", "90"), Ok(()));
+        assert_eq!(tuning.set("retries", "3"), Ok(()));
+        let config = to_value(&ingress_bootstrap(
+            "db",
+            "db-app",
+            &[port(5432, ProxyProtocol::Tcp, tuning)],
+            IngressOptions::default(),
+        ));
+        let proxy = lookup(&config, &HCM).and_then(|filter| filter.get("typed_config"));
+        assert_eq!(
+            proxy
+                .and_then(|value| value.get("@type"))
+                .and_then(Value::as_str),
+            Some("type.googleapis.com/envoy.extensions.filters.network.tcp_proxy.v3.TcpProxy")
+        );
+        assert_eq!(
+            proxy
+                .and_then(|value| value.get("cluster"))
+                .and_then(Value::as_str),
+            Some("db-app_5432")
+        );
+        assert_eq!(
+            proxy
+                .and_then(|value| value.get("idle_timeout"))
+                .and_then(Value::as_str),
+            Some("90s")
+        );
+        assert_eq!(
+            proxy
+                .and_then(|value| value.get("max_connect_attempts"))
+                .and_then(Value::as_u64),
+            Some(4)
+        );
+        let chain = lookup(
+            &config,
+            &["static_resources", "listeners", "0", "filter_chains", "0"],
+        );
+        assert!(chain
+            .and_then(|value| value.get("transport_socket"))
+            .is_none());
+    }
+
+    #[test]
+    fn database_ports_stream_a_socket_tap() {
+        let config = to_value(&ingress_bootstrap(
+            "cache",
+            "cache-app",
+            &[port(6379, ProxyProtocol::Redis, PortTuning::default())],
+            IngressOptions::default(),
+        ));
+        assert_eq!(
+            lookup(&config, &HCM)
+                .and_then(|filter| filter.get("name"))
+                .and_then(Value::as_str),
+            Some("envoy.filters.network.tcp_proxy")
+        );
+        let socket = lookup(
+            &config,
+            &[
+                "static_resources",
+                "listeners",
+                "0",
+                "filter_chains",
+                "0",
+                "transport_socket",
+                "typed_config",
+            ],
+        );
+        let output = socket
+            .and_then(|value| lookup(value, &["common_config", "static_config", "output_config"]));
+        assert_eq!(
+            output
+                .and_then(|value| value.get("streaming"))
+                .and_then(Value::as_bool),
+            Some(true)
+        );
+        assert_eq!(
+            output
+                .and_then(|value| lookup(value, &["sinks", "0", "file_per_tap", "path_prefix"]))
+                .and_then(Value::as_str),
+            Some("/sanelens/tap/db_redis")
+        );
+        assert_eq!(
+            socket
+                .and_then(|value| lookup(value, &["transport_socket", "name"]))
+                .and_then(Value::as_str),
+            Some("envoy.transport_sockets.raw_buffer")
+        );
+    }
+
+    #[test]
+    fn egress_intercepts_configured_hosts() {
+        let intercept = [InterceptHost {
+            host: "api.stripe.com".to_string(),
+            cert_path: "/sanelens/tls/api.stripe.com.pem".to_string(),
+            key_path: "/sanelens/tls/api.stripe.com.key.pem".to_string(),
+        }];
+        let config = to_value(&egress_bootstrap(&EgressOptions {
+            intercept: &intercept,
+            ..EgressOptions::default()
+        }));
+        let connect_host = lookup(&config, &HCM).and_then(|filter| {
+            lookup(
+                filter,
+                &["typed_config", "route_config", "virtual_hosts", "0"],
+            )
+        });
+        assert_eq!(
+            connect_host
+                .and_then(|host| lookup(host, &["domains", "1"]))
+                .and_then(Value::as_str),
+            Some("api.stripe.com:443")
+        );
+        assert_eq!(
+            connect_host
+                .and_then(|host| lookup(host, &["routes", "0", "route", "cluster"]))
+                .and_then(Value::as_str),
+            Some("egress_tls_loopback")
+        );
+        let tls_chain = lookup(
+            &config,
+            &["static_resources", "listeners", "1", "filter_chains", "0"],
+        );
+        assert_eq!(
+            tls_chain
+                .and_then(|chain| lookup(chain, &["filter_chain_match", "server_names", "0"]))
+                .and_then(Value::as_str),
+            Some("api.stripe.com")
+        );
+        assert_eq!(
+            tls_chain
+                .and_then(|chain| {
+                    lookup(
+                        chain,
+                        &[
+                            "transport_socket",
+                            "typed_config",
+                            "common_tls_context",
+                            "tls_certificates",
+                            "0",
+                            "private_key",
+                            "filename",
+                        ],
+                    )
+                })
+                .and_then(Value::as_str),
+            Some("/sanelens/tls/api.stripe.com.key.pem")
+        );
+        let upstream = lookup(&config, &["static_resources", "clusters", "2"]);
+        assert!(upstream
+            .and_then(|cluster| cluster.get("transport_socket"))
+            .is_some());
+    }
+
+    #[test]
+    fn transparent_egress_uses_original_destination() {
+        let config = to_value(&egress_bootstrap(&EgressOptions {
+            transparent: true,
+            ..EgressOptions::default()
+        }));
+        let listener = lookup(&config, &["static_resources", "listeners", "1"]);
+        assert_eq!(
+            listener
+                .and_then(|value| lookup(value, &["address", "socket_address", "port_value"]))
+                .and_then(Value::as_u64),
+            Some(15006)
+        );
+        assert_eq!(
+            listener
+                .and_then(|value| lookup(value, &["listener_filters", "0", "name"]))
+                .and_then(Value::as_str),
+            Some("envoy.filters.listener.original_dst")
+        );
+        assert_eq!(
+            listener
+                .and_then(|value| lookup(value, &["filter_chains", "1", "filters", "0", "name"]))
+                .and_then(Value::as_str),
+            Some("envoy.filters.network.tcp_proxy")
+        );
+        let cluster = lookup(&config, &["static_resources", "clusters", "1"]);
+        assert_eq!(
+            cluster
+                .and_then(|value| value.get("type"))
+                .and_then(Value::as_str),
+            Some("ORIGINAL_DST")
+        );
+    }
+
+    #[test]
+    fn transparent_policy_hosts_are_matched_on_sni() {
+        let policy = EgressPolicy::parse("allow", &["api.stripe.com".to_string()]).ok();
+        let config = to_value(&egress_bootstrap(&EgressOptions {
+            transparent: true,
+            policy: policy.as_ref(),
+            ..EgressOptions::default()
+        }));
+        let listener = lookup(&config, &["static_resources", "listeners", "1"]);
+        let chains = listener
+            .and_then(|value| value.get("filter_chains"))
+            .and_then(Value::as_sequence)
+            .cloned()
+            .unwrap_or_default();
+        assert!(chains.iter().any(|chain| {
+            lookup(chain, &["filter_chain_match", "server_names", "0"]).and_then(Value::as_str)
+                == Some("api.stripe.com")
+        }));
+        let filters = listener
+            .and_then(|value| value.get("listener_filters"))
+            .and_then(Value::as_sequence)
+            .cloned()
+            .unwrap_or_default();
+        assert!(filters.iter().any(|filter| {
+            filter.get("name").and_then(Value::as_str)
+                == Some("envoy.filters.listener.tls_inspector")
+        }));
+    }
+
+    #[test]
+    fn egress_policy_blocks_unlisted_hosts() {
+        let policy = EgressPolicy::parse("allow", &["api.stripe.com".to_string()]).ok();
+        let config = to_value(&egress_bootstrap(&EgressOptions {
+            policy: policy.as_ref(),
+            ..EgressOptions::default()
+        }));
+        let routes = lookup(&config, &HCM).and_then(|filter| {
+            lookup(
+                filter,
+                &[
+                    "typed_config",
+                    "route_config",
+                    "virtual_hosts",
+                    "0",
+                    "routes",
+                ],
+            )
+        });
+        let route = |index: &str, path: &[&str]| {
+            routes
+                .and_then(|routes| routes.get(index.parse::<usize>().unwrap_or_default()))
+                .and_then(|route| lookup(route, path))
+                .cloned()
+        };
+        assert_eq!(
+            route("0", &["route", "cluster"]).and_then(|value| value.as_str().map(str::to_string)),
+            Some("egress_cluster".to_string())
+        );
+        assert_eq!(
+            route("1", &["direct_response", "status"]).and_then(|value| value.as_u64()),
+            Some(403)
+        );
+        assert_eq!(
+            route("2", &["match", "headers", "0", "invert_match"])
+                .and_then(|value| value.as_bool()),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn egress_replay_routes_to_host_server() {
+        let config = to_value(&egress_bootstrap(&EgressOptions {
+            vcr: Some(VcrProxy::Replay {
+                host: "host.docker.internal",
+                port: 4100,
+            }),
+            ..EgressOptions::default()
+        }));
+        let cluster = lookup(&config, &HCM).and_then(|filter| {
+            lookup(
+                filter,
+                &[
+                    "typed_config",
+                    "route_config",
+                    "virtual_hosts",
+                    "0",
+                    "routes",
+                    "0",
+                    "route",
+                    "cluster",
+                ],
+            )
+        });
+        assert_eq!(cluster.and_then(Value::as_str), Some("vcr_replay"));
+        let port = lookup(
+            &config,
+            &[
+                "static_resources",
+                "clusters",
+                "1",
+                "load_assignment",
+                "endpoints",
+                "0",
+                "lb_endpoints",
+                "0",
+                "endpoint",
+                "address",
+                "socket_address",
+                "port_value",
+            ],
+        );
+        assert_eq!(port.and_then(Value::as_u64), Some(4100));
+    }
+
+    #[test]
+    fn egress_uses_dynamic_forward_proxy() {
+        let config = to_value(&egress_bootstrap(&EgressOptions::default()));
+        let listener_port = lookup(
+            &config,
+            &[
+                "static_resources",
+                "listeners",
+                "0",
+                "address",
+                "socket_address",
+                "port_value",
+            ],
+        );
+        assert_eq!(listener_port.and_then(Value::as_u64), Some(15001));
+        let cluster = lookup(&config, &["static_resources", "clusters", "0"]);
+        assert_eq!(
+            cluster
+                .and_then(|value| value.get("lb_policy"))
+                .and_then(Value::as_str),
+            Some("CLUSTER_PROVIDED")
+        );
+        assert_eq!(
+            cluster
+                .and_then(|value| lookup(value, &["cluster_type", "name"]))
+                .and_then(Value::as_str),
+            Some("envoy.clusters.dynamic_forward_proxy")
+        );
+        let route_timeout = lookup(&config, &HCM).and_then(|filter| {
+            lookup(
+                filter,
+                &[
+                    "typed_config",
+                    "route_config",
+                    "virtual_hosts",
+                    "0",
+                    "routes",
+                    "0",
+                    "route",
+                    "timeout",
+                ],
+            )
+        });
+        assert_eq!(route_timeout.and_then(Value::as_str), Some("0s"));
+    }
+
+    #[test]
+    fn invalid_tuning_values_are_rejected() {
+        let mut tuning = PortTuning::default();
+        assert!(tuning.set("timeout", "soon").is_err());
+        assert!(tuning.set("retries", "-1").is_err());
+        assert!(tuning.set("buffer", "1").is_err());
+        assert_eq!(tuning, PortTuning::default());
+    }
+
+    fn canary_ports() -> [PortConfig; 2] {
+        [
+            port(8080, ProxyProtocol::Http, PortTuning::default()),
+            port(9000, ProxyProtocol::Tcp, PortTuning::default()),
+        ]
+    }
+
+    const fn canary(split: CanarySplit) -> IngressOptions<'static> {
+        IngressOptions {
+            scaled: false,
+            faults: &[],
+            canary: Some(CanaryRoute {
+                app_name: "api-canary",
+                split,
+            }),
+        }
+    }
+
+    #[test]
+    fn canary_weight_splits_http_and_tcp() {
+        let weighted = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &canary_ports(),
+            canary(CanarySplit::Weight(20)),
+        ));
+        let manager = lookup(&weighted, &HCM).and_then(|filter| filter.get("typed_config"));
+        let clusters = manager.and_then(|value| {
+            lookup(
+                value,
+                &[
+                    "route_config",
+                    "virtual_hosts",
+                    "0",
+                    "routes",
+                    "0",
+                    "route",
+                    "weighted_clusters",
+                    "clusters",
+                ],
+            )
+        });
+        assert_eq!(
+            clusters
+                .and_then(|value| lookup(value, &["1", "name"]))
+                .and_then(Value::as_str),
+            Some("api-canary_8080")
+        );
+        assert_eq!(
+            clusters
+                .and_then(|value| lookup(value, &["0", "weight"]))
+                .and_then(Value::as_u64),
+            Some(80)
+        );
+        let tcp = lookup(
+            &weighted,
+            &[
+                "static_resources",
+                "listeners",
+                "1",
+                "filter_chains",
+                "0",
+                "filters",
+                "0",
+                "typed_config",
+            ],
+        );
+        assert!(tcp
+            .and_then(|value| value.get("weighted_clusters"))
+            .is_some());
+    }
+
+    #[test]
+    fn canary_mirror_uses_loopback_listener() {
+        let mirrored = to_value(&ingress_bootstrap(
+            "api",
+            "api-app",
+            &canary_ports(),
+            canary(CanarySplit::Mirror(10)),
+        ));
+        let listeners = lookup(&mirrored, &["static_resources", "listeners"]);
+        assert_eq!(
+            listeners
+                .and_then(|value| lookup(value, &["0", "address", "socket_address", "port_value"]))
+                .and_then(Value::as_u64),
+            Some(15100)
+        );
+        let policy = listeners.and_then(|value| {
+            lookup(
+                value,
+                &[
+                    "1",
+                    "filter_chains",
+                    "0",
+                    "filters",
+                    "0",
+                    "typed_config",
+                    "route_config",
+                    "virtual_hosts",
+                    "0",
+                    "routes",
+                    "0",
+                    "route",
+                    "request_mirror_policies",
+                    "0",
+                ],
+            )
+        });
+        assert_eq!(
+            policy
+                .and_then(|value| value.get("cluster"))
+                .and_then(Value::as_str),
+            Some("api_mirror_8080")
+        );
+        assert_eq!(
+            policy
+                .and_then(|value| lookup(
+                    value,
+                    &["runtime_fraction", "default_value", "numerator"]
+                ))
+                .and_then(Value::as_u64),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn tap_match_skips_paths_and_samples() {
+        let mut tuning = PortTuning::default();
+        assert_eq!(tuning.set("tap_sample", "10%"), Ok(()));
+        assert!(tuning.set("tap_sample", "0").is_err());
+        tuning.tap_ignore_paths = vec!["/health".to_string(), "/metrics/*".to_string()];
+        let config = serde_yaml::to_value(tap_match(&tuning)).unwrap_or_default();
+        let regex = |path: &[&str]| {
+            lookup(&config, path)
+                .and_then(|value| value.get("string_match"))
+                .and_then(|value| value.get("safe_regex"))
+                .and_then(|value| value.get("regex"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        };
+        assert_eq!(
+            regex(&[
+                "and_match",
+                "rules",
+                "0",
+                "not_match",
+                "http_request_headers_match",
+                "headers",
+                "0"
+            ]),
+            Some("^/health([?#].*)?$".to_string())
+        );
+        assert_eq!(
+            regex(&[
+                "and_match",
+                "rules",
+                "2",
+                "http_request_headers_match",
+                "headers",
+                "0"
+            ]),
+            Some("(?i)^.*([0][0-9a-f]|1[0123456789])$".to_string())
+        );
+        let defaults = serde_yaml::to_value(tap_match(&PortTuning::default())).unwrap_or_default();
+        assert_eq!(
+            defaults.get("any_match").and_then(Value::as_bool),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn tap_match_filters_request_content_types() {
+        let tuning = PortTuning {
+            tap_content_types: vec!["application/*".to_string(), "text/plain".to_string()],
+            tap_skip_content_types: vec!["application/octet-stream".to_string()],
+            ..PortTuning::default()
+        };
+        let config = serde_yaml::to_value(tap_match(&tuning)).unwrap_or_default();
+        let skipped = lookup(
+            &config,
+            &[
+                "and_match",
+                "rules",
+                "0",
+                "not_match",
+                "http_request_headers_match",
+                "headers",
+                "0",
+            ],
+        );
+        assert_eq!(
+            skipped
+                .and_then(|header| header.get("name"))
+                .and_then(Value::as_str),
+            Some("content-type")
+        );
+        assert_eq!(
+            skipped
+                .and_then(|header| header.get("string_match"))
+                .and_then(|value| value.get("safe_regex"))
+                .and_then(|value| value.get("regex"))
+                .and_then(Value::as_str),
+            Some(r"(?i)^\s*(application/octet\-stream)\s*(;.*)?$")
+        );
+        let kept = lookup(
+            &config,
+            &[
+                "and_match",
+                "rules",
+                "1",
+                "http_request_headers_match",
+                "headers",
+                "0",
+            ],
+        );
+        assert_eq!(
+            kept.and_then(|header| header.get("string_match"))
+                .and_then(|value| value.get("safe_regex"))
+                .and_then(|value| value.get("regex"))
+                .and_then(Value::as_str),
+            Some(r"(?i)^\s*(application/.*|text/plain|)\s*(;.*)?$")
+        );
+        assert_eq!(
+            kept.and_then(|header| header.get("treat_missing_header_as_empty"))
+                .and_then(Value::as_bool),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::infra::envoy::{parse_byte_size, CanarySplit, PortTuning, TUNING_KEYS};
+use crate::infra::fault::{FaultRule, FaultSpec};
+use crate::support::capture::validate_body_patterns;
+use crate::support::egress_policy::EgressPolicy;
+
+pub const EXTENSION_KEY: &str = "x-sanelens";
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyMode {
+    Auto,
+    Http,
+    Tcp,
+    #[serde(alias = "postgresql")]
+    Postgres,
+    #[serde(alias = "mariadb")]
+    Mysql,
+    Redis,
+    Off,
+}
+
+impl ProxyMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "auto" | "true" => Some(Self::Auto),
+            "http" => Some(Self::Http),
+            "tcp" => Some(Self::Tcp),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            "mysql" | "mariadb" => Some(Self::Mysql),
+            "redis" => Some(Self::Redis),
+            "off" | "false" => Some(Self::Off),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Setting {
+    Number(u64),
+    Text(String),
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(value) => write!(f, "{value}"),
+            Self::Text(value) => f.write_str(value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PortExtension {
+    pub proxy: Option<ProxyMode>,
+    pub capture_bodies: Option<bool>,
+    pub connect_timeout: Option<Setting>,
+    pub timeout: Option<Setting>,
+    pub idle_timeout: Option<Setting>,
+    pub retries: Option<Setting>,
+    pub max_body_bytes: Option<Setting>,
+    pub sample: Option<Setting>,
+}
+
+impl PortExtension {
+    pub fn apply_tuning(&self, tuning: &mut PortTuning) -> Result<(), String> {
+        apply_tuning(
+            [
+                &self.connect_timeout,
+                &self.timeout,
+                &self.idle_timeout,
+                &self.retries,
+                &self.max_body_bytes,
+                &self.sample,
+            ],
+            tuning,
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EgressPolicyExtension {
+    pub mode: Option<String>,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FaultExtension {
+    pub path: Option<String>,
+    pub delay: Option<Setting>,
+    pub abort: Option<u16>,
+    #[serde(default)]
+    pub reset: bool,
+    pub pct: Option<Setting>,
+}
+
+impl FaultExtension {
+    fn rule(&self) -> Result<FaultRule, String> {
+        let spec = FaultSpec::parse(
+            self.delay.as_ref().map(ToString::to_string).as_deref(),
+            self.abort.map(|status| status.to_string()).as_deref(),
+            self.reset,
+            self.pct.as_ref().map(ToString::to_string).as_deref(),
+        )?;
+        FaultRule::new(self.path.clone(), spec)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CanaryExtension {
+    pub image: Option<String>,
+    pub build: Option<Value>,
+    pub command: Option<Value>,
+    pub environment: Option<BTreeMap<String, Setting>>,
+    pub mirror: Option<Setting>,
+    pub weight: Option<Setting>,
+}
+
+impl CanaryExtension {
+    pub fn split(&self) -> Result<CanarySplit, String> {
+        match (&self.mirror, &self.weight) {
+            (Some(mirror), None) => parse_share(mirror, 1..=100).map(CanarySplit::Mirror),
+            (None, Some(weight)) => parse_share(weight, 1..=99).map(CanarySplit::Weight),
+            _ => Err("canary needs exactly one of `mirror` or `weight`".to_string()),
+        }
+    }
+}
+
+fn parse_share(value: &Setting, range: RangeInclusive<u32>) -> Result<u32, String> {
+    let raw = value.to_string();
+    raw.trim()
+        .trim_end_matches('%')
+        .parse::<u32>()
+        .ok()
+        .filter(|share| range.contains(share))
+        .ok_or_else(|| format!("invalid canary percentage '{raw}'"))
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceExtension {
+    pub name: Option<String>,
+    pub proxy: Option<ProxyMode>,
+    #[serde(default)]
+    pub ports: BTreeMap<u16, PortExtension>,
+    pub capture_bodies: Option<bool>,
+    pub redact_headers: Option<Vec<String>>,
+    pub redact_json: Option<Vec<String>>,
+    pub redact_body: Option<Vec<String>>,
+    pub ignore_paths: Option<Vec<String>>,
+    pub content_types: Option<Vec<String>>,
+    pub skip_content_types: Option<Vec<String>>,
+    pub body_preview_bytes: Option<Setting>,
+    pub routes: Option<Vec<String>>,
+    pub egress: Option<bool>,
+    pub intercept_tls: Option<Vec<String>>,
+    pub egress_policy: Option<EgressPolicyExtension>,
+    pub faults: Option<Vec<FaultExtension>>,
+    pub canary: Option<CanaryExtension>,
+    pub contract: Option<String>,
+    pub connect_timeout: Option<Setting>,
+    pub timeout: Option<Setting>,
+    pub idle_timeout: Option<Setting>,
+    pub retries: Option<Setting>,
+    pub max_body_bytes: Option<Setting>,
+    pub sample: Option<Setting>,
+}
+
+impl ServiceExtension {
+    pub fn with_defaults(self, defaults: &Self) -> Self {
+        Self {
+            capture_bodies: self.capture_bodies.or(defaults.capture_bodies),
+            redact_headers: self
+                .redact_headers
+                .or_else(|| defaults.redact_headers.clone()),
+            redact_json: self.redact_json.or_else(|| defaults.redact_json.clone()),
+            redact_body: self.redact_body.or_else(|| defaults.redact_body.clone()),
+            ignore_paths: self.ignore_paths.or_else(|| defaults.ignore_paths.clone()),
+            content_types: self
+                .content_types
+                .or_else(|| defaults.content_types.clone()),
+            skip_content_types: self
+                .skip_content_types
+                .or_else(|| defaults.skip_content_types.clone()),
+            body_preview_bytes: self
+                .body_preview_bytes
+                .or_else(|| defaults.body_preview_bytes.clone()),
+            routes: self.routes.or_else(|| defaults.routes.clone()),
+            egress: self.egress.or(defaults.egress),
+            ..self
+        }
+    }
+
+    pub fn apply_tuning(&self, tuning: &mut PortTuning) -> Result<(), String> {
+        apply_tuning(
+            [
+                &self.connect_timeout,
+                &self.timeout,
+                &self.idle_timeout,
+                &self.retries,
+                &self.max_body_bytes,
+                &self.sample,
+            ],
+            tuning,
+        )
+    }
+
+    pub fn preview_bytes(&self) -> Result<Option<u64>, String> {
+        self.body_preview_bytes
+            .as_ref()
+            .map(|value| parse_byte_size(&value.to_string()))
+            .transpose()
+    }
+
+    pub fn port_mode(&self, port: u16) -> Option<ProxyMode> {
+        self.ports
+            .get(&port)
+            .and_then(|settings| settings.proxy)
+            .or(self.proxy)
+    }
+
+    pub fn captures_bodies(&self, port: u16) -> bool {
+        self.ports
+            .get(&port)
+            .and_then(|settings| settings.capture_bodies)
+            .or(self.capture_bodies)
+            .unwrap_or(true)
+    }
+
+    pub fn participates_in_egress(&self) -> bool {
+        self.egress.unwrap_or(true)
+    }
+
+    pub fn fault_rules(&self) -> Result<Vec<FaultRule>, String> {
+        self.faults
+            .iter()
+            .flatten()
+            .map(FaultExtension::rule)
+            .collect()
+    }
+
+    pub fn egress_policy(&self) -> Result<Option<EgressPolicy>, String> {
+        self.egress_policy
+            .as_ref()
+            .map(|policy| {
+                EgressPolicy::parse(policy.mode.as_deref().unwrap_or("report"), &policy.hosts)
+            })
+            .transpose()
+            .map_err(|err| format!("invalid top-level {EXTENSION_KEY}: {err}"))
+    }
+}
+
+fn apply_tuning(values: [&Option<Setting>; 6], tuning: &mut PortTuning) -> Result<(), String> {
+    for (key, value) in TUNING_KEYS.into_iter().zip(values) {
+        if let Some(value) = value {
+            tuning.set(key, &value.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn take_top_level_extension(doc: &mut Value) -> Result<ServiceExtension, String> {
+    let Value::Mapping(map) = doc else {
+        return Ok(ServiceExtension::default());
+    };
+    let extension = take_extension(map).map_err(|err| format!("invalid top-level {err}"))?;
+    if extension.name.is_some()
+        || !extension.ports.is_empty()
+        || extension.proxy.is_some()
+        || extension.faults.is_some()
+        || extension.canary.is_some()
+        || extension.contract.is_some()
+    {
+        return Err(format!(
+            "invalid top-level {EXTENSION_KEY}: `name`, `proxy`, `ports`, `faults`, `canary` and `contract` are only allowed on services"
+        ));
+    }
+    extension
+        .apply_tuning(&mut PortTuning::default())
+        .map_err(|err| format!("invalid top-level {EXTENSION_KEY}: {err}"))?;
+    extension.egress_policy()?;
+    extension
+        .preview_bytes()
+        .map_err(|err| format!("invalid top-level {EXTENSION_KEY}: {err}"))?;
+    validate_body_patterns(extension.redact_body.as_deref().unwrap_or_default())
+        .map_err(|err| format!("invalid top-level {EXTENSION_KEY}: {err}"))?;
+    Ok(extension)
+}
+
+pub fn take_service_extension(
+    service: &mut Mapping,
+    service_name: &str,
+) -> Result<ServiceExtension, String> {
+    let extension = take_extension(service)
+        .map_err(|err| format!("invalid {err} on service {service_name}"))?;
+    if extension.intercept_tls.is_some() || extension.egress_policy.is_some() {
+        return Err(format!(
+            "invalid {EXTENSION_KEY} on service {service_name}: `intercept_tls` and `egress_policy` are only allowed at the top level"
+        ));
+    }
+    let mut probe = PortTuning::default();
+    extension
+        .apply_tuning(&mut probe)
+        .map_err(|err| format!("invalid {EXTENSION_KEY} on service {service_name}: {err}"))?;
+    extension
+        .fault_rules()
+        .map_err(|err| format!("invalid {EXTENSION_KEY} on service {service_name}: {err}"))?;
+    extension
+        .preview_bytes()
+        .map_err(|err| format!("invalid {EXTENSION_KEY} on service {service_name}: {err}"))?;
+    validate_body_patterns(extension.redact_body.as_deref().unwrap_or_default())
+        .map_err(|err| format!("invalid {EXTENSION_KEY} on service {service_name}: {err}"))?;
+    if let Some(canary) = &extension.canary {
+        canary
+            .split()
+            .map_err(|err| format!("invalid {EXTENSION_KEY} on service {service_name}: {err}"))?;
+    }
+    for (port, settings) in &extension.ports {
+        if settings.proxy == Some(ProxyMode::Off) {
+            return Err(format!(
+                "invalid {EXTENSION_KEY} on service {service_name}: port {port} cannot use `proxy: off`, disable the proxy for the whole service instead"
+            ));
+        }
+        settings.apply_tuning(&mut probe).map_err(|err| {
+            format!("invalid {EXTENSION_KEY} on service {service_name} port {port}: {err}")
+        })?;
+    }
+    Ok(extension)
+}
+
+fn take_extension(map: &mut Mapping) -> Result<ServiceExtension, String> {
+    let Some(value) = map.remove(Value::String(EXTENSION_KEY.to_string())) else {
+        return Ok(ServiceExtension::default());
+    };
+    if value.is_null() {
+        return Ok(ServiceExtension::default());
+    }
+    serde_yaml::from_value(value).map_err(|err| format!("{EXTENSION_KEY}: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_yaml::{Mapping, Value};
+
+    use super::{take_service_extension, take_top_level_extension, ProxyMode};
+    use crate::infra::envoy::CanarySplit;
+
+    fn service(raw: &str) -> Mapping {
+        serde_yaml::from_str(raw).unwrap_or_default()
+    }
+
+    #[test]
+    fn service_extension_is_parsed_and_removed() {
+        let mut api = service(
+            "image: api\nx-sanelens:\n  name: API\n  proxy: http\n  ignore_paths: [/health]\n  ports:\n    9000:\n      proxy: tcp\n      timeout: 5s\n",
+        );
+        let extension = take_service_extension(&mut api, "api");
+        assert!(!api.contains_key(Value::String("x-sanelens".to_string())));
+        let extension = extension.unwrap_or_default();
+        assert_eq!(extension.name.as_deref(), Some("API"));
+        assert_eq!(extension.port_mode(9000), Some(ProxyMode::Tcp));
+        assert_eq!(extension.port_mode(8080), Some(ProxyMode::Http));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let mut api = service("x-sanelens:\n  capture_body: false\n");
+        let err = take_service_extension(&mut api, "api")
+            .err()
+            .unwrap_or_default();
+        assert!(err.contains("on service api"), "{err}");
+        assert!(err.contains("capture_body"), "{err}");
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let mut api = service("x-sanelens:\n  ports:\n    8080:\n      timeout: soon\n");
+        let err = take_service_extension(&mut api, "api")
+            .err()
+            .unwrap_or_default();
+        assert!(err.contains("port 8080"), "{err}");
+
+        let mut api = service("x-sanelens:\n  ports:\n    8080:\n      proxy: off\n");
+        assert!(take_service_extension(&mut api, "api").is_err());
+
+        let mut doc = Value::Mapping(service("x-sanelens:\n  name: nope\n"));
+        assert!(take_top_level_extension(&mut doc).is_err());
+    }
+
+    #[test]
+    fn canary_needs_one_split() {
+        let mut api = service("x-sanelens:\n  canary:\n    image: api:next\n    weight: 20%\n");
+        let extension = take_service_extension(&mut api, "api").unwrap_or_default();
+        let split = extension.canary.map(|canary| canary.split());
+        assert_eq!(split, Some(Ok(CanarySplit::Weight(20))));
+
+        let mut api = service("x-sanelens:\n  canary:\n    mirror: 10\n    weight: 20\n");
+        assert!(take_service_extension(&mut api, "api").is_err());
+        let mut api = service("x-sanelens:\n  canary:\n    weight: 100\n");
+        assert!(take_service_extension(&mut api, "api").is_err());
+    }
+
+    #[test]
+    fn top_level_settings_are_defaults() {
+        let mut doc = Value::Mapping(service(
+            "x-sanelens:\n  egress: false\n  redact_headers: [authorization]\n",
+        ));
+        let defaults = take_top_level_extension(&mut doc).unwrap_or_default();
+        let mut api = service("x-sanelens:\n  redact_headers: [cookie]\n");
+        let extension = take_service_extension(&mut api, "api")
+            .unwrap_or_default()
+            .with_defaults(&defaults);
+        assert!(!extension.participates_in_egress());
+        assert_eq!(extension.redact_headers, Some(vec!["cookie".to_string()]));
+    }
+}
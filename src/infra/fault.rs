@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+
+use crate::domain::Scope;
+use crate::infra::engine::Engine;
+use crate::infra::envoy::{parse_duration_ms, ADMIN_PORT};
+use crate::infra::process::run_output;
+use crate::support::constants::{FAULT_ROUTES_LABEL, PROXY_NAME_LABEL};
+
+const RUNTIME_PREFIX: &str = "sanelens.fault";
+pub const SERVICE_RULE: &str = "all";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaultSpec {
+    pub percent: u32,
+    pub delay_ms: Option<u64>,
+    pub abort_status: Option<u16>,
+    pub reset: bool,
+}
+
+impl FaultSpec {
+    pub fn parse(
+        delay: Option<&str>,
+        abort: Option<&str>,
+        reset: bool,
+        percent: Option<&str>,
+    ) -> Result<Self, String> {
+        let delay_ms = delay.map(parse_duration_ms).transpose()?;
+        let abort_status = abort
+            .map(|raw| {
+                raw.trim()
+                    .parse::<u16>()
+                    .ok()
+                    .filter(|status| (200..=599).contains(status))
+                    .ok_or_else(|| format!("invalid abort status '{raw}'"))
+            })
+            .transpose()?;
+        let percent = percent
+            .map(|raw| {
+                raw.trim()
+                    .trim_end_matches('%')
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|value| *value <= 100)
+                    .ok_or_else(|| format!("invalid fault percentage '{raw}'"))
+            })
+            .transpose()?
+            .unwrap_or(100);
+        if delay_ms.is_none() && abort_status.is_none() && !reset {
+            return Err("a fault needs a delay, an abort status or a reset".to_string());
+        }
+        Ok(Self {
+            percent,
+            delay_ms,
+            abort_status,
+            reset,
+        })
+    }
+
+    pub const fn is_active(&self) -> bool {
+        self.percent > 0 && (self.delay_ms.is_some() || self.abort_status.is_some() || self.reset)
+    }
+
+    // Every key is always written so that applying a spec also clears the faults it omits.
+    // Resets are expressed as the share of requests the regular route still accepts.
+    pub fn runtime_values(&self, rule: &str) -> BTreeMap<String, u64> {
+        let percent = u64::from(self.percent);
+        let share = |enabled: bool| if enabled { percent } else { 0 };
+        [
+            ("delay_percent", share(self.delay_ms.is_some())),
+            ("delay_ms", self.delay_ms.unwrap_or(1).max(1)),
+            ("abort_percent", share(self.abort_status.is_some())),
+            ("abort_status", u64::from(self.abort_status.unwrap_or(503))),
+            ("pass_percent", 100 - share(self.reset)),
+        ]
+        .into_iter()
+        .map(|(field, value)| (runtime_key(rule, field), value))
+        .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaultRule {
+    pub path: Option<String>,
+    pub spec: FaultSpec,
+}
+
+impl FaultRule {
+    pub fn new(path: Option<String>, spec: FaultSpec) -> Result<Self, String> {
+        if let Some(path) = path.as_deref() {
+            if !path.starts_with('/') {
+                return Err(format!("fault path '{path}' must start with '/'"));
+            }
+        }
+        Ok(Self { path, spec })
+    }
+}
+
+pub fn route_rule(index: usize) -> String {
+    format!("route{index}")
+}
+
+pub fn runtime_key(rule: &str, field: &str) -> String {
+    format!("{RUNTIME_PREFIX}.{rule}.{field}")
+}
+
+pub fn routes_label(rules: &[FaultRule]) -> Option<String> {
+    let paths: Vec<&str> = rules
+        .iter()
+        .filter_map(|rule| rule.path.as_deref())
+        .collect();
+    (!paths.is_empty()).then(|| paths.join(","))
+}
+
+// Faults are changed through the sidecar's admin runtime layer, so a toggle applies to the
+// next request without restarting anything. Path rules must exist in the startup config.
+#[derive(Clone)]
+pub struct FaultControl {
+    engine: Engine,
+    run_id: String,
+}
+
+impl FaultControl {
+    pub const fn new(engine: Engine, run_id: String) -> Self {
+        Self { engine, run_id }
+    }
+
+    pub fn apply(
+        &self,
+        service: &str,
+        path: Option<&str>,
+        spec: &FaultSpec,
+    ) -> Result<String, String> {
+        let ids = self
+            .engine
+            .collect_run_proxy_container_ids(&self.run_id, Scope::Running);
+        let sidecars: Vec<_> = self
+            .engine
+            .inspect_containers(&ids)
+            .into_iter()
+            .filter(|info| info.labels.get(PROXY_NAME_LABEL).map(String::as_str) == Some(service))
+            .collect();
+        let Some(first) = sidecars.first() else {
+            return Err(format!(
+                "no running proxy for service {service} in run {}",
+                self.run_id
+            ));
+        };
+        let rule = match path {
+            None => SERVICE_RULE.to_string(),
+            Some(path) => first
+                .labels
+                .get(FAULT_ROUTES_LABEL)
+                .and_then(|paths| paths.split(',').position(|entry| entry == path))
+                .map(route_rule)
+                .ok_or_else(|| {
+                    format!("service {service} has no fault route for {path}; declare it in x-sanelens faults")
+                })?,
+        };
+        let query = spec
+            .runtime_values(&rule)
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("&");
+        for sidecar in &sidecars {
+            self.modify_runtime(&sidecar.id, &query)?;
+        }
+        Ok(describe(service, path, spec))
+    }
+
+    fn modify_runtime(&self, cid: &str, query: &str) -> Result<(), String> {
+        let script = format!(
+            "exec 3<>/dev/tcp/127.0.0.1/{ADMIN_PORT} && printf 'POST /runtime_modify?{query} HTTP/1.0\\r\\n\\r\\n' >&3 && head -n 1 <&3"
+        );
+        let output = run_output(&self.engine.exec_cmd(cid, &["bash", "-c", &script]))
+            .map_err(|err| format!("failed to reach proxy admin: {err}"))?;
+        let status = String::from_utf8_lossy(&output.stdout);
+        if status.split_whitespace().nth(1) == Some("200") {
+            return Ok(());
+        }
+        Err(format!(
+            "proxy admin rejected the fault update: {}{}",
+            status.trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+pub fn describe(service: &str, path: Option<&str>, spec: &FaultSpec) -> String {
+    let target = path.map_or_else(|| service.to_string(), |path| format!("{service}{path}"));
+    if !spec.is_active() {
+        return format!("faults cleared on {target}");
+    }
+    let mut faults = Vec::new();
+    if let Some(ms) = spec.delay_ms {
+        faults.push(format!("delay {ms}ms"));
+    }
+    if let Some(status) = spec.abort_status {
+        faults.push(format!("abort {status}"));
+    }
+    if spec.reset {
+        faults.push("connection reset".to_string());
+    }
+    format!(
+        "{} on {}% of requests to {target}",
+        faults.join(", "),
+        spec.percent
+    )
+}
+
+#[derive(Debug, Default)]
+pub struct FaultCommand {
+    pub path: Option<String>,
+    pub spec: FaultSpec,
+}
+
+pub fn parse_fault_args(args: &[String]) -> Result<FaultCommand, String> {
+    let mut path = None;
+    let mut delay = None;
+    let mut abort = None;
+    let mut percent = None;
+    let mut reset = false;
+    let mut clear = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let (flag, inline) = arg
+            .split_once('=')
+            .map_or((arg.as_str(), None), |(flag, value)| (flag, Some(value)));
+        let slot = match flag {
+            "--reset" => {
+                reset = true;
+                continue;
+            }
+            "--clear" => {
+                clear = true;
+                continue;
+            }
+            "--path" => &mut path,
+            "--delay" => &mut delay,
+            "--abort" => &mut abort,
+            "--pct" => &mut percent,
+            _ => return Err(format!("unknown fault option '{arg}'")),
+        };
+        let value = inline
+            .map(str::to_string)
+            .or_else(|| iter.next().cloned())
+            .ok_or_else(|| format!("{flag} needs a value"))?;
+        *slot = Some(value);
+    }
+    let spec = if clear {
+        FaultSpec::default()
+    } else {
+        FaultSpec::parse(
+            delay.as_deref(),
+            abort.as_deref(),
+            reset,
+            percent.as_deref(),
+        )?
+    };
+    Ok(FaultCommand { path, spec })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_fault_args, runtime_key, FaultSpec};
+
+    fn args(raw: &str) -> Vec<String> {
+        raw.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn cli_arguments_build_runtime_values() {
+        let command =
+            parse_fault_args(&args("--delay 500ms --pct 20 --path=/pay")).unwrap_or_default();
+        assert_eq!(command.path.as_deref(), Some("/pay"));
+        let values = command.spec.runtime_values("route0");
+        let value = |field: &str| values.get(&runtime_key("route0", field)).copied();
+        assert_eq!(value("delay_percent"), Some(20));
+        assert_eq!(value("delay_ms"), Some(500));
+        assert_eq!(value("abort_percent"), Some(0));
+        assert_eq!(value("pass_percent"), Some(100));
+    }
+
+    #[test]
+    fn reset_and_clear() {
+        let failure = FaultSpec::parse(None, None, true, Some("30%")).unwrap_or_default();
+        assert_eq!(
+            failure
+                .runtime_values("all")
+                .get(&runtime_key("all", "pass_percent")),
+            Some(&70)
+        );
+        let clear = parse_fault_args(&args("--clear")).map(|command| command.spec);
+        assert_eq!(clear, Ok(FaultSpec::default()));
+        assert!(parse_fault_args(&args("--pct 20")).is_err());
+        assert!(parse_fault_args(&args("--abort 700")).is_err());
+    }
+}
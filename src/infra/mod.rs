@@ -1,7 +1,16 @@
+pub mod bench;
+pub mod chaos;
 pub mod compose;
 pub mod derive;
 pub mod engine;
+pub mod envoy;
+pub mod extension;
+pub mod fault;
+pub mod otlp;
 pub mod process;
+pub mod replay;
 pub mod resolver;
+pub mod tls;
 pub mod traffic;
 pub mod ui;
+pub mod vcr;
@@ -0,0 +1,453 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::{bounded, Receiver, Sender};
+
+use crate::domain::traffic::{Observation, ObservationSink};
+use crate::domain::LogEvent;
+use crate::infra::vcr::{bind_host_gateway, read_body, read_headers, write_response};
+use crate::support::body::decompress;
+use crate::support::logging::LogHub;
+use crate::support::otlp::{
+    logs_from_json, logs_from_protobuf, spans_from_json, spans_from_protobuf,
+};
+use crate::support::otlp_export::{logs_payload, random_id, traces_payload, RunResource};
+use crate::support::traffic::TrafficHub;
+
+const BODY_LIMIT: usize = 16 * 1024 * 1024;
+const EXPORT_QUEUE_SIZE: usize = 4096;
+const EXPORT_BATCH_SIZE: usize = 512;
+const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// OTLP/HTTP endpoint for app containers: spans land in the trace index and log records in the
+// log view. Like the replay server it only listens on the host gateway address.
+pub struct OtlpReceiver {
+    stop_event: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+    port: u16,
+}
+
+#[derive(Clone)]
+struct OtlpSinks {
+    traffic_hub: Arc<TrafficHub>,
+    log_hub: Arc<LogHub>,
+}
+
+impl OtlpReceiver {
+    pub fn start(
+        traffic_hub: Arc<TrafficHub>,
+        log_hub: Arc<LogHub>,
+        gateway: Option<IpAddr>,
+    ) -> io::Result<Self> {
+        let listener = bind_host_gateway(gateway)?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let sinks = OtlpSinks {
+            traffic_hub,
+            log_hub,
+        };
+        let stop_event = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop_event.clone();
+        let handle = thread::spawn(move || {
+            run_listener(&listener, &sinks, &stop_clone);
+        });
+        Ok(Self {
+            stop_event,
+            handle: Some(handle),
+            port,
+        })
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_event.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run_listener(listener: &TcpListener, sinks: &OtlpSinks, stop_event: &Arc<AtomicBool>) {
+    while !stop_event.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => spawn_connection_handler(stream, sinks.clone()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+fn spawn_connection_handler(stream: TcpStream, sinks: OtlpSinks) {
+    thread::spawn(move || {
+        if let Err(err) = handle_connection(stream, &sinks) {
+            eprintln!("[compose] otlp connection error: {err}");
+        }
+    });
+}
+
+fn handle_connection(stream: TcpStream, sinks: &OtlpSinks) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Ok(());
+    };
+    let headers = read_headers(&mut reader)?;
+    if method != "POST" {
+        return write_text(stream, 405, "method not allowed");
+    }
+    let Some(body) = read_body(&mut reader, &headers, BODY_LIMIT)? else {
+        return write_text(stream, 413, "payload too large");
+    };
+    let body = match headers.get("content-encoding") {
+        Some(coding) => match decompress(&body, coding, BODY_LIMIT) {
+            (_, true) => return write_text(stream, 413, "payload too large"),
+            (body, false) => body,
+        },
+        None => body,
+    };
+    let json = headers
+        .get("content-type")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("json"));
+    let result = match path.split('?').next().unwrap_or(path) {
+        "/v1/traces" => ingest_spans(&body, json, sinks),
+        "/v1/logs" => ingest_logs(&body, json, sinks),
+        "/v1/metrics" => Ok(()),
+        _ => return write_text(stream, 404, "not found"),
+    };
+    match result {
+        Ok(()) if json => {
+            write_response(stream, 200, &[("content-type", "application/json")], b"{}")
+        }
+        Ok(()) => write_response(
+            stream,
+            200,
+            &[("content-type", "application/x-protobuf")],
+            &[],
+        ),
+        Err(err) => write_text(stream, 400, &err),
+    }
+}
+
+fn ingest_spans(body: &[u8], json: bool, sinks: &OtlpSinks) -> Result<(), String> {
+    let spans = if json {
+        spans_from_json(body)?
+    } else {
+        spans_from_protobuf(body)?
+    };
+    for span in spans {
+        sinks.traffic_hub.publish_span(span);
+    }
+    Ok(())
+}
+
+fn ingest_logs(body: &[u8], json: bool, sinks: &OtlpSinks) -> Result<(), String> {
+    let records = if json {
+        logs_from_json(body)?
+    } else {
+        logs_from_protobuf(body)?
+    };
+    for record in records {
+        sinks.log_hub.publish_traced(
+            &record.service,
+            &record.line(),
+            record.timestamp().as_deref(),
+            record.trace_id.as_deref(),
+        );
+    }
+    Ok(())
+}
+
+fn write_text(stream: TcpStream, status: u16, message: &str) -> io::Result<()> {
+    write_response(
+        stream,
+        status,
+        &[("content-type", "text/plain")],
+        message.as_bytes(),
+    )
+}
+
+// Forwards observations and log lines to an external OTLP/HTTP endpoint as JSON. Batches are
+// sent from a background thread; when the endpoint falls behind, observations are dropped.
+pub struct OtlpExporter {
+    sender: Sender<Observation>,
+    stop_event: Arc<AtomicBool>,
+    handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+struct ExportContext {
+    target: ExportTarget,
+    run: RunResource,
+    observations: Receiver<Observation>,
+    logs: Option<Receiver<LogEvent>>,
+    stop_event: Arc<AtomicBool>,
+}
+
+impl OtlpExporter {
+    pub fn start(
+        endpoint: &str,
+        run: RunResource,
+        log_hub: Option<&Arc<LogHub>>,
+    ) -> Result<Self, String> {
+        let target = ExportTarget::parse(endpoint)?;
+        let (sender, observations) = bounded(EXPORT_QUEUE_SIZE);
+        let stop_event = Arc::new(AtomicBool::new(false));
+        let context = ExportContext {
+            target,
+            run,
+            observations,
+            logs: log_hub.map(|hub| hub.register_client().0),
+            stop_event: stop_event.clone(),
+        };
+        let handle = thread::spawn(move || export_loop(&context));
+        Ok(Self {
+            sender,
+            stop_event,
+            handle: Mutex::new(Some(handle)),
+        })
+    }
+
+    // Sends whatever is still queued before returning.
+    pub fn stop(&self) {
+        self.stop_event.store(true, Ordering::SeqCst);
+        let handle = self
+            .handle
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take();
+        if let Some(handle) = handle {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl ObservationSink for OtlpExporter {
+    fn emit(&self, obs: Observation) {
+        let _ = self.sender.try_send(obs);
+    }
+}
+
+fn export_loop(context: &ExportContext) {
+    let mut observations = Vec::new();
+    let mut logs = Vec::new();
+    let mut last_flush = Instant::now();
+    let mut failing = false;
+    loop {
+        let stopping = context.stop_event.load(Ordering::SeqCst);
+        observations.extend(context.observations.try_iter());
+        if let Some(receiver) = &context.logs {
+            logs.extend(receiver.try_iter());
+        }
+        let due = last_flush.elapsed() >= EXPORT_INTERVAL
+            || observations.len() >= EXPORT_BATCH_SIZE
+            || logs.len() >= EXPORT_BATCH_SIZE;
+        if stopping || due {
+            let result = flush(context, &observations, &logs);
+            observations.clear();
+            logs.clear();
+            last_flush = Instant::now();
+            match result {
+                Err(err) if !failing => {
+                    eprintln!("[compose] otlp export failed: {err}");
+                    failing = true;
+                }
+                Err(_) => {}
+                Ok(()) => failing = false,
+            }
+        }
+        if stopping {
+            return;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+// Every chunk is sent even after one fails; the first error is the one reported.
+fn flush(
+    context: &ExportContext,
+    observations: &[Observation],
+    logs: &[LogEvent],
+) -> Result<(), String> {
+    let mut first_error = None;
+    let mut send = |path: &str, payload: &serde_json::Value| {
+        if let Err(err) = context.target.post(path, payload) {
+            first_error.get_or_insert(err);
+        }
+    };
+    for chunk in observations.chunks(EXPORT_BATCH_SIZE) {
+        send(
+            "/v1/traces",
+            &traces_payload(chunk, &context.run, random_id),
+        );
+    }
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| u64::try_from(now.as_millis()).unwrap_or(u64::MAX))
+        .unwrap_or_default();
+    for chunk in logs.chunks(EXPORT_BATCH_SIZE) {
+        send("/v1/logs", &logs_payload(chunk, &context.run, now_ms));
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+// Like `OTEL_EXPORTER_OTLP_ENDPOINT`, the endpoint is a base URL that signal paths are
+// appended to. Only plain HTTP is supported.
+struct ExportTarget {
+    authority: String,
+    base_path: String,
+}
+
+impl ExportTarget {
+    fn parse(endpoint: &str) -> Result<Self, String> {
+        let rest = endpoint
+            .trim()
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("unsupported OTLP endpoint {endpoint}: expected http://"))?;
+        let (authority, path) = rest
+            .find('/')
+            .map_or((rest, ""), |index| rest.split_at(index));
+        if authority.is_empty() {
+            return Err(format!("invalid OTLP endpoint {endpoint}"));
+        }
+        let authority = if authority.rsplit_once(':').is_some_and(|(_, port)| {
+            !port.is_empty() && port.bytes().all(|byte| byte.is_ascii_digit())
+        }) {
+            authority.to_string()
+        } else {
+            format!("{authority}:80")
+        };
+        Ok(Self {
+            authority,
+            base_path: path.trim_end_matches('/').to_string(),
+        })
+    }
+
+    fn post(&self, path: &str, payload: &serde_json::Value) -> Result<(), String> {
+        let body = serde_json::to_vec(payload).map_err(|err| err.to_string())?;
+        let addr = self
+            .authority
+            .to_socket_addrs()
+            .map_err(|err| format!("{}: {err}", self.authority))?
+            .next()
+            .ok_or_else(|| format!("{}: no address", self.authority))?;
+        let mut stream = TcpStream::connect_timeout(&addr, EXPORT_TIMEOUT)
+            .map_err(|err| format!("{}: {err}", self.authority))?;
+        stream
+            .set_read_timeout(Some(EXPORT_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(EXPORT_TIMEOUT)))
+            .map_err(|err| err.to_string())?;
+        let head = format!(
+            "POST {}{path} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.base_path,
+            self.authority,
+            body.len()
+        );
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|()| stream.write_all(&body))
+            .map_err(|err| err.to_string())?;
+        let mut status_line = String::new();
+        BufReader::new(stream)
+            .read_line(&mut status_line)
+            .map_err(|err| err.to_string())?;
+        let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+        if status.starts_with('2') {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}{path} answered {}",
+                self.authority,
+                status_line.trim()
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers posts with `statuses` in order and returns the request lines it saw.
+    fn collector(statuses: Vec<u16>) -> (u16, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").ok();
+        let port = listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+            .map_or(0, |addr| addr.port());
+        let handle = thread::spawn(move || {
+            let Some(listener) = listener else {
+                return Vec::new();
+            };
+            statuses
+                .into_iter()
+                .zip(listener.incoming())
+                .filter_map(|(status, stream)| Some(answer(stream.ok()?, status)))
+                .collect()
+        });
+        (port, handle)
+    }
+
+    fn answer(stream: TcpStream, status: u16) -> String {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        let _ = reader.read_line(&mut request_line);
+        let headers = read_headers(&mut reader).unwrap_or_default();
+        let _ = read_body(&mut reader, &headers, BODY_LIMIT);
+        let _ = write_response(reader.into_inner(), status, &[], b"");
+        request_line.trim().to_string()
+    }
+
+    fn log_event(seq: u64) -> LogEvent {
+        LogEvent {
+            seq,
+            service: "api".to_string(),
+            container_ts: None,
+            line: format!("line {seq}"),
+            trace_id: None,
+        }
+    }
+
+    #[test]
+    fn flush_sends_every_chunk_and_reports_the_first_failure() {
+        let (port, handle) = collector(vec![500, 200]);
+        let target = ExportTarget::parse(&format!("http://127.0.0.1:{port}"));
+        assert!(target.is_ok());
+        let Ok(target) = target else {
+            return;
+        };
+        let context = ExportContext {
+            target,
+            run: RunResource {
+                run_id: "run".to_string(),
+                project_name: "demo".to_string(),
+                started_at: "2026-01-01T00:00:00Z".to_string(),
+            },
+            observations: bounded(1).1,
+            logs: None,
+            stop_event: Arc::new(AtomicBool::new(false)),
+        };
+        let batch = u64::try_from(EXPORT_BATCH_SIZE).unwrap_or_default();
+        let logs: Vec<LogEvent> = (0..=batch).map(log_event).collect();
+
+        let error = flush(&context, &[], &logs).err().unwrap_or_default();
+        assert!(error.contains("/v1/logs answered HTTP/1.1 500"), "{error}");
+        assert_eq!(
+            handle.join().unwrap_or_default(),
+            vec!["POST /v1/logs HTTP/1.1", "POST /v1/logs HTTP/1.1"]
+        );
+    }
+}
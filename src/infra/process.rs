@@ -1,5 +1,5 @@
 use std::env;
-use std::io;
+use std::io::{self, Write};
 use std::path::Path;
 use std::process::{Child, Command, Output, Stdio};
 use std::thread;
@@ -38,6 +38,22 @@ pub fn run_output(cmd: &[String]) -> io::Result<Output> {
     command.output()
 }
 
+pub fn run_output_with_input(cmd: &[String], input: &[u8]) -> io::Result<Output> {
+    let Some((program, args)) = cmd.split_first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty command"));
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+    child.wait_with_output()
+}
+
 pub fn spawn_process_group(cmd: &mut Command) -> io::Result<Child> {
     #[cfg(unix)]
     {
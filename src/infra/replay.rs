@@ -0,0 +1,78 @@
+use crate::domain::traffic::TrafficCall;
+use crate::domain::Scope;
+use crate::infra::engine::Engine;
+use crate::infra::process::{run_output_with_input, run_status};
+use crate::support::constants::{PROXY_NAME_LABEL, REPLAY_HEADER};
+use crate::support::replay::{parse_response, ReplayEdits, ReplayRequest, ReplayResponse};
+
+const REPLAY_TIMEOUT_SECS: u64 = 30;
+
+// Replays are written to the sidecar's own listener from inside its container, so they are
+// observed and tagged like any other call into the service.
+#[derive(Clone)]
+pub struct ReplayControl {
+    engine: Engine,
+    run_id: String,
+}
+
+impl ReplayControl {
+    pub const fn new(engine: Engine, run_id: String) -> Self {
+        Self { engine, run_id }
+    }
+
+    pub fn send(&self, call: &TrafficCall, edits: &ReplayEdits) -> Result<ReplayResponse, String> {
+        let request = ReplayRequest::from_call(call, edits)?;
+        let port = request.port.ok_or_else(|| {
+            format!(
+                "the port of call {} is unknown; it cannot be replayed",
+                call.seq
+            )
+        })?;
+        let ids = self
+            .engine
+            .collect_run_proxy_container_ids(&self.run_id, Scope::Running);
+        let sidecar = self
+            .engine
+            .inspect_containers(&ids)
+            .into_iter()
+            .find(|info| {
+                info.labels.get(PROXY_NAME_LABEL).map(String::as_str)
+                    == Some(request.service.as_str())
+            })
+            .ok_or_else(|| {
+                format!(
+                    "no running proxy for service {} in run {}",
+                    request.service, self.run_id
+                )
+            })?;
+        // The request is written with bash's `/dev/tcp`, so images without bash are
+        // reported up front rather than as a failed exec.
+        if !run_status(&self.engine.exec_cmd(&sidecar.id, &["bash", "-c", "true"])) {
+            return Err(format!(
+                "the {} proxy has no bash to replay through; set SANELENS_ENVOY_IMAGE to an \
+Envoy image that ships bash",
+                request.service
+            ));
+        }
+        let script = format!(
+            "exec 3<>/dev/tcp/127.0.0.1/{port} && cat >&3 && timeout {REPLAY_TIMEOUT_SECS} cat <&3"
+        );
+        let output = run_output_with_input(
+            &self
+                .engine
+                .exec_input_cmd(&sidecar.id, &["bash", "-c", &script]),
+            &request.to_http(REPLAY_HEADER, "true"),
+        )
+        .map_err(|err| format!("failed to reach the {} proxy: {err}", request.service))?;
+        let mut response = parse_response(&output.stdout).map_err(|err| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.trim().is_empty() {
+                err
+            } else {
+                format!("{err}: {}", stderr.trim())
+            }
+        })?;
+        response.warnings = request.warnings;
+        Ok(response)
+    }
+}
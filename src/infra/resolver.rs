@@ -1,29 +1,79 @@
 use std::collections::HashMap;
 use std::net::IpAddr;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use crate::domain::traffic::{EntityId, Resolver, Socket};
 use crate::domain::Scope;
 use crate::infra::engine::{ContainerInfo, Engine};
 
-pub struct RuntimeResolver {
+#[derive(Default)]
+struct ResolverState {
+    container_ids: Vec<String>,
     ip_map: HashMap<IpAddr, EntityId>,
 }
 
+pub struct RuntimeResolver {
+    state: RwLock<ResolverState>,
+}
+
 impl RuntimeResolver {
     pub fn from_engine(
         engine: &Engine,
         run_id: &str,
         service_aliases: &HashMap<String, String>,
     ) -> Self {
-        let ids = engine.collect_run_container_ids(run_id, Scope::Running);
-        let containers = engine.inspect_containers(&ids);
-        Self {
-            ip_map: build_ip_map(containers, service_aliases),
+        let resolver = Self {
+            state: RwLock::new(ResolverState::default()),
+        };
+        resolver.refresh(engine, run_id, service_aliases);
+        resolver
+    }
+
+    pub fn refresh(
+        &self,
+        engine: &Engine,
+        run_id: &str,
+        service_aliases: &HashMap<String, String>,
+    ) -> bool {
+        let mut ids = engine.collect_run_container_ids(run_id, Scope::Running);
+        ids.sort();
+        if ids == self.state().container_ids {
+            return false;
         }
+        let containers = engine.inspect_containers(&ids);
+        let ip_map = build_ip_map(containers, service_aliases);
+        let mut state = self.state_mut();
+        state.container_ids = ids;
+        state.ip_map = ip_map;
+        true
     }
 
     pub fn resolve_ip(&self, ip: &IpAddr) -> Option<EntityId> {
-        self.ip_map.get(ip).cloned()
+        self.state().ip_map.get(ip).cloned()
+    }
+
+    pub fn ips_of(&self, name: &str) -> Vec<IpAddr> {
+        let mut ips: Vec<IpAddr> = self
+            .state()
+            .ip_map
+            .iter()
+            .filter(|(_, entity)| matches!(entity, EntityId::Workload { name: workload, .. } if workload == name))
+            .map(|(ip, _)| *ip)
+            .collect();
+        ips.sort();
+        ips
+    }
+
+    fn state(&self) -> RwLockReadGuard<'_, ResolverState> {
+        self.state
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn state_mut(&self) -> RwLockWriteGuard<'_, ResolverState> {
+        self.state
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
     }
 }
 
@@ -0,0 +1,187 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use time::{Duration, OffsetDateTime};
+
+pub const CONTAINER_TLS_DIR: &str = "/sanelens/tls";
+pub const CA_FILE: &str = "ca.pem";
+pub const BUNDLE_FILE: &str = "bundle.pem";
+pub const BUNDLE_OUT_DIR: &str = "/sanelens/bundle";
+
+const APPS_DIR: &str = "apps";
+const CERT_VALIDITY_DAYS: i64 = 30;
+const KEY_MODE: u32 = 0o600;
+// Where common base images keep their trust store.
+const SYSTEM_CA_BUNDLES: [&str; 4] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+    "/etc/ssl/ca-bundle.pem",
+];
+
+#[derive(Clone, Debug)]
+pub struct LeafCert {
+    pub host: String,
+    pub cert_file: String,
+    pub key_file: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct InterceptCa {
+    pub dir: PathBuf,
+    pub ca_pem: String,
+    pub leaves: Vec<LeafCert>,
+}
+
+impl InterceptCa {
+    pub fn host_path(&self, file: &str) -> String {
+        self.dir.join(file).to_string_lossy().into_owned()
+    }
+
+    // Until the service's CA init has appended the CA to the image's own trust store, the
+    // bundle only holds the CA; images the init cannot run in keep it that way.
+    pub fn app_bundle_dir(&self, service: &str) -> Result<PathBuf, String> {
+        let dir = self.dir.join(APPS_DIR).join(service);
+        fs::create_dir_all(&dir).map_err(|err| format!("failed to create tls dir: {err}"))?;
+        write_file(&dir.join(BUNDLE_FILE), &self.ca_pem)?;
+        Ok(dir)
+    }
+}
+
+pub fn container_path(file: &str) -> String {
+    format!("{CONTAINER_TLS_DIR}/{file}")
+}
+
+pub fn generate_intercept_ca(dir: &Path, hosts: &[String]) -> Result<InterceptCa, String> {
+    fs::create_dir_all(dir).map_err(|err| format!("failed to create tls dir: {err}"))?;
+    let ca_key = KeyPair::generate().map_err(|err| format!("generate CA key failed: {err}"))?;
+    let ca_cert = ca_params()
+        .self_signed(&ca_key)
+        .map_err(|err| format!("generate CA failed: {err}"))?;
+    let ca_pem = ca_cert.pem();
+    write_file(&dir.join(CA_FILE), &ca_pem)?;
+
+    let mut leaves = Vec::with_capacity(hosts.len());
+    for host in hosts {
+        let params = leaf_params(host)?;
+        let key = KeyPair::generate().map_err(|err| format!("generate key failed: {err}"))?;
+        let cert = params
+            .signed_by(&key, &ca_cert, &ca_key)
+            .map_err(|err| format!("sign certificate for {host} failed: {err}"))?;
+        let stem = host.replace('*', "_wildcard");
+        let leaf = LeafCert {
+            host: host.clone(),
+            cert_file: format!("{stem}.pem"),
+            key_file: format!("{stem}.key.pem"),
+        };
+        write_file(&dir.join(&leaf.cert_file), &cert.pem())?;
+        write_key(&dir.join(&leaf.key_file), &key.serialize_pem())?;
+        leaves.push(leaf);
+    }
+    Ok(InterceptCa {
+        dir: dir.to_path_buf(),
+        ca_pem,
+        leaves,
+    })
+}
+
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "sanelens egress CA");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    set_validity(&mut params);
+    params
+}
+
+fn leaf_params(host: &str) -> Result<CertificateParams, String> {
+    let mut params = CertificateParams::new(vec![host.to_string()])
+        .map_err(|err| format!("invalid TLS intercept host '{host}': {err}"))?;
+    params.distinguished_name.push(DnType::CommonName, host);
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    set_validity(&mut params);
+    Ok(params)
+}
+
+fn set_validity(params: &mut CertificateParams) {
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - Duration::days(1);
+    params.not_after = now + Duration::days(CERT_VALIDITY_DAYS);
+}
+
+// Run by the CA init inside the app's own image, so the bundle keeps whatever roots the
+// image trusts and only adds the run CA. The file is rewritten in place because the app
+// mounts it directly.
+pub fn bundle_script() -> String {
+    format!(
+        "{{ for f in {}; do if [ -s \"$f\" ]; then cat \"$f\"; echo; break; fi; done; cat {}; }} > {BUNDLE_OUT_DIR}/{BUNDLE_FILE}",
+        SYSTEM_CA_BUNDLES.join(" "),
+        container_path(CA_FILE)
+    )
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), String> {
+    fs::write(path, contents).map_err(|err| format!("write {} failed: {err}", path.display()))
+}
+
+// Keys are only readable by their owner; the egress Envoy runs as root in its container
+// to read them.
+fn write_key(path: &Path, contents: &str) -> Result<(), String> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(KEY_MODE)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|err| format!("write {} failed: {err}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::{generate_intercept_ca, BUNDLE_FILE, CA_FILE};
+
+    #[test]
+    fn writes_ca_and_leaf_certs_without_ca_key() {
+        let dir = env::temp_dir().join(format!("sanelens-tls-{}", std::process::id()));
+        let intercept = generate_intercept_ca(&dir, &["*.example.com".to_string()]);
+        assert!(intercept.is_ok());
+        let Ok(intercept) = intercept else {
+            return;
+        };
+        let leaf = intercept.leaves.first();
+        assert_eq!(
+            leaf.map(|leaf| leaf.cert_file.as_str()),
+            Some("_wildcard.example.com.pem")
+        );
+        let ca = fs::read_to_string(dir.join(CA_FILE)).unwrap_or_default();
+        assert!(ca.contains("BEGIN CERTIFICATE"));
+        let files = fs::read_dir(&dir).map_or(0, Iterator::count);
+        assert_eq!(files, 3);
+        let key_mode = leaf
+            .and_then(|leaf| fs::metadata(dir.join(&leaf.key_file)).ok())
+            .map(|metadata| metadata.permissions().mode() & 0o777);
+        assert_eq!(key_mode, Some(0o600));
+
+        let app_dir = intercept.app_bundle_dir("api-app").unwrap_or_default();
+        let bundle = fs::read_to_string(app_dir.join(BUNDLE_FILE)).unwrap_or_default();
+        assert_eq!(bundle, ca);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
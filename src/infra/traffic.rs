@@ -1,10 +1,26 @@
 use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::string::FromUtf8Error;
 
 use crate::domain::traffic::{
-    Confidence, Correlation, EntityId, FlowKey, FlowMetrics, FlowObservation, HttpObservation,
-    Observation, ObservationAttrs, Peer, Resolver, Socket, Transport, Visibility,
+    BodyEncoding, BodyEncodings, Confidence, DbObservation, DbSystem, EntityId, FlowKey,
+    FlowMetrics, FlowObservation, HttpObservation, Observation, ObservationAttrs, Peer, Resolver,
+    Socket, Transport, Visibility,
 };
+use crate::support::body::{decode_base64, decode_body};
+use crate::support::capture::BodyCapture;
+use crate::support::constants::{
+    BENCH_HEADER, BENCH_TAG, BODY_SKIPPED_TAG, BODY_TRUNCATED_TAG, CALLER_HEADER, CANARY_SUFFIX,
+    DB_TAP_PREFIX, FAULT_HEADER, FAULT_TAG, MIRROR_HEADER, MIRROR_TAG, POLICY_BLOCKED,
+    POLICY_HEADER, REPLAY_HEADER, REPLAY_TAG, VCR_HEADER, VCR_TAG,
+};
+use crate::support::db::{DbDecoder, DbStatement};
+use crate::support::egress_policy::tag_blocked;
+use crate::support::trace::{correlation, TRACE_HEADERS};
+use crate::support::vcr::{Interaction, RecordedRequest, RecordedResponse};
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
@@ -33,6 +49,9 @@ pub struct EnvoyAccessLog {
     pub response_content_type: Option<String>,
     pub response_content_length: Option<String>,
     pub response_body: Option<String>,
+    pub policy: Option<String>,
+    pub fault: Option<String>,
+    pub trace_headers: BTreeMap<String, String>,
 }
 
 struct EnvoyObservationContext<'a> {
@@ -99,9 +118,26 @@ fn build_envoy_access_log(obj: &serde_json::Map<String, serde_json::Value>) -> E
         response_content_type: string_field(obj, "response_content_type"),
         response_content_length: string_field(obj, "response_content_length"),
         response_body: string_field(obj, "response_body"),
+        policy: string_field(obj, "policy"),
+        fault: string_field(obj, "fault").or_else(|| injected_delay(obj)),
+        trace_headers: TRACE_HEADERS
+            .iter()
+            .filter_map(|name| {
+                normalize_header_value(string_field(obj, name))
+                    .map(|value| ((*name).to_string(), value))
+            })
+            .collect(),
     }
 }
 
+// Aborts and resets set the fault header; a delay only shows up as the `DI` flag.
+fn injected_delay(obj: &serde_json::Map<String, serde_json::Value>) -> Option<String> {
+    string_field(obj, "response_flags")?
+        .split(',')
+        .any(|flag| flag == "DI")
+        .then(|| "delay".to_string())
+}
+
 fn string_field(obj: &serde_json::Map<String, serde_json::Value>, key: &str) -> Option<String> {
     obj.get(key)
         .and_then(|v| v.as_str())
@@ -146,14 +182,24 @@ pub fn observation_from_envoy(
     build_flow_observation(&log, peer, attrs, now_ms, &sockets)
 }
 
+pub struct TapSource<'a> {
+    pub service_name: &'a str,
+    pub is_egress: bool,
+    pub bodies: &'a BodyCapture,
+}
+
 #[allow(clippy::too_many_lines)]
 pub fn observation_from_tap(
     payload: &str,
-    service_name: &str,
+    source: &TapSource<'_>,
     resolver: &dyn Resolver,
-    is_egress: bool,
     now_ms: u64,
 ) -> Option<Observation> {
+    let TapSource {
+        service_name,
+        is_egress,
+        bodies,
+    } = *source;
     let value: serde_json::Value = serde_json::from_str(payload).ok()?;
     let wrapper = value.as_object()?;
     let trace = tap_object(wrapper, "http_buffered_trace", "httpBufferedTrace")?;
@@ -182,12 +228,48 @@ pub fn observation_from_tap(
     let response_content_type = response_headers.get("content-type").cloned();
     let request_body_raw = parse_tap_body(tap_object(request, "body", "body"));
     let response_body_raw = parse_tap_body(tap_object(response, "body", "body"));
-    let request_body = normalize_body(request_body_raw.clone(), request_content_type.as_deref());
-    let response_body = normalize_body(response_body_raw.clone(), response_content_type.as_deref());
-    let bytes_in = parse_content_length(&request_headers)
-        .or_else(|| request_body_raw.as_ref().map(|body| body.len() as u64));
-    let bytes_out = parse_content_length(&response_headers)
-        .or_else(|| response_body_raw.as_ref().map(|body| body.len() as u64));
+    let mut skipped = Vec::new();
+    let mut truncated = Vec::new();
+    let (request_body, request_encoding) = if bodies.keeps(request_content_type.as_deref()) {
+        let (text, encoding, cut) = tap_body_text(
+            request_body_raw.as_ref(),
+            request_content_type.as_deref(),
+            header_value(&request_headers, "content-encoding").as_deref(),
+            bodies.preview_bytes,
+        );
+        if cut {
+            truncated.push("request");
+        }
+        (text, encoding)
+    } else {
+        skipped.push("request");
+        (None, None)
+    };
+    let (response_body, response_encoding) = if bodies.keeps(response_content_type.as_deref()) {
+        let (text, encoding, cut) = tap_body_text(
+            response_body_raw.as_ref(),
+            response_content_type.as_deref(),
+            header_value(&response_headers, "content-encoding").as_deref(),
+            bodies.preview_bytes,
+        );
+        if cut {
+            truncated.push("response");
+        }
+        (text, encoding)
+    } else {
+        skipped.push("response");
+        (None, None)
+    };
+    let bytes_in = parse_content_length(&request_headers).or_else(|| {
+        request_body_raw
+            .as_ref()
+            .map(|body| body.bytes.len() as u64)
+    });
+    let bytes_out = parse_content_length(&response_headers).or_else(|| {
+        response_body_raw
+            .as_ref()
+            .map(|body| body.bytes.len() as u64)
+    });
 
     let (at_ms, duration_ms) = tap_timing(request, response, now_ms);
     let downstream_socket =
@@ -195,7 +277,8 @@ pub fn observation_from_tap(
     let upstream_socket = parse_tap_connection(trace, "upstream_connection", "upstreamConnection");
     let src_entity = downstream_socket
         .as_ref()
-        .and_then(|socket| resolver.resolve_entity(socket));
+        .and_then(|socket| resolver.resolve_entity(socket))
+        .or_else(|| mirrored_caller(&request_headers, resolver));
     let dst_entity = if is_egress {
         parse_external_entity(authority.as_deref()).or_else(|| {
             upstream_socket.as_ref().map(|socket| EntityId::External {
@@ -204,26 +287,43 @@ pub fn observation_from_tap(
             })
         })
     } else {
-        Some(EntityId::Workload {
-            name: service_name.to_string(),
-            instance: None,
-        })
+        Some(workload_entity(
+            service_name,
+            upstream_socket.as_ref(),
+            resolver,
+        ))
     };
     let confidence = resolve_confidence(src_entity.as_ref(), dst_entity.as_ref());
     let peer = build_peer(src_entity, dst_entity, downstream_socket, upstream_socket);
-    let attrs = ObservationAttrs {
+    let mut attrs = ObservationAttrs {
         visibility: Visibility::L7Semantics,
         confidence,
         tags: BTreeMap::default(),
     };
+    if response_headers.get(POLICY_HEADER).map(String::as_str) == Some(POLICY_BLOCKED) {
+        tag_blocked(&mut attrs);
+    }
+    tag_from_headers(&mut attrs, &request_headers, &response_headers);
+    if !skipped.is_empty() {
+        attrs
+            .tags
+            .insert(BODY_SKIPPED_TAG.to_string(), skipped.join(","));
+    }
+    if !truncated.is_empty() {
+        attrs
+            .tags
+            .insert(BODY_TRUNCATED_TAG.to_string(), truncated.join(","));
+    }
 
     let path = build_http_path_parts(path, authority.as_deref(), None, is_egress);
+    let correlation = correlation(request_id, &request_headers);
 
-    Some(Observation::Http(HttpObservation {
+    Some(Observation::Http(Box::new(HttpObservation {
         at_ms,
         peer,
         method,
         path,
+        route: None,
         status,
         duration_ms,
         bytes_in,
@@ -232,12 +332,276 @@ pub fn observation_from_tap(
         response_headers,
         request_body,
         response_body,
-        correlation: Correlation {
-            request_id,
-            ..Default::default()
+        body_encoding: BodyEncodings {
+            request: request_encoding,
+            response: response_encoding,
         },
+        correlation,
         attrs,
-    }))
+    })))
+}
+
+// Database listeners stream one tap file per connection, named after the protocol.
+pub fn db_tap_system(path: &Path) -> Option<DbSystem> {
+    let name = path.file_name()?.to_str()?.strip_prefix(DB_TAP_PREFIX)?;
+    DbSystem::parse(name.split('_').next()?)
+}
+
+// A database connection's tap file, read as it grows. Envoy appends one JSON segment per
+// socket event; a segment still being written is picked up on the next read.
+pub struct DbTapStream {
+    system: DbSystem,
+    decoder: DbDecoder,
+    offset: u64,
+    peer: Option<Peer>,
+    confidence: Confidence,
+}
+
+impl DbTapStream {
+    pub fn new(system: DbSystem) -> Self {
+        Self {
+            system,
+            decoder: DbDecoder::new(system),
+            offset: 0,
+            peer: None,
+            confidence: Confidence::Likely,
+        }
+    }
+
+    // Returns the statements completed since the last read and whether the connection has
+    // closed, after which the file can be removed.
+    pub fn read(
+        &mut self,
+        path: &Path,
+        service_name: &str,
+        resolver: &dyn Resolver,
+    ) -> (Vec<Observation>, bool) {
+        let mut bytes = Vec::new();
+        let read = File::open(path).and_then(|mut file| {
+            file.seek(SeekFrom::Start(self.offset))?;
+            file.read_to_end(&mut bytes)
+        });
+        if read.is_err() {
+            return (Vec::new(), false);
+        }
+        let mut observations = Vec::new();
+        let mut closed = false;
+        let mut segments =
+            serde_json::Deserializer::from_slice(&bytes).into_iter::<serde_json::Value>();
+        for segment in segments.by_ref() {
+            match segment {
+                Ok(segment) => {
+                    closed |= self.segment(&segment, service_name, resolver, &mut observations);
+                }
+                Err(err) => {
+                    closed |= !err.is_eof();
+                    break;
+                }
+            }
+        }
+        self.offset += segments.byte_offset() as u64;
+        (observations, closed)
+    }
+
+    fn segment(
+        &mut self,
+        value: &serde_json::Value,
+        service_name: &str,
+        resolver: &dyn Resolver,
+        observations: &mut Vec<Observation>,
+    ) -> bool {
+        let Some(segment) = value.as_object().and_then(|wrapper| {
+            tap_object(
+                wrapper,
+                "socket_streamed_trace_segment",
+                "socketStreamedTraceSegment",
+            )
+        }) else {
+            return false;
+        };
+        if let Some(connection) = tap_object(segment, "connection", "connection") {
+            self.connect(connection, service_name, resolver);
+        }
+        let batch = tap_object(segment, "events", "events")
+            .and_then(|events| tap_array(events, "events", "events"));
+        let events = tap_object(segment, "event", "event").into_iter().chain(
+            batch
+                .into_iter()
+                .flatten()
+                .filter_map(serde_json::Value::as_object),
+        );
+        let mut closed = false;
+        for event in events {
+            closed |= self.event(event, observations);
+        }
+        closed
+    }
+
+    // Ingress listeners see the caller as the remote address and the service as the local one.
+    fn connect(
+        &mut self,
+        connection: &serde_json::Map<String, serde_json::Value>,
+        service_name: &str,
+        resolver: &dyn Resolver,
+    ) {
+        let remote = tap_socket(connection, "remote_address", "remoteAddress");
+        let local = tap_socket(connection, "local_address", "localAddress");
+        let src = remote
+            .as_ref()
+            .and_then(|socket| resolver.resolve_entity(socket));
+        let dst = Some(EntityId::Workload {
+            name: service_name.to_string(),
+            instance: None,
+        });
+        self.confidence = resolve_confidence(src.as_ref(), dst.as_ref());
+        self.peer = Some(build_peer(src, dst, remote, local));
+    }
+
+    fn event(
+        &mut self,
+        event: &serde_json::Map<String, serde_json::Value>,
+        observations: &mut Vec<Observation>,
+    ) -> bool {
+        if tap_value(event, "closed", "closed").is_some() {
+            return true;
+        }
+        let at_ms = tap_timestamp_ms(event, "timestamp", "timestamp").unwrap_or_default();
+        if let Some(data) =
+            tap_object(event, "read", "read").and_then(|read| tap_object(read, "data", "data"))
+        {
+            match socket_bytes(data) {
+                Some(bytes) => self.decoder.client(at_ms, &bytes),
+                None => self.decoder.lose(),
+            }
+        }
+        if let Some(data) =
+            tap_object(event, "write", "write").and_then(|write| tap_object(write, "data", "data"))
+        {
+            let bytes = socket_bytes(data);
+            if bytes.is_none() {
+                self.decoder.lose();
+            }
+            let statements = self.decoder.server(at_ms, &bytes.unwrap_or_default());
+            observations.extend(
+                statements
+                    .into_iter()
+                    .map(|statement| self.observation(statement)),
+            );
+        }
+        false
+    }
+
+    fn observation(&self, statement: DbStatement) -> Observation {
+        Observation::Db(DbObservation {
+            at_ms: statement.started_ms,
+            peer: self.peer.clone().unwrap_or(Peer {
+                src: None,
+                dst: None,
+                raw: None,
+            }),
+            system: self.system,
+            operation: statement.operation,
+            table: statement.table,
+            error: statement.error,
+            duration_ms: Some(statement.duration_ms),
+            attrs: ObservationAttrs {
+                visibility: Visibility::L7Semantics,
+                confidence: self.confidence.clone(),
+                tags: BTreeMap::new(),
+            },
+        })
+    }
+}
+
+// A cropped event leaves a gap the decoder cannot bridge, so it counts as unreadable.
+fn socket_bytes(data: &serde_json::Map<String, serde_json::Value>) -> Option<Vec<u8>> {
+    if tap_bool(data, "truncated", "truncated") == Some(true) {
+        return None;
+    }
+    tap_string(data, "as_bytes", "asBytes").map_or_else(|| Some(Vec::new()), decode_base64)
+}
+
+// Encoded bodies cannot carry the text marker, so every path also reports whether the body
+// was cut; the caller tags the call with it.
+fn tap_body_text(
+    body: Option<&TapBody>,
+    content_type: Option<&str>,
+    content_encoding: Option<&str>,
+    preview_bytes: usize,
+) -> (Option<String>, Option<BodyEncoding>, bool) {
+    let Some(body) = body else {
+        return (None, None, false);
+    };
+    let decoded = decode_body(&body.bytes, content_type, content_encoding, preview_bytes);
+    let truncated = body.truncated || decoded.truncated;
+    if decoded.encoding.is_some() {
+        return (Some(decoded.text), decoded.encoding, truncated);
+    }
+    let text = if truncated {
+        format!("{}\n... (truncated by tap)", decoded.text)
+    } else {
+        decoded.text
+    };
+    (
+        normalize_body(Some(text), content_type, preview_bytes),
+        None,
+        truncated,
+    )
+}
+
+// Only complete exchanges are recorded: truncated or binary bodies, policy rejections and
+// responses that were themselves replayed are skipped.
+pub fn interaction_from_tap(payload: &str, now_ms: u64) -> Option<Interaction> {
+    let value: serde_json::Value = serde_json::from_str(payload).ok()?;
+    let trace = tap_object(
+        value.as_object()?,
+        "http_buffered_trace",
+        "httpBufferedTrace",
+    )?;
+    let request = tap_object(trace, "request", "request")?;
+    let response = tap_object(trace, "response", "response")?;
+    let mut request_headers = parse_tap_headers(tap_array(request, "headers", "headers"));
+    let mut response_headers = parse_tap_headers(tap_array(response, "headers", "headers"));
+    if response_headers.contains_key(POLICY_HEADER) || response_headers.contains_key(VCR_HEADER) {
+        return None;
+    }
+    let method = request_headers.remove(":method")?;
+    let target = request_headers.remove(":path")?;
+    let host = request_headers
+        .remove(":authority")
+        .or_else(|| request_headers.get("host").cloned())?;
+    let status = response_headers.remove(":status")?.parse::<u16>().ok()?;
+    request_headers.retain(|key, _| !key.starts_with(':'));
+    response_headers.retain(|key, _| !key.starts_with(':') && !key.starts_with("x-envoy-"));
+    let request_body = tap_object(request, "body", "body");
+    let response_body = tap_object(response, "body", "body");
+    if tap_body_truncated(request_body) || tap_body_truncated(response_body) {
+        return None;
+    }
+    let request_body = raw_tap_body(request_body).ok()?;
+    let response_body = raw_tap_body(response_body).ok()?;
+    Some(Interaction {
+        recorded_at_ms: now_ms,
+        request: RecordedRequest::new(&method, &host, &target, request_headers, request_body),
+        response: RecordedResponse {
+            status,
+            headers: response_headers,
+            body: response_body,
+        },
+    })
+}
+
+fn raw_tap_body(
+    body: Option<&serde_json::Map<String, serde_json::Value>>,
+) -> Result<Option<String>, FromUtf8Error> {
+    parse_tap_body(body)
+        .map(|body| String::from_utf8(body.bytes))
+        .transpose()
+}
+
+fn tap_body_truncated(body: Option<&serde_json::Map<String, serde_json::Value>>) -> bool {
+    body.and_then(|body| tap_bool(body, "truncated", "truncated"))
+        .unwrap_or(false)
 }
 
 fn parse_envoy_sockets(log: &EnvoyAccessLog) -> EnvoySockets {
@@ -261,12 +625,7 @@ fn resolve_peer_and_attrs(
         .downstream
         .as_ref()
         .and_then(|socket| context.resolver.resolve_entity(socket));
-    let dst_entity = resolve_dst_entity(
-        log,
-        context.is_egress,
-        context.service_name,
-        sockets.upstream.as_ref(),
-    );
+    let dst_entity = resolve_dst_entity(log, context, sockets.upstream.as_ref());
     let confidence = resolve_confidence(src_entity.as_ref(), dst_entity.as_ref());
     let peer = build_peer(
         src_entity,
@@ -286,11 +645,13 @@ fn build_http_observation(
     is_egress: bool,
 ) -> Observation {
     let parts = build_http_parts(log, is_egress);
-    Observation::Http(HttpObservation {
+    let correlation = correlation(parts.request_id, &parts.request_headers);
+    Observation::Http(Box::new(HttpObservation {
         at_ms: now_ms,
         peer,
         method: parts.method,
         path: parts.path,
+        route: None,
         status: parts.status,
         duration_ms: parts.duration_ms,
         bytes_in: parts.bytes_in,
@@ -299,12 +660,10 @@ fn build_http_observation(
         response_headers: parts.response_headers,
         request_body: parts.request_body,
         response_body: parts.response_body,
-        correlation: Correlation {
-            request_id: parts.request_id,
-            ..Default::default()
-        },
+        body_encoding: BodyEncodings::default(),
+        correlation,
         attrs,
-    })
+    }))
 }
 
 fn build_http_parts(log: EnvoyAccessLog, is_egress: bool) -> HttpLogParts {
@@ -327,6 +686,7 @@ fn build_http_parts(log: EnvoyAccessLog, is_egress: bool) -> HttpLogParts {
         response_content_type,
         response_content_length,
         response_body,
+        trace_headers,
         ..
     } = log;
     let path = build_http_path_parts(
@@ -335,7 +695,7 @@ fn build_http_parts(log: EnvoyAccessLog, is_egress: bool) -> HttpLogParts {
         upstream_host.as_deref(),
         is_egress,
     );
-    let request_headers = build_request_headers(RequestHeaderParts {
+    let mut request_headers = build_request_headers(RequestHeaderParts {
         authority,
         request_id: request_id.clone(),
         request_user_agent,
@@ -344,10 +704,16 @@ fn build_http_parts(log: EnvoyAccessLog, is_egress: bool) -> HttpLogParts {
         request_forwarded_for,
         request_forwarded_proto,
     });
+    request_headers.extend(trace_headers);
     let response_headers =
         build_response_headers_from_parts(response_content_type.clone(), response_content_length);
-    let request_body = normalize_body(request_body, request_content_type.as_deref());
-    let response_body = normalize_body(response_body, response_content_type.as_deref());
+    let preview_bytes = BodyCapture::default().preview_bytes;
+    let request_body = normalize_body(request_body, request_content_type.as_deref(), preview_bytes);
+    let response_body = normalize_body(
+        response_body,
+        response_content_type.as_deref(),
+        preview_bytes,
+    );
 
     HttpLogParts {
         method,
@@ -418,11 +784,10 @@ fn build_flow_observation(
 
 fn resolve_dst_entity(
     log: &EnvoyAccessLog,
-    is_egress: bool,
-    service_name: &str,
+    context: &EnvoyObservationContext<'_>,
     upstream: Option<&Socket>,
 ) -> Option<EntityId> {
-    if is_egress {
+    if context.is_egress {
         parse_external_entity(log.authority.as_deref().or(log.upstream_host.as_deref())).or_else(
             || {
                 upstream.map(|socket| EntityId::External {
@@ -432,13 +797,53 @@ fn resolve_dst_entity(
             },
         )
     } else {
-        Some(EntityId::Workload {
+        Some(workload_entity(
+            context.service_name,
+            upstream,
+            context.resolver,
+        ))
+    }
+}
+
+// Canary containers stay distinct workloads so both versions can be compared.
+fn workload_entity(
+    service_name: &str,
+    upstream: Option<&Socket>,
+    resolver: &dyn Resolver,
+) -> EntityId {
+    let upstream = upstream.and_then(|socket| resolver.resolve_entity(socket));
+    match upstream {
+        Some(EntityId::Workload { name, instance })
+            if name.strip_suffix(CANARY_SUFFIX) == Some(service_name) =>
+        {
+            EntityId::Workload { name, instance }
+        }
+        upstream => EntityId::Workload {
             name: service_name.to_string(),
-            instance: None,
-        })
+            instance: upstream.and_then(|entity| match entity {
+                EntityId::Workload { name, instance } if name == service_name => instance,
+                _ => None,
+            }),
+        },
     }
 }
 
+// Mirrored copies reach the canary from the sidecar itself; the original caller travels in a
+// header set by the mirroring listener.
+fn mirrored_caller(
+    request_headers: &BTreeMap<String, String>,
+    resolver: &dyn Resolver,
+) -> Option<EntityId> {
+    let ip = request_headers
+        .get(CALLER_HEADER)?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse::<IpAddr>()
+        .ok()?;
+    resolver.resolve_entity(&Socket { ip, port: 0 })
+}
+
 const fn resolve_confidence(
     src_entity: Option<&EntityId>,
     dst_entity: Option<&EntityId>,
@@ -473,11 +878,22 @@ fn build_attrs(log: &EnvoyAccessLog, confidence: Confidence) -> ObservationAttrs
     } else {
         Visibility::L4Flow
     };
-    ObservationAttrs {
+    let mut attrs = ObservationAttrs {
         visibility,
         confidence,
         tags: BTreeMap::default(),
+    };
+    if log.policy.as_deref() == Some(POLICY_BLOCKED) {
+        tag_blocked(&mut attrs);
+    }
+    if let Some(fault) = log
+        .fault
+        .as_ref()
+        .filter(|value| !matches!(value.as_str(), "" | "-"))
+    {
+        attrs.tags.insert(FAULT_TAG.to_string(), fault.clone());
     }
+    attrs
 }
 
 fn build_http_path_parts(
@@ -534,6 +950,26 @@ fn parse_socket(raw: &str) -> Option<Socket> {
     Some(Socket { ip, port })
 }
 
+// Sidecar filters mark their responses; sanelens-generated requests mark themselves.
+fn tag_from_headers(
+    attrs: &mut ObservationAttrs,
+    request_headers: &BTreeMap<String, String>,
+    response_headers: &BTreeMap<String, String>,
+) {
+    let marks = [
+        (response_headers, VCR_HEADER, VCR_TAG),
+        (response_headers, FAULT_HEADER, FAULT_TAG),
+        (response_headers, MIRROR_HEADER, MIRROR_TAG),
+        (request_headers, REPLAY_HEADER, REPLAY_TAG),
+        (request_headers, BENCH_HEADER, BENCH_TAG),
+    ];
+    for (headers, header, tag) in marks {
+        if let Some(value) = headers.get(header) {
+            attrs.tags.insert(tag.to_string(), value.clone());
+        }
+    }
+}
+
 fn parse_tap_headers(entries: Option<&Vec<serde_json::Value>>) -> BTreeMap<String, String> {
     let mut headers = BTreeMap::new();
     let Some(entries) = entries else {
@@ -561,19 +997,23 @@ fn parse_tap_headers(entries: Option<&Vec<serde_json::Value>>) -> BTreeMap<Strin
     headers
 }
 
-fn parse_tap_body(body: Option<&serde_json::Map<String, serde_json::Value>>) -> Option<String> {
+struct TapBody {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+fn parse_tap_body(body: Option<&serde_json::Map<String, serde_json::Value>>) -> Option<TapBody> {
     let body = body?;
-    let value = tap_string(body, "as_string", "asString")?;
-    let trimmed = value.trim();
-    if trimmed.is_empty() {
-        return None;
-    }
-    let truncated = tap_bool(body, "truncated", "truncated").unwrap_or(false);
-    if truncated {
-        Some(format!("{value}\n... (truncated by tap)"))
-    } else {
-        Some(value.to_string())
-    }
+    let bytes = tap_string(body, "as_bytes", "asBytes")
+        .and_then(decode_base64)
+        .or_else(|| {
+            tap_string(body, "as_string", "asString").map(|value| value.as_bytes().to_vec())
+        })
+        .filter(|bytes| !bytes.is_empty())?;
+    Some(TapBody {
+        bytes,
+        truncated: tap_bool(body, "truncated", "truncated").unwrap_or(false),
+    })
 }
 
 fn parse_tap_connection(
@@ -582,8 +1022,16 @@ fn parse_tap_connection(
     camel: &str,
 ) -> Option<Socket> {
     let connection = tap_object(trace, snake, camel)?;
-    let remote = tap_object(connection, "remote_address", "remoteAddress")?;
-    let socket = tap_object(remote, "socket_address", "socketAddress")?;
+    tap_socket(connection, "remote_address", "remoteAddress")
+}
+
+fn tap_socket(
+    connection: &serde_json::Map<String, serde_json::Value>,
+    snake: &str,
+    camel: &str,
+) -> Option<Socket> {
+    let address = tap_object(connection, snake, camel)?;
+    let socket = tap_object(address, "socket_address", "socketAddress")?;
     let address = tap_string(socket, "address", "address")?;
     let port =
         tap_u64(socket, "port_value", "portValue").and_then(|value| u16::try_from(value).ok())?;
@@ -751,9 +1199,11 @@ fn insert_header(headers: &mut BTreeMap<String, String>, key: &str, value: Optio
     headers.insert(key.to_string(), value);
 }
 
-const NON_JSON_BODY_PREVIEW_LIMIT: usize = 4096;
-
-fn normalize_body(body: Option<String>, content_type: Option<&str>) -> Option<String> {
+fn normalize_body(
+    body: Option<String>,
+    content_type: Option<&str>,
+    preview_bytes: usize,
+) -> Option<String> {
     let body = body?;
     let trimmed = body.trim();
     if trimmed.is_empty() || trimmed == "-" {
@@ -777,7 +1227,7 @@ fn normalize_body(body: Option<String>, content_type: Option<&str>) -> Option<St
     if is_json_content_type(content_type.as_deref()) {
         return Some(body);
     }
-    let (snippet, truncated) = truncate_body(&body, NON_JSON_BODY_PREVIEW_LIMIT);
+    let (snippet, truncated) = truncate_body(&body, preview_bytes);
     if truncated {
         Some(format!("{snippet}\n... (cropped)"))
     } else {
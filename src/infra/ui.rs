@@ -1,18 +1,26 @@
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, BufRead, BufReader, Read, Write};
 use std::net::{TcpListener, TcpStream};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
-use crate::domain::traffic::{TrafficCall, TrafficEdge};
+use crate::domain::traffic::{TraceSummary, TrafficCall, TrafficEdge};
 use crate::domain::{LogEvent, ServiceInfo};
+use crate::infra::chaos::{parse_chaos_args, ChaosControl};
+use crate::infra::fault::{parse_fault_args, FaultControl};
+use crate::infra::replay::ReplayControl;
+use crate::support::chaos::ChaosRule;
+use crate::support::har;
 use crate::support::logging::LogHub;
+use crate::support::openapi;
+use crate::support::replay::{ReplayEdits, ReplayRequest};
 use crate::support::traffic::TrafficHub;
 
 static INDEX_HTML: &str = include_str!(env!("SANELENS_INDEX_HTML"));
 static APP_JS: &str = include_str!(env!("SANELENS_APP_JS"));
 static STYLES_CSS: &str = include_str!(env!("SANELENS_STYLES_CSS"));
+const MAX_REQUEST_BODY: usize = 1024 * 1024;
 
 pub struct UiServer {
     stop_event: Arc<AtomicBool>,
@@ -20,26 +28,42 @@ pub struct UiServer {
     port: u16,
 }
 
+struct UiShared {
+    port: u16,
+    log_hub: Arc<LogHub>,
+    service_info: Vec<ServiceInfo>,
+    traffic_hub: Option<Arc<TrafficHub>>,
+    controls: UiControls,
+}
+
+#[derive(Default)]
+pub struct UiControls {
+    pub faults: Option<FaultControl>,
+    pub chaos: Option<ChaosControl>,
+    pub replay: Option<ReplayControl>,
+}
+
 impl UiServer {
     pub fn start(
         log_hub: Arc<LogHub>,
         service_info: Vec<ServiceInfo>,
         traffic_hub: Option<Arc<TrafficHub>>,
+        controls: UiControls,
         stop_event: Arc<AtomicBool>,
     ) -> io::Result<Self> {
         let listener = TcpListener::bind("127.0.0.1:0")?;
         listener.set_nonblocking(true)?;
         let port = listener.local_addr()?.port();
-        let services = Arc::new(service_info);
+        let shared = Arc::new(UiShared {
+            port,
+            log_hub,
+            service_info,
+            traffic_hub,
+            controls,
+        });
         let stop_clone = stop_event.clone();
         let handle = thread::spawn(move || {
-            run_listener(
-                &listener,
-                &log_hub,
-                &services,
-                traffic_hub.as_ref(),
-                &stop_clone,
-            );
+            run_listener(&listener, &shared, &stop_clone);
         });
         Ok(Self {
             stop_event,
@@ -78,43 +102,21 @@ fn accept_next(listener: &TcpListener) -> AcceptOutcome {
     }
 }
 
-fn run_listener(
-    listener: &TcpListener,
-    log_hub: &Arc<LogHub>,
-    services: &Arc<Vec<ServiceInfo>>,
-    traffic_hub: Option<&Arc<TrafficHub>>,
-    stop_event: &Arc<AtomicBool>,
-) {
+fn run_listener(listener: &TcpListener, shared: &Arc<UiShared>, stop_event: &Arc<AtomicBool>) {
     while !stop_event.load(Ordering::SeqCst) {
         match accept_next(listener) {
-            AcceptOutcome::Stream(stream) => spawn_connection_handler(
-                stream,
-                log_hub.clone(),
-                services.clone(),
-                traffic_hub.cloned(),
-                stop_event.clone(),
-            ),
+            AcceptOutcome::Stream(stream) => {
+                spawn_connection_handler(stream, shared.clone(), stop_event.clone());
+            }
             AcceptOutcome::Wait => thread::sleep(Duration::from_millis(100)),
             AcceptOutcome::Stop => return,
         }
     }
 }
 
-fn spawn_connection_handler(
-    stream: TcpStream,
-    log_hub: Arc<LogHub>,
-    services: Arc<Vec<ServiceInfo>>,
-    traffic_hub: Option<Arc<TrafficHub>>,
-    stop_event: Arc<AtomicBool>,
-) {
+fn spawn_connection_handler(stream: TcpStream, shared: Arc<UiShared>, stop_event: Arc<AtomicBool>) {
     thread::spawn(move || {
-        if let Err(err) = handle_connection(
-            stream,
-            &log_hub,
-            &services,
-            traffic_hub.as_ref(),
-            &stop_event,
-        ) {
+        if let Err(err) = handle_connection(stream, &shared, &stop_event) {
             eprintln!("[compose] ui connection error: {err}");
         }
     });
@@ -122,35 +124,55 @@ fn spawn_connection_handler(
 
 struct UiRouteContext<'a> {
     log_hub: &'a Arc<LogHub>,
-    service_info: &'a Arc<Vec<ServiceInfo>>,
+    service_info: &'a [ServiceInfo],
     traffic_hub: Option<&'a Arc<TrafficHub>>,
+    chaos: Option<&'a ChaosControl>,
     stop_event: &'a Arc<AtomicBool>,
 }
 
 fn handle_connection(
     stream: TcpStream,
-    log_hub: &Arc<LogHub>,
-    service_info: &Arc<Vec<ServiceInfo>>,
-    traffic_hub: Option<&Arc<TrafficHub>>,
+    shared: &UiShared,
     stop_event: &Arc<AtomicBool>,
 ) -> io::Result<()> {
     let mut reader = BufReader::new(stream.try_clone()?);
     let Some(request_line) = read_request_line(&mut reader)? else {
         return Ok(());
     };
-    let Some((method, path)) = parse_request_line(&request_line) else {
+    let Some((method, path, query)) = parse_request_line(&request_line) else {
         return Ok(());
     };
-    drain_headers(&mut reader)?;
+    let headers = read_request_headers(&mut reader)?;
 
+    if method == "POST" && !headers.allows_post(shared.port) {
+        return write_response(
+            stream,
+            403,
+            "text/plain",
+            b"POST needs Content-Type: application/json from the log UI's origin",
+        );
+    }
+    if method == "POST" {
+        if let Some(seq) = call_action(path, "replay") {
+            let body = read_body(&mut reader, headers.content_length)?;
+            return write_replay_response(stream, shared, seq, &body);
+        }
+    }
+    if method == "POST" && path == "/api/faults" {
+        return write_fault_response(stream, shared.controls.faults.as_ref(), query);
+    }
+    if method == "POST" && path == "/api/chaos" {
+        return write_chaos_response(stream, shared.controls.chaos.as_ref(), query);
+    }
     if method != "GET" {
         return write_response(stream, 405, "text/plain", b"Method not allowed");
     }
 
     let context = UiRouteContext {
-        log_hub,
-        service_info,
-        traffic_hub,
+        log_hub: &shared.log_hub,
+        service_info: &shared.service_info,
+        traffic_hub: shared.traffic_hub.as_ref(),
+        chaos: shared.controls.chaos.as_ref(),
         stop_event,
     };
     route_request(path, stream, &context)
@@ -164,23 +186,74 @@ fn read_request_line(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Str
     Ok(Some(request_line))
 }
 
-fn parse_request_line(line: &str) -> Option<(&str, &str)> {
+fn parse_request_line(line: &str) -> Option<(&str, &str, &str)> {
     let mut parts = line.split_whitespace();
     let method = parts.next()?;
-    let path = parts.next().unwrap_or("/");
-    let path = path.split('?').next().unwrap_or(path);
-    Some((method, path))
+    let target = parts.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Some((method, path, query))
+}
+
+#[derive(Default)]
+struct RequestHeaders {
+    content_length: usize,
+    content_type: Option<String>,
+    origin: Option<String>,
+}
+
+impl RequestHeaders {
+    // Pages on other origins can only send a JSON POST after a CORS preflight, which is
+    // never answered, and browsers always attach their origin to it.
+    fn allows_post(&self, port: u16) -> bool {
+        let json = self.content_type.as_deref().is_some_and(|value| {
+            value
+                .split(';')
+                .next()
+                .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"))
+        });
+        let same_origin = self.origin.as_deref().is_none_or(|origin| {
+            origin == format!("http://127.0.0.1:{port}")
+                || origin == format!("http://localhost:{port}")
+        });
+        json && same_origin
+    }
 }
 
-fn drain_headers(reader: &mut BufReader<TcpStream>) -> io::Result<()> {
+fn read_request_headers(reader: &mut impl BufRead) -> io::Result<RequestHeaders> {
+    let mut headers = RequestHeaders::default();
     loop {
         let mut line = String::new();
         let bytes = reader.read_line(&mut line)?;
         if bytes == 0 || line == "\r\n" {
             break;
         }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => headers.content_length = value.parse().unwrap_or_default(),
+            "content-type" => headers.content_type = Some(value.to_string()),
+            "origin" => headers.origin = Some(value.to_string()),
+            _ => {}
+        }
     }
-    Ok(())
+    Ok(headers)
+}
+
+fn read_body(reader: &mut BufReader<TcpStream>, content_length: usize) -> io::Result<Vec<u8>> {
+    let mut body = vec![0; content_length.min(MAX_REQUEST_BODY)];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+// `/api/calls/{seq}/{action}`.
+fn call_action(path: &str, action: &str) -> Option<u64> {
+    path.strip_prefix("/api/calls/")?
+        .strip_suffix(action)?
+        .strip_suffix('/')?
+        .parse()
+        .ok()
 }
 
 fn route_request(path: &str, stream: TcpStream, context: &UiRouteContext<'_>) -> io::Result<()> {
@@ -204,6 +277,20 @@ fn route_request(path: &str, stream: TcpStream, context: &UiRouteContext<'_>) ->
             STYLES_CSS.as_bytes(),
         ),
         "/api/services" => write_services_response(stream, context.service_info),
+        "/api/chaos" | "/api/timeline" => write_chaos_state(stream, path, context.chaos),
+        "/api/traces" => write_traces_response(stream, None, context),
+        _ if path.starts_with("/api/traces/") => {
+            write_traces_response(stream, path.strip_prefix("/api/traces/"), context)
+        }
+        "/api/calls.har" => write_har_response(stream, context.traffic_hub),
+        _ if path.starts_with("/api/calls/") => {
+            write_curl_response(stream, call_action(path, "curl"), context.traffic_hub)
+        }
+        _ if path.starts_with("/api/openapi/") => write_openapi_response(
+            stream,
+            path.strip_prefix("/api/openapi/").unwrap_or_default(),
+            context.traffic_hub,
+        ),
         "/events" => write_event_stream(stream, context.log_hub, context.stop_event),
         "/traffic" => route_traffic_stream(stream, context.traffic_hub, context.stop_event),
         "/traffic/calls" => {
@@ -213,12 +300,9 @@ fn route_request(path: &str, stream: TcpStream, context: &UiRouteContext<'_>) ->
     }
 }
 
-fn write_services_response(
-    stream: TcpStream,
-    service_info: &Arc<Vec<ServiceInfo>>,
-) -> io::Result<()> {
+fn write_services_response(stream: TcpStream, service_info: &[ServiceInfo]) -> io::Result<()> {
     let payload = serde_json::to_vec(&ServicesResponse {
-        services: service_info.as_slice(),
+        services: service_info,
     })
     .unwrap_or_default();
     write_response_with_headers(
@@ -230,6 +314,283 @@ fn write_services_response(
     )
 }
 
+// The query mirrors the `sanelens fault` options, e.g.
+// `POST /api/faults?service=api&delay=500ms&pct=20`.
+fn write_fault_response(
+    stream: TcpStream,
+    faults: Option<&FaultControl>,
+    query: &str,
+) -> io::Result<()> {
+    let Some(faults) = faults else {
+        return write_response(stream, 404, "text/plain", b"Not found");
+    };
+    let mut service = None;
+    let mut args = Vec::new();
+    for (key, value) in query_params(query) {
+        match key.as_str() {
+            "service" => service = Some(value),
+            "reset" | "clear" if matches!(value.as_str(), "" | "1" | "true") => {
+                args.push(format!("--{key}"));
+            }
+            "reset" | "clear" => {}
+            _ => args.push(format!("--{key}={value}")),
+        }
+    }
+    let result = service
+        .ok_or_else(|| "missing service".to_string())
+        .and_then(|service| {
+            let command = parse_fault_args(&args)?;
+            faults.apply(&service, command.path.as_deref(), &command.spec)
+        });
+    match result {
+        Ok(message) => {
+            let payload = serde_json::to_vec(&ControlResponse { message }).unwrap_or_default();
+            write_response(stream, 200, "application/json", &payload)
+        }
+        Err(err) => write_response(stream, 400, "text/plain", err.as_bytes()),
+    }
+}
+
+// `POST /api/chaos?from=web&to=db&delay=300ms&for=30s`; `block=1` and `clear=1` stand in
+// for the flag options. Timed rules are lifted by a background thread.
+fn write_chaos_response(
+    stream: TcpStream,
+    chaos: Option<&ChaosControl>,
+    query: &str,
+) -> io::Result<()> {
+    let Some(chaos) = chaos else {
+        return write_response(stream, 404, "text/plain", b"Not found");
+    };
+    let mut from = None;
+    let mut to = None;
+    let mut args = Vec::new();
+    for (key, value) in query_params(query) {
+        match key.as_str() {
+            "from" => from = Some(value),
+            "to" => to = Some(value),
+            "block" | "clear" if matches!(value.as_str(), "" | "1" | "true") => {
+                args.push(format!("--{key}"));
+            }
+            "block" | "clear" => {}
+            _ => args.push(format!("--{key}={value}")),
+        }
+    }
+    let result = from
+        .zip(to)
+        .ok_or_else(|| "missing from or to".to_string())
+        .and_then(|(from, to)| apply_chaos(chaos, from, to, &args));
+    match result {
+        Ok(message) => {
+            let payload = serde_json::to_vec(&ControlResponse { message }).unwrap_or_default();
+            write_response(stream, 200, "application/json", &payload)
+        }
+        Err(err) => write_response(stream, 400, "text/plain", err.as_bytes()),
+    }
+}
+
+fn apply_chaos(
+    chaos: &ChaosControl,
+    from: String,
+    to: String,
+    args: &[String],
+) -> Result<String, String> {
+    let command = parse_chaos_args(args)?;
+    let Some(action) = command.action else {
+        return chaos.clear(&from, &to, command.port);
+    };
+    let rule = ChaosRule {
+        from,
+        to,
+        port: command.port,
+        action,
+    };
+    let message = chaos.apply(&rule, command.duration_ms)?;
+    if let Some(ms) = command.duration_ms {
+        let chaos = chaos.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(ms));
+            if let Err(err) = chaos.expire(&rule) {
+                eprintln!("[compose] failed to lift chaos rule: {err}");
+            }
+        });
+    }
+    Ok(message)
+}
+
+fn write_chaos_state(
+    stream: TcpStream,
+    path: &str,
+    chaos: Option<&ChaosControl>,
+) -> io::Result<()> {
+    let Some(chaos) = chaos else {
+        return write_response(stream, 404, "text/plain", b"Not found");
+    };
+    let payload = if path == "/api/timeline" {
+        serde_json::to_vec(&chaos.timeline())
+    } else {
+        serde_json::to_vec(&chaos.rules())
+    }
+    .unwrap_or_default();
+    write_response_with_headers(
+        stream,
+        200,
+        "application/json",
+        &payload,
+        &["Cache-Control: no-store"],
+    )
+}
+
+// `/api/traces` lists recent traces; `/api/traces/{trace_id}` returns one as a call tree,
+// along with any log records written under that trace.
+fn write_traces_response(
+    stream: TcpStream,
+    trace_id: Option<&str>,
+    context: &UiRouteContext<'_>,
+) -> io::Result<()> {
+    let Some(hub) = context.traffic_hub else {
+        return write_response(stream, 404, "text/plain", b"Not found");
+    };
+    let payload = match trace_id {
+        Some(trace_id) => {
+            let trace_id = percent_decode(trace_id);
+            let Some(mut tree) = hub.trace(&trace_id) else {
+                return write_response(stream, 404, "text/plain", b"Trace not found");
+            };
+            tree.logs = context.log_hub.trace_events(&trace_id);
+            serde_json::to_vec(&tree)
+        }
+        None => serde_json::to_vec(&TracesResponse {
+            traces: hub.traces(),
+        }),
+    }
+    .unwrap_or_default();
+    write_response_with_headers(
+        stream,
+        200,
+        "application/json",
+        &payload,
+        &["Cache-Control: no-store"],
+    )
+}
+
+// `/api/openapi/{service}` infers a spec from the calls the service received so far.
+fn write_openapi_response(
+    stream: TcpStream,
+    service: &str,
+    traffic_hub: Option<&Arc<TrafficHub>>,
+) -> io::Result<()> {
+    let Some(hub) = traffic_hub else {
+        return write_response(stream, 404, "text/plain", b"Not found");
+    };
+    let document = openapi::infer(&percent_decode(service), &hub.calls());
+    let payload = serde_json::to_vec_pretty(&document).unwrap_or_default();
+    write_response_with_headers(
+        stream,
+        200,
+        "application/json",
+        &payload,
+        &["Cache-Control: no-store"],
+    )
+}
+
+fn write_har_response(stream: TcpStream, traffic_hub: Option<&Arc<TrafficHub>>) -> io::Result<()> {
+    let calls = traffic_hub.map(|hub| hub.calls()).unwrap_or_default();
+    let payload = serde_json::to_vec_pretty(&har::export(&calls)).unwrap_or_default();
+    write_response_with_headers(
+        stream,
+        200,
+        "application/json",
+        &payload,
+        &[
+            "Cache-Control: no-store",
+            "Content-Disposition: attachment; filename=\"calls.har\"",
+        ],
+    )
+}
+
+fn find_call(traffic_hub: Option<&Arc<TrafficHub>>, seq: u64) -> Option<TrafficCall> {
+    traffic_hub?
+        .calls()
+        .into_iter()
+        .find(|call| call.seq == seq)
+}
+
+// The body is an optional JSON object of edits: method, path, headers and body.
+fn write_replay_response(
+    stream: TcpStream,
+    shared: &UiShared,
+    seq: u64,
+    body: &[u8],
+) -> io::Result<()> {
+    let Some(replay) = shared.controls.replay.as_ref() else {
+        return write_response(stream, 404, "text/plain", b"Replay is not available");
+    };
+    let Some(call) = find_call(shared.traffic_hub.as_ref(), seq) else {
+        return write_response(stream, 404, "text/plain", b"Call not found");
+    };
+    let edits = if body.iter().all(u8::is_ascii_whitespace) {
+        Ok(ReplayEdits::default())
+    } else {
+        serde_json::from_slice::<ReplayEdits>(body).map_err(|err| format!("invalid edits: {err}"))
+    };
+    match edits.and_then(|edits| replay.send(&call, &edits)) {
+        Ok(response) => {
+            let payload = serde_json::to_vec(&response).unwrap_or_default();
+            write_response(stream, 200, "application/json", &payload)
+        }
+        Err(err) => write_response(stream, 400, "text/plain", err.as_bytes()),
+    }
+}
+
+fn write_curl_response(
+    stream: TcpStream,
+    seq: Option<u64>,
+    traffic_hub: Option<&Arc<TrafficHub>>,
+) -> io::Result<()> {
+    let Some(call) = seq.and_then(|seq| find_call(traffic_hub, seq)) else {
+        return write_response(stream, 404, "text/plain", b"Call not found");
+    };
+    match ReplayRequest::from_call(&call, &ReplayEdits::default()) {
+        Ok(request) => write_response(stream, 200, "text/plain", request.curl().as_bytes()),
+        Err(err) => write_response(stream, 400, "text/plain", err.as_bytes()),
+    }
+}
+
+fn query_params(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(raw: &str) -> String {
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut iter = raw.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex: Vec<u8> = iter.clone().take(2).collect();
+                let decoded = std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                if let Some(decoded) = decoded.filter(|_| hex.len() == 2) {
+                    bytes.push(decoded);
+                    iter.nth(1);
+                } else {
+                    bytes.push(byte);
+                }
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
 fn route_traffic_stream(
     stream: TcpStream,
     traffic_hub: Option<&Arc<TrafficHub>>,
@@ -269,6 +630,8 @@ fn write_response_with_headers(
     headers: &[&str],
 ) -> io::Result<()> {
     let status_text = match status {
+        400 => "Bad Request",
+        403 => "Forbidden",
         404 => "Not Found",
         405 => "Method Not Allowed",
         _ => "OK",
@@ -450,7 +813,45 @@ fn write_traffic_call_event(stream: &mut TcpStream, call: &TrafficCall) -> io::R
     Ok(())
 }
 
+#[derive(serde::Serialize)]
+struct ControlResponse {
+    message: String,
+}
+
 #[derive(serde::Serialize)]
 struct ServicesResponse<'a> {
     services: &'a [ServiceInfo],
 }
+
+#[derive(serde::Serialize)]
+struct TracesResponse {
+    traces: Vec<TraceSummary>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::read_request_headers;
+
+    fn allows(raw: &str) -> bool {
+        read_request_headers(&mut Cursor::new(raw.as_bytes()))
+            .is_ok_and(|headers| headers.allows_post(8080))
+    }
+
+    #[test]
+    fn posts_need_json_from_the_ui_origin() {
+        assert!(allows("Content-Type: application/json\r\n\r\n"));
+        assert!(allows(
+            "content-type: Application/JSON; charset=utf-8\r\nOrigin: http://127.0.0.1:8080\r\n\r\n"
+        ));
+        assert!(!allows("Content-Type: text/plain\r\n\r\n"));
+        assert!(!allows("Content-Length: 0\r\n\r\n"));
+        assert!(!allows(
+            "Content-Type: application/json\r\nOrigin: http://evil.example\r\n\r\n"
+        ));
+        assert!(!allows(
+            "Content-Type: application/json\r\nOrigin: http://localhost:9090\r\n\r\n"
+        ));
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::support::constants::{VCR_HEADER, VCR_MISS, VCR_REPLAYED};
+use crate::support::vcr::{Player, RecordedRequest, RecordedResponse};
+
+const BODY_LIMIT: usize = 16 * 1024 * 1024;
+const HOP_BY_HOP: [&str; 5] = [
+    "connection",
+    "content-length",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// Serves recorded responses to the egress proxy. Cassettes hold unredacted responses, so it
+// only listens where containers reach it, on the engine's host gateway address.
+pub struct ReplayServer {
+    stop_event: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+    port: u16,
+    player: Arc<Player>,
+}
+
+impl ReplayServer {
+    pub fn start(player: Player, gateway: Option<IpAddr>) -> io::Result<Self> {
+        let listener = bind_host_gateway(gateway)?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let player = Arc::new(player);
+        let stop_event = Arc::new(AtomicBool::new(false));
+        let stop_clone = stop_event.clone();
+        let player_clone = player.clone();
+        let handle = thread::spawn(move || {
+            run_listener(&listener, &player_clone, &stop_clone);
+        });
+        Ok(Self {
+            stop_event,
+            handle: Some(handle),
+            port,
+            player,
+        })
+    }
+
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn player(&self) -> &Player {
+        &self.player
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_event.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// Containers reach listeners in this process through the engine's host gateway, so they bind
+// to the gateway address when it belongs to this host. Engines running in a VM forward the
+// gateway name to the host's loopback instead.
+pub fn bind_host_gateway(gateway: Option<IpAddr>) -> io::Result<TcpListener> {
+    if let Some(listener) = gateway.and_then(|ip| TcpListener::bind((ip, 0)).ok()) {
+        return Ok(listener);
+    }
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+}
+
+fn run_listener(listener: &TcpListener, player: &Arc<Player>, stop_event: &Arc<AtomicBool>) {
+    while !stop_event.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => spawn_connection_handler(stream, player.clone()),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(50));
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+fn spawn_connection_handler(stream: TcpStream, player: Arc<Player>) {
+    thread::spawn(move || {
+        if let Err(err) = handle_connection(stream, &player) {
+            eprintln!("[compose] vcr connection error: {err}");
+        }
+    });
+}
+
+fn handle_connection(stream: TcpStream, player: &Player) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match read_request(&mut reader) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::FileTooLarge => {
+            return write_response(
+                stream,
+                413,
+                &[("content-type", "text/plain")],
+                b"request body too large\n",
+            );
+        }
+        Err(err) => return Err(err),
+    };
+    if let Some(response) = player.lookup(&request) {
+        return write_recorded(stream, &response);
+    }
+    let body = format!(
+        "no recorded interaction for {} {}\n",
+        request.method,
+        request.target()
+    );
+    write_response(
+        stream,
+        502,
+        &[("content-type", "text/plain"), (VCR_HEADER, VCR_MISS)],
+        body.as_bytes(),
+    )
+}
+
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<RecordedRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let headers = read_headers(reader)?;
+    let body = read_body(reader, &headers, BODY_LIMIT)?
+        .ok_or_else(|| io::Error::from(io::ErrorKind::FileTooLarge))?;
+    let host = headers.get("host").cloned().unwrap_or_default();
+    let body = String::from_utf8_lossy(&body).into_owned();
+    Ok(Some(RecordedRequest::new(
+        method,
+        &host,
+        target,
+        headers,
+        Some(body),
+    )))
+}
+
+pub fn read_headers(reader: &mut impl BufRead) -> io::Result<BTreeMap<String, String>> {
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            headers.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    Ok(headers)
+}
+
+// Bodies are refused (`None`) as soon as they would exceed `limit`, chunked or not, so a
+// client cannot make this process allocate more than that.
+pub fn read_body(
+    reader: &mut impl BufRead,
+    headers: &BTreeMap<String, String>,
+    limit: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    if headers
+        .get("transfer-encoding")
+        .is_some_and(|value| value.to_lowercase().contains("chunked"))
+    {
+        return read_chunked(reader, limit);
+    }
+    let len = headers
+        .get("content-length")
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    if len > limit {
+        return Ok(None);
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn read_chunked(reader: &mut impl BufRead, limit: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut body = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let size = line.trim().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+        if size == 0 {
+            read_headers(reader)?;
+            return Ok(Some(body));
+        }
+        let start = body.len();
+        let Some(end) = start.checked_add(size).filter(|end| *end <= limit) else {
+            return Ok(None);
+        };
+        body.resize(end, 0);
+        reader.read_exact(body.get_mut(start..).unwrap_or_default())?;
+        let mut crlf = [0; 2];
+        reader.read_exact(&mut crlf)?;
+    }
+}
+
+fn write_recorded(stream: TcpStream, response: &RecordedResponse) -> io::Result<()> {
+    let mut headers: Vec<(&str, &str)> = response
+        .headers
+        .iter()
+        .filter(|(key, _)| !HOP_BY_HOP.contains(&key.as_str()) && key.as_str() != VCR_HEADER)
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    headers.push((VCR_HEADER, VCR_REPLAYED));
+    let body = response.body.as_deref().unwrap_or_default();
+    write_response(stream, response.status, &headers, body.as_bytes())
+}
+
+pub fn write_response(
+    mut stream: TcpStream,
+    status: u16,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        reason(status),
+        body.len()
+    );
+    for (key, value) in headers {
+        let _ = write!(head, "{key}: {value}\r\n");
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+const fn reason(status: u16) -> &'static str {
+    match status {
+        200..=299 => "OK",
+        300..=399 => "Redirect",
+        404 => "Not Found",
+        400..=499 => "Client Error",
+        502 => "Bad Gateway",
+        _ => "Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::read_body;
+
+    fn body(raw: &[u8], headers: &[(&str, &str)], limit: usize) -> Option<Vec<u8>> {
+        let headers: BTreeMap<String, String> = headers
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+            .collect();
+        let mut reader = raw;
+        let body = read_body(&mut reader, &headers, limit);
+        assert!(body.is_ok(), "{body:?}");
+        body.ok().flatten()
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_refused_before_they_are_buffered() {
+        let chunked = [("transfer-encoding", "chunked")];
+        assert_eq!(
+            body(b"3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n", &chunked, 5),
+            Some(b"abcde".to_vec())
+        );
+        assert_eq!(
+            body(b"3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n", &chunked, 5),
+            None
+        );
+        assert_eq!(body(b"ffffffffffffffff\r\n", &chunked, 5), None);
+        assert_eq!(body(b"abcdef", &[("content-length", "6")], 5), None);
+        assert_eq!(
+            body(b"abcde", &[("content-length", "5")], 5),
+            Some(b"abcde".to_vec())
+        );
+    }
+}
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::env;
 
 use crate::domain::EngineKind;
+use crate::support::constants::DEFAULT_NET_INIT_IMAGE;
 
 pub fn extract_engine_arg(args: &[String]) -> Result<(Vec<String>, Option<EngineKind>), String> {
     let mut updated = Vec::with_capacity(args.len());
@@ -219,6 +221,62 @@ pub fn strip_compose_file_args(args: &[String]) -> Vec<String> {
     updated
 }
 
+pub fn rewrite_scale_args(args: &[String], targets: &HashMap<String, String>) -> Vec<String> {
+    let retarget = |value: &str| -> String {
+        let Some((service, count)) = value.split_once('=') else {
+            return value.to_string();
+        };
+        targets
+            .get(service)
+            .map_or_else(|| value.to_string(), |target| format!("{target}={count}"))
+    };
+    let mut updated = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            updated.push(arg.clone());
+            updated.extend(iter.cloned());
+            break;
+        }
+        if arg == "--scale" {
+            updated.push(arg.clone());
+            if let Some(value) = iter.next() {
+                updated.push(retarget(value));
+            }
+            continue;
+        }
+        if let Some(value) = arg.strip_prefix("--scale=") {
+            updated.push(format!("--scale={}", retarget(value)));
+            continue;
+        }
+        updated.push(arg.clone());
+    }
+    updated
+}
+
+// Replica counts requested with `--scale service=count`; later flags win.
+pub fn scale_overrides(args: &[String]) -> HashMap<String, u64> {
+    let mut overrides = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        }
+        let value = if arg == "--scale" {
+            iter.next().map(String::as_str)
+        } else {
+            arg.strip_prefix("--scale=")
+        };
+        let Some((service, count)) = value.and_then(|value| value.split_once('=')) else {
+            continue;
+        };
+        if let Ok(count) = count.trim().parse() {
+            overrides.insert(service.to_string(), count);
+        }
+    }
+    overrides
+}
+
 pub fn first_compose_file(value: &str) -> Option<String> {
     let separator = if cfg!(windows) { ';' } else { ':' };
     value
@@ -252,6 +310,19 @@ pub fn is_env_truthy(name: &str) -> bool {
     env::var(name).is_ok_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
 }
 
+pub fn env_list(name: &str) -> Vec<String> {
+    env::var(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 fn parse_engine_kind(value: Option<&str>) -> Result<EngineKind, String> {
     let raw =
         value.ok_or_else(|| "--engine requires a value of 'podman' or 'docker'.".to_string())?;
@@ -267,3 +338,7 @@ fn parse_engine_kind(value: Option<&str>) -> Result<EngineKind, String> {
 fn is_falsey(value: &str) -> bool {
     matches!(value, "0" | "false" | "no")
 }
+
+pub fn net_init_image() -> String {
+    env::var("SANELENS_NET_INIT_IMAGE").unwrap_or_else(|_| DEFAULT_NET_INIT_IMAGE.to_string())
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use super::args::{rewrite_scale_args, scale_overrides};
+
+fn args(raw: &[&str]) -> Vec<String> {
+    raw.iter().map(ToString::to_string).collect()
+}
+
+fn targets() -> HashMap<String, String> {
+    HashMap::from([("api".to_string(), "api-app".to_string())])
+}
+
+#[test]
+fn scale_flags_are_retargeted_to_the_app_service() {
+    assert_eq!(
+        rewrite_scale_args(
+            &args(&["up", "--scale", "api=3", "--scale=api=2", "-d"]),
+            &targets()
+        ),
+        args(&["up", "--scale", "api-app=3", "--scale=api-app=2", "-d"])
+    );
+}
+
+#[test]
+fn other_scale_values_and_trailing_args_are_kept() {
+    assert_eq!(
+        rewrite_scale_args(
+            &args(&["up", "--scale", "db=2", "--scale=worker", "--scale"]),
+            &targets()
+        ),
+        args(&["up", "--scale", "db=2", "--scale=worker", "--scale"])
+    );
+    assert_eq!(
+        rewrite_scale_args(&args(&["run", "api", "--", "--scale", "api=3"]), &targets()),
+        args(&["run", "api", "--", "--scale", "api=3"])
+    );
+}
+
+#[test]
+fn scale_overrides_are_read_from_both_flag_forms() {
+    let overrides = scale_overrides(&args(&[
+        "up",
+        "--scale",
+        "api=3",
+        "--scale=worker=2",
+        "--scale=api=4",
+        "--scale=db",
+        "--",
+        "--scale=cache=5",
+    ]));
+    assert_eq!(
+        overrides,
+        HashMap::from([("api".to_string(), 4), ("worker".to_string(), 2)])
+    );
+}
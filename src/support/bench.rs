@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::domain::traffic::{EdgeKey, TrafficEdge};
+use crate::support::traffic::percentile;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BenchMode {
+    // Requests are started on a fixed schedule, by up to `workers` at a time.
+    Rate { per_second: u32, workers: usize },
+    // Each worker sends its next request as soon as the previous one completes.
+    Concurrency(usize),
+}
+
+impl BenchMode {
+    pub const fn workers(self) -> usize {
+        match self {
+            Self::Rate { workers, .. } | Self::Concurrency(workers) => workers,
+        }
+    }
+
+    pub fn describe(self) -> String {
+        match self {
+            Self::Rate { per_second, .. } => format!("{per_second} rps"),
+            Self::Concurrency(workers) => format!("{workers} workers"),
+        }
+    }
+}
+
+// Client-side results. Latencies are kept in microseconds so sub-millisecond local calls
+// still produce useful percentiles.
+#[derive(Debug, Default)]
+pub struct BenchStats {
+    latencies_us: Vec<u64>,
+    statuses: BTreeMap<u16, u64>,
+    failures: BTreeMap<String, u64>,
+    dropped: u64,
+}
+
+impl BenchStats {
+    pub fn record(&mut self, elapsed: Duration, outcome: Result<u16, String>) {
+        match outcome {
+            Ok(status) => {
+                self.latencies_us
+                    .push(u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX));
+                *self.statuses.entry(status).or_default() += 1;
+            }
+            Err(err) => *self.failures.entry(err).or_default() += 1,
+        }
+    }
+
+    // A scheduled request that found every worker busy; the client could not keep up.
+    pub const fn record_dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.statuses.values().sum()
+    }
+
+    pub fn report(&self, elapsed: Duration) -> Vec<String> {
+        let completed = self.completed();
+        let failed: u64 = self.failures.values().sum();
+        let elapsed_ms = u64::try_from(elapsed.as_millis())
+            .unwrap_or(u64::MAX)
+            .max(1);
+        let tenths = completed.saturating_mul(10_000) / elapsed_ms;
+        let mut lines = vec![format!(
+            "requests: {completed} in {}.{}s ({}.{}/s), {failed} failed, {} dropped",
+            elapsed_ms / 1000,
+            elapsed_ms % 1000 / 100,
+            tenths / 10,
+            tenths % 10,
+            self.dropped
+        )];
+        if !self.statuses.is_empty() {
+            let statuses: Vec<String> = self
+                .statuses
+                .iter()
+                .map(|(status, count)| format!("{status} x{count}"))
+                .collect();
+            lines.push(format!("status: {}", statuses.join(", ")));
+        }
+        for (err, count) in &self.failures {
+            lines.push(format!("failed x{count}: {err}"));
+        }
+        let mut sorted = self.latencies_us.clone();
+        sorted.sort_unstable();
+        if let Some(max) = sorted.last() {
+            lines.push(format!(
+                "client latency: p50 {}  p90 {}  p99 {}  max {}",
+                millis(percentile(&sorted, 50)),
+                millis(percentile(&sorted, 90)),
+                millis(percentile(&sorted, 99)),
+                millis(*max)
+            ));
+        }
+        lines
+    }
+}
+
+fn millis(us: u64) -> String {
+    format!("{}.{}ms", us / 1000, us % 1000 / 100)
+}
+
+// The sidecar's view of the same requests, one line per edge they produced.
+pub fn format_edge_stats(edges: &[TrafficEdge]) -> Vec<String> {
+    let mut lines: Vec<String> = edges
+        .iter()
+        .map(|edge| {
+            let target = match &edge.key {
+                EdgeKey::Http { method, route, .. } => format!("{method} {route}"),
+                EdgeKey::Flow { port, .. } => format!("port {port}"),
+                EdgeKey::Grpc {
+                    service, method, ..
+                } => format!("{service}/{method}"),
+                EdgeKey::Db {
+                    operation, table, ..
+                } => table
+                    .as_ref()
+                    .map_or_else(|| operation.clone(), |table| format!("{operation} {table}")),
+            };
+            let stats = &edge.stats;
+            let latency =
+                |value: Option<u64>| value.map_or_else(|| "-".to_string(), |ms| format!("{ms}ms"));
+            format!(
+                "sidecar {target}: {} calls, {} errors, p50 {}  p95 {}",
+                stats.count,
+                stats.errors,
+                latency(stats.p50_ms),
+                latency(stats.p95_ms)
+            )
+        })
+        .collect();
+    lines.sort();
+    lines
+}
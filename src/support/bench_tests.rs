@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use super::bench::{format_edge_stats, BenchStats};
+use super::fixtures::{workload, HttpCall};
+use crate::support::traffic::TrafficHub;
+
+#[test]
+fn client_report_has_rate_statuses_and_percentiles() {
+    let mut stats = BenchStats::default();
+    for ms in 1..=100 {
+        let code = if ms % 50 == 0 { 503 } else { 200 };
+        stats.record(Duration::from_micros(ms * 1000 + 250), Ok(code));
+    }
+    stats.record(
+        Duration::from_millis(3),
+        Err("connect: connection refused".to_string()),
+    );
+    stats.record_dropped();
+    assert_eq!(stats.completed(), 100);
+    assert_eq!(
+        stats.report(Duration::from_secs(2)),
+        vec![
+            "requests: 100 in 2.0s (50.0/s), 1 failed, 1 dropped".to_string(),
+            "status: 200 x98, 503 x2".to_string(),
+            "failed x1: connect: connection refused".to_string(),
+            "client latency: p50 50.2ms  p90 90.2ms  p99 99.2ms  max 100.2ms".to_string(),
+        ]
+    );
+    assert_eq!(
+        BenchStats::default().report(Duration::ZERO),
+        vec!["requests: 0 in 0.0s (0.0/s), 0 failed, 0 dropped".to_string()]
+    );
+}
+
+#[test]
+fn sidecar_stats_come_from_the_observed_edges() {
+    let hub = TrafficHub::new();
+    for (duration, status) in [(4, 200), (8, 200), (30, 500)] {
+        hub.import_call(
+            HttpCall::get("/users?limit=1")
+                .dst(workload("api"))
+                .status(status)
+                .duration(duration)
+                .call(0),
+        );
+    }
+    assert_eq!(
+        format_edge_stats(&hub.edges()),
+        vec!["sidecar GET /users: 3 calls, 1 errors, p50 8ms  p95 8ms".to_string()]
+    );
+}
@@ -0,0 +1,239 @@
+use std::io::Read;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use serde_json::{json, Map, Value};
+
+use crate::domain::traffic::BodyEncoding;
+
+// Upper bound on what one body may expand to, so a small compressed body cannot balloon
+// inside this process.
+pub const DECOMPRESS_LIMIT: usize = 16 * 1024 * 1024;
+
+pub struct DecodedBody {
+    pub text: String,
+    pub encoding: Option<BodyEncoding>,
+    pub truncated: bool,
+}
+
+// Bodies are decompressed first; forms and multipart uploads are then rendered as JSON and
+// anything that is still not UTF-8 is kept as base64 so no bytes are lost.
+pub fn decode_body(
+    raw: &[u8],
+    content_type: Option<&str>,
+    content_encoding: Option<&str>,
+    preview_bytes: usize,
+) -> DecodedBody {
+    let (bytes, truncated) = content_encoding.map_or_else(
+        || (raw.to_vec(), false),
+        |coding| decompress(raw, coding, DECOMPRESS_LIMIT),
+    );
+    let media_type = media_type(content_type);
+    if media_type == "application/x-www-form-urlencoded" {
+        if let Ok(text) = std::str::from_utf8(&bytes) {
+            return DecodedBody {
+                text: Value::Object(parse_form(text)).to_string(),
+                encoding: Some(BodyEncoding::Form),
+                truncated,
+            };
+        }
+    }
+    if media_type.starts_with("multipart/") {
+        if let Some(parts) = content_type
+            .and_then(boundary)
+            .and_then(|boundary| multipart_parts(&bytes, &boundary, preview_bytes))
+        {
+            return DecodedBody {
+                text: Value::Array(parts).to_string(),
+                encoding: Some(BodyEncoding::Multipart),
+                truncated,
+            };
+        }
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => DecodedBody {
+            text,
+            encoding: None,
+            truncated,
+        },
+        Err(err) => {
+            let bytes = err.into_bytes();
+            let end = bytes.len().min(preview_bytes);
+            DecodedBody {
+                text: STANDARD.encode(bytes.get(..end).unwrap_or_default()),
+                encoding: Some(BodyEncoding::Base64),
+                truncated: truncated || end < bytes.len(),
+            }
+        }
+    }
+}
+
+pub fn decode_base64(value: &str) -> Option<Vec<u8>> {
+    STANDARD.decode(value.trim()).ok()
+}
+
+// Codings are listed in the order they were applied. A truncated capture still yields
+// whatever could be decoded; an unknown coding leaves the bytes untouched. Output stops at
+// `limit` bytes, reported as truncated.
+pub fn decompress(raw: &[u8], content_encoding: &str, limit: usize) -> (Vec<u8>, bool) {
+    let mut bytes = raw.to_vec();
+    let mut truncated = false;
+    for coding in content_encoding.rsplit(',').map(str::trim) {
+        let mut out = Vec::new();
+        let result = match coding.to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => read_limited(GzDecoder::new(bytes.as_slice()), limit, &mut out),
+            "deflate" => {
+                let zlib = read_limited(ZlibDecoder::new(bytes.as_slice()), limit, &mut out);
+                if zlib.is_err() && out.is_empty() {
+                    read_limited(DeflateDecoder::new(bytes.as_slice()), limit, &mut out)
+                } else {
+                    zlib
+                }
+            }
+            "br" => read_limited(
+                brotli_decompressor::Decompressor::new(bytes.as_slice(), 4096),
+                limit,
+                &mut out,
+            ),
+            _ => return (bytes, truncated),
+        };
+        if result.is_err() && out.is_empty() {
+            return (bytes, truncated);
+        }
+        if out.len() > limit {
+            out.truncate(limit);
+            truncated = true;
+        }
+        bytes = out;
+    }
+    (bytes, truncated)
+}
+
+// Reads one byte past `limit` so the caller can tell a body that fits from one that was cut.
+fn read_limited(reader: impl Read, limit: usize, out: &mut Vec<u8>) -> std::io::Result<usize> {
+    let limit = u64::try_from(limit).unwrap_or(u64::MAX).saturating_add(1);
+    reader.take(limit).read_to_end(out)
+}
+
+pub fn media_type(content_type: Option<&str>) -> String {
+    content_type
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn parse_form(text: &str) -> Map<String, Value> {
+    let mut fields = Map::new();
+    for pair in text.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = Value::String(percent_decode(value));
+        match fields.get_mut(&percent_decode(key)) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                fields.insert(percent_decode(key), value);
+            }
+        }
+    }
+    fields
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while let Some(&byte) = bytes.get(index) {
+        let escaped = (byte == b'%')
+            .then(|| bytes.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(decoded) = escaped {
+            out.push(decoded);
+            index += 3;
+            continue;
+        }
+        out.push(if byte == b'+' { b' ' } else { byte });
+        index += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+// Each part is listed with its headers and size; small text fields keep their value.
+fn multipart_parts(body: &[u8], boundary: &str, preview_bytes: usize) -> Option<Vec<Value>> {
+    let delimiter = format!("--{boundary}");
+    let mut sections = split_bytes(body, delimiter.as_bytes());
+    sections.next()?;
+    let mut parts = Vec::new();
+    for section in sections {
+        if section.starts_with(b"--") {
+            break;
+        }
+        let section = section.strip_prefix(b"\r\n").unwrap_or(section);
+        let section = section.strip_suffix(b"\r\n").unwrap_or(section);
+        let (head, content) = split_bytes(section, b"\r\n\r\n")
+            .next()
+            .map(|head| (head, section.get(head.len() + 4..).unwrap_or_default()))?;
+        parts.push(multipart_part(
+            &String::from_utf8_lossy(head),
+            content,
+            preview_bytes,
+        ));
+    }
+    Some(parts)
+}
+
+fn multipart_part(head: &str, content: &[u8], preview_bytes: usize) -> Value {
+    let mut part = Map::new();
+    for line in head.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim().eq_ignore_ascii_case("content-type") {
+            part.insert("content_type".to_string(), json!(value.trim()));
+            continue;
+        }
+        if !name.trim().eq_ignore_ascii_case("content-disposition") {
+            continue;
+        }
+        let params = value
+            .split(';')
+            .skip(1)
+            .filter_map(|param| param.split_once('='))
+            .map(|(key, value)| (key.trim().to_ascii_lowercase(), value));
+        for (key, value) in params {
+            if key == "name" || key == "filename" {
+                part.insert(key, json!(value.trim().trim_matches('"')));
+            }
+        }
+    }
+    part.insert("size".to_string(), json!(content.len()));
+    if !part.contains_key("filename") && content.len() <= preview_bytes {
+        if let Ok(text) = std::str::from_utf8(content) {
+            part.insert("value".to_string(), json!(text));
+        }
+    }
+    Value::Object(part)
+}
+
+fn split_bytes<'a>(haystack: &'a [u8], needle: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+    let mut rest = Some(haystack);
+    std::iter::from_fn(move || {
+        let current = rest?;
+        let found = current
+            .windows(needle.len())
+            .position(|window| window == needle);
+        rest = found.and_then(|index| current.get(index + needle.len()..));
+        found.map_or(Some(current), |index| current.get(..index))
+    })
+}
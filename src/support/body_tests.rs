@@ -0,0 +1,63 @@
+use std::io::Write;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::body::{decode_body, decompress};
+use crate::domain::traffic::BodyEncoding;
+
+#[test]
+fn gzip_bodies_are_decompressed() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let _ = encoder.write_all(br#"{"ok":true}"#);
+    let compressed = encoder.finish().unwrap_or_default();
+    let decoded = decode_body(&compressed, Some("application/json"), Some("gzip"), 4096);
+    assert_eq!(decoded.text, r#"{"ok":true}"#);
+    assert_eq!(decoded.encoding, None);
+
+    let binary = decode_body(&[0xff, 0x00, 0x10], Some("image/png"), None, 4096);
+    assert_eq!(binary.text, "/wAQ");
+    assert_eq!(binary.encoding, Some(BodyEncoding::Base64));
+    assert!(!binary.truncated);
+}
+
+#[test]
+fn cropped_binary_bodies_are_reported_truncated() {
+    let binary = decode_body(&[0xff, 0x00, 0x10, 0x20], Some("image/png"), None, 3);
+    assert_eq!(binary.text, "/wAQ");
+    assert_eq!(binary.encoding, Some(BodyEncoding::Base64));
+    assert!(binary.truncated);
+}
+
+#[test]
+fn forms_and_multipart_are_listed() {
+    let form = decode_body(
+        b"name=Ada+Lovelace&tag=a&tag=b%26c",
+        Some("application/x-www-form-urlencoded"),
+        None,
+        4096,
+    );
+    assert_eq!(form.text, r#"{"name":"Ada Lovelace","tag":["a","b&c"]}"#);
+    assert_eq!(form.encoding, Some(BodyEncoding::Form));
+
+    let body = b"--xyz\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nhello\r\n--xyz\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.bin\"\r\nContent-Type: application/octet-stream\r\n\r\n\x00\x01\x02\r\n--xyz--\r\n";
+    let multipart = decode_body(body, Some("multipart/form-data; boundary=xyz"), None, 4096);
+    assert_eq!(multipart.encoding, Some(BodyEncoding::Multipart));
+    assert_eq!(
+        multipart.text,
+        r#"[{"name":"title","size":5,"value":"hello"},{"content_type":"application/octet-stream","filename":"a.bin","name":"file","size":3}]"#
+    );
+}
+
+#[test]
+fn decompression_stops_at_the_limit() {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let _ = encoder.write_all(&vec![b'a'; 64 * 1024]);
+    let compressed = encoder.finish().unwrap_or_default();
+    let (bytes, truncated) = decompress(&compressed, "gzip", 1024);
+    assert_eq!(bytes.len(), 1024);
+    assert!(truncated);
+    let (bytes, truncated) = decompress(&compressed, "gzip", 64 * 1024);
+    assert_eq!(bytes.len(), 64 * 1024);
+    assert!(!truncated);
+}
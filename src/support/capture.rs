@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use regex_lite::Regex;
+use serde_json::Value;
+
+use crate::domain::traffic::Observation;
+use crate::support::constants::{
+    CAPTURE_CONTENT_TYPES_LABEL, CAPTURE_IGNORE_PATHS_LABEL, CAPTURE_PREVIEW_BYTES_LABEL,
+    CAPTURE_REDACT_BODY_LABEL, CAPTURE_REDACT_HEADERS_LABEL, CAPTURE_REDACT_JSON_LABEL,
+    CAPTURE_ROUTES_LABEL, CAPTURE_SKIP_CONTENT_TYPES_LABEL, REDACTED_TAG, REDACTED_VALUE,
+};
+use crate::support::egress_policy::EgressPolicy;
+use crate::support::routes::RouteTemplates;
+
+const DEFAULT_PREVIEW_BYTES: usize = 4096;
+const SENSITIVE_HEADERS: [&str; 8] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-csrf-token",
+    "x-xsrf-token",
+];
+
+pub fn is_sensitive_header(name: &str) -> bool {
+    SENSITIVE_HEADERS
+        .iter()
+        .any(|header| header.eq_ignore_ascii_case(name))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CapturePolicy {
+    redact_headers: Vec<String>,
+    redact_json: Vec<Vec<String>>,
+    redact_json_keys: Vec<Regex>,
+    redact_body: Vec<Regex>,
+    ignore_paths: Vec<String>,
+    egress: Option<EgressPolicy>,
+    bodies: BodyCapture,
+    routes: RouteTemplates,
+}
+
+// Which bodies are kept once Envoy has captured them. JSON bodies are kept whole; other text
+// is cropped to `preview_bytes`.
+#[derive(Clone, Debug)]
+pub struct BodyCapture {
+    pub preview_bytes: usize,
+    content_types: Vec<String>,
+    skip_content_types: Vec<String>,
+}
+
+impl Default for BodyCapture {
+    fn default() -> Self {
+        Self {
+            preview_bytes: DEFAULT_PREVIEW_BYTES,
+            content_types: Vec::new(),
+            skip_content_types: Vec::new(),
+        }
+    }
+}
+
+impl BodyCapture {
+    pub fn from_labels(labels: &HashMap<String, String>) -> Self {
+        let preview_bytes = labels
+            .get(CAPTURE_PREVIEW_BYTES_LABEL)
+            .and_then(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| {
+                        eprintln!(
+                            "[compose] ignoring invalid {CAPTURE_PREVIEW_BYTES_LABEL} '{value}'"
+                        );
+                    })
+                    .ok()
+            })
+            .unwrap_or(DEFAULT_PREVIEW_BYTES);
+        let lowercase = |key| {
+            label_list(labels, key)
+                .into_iter()
+                .map(|entry| entry.to_lowercase())
+                .collect()
+        };
+        Self {
+            preview_bytes,
+            content_types: lowercase(CAPTURE_CONTENT_TYPES_LABEL),
+            skip_content_types: lowercase(CAPTURE_SKIP_CONTENT_TYPES_LABEL),
+        }
+    }
+
+    pub fn keeps(&self, content_type: Option<&str>) -> bool {
+        let media_type = content_type
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase())
+            .unwrap_or_default();
+        let matches = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| glob_match(pattern, &media_type))
+        };
+        if matches(&self.skip_content_types) {
+            return false;
+        }
+        self.content_types.is_empty() || matches(&self.content_types)
+    }
+}
+
+impl CapturePolicy {
+    pub fn from_labels(labels: &HashMap<String, String>) -> Self {
+        let redact_body = labels
+            .get(CAPTURE_REDACT_BODY_LABEL)
+            .map(|value| parse_body_patterns(value))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|pattern| match Regex::new(&pattern) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    eprintln!("[compose] ignoring body redaction pattern '{pattern}': {err}");
+                    None
+                }
+            })
+            .collect();
+        let redact_json: Vec<Vec<String>> = label_list(labels, CAPTURE_REDACT_JSON_LABEL)
+            .iter()
+            .map(|path| path.split('.').map(str::to_string).collect())
+            .collect();
+        Self {
+            redact_headers: SENSITIVE_HEADERS
+                .iter()
+                .map(ToString::to_string)
+                .chain(label_list(labels, CAPTURE_REDACT_HEADERS_LABEL))
+                .map(|header| header.to_lowercase())
+                .collect(),
+            redact_json: redact_json.clone(),
+            redact_json_keys: redact_json
+                .iter()
+                .filter_map(|path| key_regex(path))
+                .collect(),
+            redact_body,
+            ignore_paths: label_list(labels, CAPTURE_IGNORE_PATHS_LABEL),
+            egress: EgressPolicy::from_labels(labels),
+            bodies: BodyCapture::from_labels(labels),
+            routes: RouteTemplates::new(label_list(labels, CAPTURE_ROUTES_LABEL)),
+        }
+    }
+
+    pub const fn bodies(&self) -> &BodyCapture {
+        &self.bodies
+    }
+
+    pub fn apply(&self, obs: Observation) -> Option<Observation> {
+        let obs = self.filter(obs)?;
+        if let Some(policy) = &self.egress {
+            return Some(policy.tag(obs));
+        }
+        Some(obs)
+    }
+
+    fn filter(&self, obs: Observation) -> Option<Observation> {
+        let Observation::Http(mut http) = obs else {
+            return Some(obs);
+        };
+        if http
+            .path
+            .as_deref()
+            .is_some_and(|path| self.is_ignored(path))
+        {
+            return None;
+        }
+        http.route = http.path.as_deref().map(|path| self.routes.route(path));
+        let mut redacted = BTreeSet::new();
+        if self.redact(&mut http.request_headers) {
+            redacted.insert("request_headers");
+        }
+        if self.redact(&mut http.response_headers) {
+            redacted.insert("response_headers");
+        }
+        if self.redact_body(&mut http.request_body) {
+            redacted.insert("request_body");
+        }
+        if self.redact_body(&mut http.response_body) {
+            redacted.insert("response_body");
+        }
+        if !redacted.is_empty() {
+            let parts: Vec<&str> = redacted.into_iter().collect();
+            http.attrs
+                .tags
+                .insert(REDACTED_TAG.to_string(), parts.join(","));
+        }
+        Some(Observation::Http(http))
+    }
+
+    fn is_ignored(&self, path: &str) -> bool {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        self.ignore_paths.iter().any(|pattern| {
+            pattern
+                .strip_suffix('*')
+                .map_or_else(|| path == pattern, |prefix| path.starts_with(prefix))
+        })
+    }
+
+    fn redact(&self, headers: &mut BTreeMap<String, String>) -> bool {
+        let mut changed = false;
+        for (name, value) in headers.iter_mut() {
+            let name = name.to_lowercase();
+            if self
+                .redact_headers
+                .iter()
+                .any(|pattern| glob_match(pattern, &name))
+            {
+                *value = REDACTED_VALUE.to_string();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn redact_body(&self, body: &mut Option<String>) -> bool {
+        let Some(text) = body.as_mut() else {
+            return false;
+        };
+        let mut changed = self.redact_json_body(text);
+        for regex in &self.redact_body {
+            if regex.is_match(text) {
+                *text = regex.replace_all(text, REDACTED_VALUE).into_owned();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    // Bodies the fields cannot be reached in (a cropped preview, JSON embedded in a string,
+    // anything else that does not parse) are dropped whole while they still name one.
+    fn redact_json_body(&self, text: &mut String) -> bool {
+        if self.redact_json.is_empty() {
+            return false;
+        }
+        let changed = match serde_json::from_str::<Value>(text) {
+            Ok(mut json) => {
+                let changed = self.redact_json_value(&mut json);
+                if changed {
+                    *text = serde_json::to_string(&json).unwrap_or_default();
+                }
+                changed
+            }
+            Err(_) => self.redact_json_lines(text),
+        };
+        if self.names_redacted_field(text) {
+            *text = REDACTED_VALUE.to_string();
+            return true;
+        }
+        changed
+    }
+
+    fn redact_json_value(&self, json: &mut Value) -> bool {
+        let mut changed = false;
+        for path in &self.redact_json {
+            changed |= redact_json(json, path);
+        }
+        changed
+    }
+
+    // Newline-delimited JSON is redacted one document per line.
+    fn redact_json_lines(&self, text: &mut String) -> bool {
+        let Some(mut documents) = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str::<Value>(line).ok())
+            .collect::<Option<Vec<Value>>>()
+        else {
+            return false;
+        };
+        let mut changed = false;
+        for json in &mut documents {
+            changed |= self.redact_json_value(json);
+        }
+        if changed {
+            *text = documents
+                .iter()
+                .map(|json| serde_json::to_string(json).unwrap_or_default())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+        changed
+    }
+
+    fn names_redacted_field(&self, text: &str) -> bool {
+        self.redact_json_keys.iter().any(|regex| {
+            regex
+                .captures_iter(text)
+                .any(|captures| captures.get(1).is_none())
+        })
+    }
+}
+
+// Matches `"key":` (or `\"key\":` inside a string) for the last segment of a redacted path,
+// capturing a value that is already redacted.
+fn key_regex(path: &[String]) -> Option<Regex> {
+    let field = path.last()?;
+    let key = field
+        .split('*')
+        .map(regex_lite::escape)
+        .collect::<Vec<_>>()
+        .join(r#"[^"\\]*"#);
+    let redacted = regex_lite::escape(REDACTED_VALUE);
+    Regex::new(&format!(r#"\\?"{key}\\?"\s*:\s*(\\?"{redacted})?"#)).ok()
+}
+
+// A single segment names a field at any depth; dotted paths start at the document root.
+// `*` matches any field name or array element.
+fn redact_json(value: &mut Value, path: &[String]) -> bool {
+    match path {
+        [field] => redact_field_anywhere(value, field),
+        _ => redact_json_path(value, path),
+    }
+}
+
+fn redact_field_anywhere(value: &mut Value, field: &str) -> bool {
+    let mut changed = false;
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if glob_match(field, key) {
+                    *child = Value::String(REDACTED_VALUE.to_string());
+                    changed = true;
+                } else {
+                    changed |= redact_field_anywhere(child, field);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                changed |= redact_field_anywhere(item, field);
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+fn redact_json_path(value: &mut Value, path: &[String]) -> bool {
+    let Some((segment, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED_VALUE.to_string());
+        return true;
+    };
+    let mut changed = false;
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if glob_match(segment, key) {
+                    changed |= redact_json_path(child, rest);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (index, item) in items.iter_mut().enumerate() {
+                if segment == "*" || segment.parse::<usize>() == Ok(index) {
+                    changed |= redact_json_path(item, rest);
+                }
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return false;
+    };
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        let Some(index) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[index + part.len()..];
+    }
+    rest.ends_with(last)
+}
+
+// Regexes may contain commas, so the label holds a JSON list; a plain value is one pattern.
+fn parse_body_patterns(value: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<String>>(value).unwrap_or_else(|_| vec![value.to_string()])
+}
+
+pub fn body_patterns_label(patterns: &[String]) -> String {
+    serde_json::to_string(patterns).unwrap_or_default()
+}
+
+pub fn validate_body_patterns(patterns: &[String]) -> Result<(), String> {
+    for pattern in patterns {
+        Regex::new(pattern)
+            .map_err(|err| format!("invalid body redaction pattern '{pattern}': {err}"))?;
+    }
+    Ok(())
+}
+
+fn label_list(labels: &HashMap<String, String>, key: &str) -> Vec<String> {
+    labels
+        .get(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::capture::{BodyCapture, CapturePolicy};
+use super::fixtures::HttpCall;
+use crate::domain::traffic::Observation;
+use crate::support::constants::{
+    CAPTURE_CONTENT_TYPES_LABEL, CAPTURE_IGNORE_PATHS_LABEL, CAPTURE_REDACT_BODY_LABEL,
+    CAPTURE_REDACT_HEADERS_LABEL, CAPTURE_REDACT_JSON_LABEL, CAPTURE_SKIP_CONTENT_TYPES_LABEL,
+    REDACTED_TAG,
+};
+
+fn http_call(path: &str) -> HttpCall {
+    HttpCall::get(path)
+        .request_header("Authorization", "Bearer secret")
+        .request_header("accept", "*/*")
+}
+
+fn policy() -> CapturePolicy {
+    CapturePolicy::from_labels(&HashMap::from([
+        (
+            CAPTURE_REDACT_HEADERS_LABEL.to_string(),
+            "authorization, x-api-key".to_string(),
+        ),
+        (
+            CAPTURE_IGNORE_PATHS_LABEL.to_string(),
+            "/health,/metrics/*".to_string(),
+        ),
+    ]))
+}
+
+#[test]
+fn ignored_paths_are_dropped() {
+    let policy = policy();
+    assert!(policy.apply(http_call("/health").observation()).is_none());
+    assert!(policy
+        .apply(http_call("/health?verbose=1").observation())
+        .is_none());
+    assert!(policy
+        .apply(http_call("/metrics/prometheus").observation())
+        .is_none());
+    assert!(policy.apply(http_call("/healthz").observation()).is_some());
+}
+
+#[test]
+fn configured_headers_are_redacted() {
+    let headers = match policy().apply(http_call("/users").observation()) {
+        Some(Observation::Http(http)) => http.request_headers,
+        _ => BTreeMap::new(),
+    };
+    assert_eq!(
+        headers.get("Authorization").map(String::as_str),
+        Some("[redacted]")
+    );
+    assert_eq!(headers.get("accept").map(String::as_str), Some("*/*"));
+}
+
+#[test]
+fn bodies_are_redacted_and_marked() {
+    let policy = CapturePolicy::from_labels(&HashMap::from([
+        (
+            CAPTURE_REDACT_HEADERS_LABEL.to_string(),
+            "x-*-token".to_string(),
+        ),
+        (
+            CAPTURE_REDACT_JSON_LABEL.to_string(),
+            "password,cards.*.number".to_string(),
+        ),
+        (
+            CAPTURE_REDACT_BODY_LABEL.to_string(),
+            r#"["\\d{3}-\\d{2}-\\d{4}"]"#.to_string(),
+        ),
+    ]));
+    let call = http_call("/users")
+        .request_header("X-Session-Token", "abc")
+        .request_body(
+            r#"{"user":{"password":"hunter2"},"cards":[{"number":"4111","exp":"12/30"}]}"#,
+        )
+        .response_body("ssn 123-45-6789 on file");
+    let Some(Observation::Http(http)) = policy.apply(call.observation()) else {
+        return;
+    };
+    assert_eq!(
+        http.request_body.as_deref(),
+        Some(
+            r#"{"cards":[{"exp":"12/30","number":"[redacted]"}],"user":{"password":"[redacted]"}}"#
+        )
+    );
+    assert_eq!(
+        http.response_body.as_deref(),
+        Some("ssn [redacted] on file")
+    );
+    assert_eq!(
+        http.request_headers
+            .get("X-Session-Token")
+            .map(String::as_str),
+        Some("[redacted]")
+    );
+    assert_eq!(
+        http.attrs.tags.get(REDACTED_TAG).map(String::as_str),
+        Some("request_body,request_headers,response_body")
+    );
+}
+
+#[test]
+fn json_fields_out_of_reach_drop_the_whole_body() {
+    let policy = CapturePolicy::from_labels(&HashMap::from([(
+        CAPTURE_REDACT_JSON_LABEL.to_string(),
+        "password,*token".to_string(),
+    )]));
+    let redacted =
+        |body: &str| match policy.apply(http_call("/login").request_body(body).observation()) {
+            Some(Observation::Http(http)) => http.request_body,
+            _ => None,
+        };
+    assert_eq!(
+        redacted("{\"user\":\"ada\",\"password\":\"hun\n... (truncated by tap)").as_deref(),
+        Some("[redacted]")
+    );
+    assert_eq!(
+        redacted(r#"{"payload":"{\"password\":\"hunter2\"}"}"#).as_deref(),
+        Some("[redacted]")
+    );
+    assert_eq!(
+        redacted("{\"access_token\":\"a\"}\n{\"user\":\"ada\"}\n").as_deref(),
+        Some("{\"access_token\":\"[redacted]\"}\n{\"user\":\"ada\"}")
+    );
+    assert_eq!(
+        redacted("<p>reset your password here</p>").as_deref(),
+        Some("<p>reset your password here</p>")
+    );
+}
+
+#[test]
+fn body_content_types_filter() {
+    let bodies = BodyCapture::from_labels(&HashMap::from([
+        (
+            CAPTURE_CONTENT_TYPES_LABEL.to_string(),
+            "application/*, text/plain".to_string(),
+        ),
+        (
+            CAPTURE_SKIP_CONTENT_TYPES_LABEL.to_string(),
+            "application/octet-stream".to_string(),
+        ),
+    ]));
+    assert!(bodies.keeps(Some("Application/JSON; charset=utf-8")));
+    assert!(bodies.keeps(Some("text/plain")));
+    assert!(!bodies.keeps(Some("application/octet-stream")));
+    assert!(!bodies.keeps(Some("image/png")));
+    assert!(!bodies.keeps(None));
+    assert!(BodyCapture::default().keeps(None));
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+const RULES_FILE: &str = "chaos.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChaosAction {
+    Block,
+    Delay { ms: u64 },
+}
+
+// A rule targets the edges between two workloads as they appear in `EdgeKey`s, optionally
+// narrowed to one destination port.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChaosRule {
+    pub from: String,
+    pub to: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    pub action: ChaosAction,
+}
+
+impl ChaosRule {
+    pub fn same_edge(&self, from: &str, to: &str, port: Option<u16>) -> bool {
+        self.from == from && self.to == to && self.port == port
+    }
+}
+
+impl fmt::Display for ChaosRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", edge_label(&self.from, &self.to, self.port))?;
+        match self.action {
+            ChaosAction::Block => f.write_str(" blocked"),
+            ChaosAction::Delay { ms } => write!(f, " delayed {ms}ms"),
+        }
+    }
+}
+
+pub fn edge_label(from: &str, to: &str, port: Option<u16>) -> String {
+    port.map_or_else(
+        || format!("{from} -> {to}"),
+        |port| format!("{from} -> {to}:{port}"),
+    )
+}
+
+pub fn upsert(rules: &mut Vec<ChaosRule>, rule: ChaosRule) {
+    rules.retain(|existing| !existing.same_edge(&rule.from, &rule.to, rule.port));
+    rules.push(rule);
+}
+
+pub fn remove(rules: &mut Vec<ChaosRule>, from: &str, to: &str, port: Option<u16>) -> bool {
+    let before = rules.len();
+    rules.retain(|rule| !rule.same_edge(from, to, port));
+    rules.len() != before
+}
+
+pub fn load(run_dir: &Path) -> io::Result<Vec<ChaosRule>> {
+    match fs::read_to_string(run_dir.join(RULES_FILE)) {
+        Ok(payload) => serde_json::from_str(&payload).map_err(io::Error::other),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn save(run_dir: &Path, rules: &[ChaosRule]) -> io::Result<()> {
+    let payload = serde_json::to_vec_pretty(rules).map_err(io::Error::other)?;
+    fs::write(run_dir.join(RULES_FILE), payload)
+}
@@ -0,0 +1,62 @@
+use std::env;
+use std::fs;
+
+use super::chaos::{load, remove, save, upsert, ChaosAction, ChaosRule};
+use super::timeline::{self, TimelineEvent};
+
+fn rule(from: &str, to: &str, port: Option<u16>, action: ChaosAction) -> ChaosRule {
+    ChaosRule {
+        from: from.to_string(),
+        to: to.to_string(),
+        port,
+        action,
+    }
+}
+
+#[test]
+fn rules_are_keyed_by_edge_and_port() {
+    let mut rules = Vec::new();
+    upsert(&mut rules, rule("api", "db", None, ChaosAction::Block));
+    upsert(
+        &mut rules,
+        rule("api", "db", Some(5432), ChaosAction::Block),
+    );
+    upsert(
+        &mut rules,
+        rule("api", "db", None, ChaosAction::Delay { ms: 300 }),
+    );
+    assert_eq!(rules.len(), 2);
+    assert_eq!(
+        rules.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        vec!["api -> db:5432 blocked", "api -> db delayed 300ms"]
+    );
+    assert!(remove(&mut rules, "api", "db", Some(5432)));
+    assert!(!remove(&mut rules, "db", "api", None));
+    assert_eq!(rules.len(), 1);
+}
+
+#[test]
+fn rules_and_timeline_persist_in_run_dir() {
+    let dir = env::temp_dir().join(format!("sanelens-chaos-{}", std::process::id()));
+    let _ = fs::create_dir_all(&dir);
+    let rules = vec![rule(
+        "web",
+        "api",
+        Some(8080),
+        ChaosAction::Delay { ms: 50 },
+    )];
+    assert!(save(&dir, &rules).is_ok());
+    assert_eq!(load(&dir).ok(), Some(rules));
+    let event = TimelineEvent {
+        at_ms: 7,
+        kind: "chaos".to_string(),
+        message: "web -> api:8080 delayed 50ms".to_string(),
+    };
+    assert!(timeline::append(&dir, &event).is_ok());
+    assert!(timeline::append(&dir, &event).is_ok());
+    assert_eq!(
+        timeline::read(&dir).map(|events| events.len()).ok(),
+        Some(2)
+    );
+    let _ = fs::remove_dir_all(&dir);
+}
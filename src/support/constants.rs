@@ -2,6 +2,8 @@ pub const HISTORY_LIMIT: usize = 20000;
 pub const CLIENT_QUEUE_SIZE: usize = 10000;
 pub const TRAFFIC_CLIENT_QUEUE_SIZE: usize = 2000;
 pub const TRAFFIC_CALL_HISTORY_LIMIT: usize = 2000;
+pub const TRACE_HISTORY_LIMIT: usize = 500;
+pub const TRACE_CALL_LIMIT: usize = 1000;
 pub const BIN_NAME: &str = "sanelens";
 pub const PROJECT_PREFIX: &str = "sanelens_";
 pub const RUN_ID_LABEL: &str = "sanelens.run_id";
@@ -12,3 +14,46 @@ pub const COMPOSE_FILE_LABEL: &str = "sanelens.compose_file";
 pub const DERIVED_COMPOSE_LABEL: &str = "sanelens.derived_compose";
 pub const STARTED_AT_LABEL: &str = "sanelens.started_at";
 pub const PROJECT_NAME_LABEL: &str = "sanelens.project_name";
+pub const CAPTURE_REDACT_HEADERS_LABEL: &str = "sanelens.capture.redact_headers";
+pub const CAPTURE_IGNORE_PATHS_LABEL: &str = "sanelens.capture.ignore_paths";
+pub const CAPTURE_REDACT_JSON_LABEL: &str = "sanelens.capture.redact_json";
+pub const CAPTURE_REDACT_BODY_LABEL: &str = "sanelens.capture.redact_body";
+pub const CAPTURE_CONTENT_TYPES_LABEL: &str = "sanelens.capture.content_types";
+pub const CAPTURE_SKIP_CONTENT_TYPES_LABEL: &str = "sanelens.capture.skip_content_types";
+pub const CAPTURE_PREVIEW_BYTES_LABEL: &str = "sanelens.capture.preview_bytes";
+pub const CAPTURE_ROUTES_LABEL: &str = "sanelens.capture.routes";
+pub const BODY_SKIPPED_TAG: &str = "body_skipped";
+pub const BODY_TRUNCATED_TAG: &str = "body_truncated";
+pub const REDACTED_TAG: &str = "redacted";
+pub const REDACTED_VALUE: &str = "[redacted]";
+pub const EGRESS_POLICY_MODE_LABEL: &str = "sanelens.egress.policy";
+pub const EGRESS_POLICY_HOSTS_LABEL: &str = "sanelens.egress.hosts";
+pub const POLICY_HEADER: &str = "x-sanelens-policy";
+pub const POLICY_TAG: &str = "policy";
+pub const POLICY_BLOCKED: &str = "blocked";
+pub const POLICY_REPORTED: &str = "reported";
+pub const VCR_HEADER: &str = "x-sanelens-vcr";
+pub const VCR_TAG: &str = "vcr";
+pub const VCR_REPLAYED: &str = "replayed";
+pub const VCR_MISS: &str = "miss";
+pub const PROXY_NAME_LABEL: &str = "sanelens.proxy.name";
+pub const FAULT_ROUTES_LABEL: &str = "sanelens.fault.routes";
+pub const FAULT_DELAY_LABEL: &str = "sanelens.fault.delay";
+pub const FAULT_ABORT_LABEL: &str = "sanelens.fault.abort";
+pub const FAULT_RESET_LABEL: &str = "sanelens.fault.reset";
+pub const FAULT_PERCENT_LABEL: &str = "sanelens.fault.pct";
+pub const FAULT_HEADER: &str = "x-sanelens-fault";
+pub const FAULT_TAG: &str = "fault";
+pub const DEFAULT_NET_INIT_IMAGE: &str = "nicolaka/netshoot:latest";
+pub const CHAOS_KIND: &str = "chaos";
+pub const CANARY_SUFFIX: &str = "-canary";
+pub const CALLER_HEADER: &str = "x-sanelens-caller";
+pub const MIRROR_HEADER: &str = "x-sanelens-mirror";
+pub const MIRROR_TAG: &str = "mirror";
+pub const REPLAY_HEADER: &str = "x-sanelens-replay";
+pub const REPLAY_TAG: &str = "replay";
+pub const BENCH_HEADER: &str = "x-sanelens-bench";
+pub const BENCH_TAG: &str = "bench";
+pub const BENCH_KIND: &str = "bench";
+pub const DB_TAP_PREFIX: &str = "db_";
+pub const TAP_ARCHIVE_DIR: &str = "seen";
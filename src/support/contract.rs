@@ -0,0 +1,636 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use regex_lite::Regex;
+use serde_json::{Map, Number, Value};
+use serde_yaml::Value as YamlValue;
+
+use crate::domain::traffic::{BodyEncoding, ContractViolation, TrafficCall, ViolationKind};
+use crate::support::body::media_type;
+use crate::support::constants::REDACTED_VALUE;
+
+const SCHEMA_ERROR_LIMIT: usize = 5;
+const REF_DEPTH_LIMIT: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Request => "request",
+            Self::Response => "response",
+        }
+    }
+
+    const fn kind(self) -> ViolationKind {
+        match self {
+            Self::Request => ViolationKind::RequestSchema,
+            Self::Response => ViolationKind::ResponseSchema,
+        }
+    }
+}
+
+// One side of a call, as far as its body is concerned.
+struct Message<'a> {
+    direction: Direction,
+    headers: &'a BTreeMap<String, String>,
+    body: Option<&'a str>,
+    encoding: Option<BodyEncoding>,
+}
+
+struct PathTemplate {
+    key: String,
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Literal(String),
+    // `{id}` or `{name}.json`: whatever sits around the placeholder must match.
+    Param { prefix: String, suffix: String },
+}
+
+impl PathTemplate {
+    fn parse(key: &str) -> Self {
+        let segments = split_path(key)
+            .map(|segment| match (segment.find('{'), segment.rfind('}')) {
+                (Some(open), Some(close)) if open < close => Segment::Param {
+                    prefix: segment.get(..open).unwrap_or_default().to_string(),
+                    suffix: segment.get(close + 1..).unwrap_or_default().to_string(),
+                },
+                _ => Segment::Literal(segment.to_string()),
+            })
+            .collect();
+        Self {
+            key: key.to_string(),
+            segments,
+        }
+    }
+
+    // Literal segments score higher, so `/users/me` wins over `/users/{id}`.
+    fn score(&self, path: &str) -> Option<usize> {
+        let parts: Vec<&str> = split_path(path).collect();
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+        let mut literals = 0;
+        for (segment, part) in self.segments.iter().zip(parts) {
+            match segment {
+                Segment::Literal(literal) if literal == part => literals += 1,
+                Segment::Param { prefix, suffix }
+                    if part.len() > prefix.len() + suffix.len()
+                        && part.starts_with(prefix.as_str())
+                        && part.ends_with(suffix.as_str()) => {}
+                _ => return None,
+            }
+        }
+        Some(literals)
+    }
+}
+
+// An OpenAPI 3 document for one service, checked against every call that reaches it.
+pub struct Contract {
+    doc: Value,
+    base_paths: Vec<String>,
+    paths: Vec<PathTemplate>,
+}
+
+impl Contract {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+        Self::parse(&raw).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let doc = serde_yaml::from_str::<YamlValue>(raw)
+            .map(to_json)
+            .map_err(|err| format!("invalid OpenAPI document: {err}"))?;
+        let version = doc.get("openapi").and_then(Value::as_str).unwrap_or("");
+        if !version.starts_with('3') {
+            return Err("only OpenAPI 3 documents are supported".to_string());
+        }
+        let paths = doc
+            .get("paths")
+            .and_then(Value::as_object)
+            .map(|paths| paths.keys().map(|key| PathTemplate::parse(key)).collect())
+            .unwrap_or_default();
+        let base_paths = doc
+            .get("servers")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|server| server.get("url").and_then(Value::as_str))
+            .filter_map(server_base_path)
+            .collect();
+        Ok(Self {
+            doc,
+            base_paths,
+            paths,
+        })
+    }
+
+    pub fn check(&self, call: &TrafficCall) -> Vec<ContractViolation> {
+        let raw_path = call.path.as_deref().unwrap_or("/");
+        let path = raw_path.split(['?', '#']).next().unwrap_or("/");
+        let Some(template) = self.match_path(path) else {
+            return vec![violation(
+                ViolationKind::UnknownRoute,
+                format!("no path in the contract matches {path}"),
+            )];
+        };
+        let item = self.path_item(&template.key);
+        let method = call.method.as_deref().unwrap_or("GET").to_lowercase();
+        // HEAD is answered by the GET handler unless the contract says otherwise.
+        let operation = item
+            .get(&method)
+            .or_else(|| (method == "head").then(|| item.get("get")).flatten());
+        let Some(operation) = operation.map(|operation| self.resolve(operation)) else {
+            return vec![violation(
+                ViolationKind::WrongMethod,
+                format!(
+                    "{} is not documented for {}",
+                    method.to_uppercase(),
+                    template.key
+                ),
+            )];
+        };
+        let mut violations = Vec::new();
+        if call.path.is_some() {
+            self.check_query(raw_path, item, operation, &mut violations);
+        }
+        if let Some(request_body) = operation.get("requestBody") {
+            let message = Message {
+                direction: Direction::Request,
+                headers: &call.request_headers,
+                body: call.request_body.as_deref(),
+                encoding: call.body_encoding.request,
+            };
+            self.check_body(&message, self.resolve(request_body), &mut violations);
+        }
+        let Some(status) = call.status else {
+            return violations;
+        };
+        let message = Message {
+            direction: Direction::Response,
+            headers: &call.response_headers,
+            body: call.response_body.as_deref(),
+            encoding: call.body_encoding.response,
+        };
+        match self.response(operation, status) {
+            Some(response) => self.check_body(&message, response, &mut violations),
+            None => violations.push(violation(
+                ViolationKind::UndocumentedStatus,
+                format!(
+                    "status {status} is not documented for {} {}",
+                    method.to_uppercase(),
+                    template.key
+                ),
+            )),
+        }
+        violations
+    }
+
+    fn match_path(&self, path: &str) -> Option<&PathTemplate> {
+        let stripped = self
+            .base_paths
+            .iter()
+            .filter_map(|base| path.strip_prefix(base.as_str()))
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'));
+        std::iter::once(path)
+            .chain(stripped)
+            .flat_map(|candidate| {
+                self.paths
+                    .iter()
+                    .filter_map(move |template| Some((template.score(candidate)?, template)))
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(_, template)| template)
+    }
+
+    fn path_item(&self, key: &str) -> &Value {
+        self.resolve(
+            self.doc
+                .get("paths")
+                .and_then(|paths| paths.get(key))
+                .unwrap_or(&Value::Null),
+        )
+    }
+
+    fn check_query(
+        &self,
+        raw_path: &str,
+        item: &Value,
+        operation: &Value,
+        violations: &mut Vec<ContractViolation>,
+    ) {
+        let query = raw_path.split_once('?').map_or("", |(_, query)| query);
+        let present: Vec<&str> = query
+            .split('&')
+            .filter_map(|pair| pair.split('=').next())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let parameters = [item, operation]
+            .into_iter()
+            .filter_map(|owner| owner.get("parameters").and_then(Value::as_array))
+            .flatten()
+            .map(|parameter| self.resolve(parameter));
+        for parameter in parameters {
+            let required = parameter.get("required").and_then(Value::as_bool) == Some(true);
+            if !required || parameter.get("in").and_then(Value::as_str) != Some("query") {
+                continue;
+            }
+            let name = parameter.get("name").and_then(Value::as_str).unwrap_or("");
+            if !present.contains(&name) {
+                violations.push(violation(
+                    ViolationKind::RequestSchema,
+                    format!("missing required query parameter '{name}'"),
+                ));
+            }
+        }
+    }
+
+    fn response<'a>(&'a self, operation: &'a Value, status: u16) -> Option<&'a Value> {
+        let responses = operation.get("responses")?.as_object()?;
+        let range = format!("{}XX", status / 100);
+        responses
+            .get(&status.to_string())
+            .or_else(|| {
+                responses
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(&range))
+                    .map(|(_, response)| response)
+            })
+            .or_else(|| responses.get("default"))
+            .map(|response| self.resolve(response))
+    }
+
+    // Only JSON bodies that were captured whole are checked against a schema; base64,
+    // form and multipart captures and truncated previews are skipped.
+    fn check_body(
+        &self,
+        message: &Message<'_>,
+        spec: &Value,
+        violations: &mut Vec<ContractViolation>,
+    ) {
+        let direction = message.direction;
+        let Some(body) = message.body.filter(|body| !body.is_empty()) else {
+            return;
+        };
+        let Some(content) = spec.get("content").and_then(Value::as_object) else {
+            return;
+        };
+        if content.is_empty() {
+            return;
+        }
+        let content_type = media_type(message.headers.get("content-type").map(String::as_str));
+        let Some(media) = media_entry(content, &content_type) else {
+            violations.push(violation(
+                direction.kind(),
+                format!(
+                    "{} content type '{content_type}' is not documented",
+                    direction.label()
+                ),
+            ));
+            return;
+        };
+        let Some(schema) = media.get("schema") else {
+            return;
+        };
+        if message.encoding.is_some() || !content_type.contains("json") {
+            return;
+        }
+        let Ok(value) = serde_json::from_str::<Value>(body) else {
+            return;
+        };
+        let mut validator = Validator {
+            contract: self,
+            direction,
+            errors: Vec::new(),
+        };
+        validator.validate(schema, &value, "$", 0);
+        violations.extend(
+            validator
+                .errors
+                .into_iter()
+                .map(|error| violation(direction.kind(), format!("{} {error}", direction.label()))),
+        );
+    }
+
+    fn resolve<'a>(&'a self, mut value: &'a Value) -> &'a Value {
+        for _ in 0..REF_DEPTH_LIMIT {
+            let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+                return value;
+            };
+            let Some(target) = reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.doc.pointer(pointer))
+            else {
+                return &Value::Null;
+            };
+            value = target;
+        }
+        value
+    }
+}
+
+struct Validator<'a> {
+    contract: &'a Contract,
+    direction: Direction,
+    errors: Vec<String>,
+}
+
+impl Validator<'_> {
+    const fn full(&self) -> bool {
+        self.errors.len() >= SCHEMA_ERROR_LIMIT
+    }
+
+    fn fail(&mut self, at: &str, message: &str) {
+        if !self.full() {
+            self.errors.push(format!("{at}: {message}"));
+        }
+    }
+
+    fn matches(&self, schema: &Value, value: &Value, depth: usize) -> bool {
+        let mut probe = Validator {
+            contract: self.contract,
+            direction: self.direction,
+            errors: Vec::new(),
+        };
+        probe.validate(schema, value, "$", depth);
+        probe.errors.is_empty()
+    }
+
+    fn validate(&mut self, schema: &Value, value: &Value, at: &str, depth: usize) {
+        if self.full() || depth > REF_DEPTH_LIMIT {
+            return;
+        }
+        // Values redacted at capture no longer carry their type.
+        if value.as_str() == Some(REDACTED_VALUE) {
+            return;
+        }
+        let schema = self.contract.resolve(schema);
+        let Some(rules) = schema.as_object() else {
+            return;
+        };
+        if value.is_null() && rules.get("nullable").and_then(Value::as_bool) == Some(true) {
+            return;
+        }
+        for part in rules
+            .get("allOf")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            self.validate(part, value, at, depth + 1);
+        }
+        // Overlapping `oneOf` branches are common without a discriminator, so both
+        // combinators only report a value that matches none of their branches.
+        let combinators = ["anyOf", "oneOf"]
+            .into_iter()
+            .filter_map(|combinator| Some((combinator, rules.get(combinator)?.as_array()?)));
+        for (combinator, branches) in combinators {
+            if !branches
+                .iter()
+                .any(|branch| self.matches(branch, value, depth + 1))
+            {
+                self.fail(at, &format!("does not match any {combinator} schema"));
+            }
+        }
+        if let Some(allowed) = rules.get("enum").and_then(Value::as_array) {
+            if !allowed.contains(value) {
+                self.fail(at, &format!("{value} is not one of the allowed values"));
+            }
+        }
+        if let Some(expected) = rules.get("type") {
+            if !type_matches(expected, value) {
+                let expected = expected
+                    .as_str()
+                    .map_or_else(|| expected.to_string(), ToString::to_string);
+                self.fail(
+                    at,
+                    &format!("expected {expected}, got {}", type_name(value)),
+                );
+                return;
+            }
+        }
+        match value {
+            Value::String(text) => self.validate_string(rules, text, at),
+            Value::Number(number) => self.validate_number(rules, number, at),
+            Value::Array(items) => self.validate_array(rules, items, at, depth),
+            Value::Object(object) => self.validate_object(rules, object, at, depth),
+            Value::Null | Value::Bool(_) => {}
+        }
+    }
+
+    fn validate_string(&mut self, rules: &Map<String, Value>, text: &str, at: &str) {
+        let length = text.chars().count() as u64;
+        if let Some(min) = rules.get("minLength").and_then(Value::as_u64) {
+            if length < min {
+                self.fail(at, &format!("shorter than {min} characters"));
+            }
+        }
+        if let Some(max) = rules.get("maxLength").and_then(Value::as_u64) {
+            if length > max {
+                self.fail(at, &format!("longer than {max} characters"));
+            }
+        }
+        if let Some(pattern) = rules.get("pattern").and_then(Value::as_str) {
+            if Regex::new(pattern).is_ok_and(|regex| !regex.is_match(text)) {
+                self.fail(at, &format!("does not match pattern '{pattern}'"));
+            }
+        }
+    }
+
+    fn validate_number(&mut self, rules: &Map<String, Value>, number: &Number, at: &str) {
+        let Some(value) = number.as_f64() else {
+            return;
+        };
+        let exclusive = |key: &str| rules.get(key).and_then(Value::as_bool) == Some(true);
+        if let Some(min) = rules.get("minimum").and_then(Value::as_f64) {
+            if value < min || (exclusive("exclusiveMinimum") && value <= min) {
+                self.fail(at, &format!("{number} is below the minimum {min}"));
+            }
+        }
+        if let Some(max) = rules.get("maximum").and_then(Value::as_f64) {
+            if value > max || (exclusive("exclusiveMaximum") && value >= max) {
+                self.fail(at, &format!("{number} is above the maximum {max}"));
+            }
+        }
+    }
+
+    fn validate_array(
+        &mut self,
+        rules: &Map<String, Value>,
+        items: &[Value],
+        at: &str,
+        depth: usize,
+    ) {
+        let count = items.len() as u64;
+        if let Some(min) = rules.get("minItems").and_then(Value::as_u64) {
+            if count < min {
+                self.fail(at, &format!("fewer than {min} items"));
+            }
+        }
+        if let Some(max) = rules.get("maxItems").and_then(Value::as_u64) {
+            if count > max {
+                self.fail(at, &format!("more than {max} items"));
+            }
+        }
+        if let Some(schema) = rules.get("items") {
+            for (index, item) in items.iter().enumerate() {
+                self.validate(schema, item, &format!("{at}[{index}]"), depth + 1);
+            }
+        }
+    }
+
+    fn validate_object(
+        &mut self,
+        rules: &Map<String, Value>,
+        object: &Map<String, Value>,
+        at: &str,
+        depth: usize,
+    ) {
+        let properties = rules.get("properties").and_then(Value::as_object);
+        let required = rules.get("required").and_then(Value::as_array);
+        for name in required.into_iter().flatten().filter_map(Value::as_str) {
+            let property = properties
+                .and_then(|properties| properties.get(name))
+                .map(|property| self.contract.resolve(property));
+            if !object.contains_key(name) && !property.is_some_and(|p| self.one_way(p)) {
+                self.fail(at, &format!("missing required property '{name}'"));
+            }
+        }
+        for (name, value) in object {
+            let path = format!("{at}.{name}");
+            match (
+                properties.and_then(|properties| properties.get(name)),
+                rules.get("additionalProperties"),
+            ) {
+                (Some(schema), _) => self.validate(schema, value, &path, depth + 1),
+                (None, Some(Value::Bool(false))) => {
+                    self.fail(at, &format!("unexpected property '{name}'"));
+                }
+                (None, Some(schema @ Value::Object(_))) => {
+                    self.validate(schema, value, &path, depth + 1);
+                }
+                (None, _) => {}
+            }
+        }
+    }
+
+    // `readOnly` properties are never sent and `writeOnly` ones never returned, so
+    // their `required` only applies in the other direction.
+    fn one_way(&self, property: &Value) -> bool {
+        let flag = match self.direction {
+            Direction::Request => "readOnly",
+            Direction::Response => "writeOnly",
+        };
+        property.get(flag).and_then(Value::as_bool) == Some(true)
+    }
+}
+
+fn type_matches(expected: &Value, value: &Value) -> bool {
+    match expected {
+        Value::String(name) => type_is(name, value),
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|name| type_is(name, value)),
+        _ => true,
+    }
+}
+
+fn type_is(name: &str, value: &Value) -> bool {
+    match name {
+        "integer" => value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+const fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn media_entry<'a>(content: &'a Map<String, Value>, content_type: &str) -> Option<&'a Value> {
+    let family = content_type
+        .split_once('/')
+        .map(|(family, _)| format!("{family}/*"));
+    content
+        .iter()
+        .find(|(key, _)| media_type(Some(key)) == content_type)
+        .or_else(|| {
+            content
+                .iter()
+                .find(|(key, _)| Some(key.as_str()) == family.as_deref())
+        })
+        .or_else(|| content.iter().find(|(key, _)| key.as_str() == "*/*"))
+        .map(|(_, media)| media)
+}
+
+const fn violation(kind: ViolationKind, message: String) -> ContractViolation {
+    ContractViolation { kind, message }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn server_base_path(url: &str) -> Option<String> {
+    let path = url.split_once("://").map_or(url, |(_, rest)| {
+        rest.find('/').and_then(|at| rest.get(at..)).unwrap_or("")
+    });
+    let path = path.trim_end_matches('/');
+    (!path.is_empty() && path.starts_with('/')).then(|| path.to_string())
+}
+
+// YAML allows non-string keys such as `200:` under `responses`; JSON does not.
+fn to_json(value: YamlValue) -> Value {
+    match value {
+        YamlValue::Null => Value::Null,
+        YamlValue::Bool(flag) => Value::Bool(flag),
+        YamlValue::Number(number) => number
+            .as_u64()
+            .map(Number::from)
+            .or_else(|| number.as_i64().map(Number::from))
+            .or_else(|| number.as_f64().and_then(Number::from_f64))
+            .map_or(Value::Null, Value::Number),
+        YamlValue::String(text) => Value::String(text),
+        YamlValue::Sequence(items) => Value::Array(items.into_iter().map(to_json).collect()),
+        YamlValue::Mapping(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (yaml_key(key), to_json(value)))
+                .collect(),
+        ),
+        YamlValue::Tagged(tagged) => to_json(tagged.value),
+    }
+}
+
+fn yaml_key(key: YamlValue) -> String {
+    match key {
+        YamlValue::String(text) => text,
+        YamlValue::Number(number) => number.to_string(),
+        YamlValue::Bool(flag) => flag.to_string(),
+        other => serde_yaml::to_string(&other)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
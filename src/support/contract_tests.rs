@@ -0,0 +1,182 @@
+use super::contract::Contract;
+use super::fixtures::HttpCall;
+use crate::domain::traffic::{TrafficCall, ViolationKind};
+
+const SPEC: &str = r##"
+openapi: 3.0.3
+info: { title: api, version: "1" }
+servers:
+  - url: https://api.example.com/v1
+paths:
+  /users:
+    get:
+      parameters:
+        - { name: limit, in: query, required: true, schema: { type: integer } }
+      responses:
+        2XX:
+          description: ok
+          content:
+            application/json:
+              schema:
+                type: array
+                items: { $ref: "#/components/schemas/User" }
+    post:
+      requestBody:
+        content:
+          application/json:
+            schema: { $ref: "#/components/schemas/User" }
+      responses:
+        201: { description: created }
+  /users/me:
+    get:
+      responses:
+        200: { description: ok }
+  /users/{id}:
+    get:
+      responses:
+        200:
+          description: ok
+          content:
+            application/json:
+              schema: { $ref: "#/components/schemas/User" }
+        default: { description: error }
+components:
+  schemas:
+    User:
+      type: object
+      additionalProperties: false
+      required: [id, name]
+      properties:
+        id: { type: integer, readOnly: true }
+        name: { type: string, minLength: 1 }
+        email: { type: string, nullable: true }
+        role: { type: string, enum: [admin, member] }
+"##;
+
+fn with_response(call: HttpCall, body: &str) -> TrafficCall {
+    call.response_header("content-type", "application/json")
+        .response_body(body)
+        .call(1)
+}
+
+fn with_request(call: HttpCall, content_type: &str, body: &str) -> TrafficCall {
+    call.request_header("content-type", content_type)
+        .request_body(body)
+        .call(1)
+}
+
+fn messages(contract: &Contract, call: &TrafficCall) -> Vec<String> {
+    contract
+        .check(call)
+        .into_iter()
+        .map(|violation| format!("{}: {}", violation.kind.as_str(), violation.message))
+        .collect()
+}
+
+#[test]
+fn routes_methods_and_statuses_are_checked() {
+    let contract = Contract::parse(SPEC);
+    assert!(contract.is_ok());
+    let Ok(contract) = contract else {
+        return;
+    };
+    let kinds = |call: HttpCall| -> Vec<ViolationKind> {
+        contract
+            .check(&call.call(1))
+            .into_iter()
+            .map(|violation| violation.kind)
+            .collect()
+    };
+    assert!(kinds(HttpCall::get("/v1/users?limit=5").status(204)).is_empty());
+    assert!(kinds(HttpCall::get("/users/me")).is_empty());
+    assert!(kinds(HttpCall::new("HEAD", "/users/42")).is_empty());
+    assert!(kinds(HttpCall::get("/users/42").status(503)).is_empty());
+    assert_eq!(
+        messages(&contract, &HttpCall::get("/orders").call(1)),
+        vec!["unknown_route: no path in the contract matches /orders".to_string()]
+    );
+    assert_eq!(
+        messages(
+            &contract,
+            &HttpCall::new("DELETE", "/users/42").status(204).call(1)
+        ),
+        vec!["wrong_method: DELETE is not documented for /users/{id}".to_string()]
+    );
+    assert_eq!(
+        messages(&contract, &HttpCall::new("POST", "/users").call(1)),
+        vec!["undocumented_status: status 200 is not documented for POST /users".to_string()]
+    );
+    assert_eq!(
+        messages(&contract, &HttpCall::get("/users").call(1)),
+        vec!["request_schema: missing required query parameter 'limit'".to_string()]
+    );
+}
+
+#[test]
+fn bodies_are_validated_against_schemas() {
+    let contract = Contract::parse(SPEC);
+    assert!(contract.is_ok());
+    let Ok(contract) = contract else {
+        return;
+    };
+    let valid = with_response(
+        HttpCall::get("/users/7"),
+        r#"{"id":7,"name":"ada","email":null}"#,
+    );
+    assert!(contract.check(&valid).is_empty());
+
+    let invalid = with_response(
+        HttpCall::get("/users/7"),
+        r#"{"id":"7","role":"owner","extra":true}"#,
+    );
+    assert_eq!(
+        messages(&contract, &invalid),
+        vec![
+            "response_schema: response $: missing required property 'name'".to_string(),
+            "response_schema: response $: unexpected property 'extra'".to_string(),
+            "response_schema: response $.id: expected integer, got string".to_string(),
+            "response_schema: response $.role: \"owner\" is not one of the allowed values"
+                .to_string(),
+        ]
+    );
+
+    let listed = with_response(HttpCall::get("/users?limit=1"), r#"[{"id":1,"name":""}]"#);
+    assert_eq!(
+        messages(&contract, &listed),
+        vec!["response_schema: response $[0].name: shorter than 1 characters".to_string()]
+    );
+
+    // `id` is read-only, so a create request may leave it out.
+    let created = with_request(
+        HttpCall::new("POST", "/users").status(201),
+        "application/json",
+        r#"{"name":"ada"}"#,
+    );
+    assert!(contract.check(&created).is_empty());
+    let redacted = with_response(
+        HttpCall::get("/users/7"),
+        r#"{"id":"[redacted]","name":"ada"}"#,
+    );
+    assert!(contract.check(&redacted).is_empty());
+    let truncated = with_request(
+        HttpCall::new("POST", "/users").status(201),
+        "application/json",
+        r#"{"na"#,
+    );
+    assert!(contract.check(&truncated).is_empty());
+    let text = with_request(
+        HttpCall::new("POST", "/users").status(201),
+        "text/plain",
+        "ada",
+    );
+    assert_eq!(
+        messages(&contract, &text),
+        vec!["request_schema: request content type 'text/plain' is not documented".to_string()]
+    );
+}
+
+#[test]
+fn only_openapi_3_documents_are_accepted() {
+    assert!(Contract::parse("swagger: '2.0'\npaths: {}\n").is_err());
+    assert!(Contract::parse("openapi: [").is_err());
+}
@@ -0,0 +1,678 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::domain::traffic::DbSystem;
+
+// A single message larger than this means the stream is not the protocol we expected.
+const MAX_BUFFERED_BYTES: usize = 64 * 1024 * 1024;
+const MAX_OPERATION_CHARS: usize = 32;
+
+// Postgres startup codes that are answered with a single byte instead of a message.
+const PG_SSL_REQUEST: u32 = 80_877_103;
+const PG_GSSENC_REQUEST: u32 = 80_877_104;
+
+const MYSQL_CLIENT_SSL: u32 = 0x0800;
+const MYSQL_DEPRECATE_EOF: u32 = 0x0100_0000;
+const MYSQL_COM_QUERY: u8 = 0x03;
+const MYSQL_COM_STMT_PREPARE: u8 = 0x16;
+const MYSQL_COM_STMT_EXECUTE: u8 = 0x17;
+const MYSQL_COM_STMT_CLOSE: u8 = 0x19;
+
+// A statement or command matched with its reply.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DbStatement {
+    pub operation: String,
+    pub table: Option<String>,
+    pub error: Option<String>,
+    pub started_ms: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Command {
+    operation: String,
+    table: Option<String>,
+    started_ms: u64,
+}
+
+impl Command {
+    fn sql(sql: &str, at_ms: u64) -> Self {
+        let (operation, table) = classify_sql(sql);
+        Self {
+            operation,
+            table,
+            started_ms: at_ms,
+        }
+    }
+
+    fn named(operation: &str, at_ms: u64) -> Self {
+        Self {
+            operation: operation.to_string(),
+            table: None,
+            started_ms: at_ms,
+        }
+    }
+
+    fn finish(self, at_ms: u64, error: Option<String>) -> DbStatement {
+        DbStatement {
+            operation: self.operation,
+            table: self.table,
+            error,
+            started_ms: self.started_ms,
+            duration_ms: at_ms.saturating_sub(self.started_ms),
+        }
+    }
+}
+
+enum Step {
+    Wait,
+    Took(usize),
+    Lost,
+}
+
+// How much of a message the buffer holds.
+enum Frame<T> {
+    Partial,
+    Invalid,
+    Complete(T),
+}
+
+impl<T> Frame<T> {
+    // The message, or the step to take while there is none.
+    fn ready(self) -> Result<T, Step> {
+        match self {
+            Self::Partial => Err(Step::Wait),
+            Self::Invalid => Err(Step::Lost),
+            Self::Complete(value) => Ok(value),
+        }
+    }
+}
+
+trait Wire: Send {
+    fn client(&mut self, buf: &[u8], at_ms: u64) -> Step;
+    fn server(&mut self, buf: &[u8], at_ms: u64, out: &mut Vec<DbStatement>) -> Step;
+}
+
+// Follows one connection from its first byte. Once the stream can no longer be followed
+// (TLS, a truncated capture, unexpected framing) the rest of the connection is ignored.
+pub struct DbDecoder {
+    wire: Box<dyn Wire>,
+    client: Vec<u8>,
+    server: Vec<u8>,
+    lost: bool,
+}
+
+impl DbDecoder {
+    pub fn new(system: DbSystem) -> Self {
+        let wire: Box<dyn Wire> = match system {
+            DbSystem::Postgres => Box::<Postgres>::default(),
+            DbSystem::Mysql => Box::<Mysql>::default(),
+            DbSystem::Redis => Box::<Redis>::default(),
+        };
+        Self {
+            wire,
+            client: Vec::new(),
+            server: Vec::new(),
+            lost: false,
+        }
+    }
+
+    pub fn lose(&mut self) {
+        self.lost = true;
+        self.client = Vec::new();
+        self.server = Vec::new();
+    }
+
+    pub fn client(&mut self, at_ms: u64, data: &[u8]) {
+        self.feed(true, at_ms, data, &mut Vec::new());
+    }
+
+    pub fn server(&mut self, at_ms: u64, data: &[u8]) -> Vec<DbStatement> {
+        let mut out = Vec::new();
+        self.feed(false, at_ms, data, &mut out);
+        out
+    }
+
+    fn feed(&mut self, from_client: bool, at_ms: u64, data: &[u8], out: &mut Vec<DbStatement>) {
+        if self.lost {
+            return;
+        }
+        let Self {
+            wire,
+            client,
+            server,
+            ..
+        } = self;
+        let buf = if from_client { client } else { server };
+        buf.extend_from_slice(data);
+        let mut offset = 0;
+        while let Some(rest) = buf.get(offset..).filter(|rest| !rest.is_empty()) {
+            let step = if from_client {
+                wire.client(rest, at_ms)
+            } else {
+                wire.server(rest, at_ms, out)
+            };
+            match step {
+                Step::Wait => break,
+                Step::Took(len) => offset += len,
+                Step::Lost => {
+                    self.lose();
+                    return;
+                }
+            }
+        }
+        buf.drain(..offset);
+        if buf.len() > MAX_BUFFERED_BYTES {
+            self.lose();
+        }
+    }
+}
+
+// Frontend messages queue commands; each completion or error answers the oldest one. A
+// Sync (or the end of a simple query) is queued as `None`, and ReadyForQuery drops whatever
+// the server skipped before it.
+#[derive(Default)]
+struct Postgres {
+    started: bool,
+    awaiting_ssl: bool,
+    statements: HashMap<String, String>,
+    portals: HashMap<String, String>,
+    pending: VecDeque<Option<Command>>,
+}
+
+impl Postgres {
+    fn startup(&mut self, buf: &[u8]) -> Step {
+        let (Some(len), Some(code)) = (be_u32(buf, 0), be_u32(buf, 4)) else {
+            return Step::Wait;
+        };
+        let len = len as usize;
+        if len < 8 {
+            return Step::Lost;
+        }
+        if buf.len() < len {
+            return Step::Wait;
+        }
+        if matches!(code, PG_SSL_REQUEST | PG_GSSENC_REQUEST) {
+            self.awaiting_ssl = true;
+        } else {
+            self.started = true;
+        }
+        Step::Took(len)
+    }
+
+    fn frontend(&mut self, tag: u8, body: &[u8], at_ms: u64) {
+        match tag {
+            b'Q' => {
+                let query = cstring(body).map_or_else(String::new, |(query, _)| query);
+                self.pending.push_back(Some(Command::sql(&query, at_ms)));
+                self.pending.push_back(None);
+            }
+            b'P' => {
+                if let Some((name, rest)) = cstring(body) {
+                    let query = cstring(rest).map_or_else(String::new, |(query, _)| query);
+                    self.statements.insert(name, query);
+                }
+            }
+            b'B' => {
+                if let Some((portal, rest)) = cstring(body) {
+                    let query = cstring(rest)
+                        .and_then(|(name, _)| self.statements.get(&name).cloned())
+                        .unwrap_or_default();
+                    self.portals.insert(portal, query);
+                }
+            }
+            b'E' => {
+                let command = cstring(body)
+                    .and_then(|(portal, _)| self.portals.get(&portal))
+                    .map_or_else(
+                        || Command::named("EXECUTE", at_ms),
+                        |query| Command::sql(query, at_ms),
+                    );
+                self.pending.push_back(Some(command));
+            }
+            b'S' => self.pending.push_back(None),
+            _ => {}
+        }
+    }
+
+    fn complete(&mut self, at_ms: u64, error: Option<String>, out: &mut Vec<DbStatement>) {
+        if !matches!(self.pending.front(), Some(Some(_))) {
+            return;
+        }
+        if let Some(Some(command)) = self.pending.pop_front() {
+            out.push(command.finish(at_ms, error));
+        }
+    }
+}
+
+impl Wire for Postgres {
+    fn client(&mut self, buf: &[u8], at_ms: u64) -> Step {
+        if !self.started {
+            return self.startup(buf);
+        }
+        let (tag, body, len) = match pg_message(buf).ready() {
+            Ok(message) => message,
+            Err(step) => return step,
+        };
+        self.frontend(tag, body, at_ms);
+        Step::Took(len)
+    }
+
+    fn server(&mut self, buf: &[u8], at_ms: u64, out: &mut Vec<DbStatement>) -> Step {
+        if self.awaiting_ssl {
+            self.awaiting_ssl = false;
+            return if buf.first() == Some(&b'N') {
+                Step::Took(1)
+            } else {
+                Step::Lost
+            };
+        }
+        let (tag, body, len) = match pg_message(buf).ready() {
+            Ok(message) => message,
+            Err(step) => return step,
+        };
+        match tag {
+            b'C' | b'I' => self.complete(at_ms, None, out),
+            b'E' => self.complete(at_ms, Some(pg_error_code(body)), out),
+            b'Z' => while let Some(Some(_)) = self.pending.pop_front() {},
+            _ => {}
+        }
+        Step::Took(len)
+    }
+}
+
+// A type byte, then a length that counts itself but not the type.
+fn pg_message(buf: &[u8]) -> Frame<(u8, &[u8], usize)> {
+    let Some(len) = be_u32(buf, 1) else {
+        return Frame::Partial;
+    };
+    let len = len as usize;
+    if len < 4 {
+        return Frame::Invalid;
+    }
+    match (buf.first(), buf.get(5..=len)) {
+        (Some(tag), Some(body)) => Frame::Complete((*tag, body, len + 1)),
+        _ => Frame::Partial,
+    }
+}
+
+// ErrorResponse fields are a type byte followed by a string; `C` holds the SQLSTATE.
+fn pg_error_code(mut body: &[u8]) -> String {
+    while let Some((&field, rest)) = body.split_first() {
+        let Some((value, rest)) = cstring(rest) else {
+            break;
+        };
+        if field == b'C' {
+            return value;
+        }
+        body = rest;
+    }
+    "ERROR".to_string()
+}
+
+// Every command starts a new packet sequence. OK and ERR replies end it at once; a result set
+// ends with its last EOF (or the OK that replaces it when the client deprecates EOF).
+#[derive(Default)]
+struct Mysql {
+    authenticated: bool,
+    capabilities: Option<u32>,
+    statements: HashMap<u32, String>,
+    preparing: Option<String>,
+    current: Option<Command>,
+    eofs_left: Option<u8>,
+}
+
+impl Mysql {
+    fn command(&mut self, payload: &[u8], at_ms: u64) {
+        self.current = None;
+        self.preparing = None;
+        self.eofs_left = None;
+        let Some((&code, rest)) = payload.split_first() else {
+            return;
+        };
+        match code {
+            MYSQL_COM_QUERY => {
+                self.current = Some(Command::sql(&String::from_utf8_lossy(rest), at_ms));
+            }
+            MYSQL_COM_STMT_PREPARE => {
+                self.preparing = Some(String::from_utf8_lossy(rest).into_owned());
+            }
+            MYSQL_COM_STMT_EXECUTE => {
+                let query = le_u32(rest).and_then(|id| self.statements.get(&id));
+                self.current = Some(query.map_or_else(
+                    || Command::named("EXECUTE", at_ms),
+                    |query| Command::sql(query, at_ms),
+                ));
+            }
+            MYSQL_COM_STMT_CLOSE => {
+                if let Some(id) = le_u32(rest) {
+                    self.statements.remove(&id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn reply(&mut self, payload: &[u8], at_ms: u64, out: &mut Vec<DbStatement>) {
+        if let Some(query) = self.preparing.take() {
+            let prepared = payload.split_first().filter(|(first, _)| **first == 0x00);
+            if let Some(id) = prepared.and_then(|(_, rest)| le_u32(rest)) {
+                self.statements.insert(id, query);
+            }
+            return;
+        }
+        let Some(first) = payload.first().copied() else {
+            return;
+        };
+        let done = match (first, self.eofs_left) {
+            (0xff, _) => Some(Some(
+                payload
+                    .get(1..3)
+                    .and_then(|code| <[u8; 2]>::try_from(code).ok())
+                    .map_or(0, u16::from_le_bytes)
+                    .to_string(),
+            )),
+            (0x00, None) => Some(None),
+            (0xfb, None) => None,
+            (_, None) => {
+                let deprecate_eof = self
+                    .capabilities
+                    .is_some_and(|caps| caps & MYSQL_DEPRECATE_EOF != 0);
+                self.eofs_left = Some(if deprecate_eof { 1 } else { 2 });
+                None
+            }
+            (0xfe, Some(left)) if payload.len() < 0x00ff_ffff => {
+                self.eofs_left = Some(left.saturating_sub(1));
+                (left <= 1).then_some(None)
+            }
+            (_, Some(_)) => None,
+        };
+        if let Some(error) = done {
+            self.eofs_left = None;
+            if let Some(command) = self.current.take() {
+                out.push(command.finish(at_ms, error));
+            }
+        }
+    }
+}
+
+impl Wire for Mysql {
+    fn client(&mut self, buf: &[u8], at_ms: u64) -> Step {
+        let Some((seq, payload, len)) = mysql_packet(buf) else {
+            return Step::Wait;
+        };
+        if self.authenticated {
+            if seq == 0 {
+                self.command(payload, at_ms);
+            }
+        } else if self.capabilities.is_none() {
+            let caps = le_u32(payload).unwrap_or(0);
+            if caps & MYSQL_CLIENT_SSL != 0 {
+                return Step::Lost;
+            }
+            self.capabilities = Some(caps);
+        }
+        Step::Took(len)
+    }
+
+    fn server(&mut self, buf: &[u8], at_ms: u64, out: &mut Vec<DbStatement>) -> Step {
+        let Some((seq, payload, len)) = mysql_packet(buf) else {
+            return Step::Wait;
+        };
+        if self.authenticated {
+            self.reply(payload, at_ms, out);
+        } else if seq > 0 && payload.first() == Some(&0x00) {
+            self.authenticated = true;
+        }
+        Step::Took(len)
+    }
+}
+
+// A three byte little-endian length and a sequence number.
+fn mysql_packet(buf: &[u8]) -> Option<(u8, &[u8], usize)> {
+    let (&[low, mid, high, seq], rest) = buf.split_first_chunk::<4>()?;
+    let len = usize::from(low) | usize::from(mid) << 8 | usize::from(high) << 16;
+    let payload = rest.get(..len)?;
+    Some((seq, payload, 4 + len))
+}
+
+// Replies arrive in command order; pushes (RESP3 `>`) and attributes are not replies.
+#[derive(Default)]
+struct Redis {
+    pending: VecDeque<Command>,
+}
+
+impl Wire for Redis {
+    fn client(&mut self, buf: &[u8], at_ms: u64) -> Step {
+        if buf.first() != Some(&b'*') {
+            let Some(end) = buf.iter().position(|byte| *byte == b'\n') else {
+                return Step::Wait;
+            };
+            let line = buf
+                .get(..end)
+                .map(String::from_utf8_lossy)
+                .unwrap_or_default();
+            if let Some(name) = line.split_whitespace().next() {
+                self.pending
+                    .push_back(Command::named(&operation_name(name), at_ms));
+            }
+            return Step::Took(end + 1);
+        }
+        let len = match resp_value(buf).ready() {
+            Ok(len) => len,
+            Err(step) => return step,
+        };
+        let name = resp_first_bulk(buf).unwrap_or_else(|| "UNKNOWN".to_string());
+        self.pending
+            .push_back(Command::named(&operation_name(&name), at_ms));
+        Step::Took(len)
+    }
+
+    fn server(&mut self, buf: &[u8], at_ms: u64, out: &mut Vec<DbStatement>) -> Step {
+        let len = match resp_value(buf).ready() {
+            Ok(len) => len,
+            Err(step) => return step,
+        };
+        let error = match buf.get(..len).and_then(<[u8]>::split_first) {
+            Some((b'>' | b'|', _)) => return Step::Took(len),
+            Some((b'-', message)) => Some(redis_error(message)),
+            Some((b'!', blob)) => Some(redis_error(
+                blob.iter()
+                    .position(|byte| *byte == b'\n')
+                    .and_then(|start| blob.get(start + 1..))
+                    .unwrap_or_default(),
+            )),
+            _ => None,
+        };
+        if let Some(command) = self.pending.pop_front() {
+            out.push(command.finish(at_ms, error));
+        }
+        Step::Took(len)
+    }
+}
+
+// Length of the complete RESP value at the start of `buf`.
+fn resp_value(buf: &[u8]) -> Frame<usize> {
+    let Some(line_end) = buf.windows(2).position(|pair| pair == b"\r\n") else {
+        return Frame::Partial;
+    };
+    let Some((&kind, header)) = buf.get(..line_end).and_then(<[u8]>::split_first) else {
+        return Frame::Invalid;
+    };
+    let pos = line_end + 2;
+    let count = std::str::from_utf8(header)
+        .ok()
+        .and_then(|header| header.trim().parse::<i64>().ok())
+        .map(usize::try_from);
+    match (kind, count) {
+        (b'+' | b'-' | b':' | b'_' | b',' | b'#' | b'(', _) => Frame::Complete(pos),
+        (b'$' | b'!' | b'=' | b'*' | b'~' | b'>' | b'%' | b'|', Some(Err(_))) => {
+            Frame::Complete(pos)
+        }
+        (b'$' | b'!' | b'=', Some(Ok(len))) => {
+            let end = pos.saturating_add(len).saturating_add(2);
+            if buf.len() >= end {
+                Frame::Complete(end)
+            } else {
+                Frame::Partial
+            }
+        }
+        (b'*' | b'~' | b'>', Some(Ok(items))) => resp_items(buf, pos, items),
+        (b'%' | b'|', Some(Ok(pairs))) => resp_items(buf, pos, pairs.saturating_mul(2)),
+        _ => Frame::Invalid,
+    }
+}
+
+fn resp_items(buf: &[u8], mut pos: usize, items: usize) -> Frame<usize> {
+    for _ in 0..items {
+        match resp_value(buf.get(pos..).unwrap_or_default()) {
+            Frame::Complete(len) => pos += len,
+            other => return other,
+        }
+    }
+    Frame::Complete(pos)
+}
+
+fn resp_first_bulk(buf: &[u8]) -> Option<String> {
+    let start = buf.windows(2).position(|pair| pair == b"\r\n")? + 2;
+    let rest = buf.get(start..)?;
+    let line_end = rest.windows(2).position(|pair| pair == b"\r\n")?;
+    let len: usize = std::str::from_utf8(rest.get(1..line_end)?)
+        .ok()?
+        .parse()
+        .ok()?;
+    let value = rest.get(line_end + 2..line_end + 2 + len)?;
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+fn redis_error(message: &[u8]) -> String {
+    String::from_utf8_lossy(message)
+        .split_whitespace()
+        .next()
+        .unwrap_or("ERR")
+        .to_string()
+}
+
+fn operation_name(word: &str) -> String {
+    word.chars()
+        .take(MAX_OPERATION_CHARS)
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// Statement type and main table of a SQL statement, read from its top-level keywords. A
+// leading `WITH` is skipped to the statement its CTEs feed.
+pub fn classify_sql(sql: &str) -> (String, Option<String>) {
+    let words = sql_words(sql);
+    let top = |index: usize| words.get(index).filter(|(depth, _)| *depth == 0);
+    let Some((_, first)) = top(0) else {
+        return ("UNKNOWN".to_string(), None);
+    };
+    let mut start = 0;
+    let mut operation = operation_name(first);
+    if operation == "WITH" {
+        if let Some(index) = (1..words.len()).find(|index| {
+            top(*index).is_some_and(|(_, word)| {
+                ["SELECT", "INSERT", "UPDATE", "DELETE"]
+                    .iter()
+                    .any(|keyword| word.eq_ignore_ascii_case(keyword))
+            })
+        }) {
+            start = index;
+            operation = top(index).map_or(operation, |(_, word)| operation_name(word));
+        }
+    }
+    let after = |keyword: &str| {
+        (start..words.len())
+            .find(|index| top(*index).is_some_and(|(_, word)| word.eq_ignore_ascii_case(keyword)))
+            .and_then(|index| top(index + 1))
+            .map(|(_, word)| word.clone())
+    };
+    let table = match operation.as_str() {
+        "SELECT" | "DELETE" => after("FROM"),
+        "INSERT" | "REPLACE" => after("INTO"),
+        "UPDATE" | "COPY" | "TRUNCATE" => top(start + 1)
+            .map(|(_, word)| word.clone())
+            .filter(|word| !word.eq_ignore_ascii_case("TABLE")),
+        _ => None,
+    };
+    (operation, table.and_then(|table| table_name(&table)))
+}
+
+// Words with their parenthesis depth; comments and string literals are skipped and quoted
+// identifiers keep their quotes.
+fn sql_words(sql: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut depth = 0_usize;
+    let mut chars = sql.chars().peekable();
+    let mut flush = |word: &mut String, depth: usize| {
+        if !word.is_empty() {
+            words.push((depth, std::mem::take(word)));
+        }
+    };
+    while let Some(ch) = chars.next() {
+        match ch {
+            '-' if chars.peek() == Some(&'-') => {
+                flush(&mut word, depth);
+                chars.by_ref().find(|ch| *ch == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                flush(&mut word, depth);
+                let mut previous = ' ';
+                chars.by_ref().find(|ch| {
+                    let end = previous == '*' && *ch == '/';
+                    previous = *ch;
+                    end
+                });
+            }
+            '\'' => {
+                flush(&mut word, depth);
+                chars.by_ref().find(|ch| *ch == '\'');
+            }
+            '"' | '`' => {
+                let quoted: String = chars.by_ref().take_while(|next| *next != ch).collect();
+                word.push(ch);
+                word.push_str(&quoted);
+                word.push(ch);
+            }
+            '(' | ')' => {
+                flush(&mut word, depth);
+                depth = if ch == '(' {
+                    depth + 1
+                } else {
+                    depth.saturating_sub(1)
+                };
+            }
+            ',' | ';' => flush(&mut word, depth),
+            ch if ch.is_whitespace() || ch.is_control() => flush(&mut word, depth),
+            ch => word.push(ch),
+        }
+    }
+    flush(&mut word, depth);
+    words
+}
+
+fn table_name(raw: &str) -> Option<String> {
+    let name: String = raw.chars().filter(|ch| !matches!(ch, '"' | '`')).collect();
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_alphabetic() || ch == '_');
+    valid.then_some(name)
+}
+
+fn cstring(buf: &[u8]) -> Option<(String, &[u8])> {
+    let mut parts = buf.splitn(2, |byte| *byte == 0);
+    let text = parts.next()?;
+    let rest = parts.next()?;
+    Some((String::from_utf8_lossy(text).into_owned(), rest))
+}
+
+fn be_u32(buf: &[u8], at: usize) -> Option<u32> {
+    let bytes = buf.get(at..)?.first_chunk::<4>()?;
+    Some(u32::from_be_bytes(*bytes))
+}
+
+fn le_u32(buf: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(*buf.first_chunk::<4>()?))
+}
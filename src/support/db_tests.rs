@@ -0,0 +1,192 @@
+use super::db::{classify_sql, DbDecoder, DbStatement};
+use crate::domain::traffic::DbSystem;
+
+fn sql(statement: &str) -> (String, Option<String>) {
+    classify_sql(statement)
+}
+
+fn classified(operation: &str, table: Option<&str>) -> (String, Option<String>) {
+    (operation.to_string(), table.map(ToString::to_string))
+}
+
+fn statement(
+    operation: &str,
+    table: Option<&str>,
+    error: Option<&str>,
+) -> (String, Option<String>, Option<String>) {
+    (
+        operation.to_string(),
+        table.map(ToString::to_string),
+        error.map(ToString::to_string),
+    )
+}
+
+fn summary(statements: &[DbStatement]) -> Vec<(String, Option<String>, Option<String>)> {
+    statements
+        .iter()
+        .map(|statement| {
+            (
+                statement.operation.clone(),
+                statement.table.clone(),
+                statement.error.clone(),
+            )
+        })
+        .collect()
+}
+
+fn pg(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = vec![tag];
+    message.extend_from_slice(&u32::try_from(body.len() + 4).unwrap_or(0).to_be_bytes());
+    message.extend_from_slice(body);
+    message
+}
+
+fn pg_startup(code: u32, body: &[u8]) -> Vec<u8> {
+    let mut message = u32::try_from(body.len() + 8)
+        .unwrap_or(0)
+        .to_be_bytes()
+        .to_vec();
+    message.extend_from_slice(&code.to_be_bytes());
+    message.extend_from_slice(body);
+    message
+}
+
+fn mysql(seq: u8, payload: &[u8]) -> Vec<u8> {
+    let len = u32::try_from(payload.len()).unwrap_or(0).to_le_bytes();
+    let mut packet = vec![len[0], len[1], len[2], seq];
+    packet.extend_from_slice(payload);
+    packet
+}
+
+#[test]
+fn sql_statements_are_classified_by_type_and_table() {
+    assert_eq!(
+        sql("SELECT id, (SELECT count(*) FROM posts) FROM users WHERE id = $1"),
+        classified("SELECT", Some("users"))
+    );
+    assert_eq!(
+        sql("insert into \"public\".\"orders\" (id) values ('a from b')"),
+        classified("INSERT", Some("public.orders"))
+    );
+    assert_eq!(
+        sql("/* app */ -- note\n delete from `sessions` where expired"),
+        classified("DELETE", Some("sessions"))
+    );
+    assert_eq!(
+        sql("WITH stale AS (SELECT id FROM carts) UPDATE carts SET done = true"),
+        classified("UPDATE", Some("carts"))
+    );
+    assert_eq!(sql("begin"), classified("BEGIN", None));
+    assert_eq!(sql("SELECT 1"), classified("SELECT", None));
+    assert_eq!(sql("  "), classified("UNKNOWN", None));
+}
+
+#[test]
+fn postgres_queries_are_matched_with_their_replies() {
+    let mut decoder = DbDecoder::new(DbSystem::Postgres);
+    decoder.client(0, &pg_startup(80_877_103, &[]));
+    assert!(decoder.server(1, b"N").is_empty());
+    decoder.client(2, &pg_startup(196_608, b"user\0app\0\0"));
+    let mut ready = pg(b'R', &[0, 0, 0, 0]);
+    ready.extend(pg(b'Z', b"I"));
+    assert!(decoder.server(3, &ready).is_empty());
+
+    decoder.client(10, &pg(b'Q', b"SELECT * FROM users\0"));
+    let mut reply = pg(b'T', &[0, 0]);
+    reply.extend(pg(b'C', b"SELECT 0\0"));
+    let (head, tail) = reply.split_at(4);
+    assert!(decoder.server(12, head).is_empty());
+    let done = decoder.server(14, tail);
+    assert_eq!(
+        summary(&done),
+        vec![statement("SELECT", Some("users"), None)]
+    );
+    assert_eq!(done.first().map(|statement| statement.duration_ms), Some(4));
+    assert!(decoder.server(15, &pg(b'Z', b"I")).is_empty());
+
+    let mut pipeline = pg(b'P', b"s1\0INSERT INTO orders VALUES ($1)\0\0\0");
+    pipeline.extend(pg(b'B', b"\0s1\0\0\0\0\0\0\0"));
+    pipeline.extend(pg(b'E', b"\0\0\0\0\0"));
+    pipeline.extend(pg(b'S', b""));
+    pipeline.extend(pg(b'B', b"\0s1\0\0\0\0\0\0\0"));
+    pipeline.extend(pg(b'E', b"\0\0\0\0\0"));
+    pipeline.extend(pg(b'S', b""));
+    decoder.client(20, &pipeline);
+    let mut reply = pg(b'1', b"");
+    reply.extend(pg(b'2', b""));
+    reply.extend(pg(b'E', b"SERROR\0C23505\0Mduplicate key\0\0"));
+    reply.extend(pg(b'Z', b"I"));
+    reply.extend(pg(b'2', b""));
+    reply.extend(pg(b'C', b"INSERT 0 1\0"));
+    reply.extend(pg(b'Z', b"I"));
+    assert_eq!(
+        summary(&decoder.server(25, &reply)),
+        vec![
+            statement("INSERT", Some("orders"), Some("23505")),
+            statement("INSERT", Some("orders"), None),
+        ]
+    );
+}
+
+#[test]
+fn mysql_commands_end_with_ok_err_or_the_last_eof() {
+    let mut decoder = DbDecoder::new(DbSystem::Mysql);
+    assert!(decoder.server(0, &mysql(0, b"\x0a8.0.36\0")).is_empty());
+    let mut handshake = 0x0000_0200_u32.to_le_bytes().to_vec();
+    handshake.extend_from_slice(&[0; 28]);
+    decoder.client(1, &mysql(1, &handshake));
+    assert!(decoder
+        .server(2, &mysql(2, &[0, 0, 0, 2, 0, 0, 0]))
+        .is_empty());
+
+    decoder.client(10, &mysql(0, b"\x03SELECT name FROM users"));
+    let mut reply = mysql(1, &[1]);
+    reply.extend(mysql(2, b"\x03def"));
+    reply.extend(mysql(3, &[0xfe, 0, 0, 2, 0]));
+    reply.extend(mysql(4, b"\x03ada"));
+    assert!(decoder.server(11, &reply).is_empty());
+    let done = decoder.server(13, &mysql(5, &[0xfe, 0, 0, 2, 0]));
+    assert_eq!(
+        summary(&done),
+        vec![statement("SELECT", Some("users"), None)]
+    );
+    assert_eq!(done.first().map(|statement| statement.duration_ms), Some(3));
+
+    decoder.client(20, &mysql(0, b"\x16UPDATE accounts SET name = ?"));
+    let prepared = [0, 7, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    assert!(decoder.server(21, &mysql(1, &prepared)).is_empty());
+    decoder.client(22, &mysql(0, &[0x17, 7, 0, 0, 0, 0, 1, 0, 0, 0]));
+    let mut error = vec![0xff, 0x26, 0x04, b'#'];
+    error.extend_from_slice(b"23000Duplicate entry");
+    assert_eq!(
+        summary(&decoder.server(23, &mysql(1, &error))),
+        vec![statement("UPDATE", Some("accounts"), Some("1062"))]
+    );
+}
+
+#[test]
+fn redis_replies_follow_pipelined_commands() {
+    let mut decoder = DbDecoder::new(DbSystem::Redis);
+    decoder.client(
+        0,
+        b"*2\r\n$3\r\nget\r\n$7\r\nuser:42\r\n*3\r\n$4\r\nHSET\r\n$1\r\nh\r\n$1\r\nv\r\nPING\r\n",
+    );
+    let replies: &[u8] = b"$-1\r\n>2\r\n$7\r\nmessage\r\n$1\r\nx\r\n-WRONGTYPE Operation\r\n+PO";
+    assert_eq!(
+        summary(&decoder.server(2, replies)),
+        vec![
+            statement("GET", None, None),
+            statement("HSET", None, Some("WRONGTYPE")),
+        ]
+    );
+    assert_eq!(
+        summary(&decoder.server(3, b"NG\r\n")),
+        vec![statement("PING", None, None)]
+    );
+
+    let mut encrypted = DbDecoder::new(DbSystem::Postgres);
+    encrypted.client(0, &pg_startup(80_877_103, &[]));
+    assert!(encrypted.server(1, b"S").is_empty());
+    encrypted.client(2, &pg(b'Q', b"SELECT 1\0"));
+    assert!(encrypted.server(3, &pg(b'C', b"SELECT 1\0")).is_empty());
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::net::IpAddr;
+
+use crate::domain::traffic::{EntityId, Observation, ObservationAttrs};
+use crate::support::constants::{
+    EGRESS_POLICY_HOSTS_LABEL, EGRESS_POLICY_MODE_LABEL, POLICY_BLOCKED, POLICY_REPORTED,
+    POLICY_TAG,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PolicyMode {
+    Allow,
+    Deny,
+    Report,
+}
+
+impl PolicyMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "allow" => Some(Self::Allow),
+            "deny" => Some(Self::Deny),
+            "report" => Some(Self::Report),
+            _ => None,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+            Self::Report => "report",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostPattern {
+    Host(String),
+    Cidr(IpAddr, u8),
+}
+
+impl HostPattern {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let value = raw.trim().to_lowercase();
+        if let Some((addr, len)) = value.split_once('/') {
+            let ip = addr
+                .parse::<IpAddr>()
+                .map_err(|_| format!("invalid CIDR '{raw}'"))?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+            let len = len
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max)
+                .ok_or_else(|| format!("invalid CIDR '{raw}'"))?;
+            return Ok(Self::Cidr(ip, len));
+        }
+        if let Ok(ip) = value.parse::<IpAddr>() {
+            let len = if ip.is_ipv4() { 32 } else { 128 };
+            return Ok(Self::Cidr(ip, len));
+        }
+        let name = value.strip_prefix("*.").unwrap_or(&value);
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '.');
+        if !valid {
+            return Err(format!("invalid host pattern '{raw}'"));
+        }
+        Ok(Self::Host(value))
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            Self::Host(pattern) => pattern.strip_prefix("*.").map_or_else(
+                || host == pattern,
+                |suffix| {
+                    host.strip_suffix(suffix)
+                        .is_some_and(|prefix| prefix.ends_with('.') && prefix.len() > 1)
+                },
+            ),
+            Self::Cidr(network, len) => host
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| cidr_contains(*network, *len, ip)),
+        }
+    }
+}
+
+impl std::fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Host(host) => f.write_str(host),
+            Self::Cidr(ip, len) => write!(f, "{ip}/{len}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EgressPolicy {
+    pub mode: PolicyMode,
+    pub patterns: Vec<HostPattern>,
+}
+
+impl EgressPolicy {
+    pub fn parse(mode: &str, patterns: &[String]) -> Result<Self, String> {
+        let mode = PolicyMode::parse(mode).ok_or_else(|| {
+            format!("invalid egress policy mode '{mode}' (allow, deny or report)")
+        })?;
+        let patterns = patterns
+            .iter()
+            .map(|raw| HostPattern::parse(raw))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { mode, patterns })
+    }
+
+    pub fn from_labels(labels: &HashMap<String, String>) -> Option<Self> {
+        let mode = labels.get(EGRESS_POLICY_MODE_LABEL)?;
+        let patterns: Vec<String> = labels
+            .get(EGRESS_POLICY_HOSTS_LABEL)
+            .map(|value| {
+                value
+                    .split(',')
+                    .filter(|entry| !entry.trim().is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self::parse(mode, &patterns).ok()
+    }
+
+    pub fn patterns_label(&self) -> String {
+        self.patterns
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        let host = strip_port(host).to_lowercase();
+        self.patterns.iter().any(|pattern| pattern.matches(&host))
+    }
+
+    pub fn hosts(&self) -> Vec<String> {
+        self.patterns
+            .iter()
+            .filter_map(|pattern| match pattern {
+                HostPattern::Host(host) => Some(host.clone()),
+                HostPattern::Cidr(..) => None,
+            })
+            .collect()
+    }
+
+    pub fn cidrs(&self) -> Vec<(IpAddr, u8)> {
+        self.patterns
+            .iter()
+            .filter_map(|pattern| match pattern {
+                HostPattern::Cidr(ip, len) => Some((*ip, *len)),
+                HostPattern::Host(_) => None,
+            })
+            .collect()
+    }
+
+    // Envoy only sees the `:authority` header for HTTP and CONNECT requests, so IPv4
+    // ranges are expanded into literal matches; IPv6 ranges only apply to transparent egress.
+    pub fn authority_regex(&self) -> Option<String> {
+        let alternatives: Vec<String> = self
+            .patterns
+            .iter()
+            .filter_map(|pattern| match pattern {
+                HostPattern::Host(host) => Some(host_regex(host)),
+                HostPattern::Cidr(IpAddr::V4(ip), len) => Some(ipv4_regex(ip.octets(), *len)),
+                HostPattern::Cidr(IpAddr::V6(_), _) => None,
+            })
+            .collect();
+        if alternatives.is_empty() {
+            return None;
+        }
+        Some(format!("(?i)^(?:{})(?::[0-9]+)?$", alternatives.join("|")))
+    }
+
+    // Observations the proxy blocked are tagged from its own signal; in report mode the
+    // allow list is only evaluated here.
+    pub fn tag(&self, mut obs: Observation) -> Observation {
+        if self.mode != PolicyMode::Report {
+            return obs;
+        }
+        let (dst, attrs) = match &mut obs {
+            Observation::Http(http) => (http.peer.dst.as_ref(), &mut http.attrs),
+            Observation::Flow(flow) => (flow.peer.dst.as_ref(), &mut flow.attrs),
+            Observation::Db(db) => (db.peer.dst.as_ref(), &mut db.attrs),
+        };
+        let Some(host) = dst.and_then(external_host) else {
+            return obs;
+        };
+        if !self.matches(&host) {
+            tag_attrs(attrs, POLICY_REPORTED);
+        }
+        obs
+    }
+}
+
+pub fn tag_blocked(attrs: &mut ObservationAttrs) {
+    tag_attrs(attrs, POLICY_BLOCKED);
+}
+
+fn tag_attrs(attrs: &mut ObservationAttrs, value: &str) {
+    attrs.tags.insert(POLICY_TAG.to_string(), value.to_string());
+}
+
+fn external_host(entity: &EntityId) -> Option<String> {
+    match entity {
+        EntityId::External { dns_name, ip } => {
+            Some(dns_name.clone().unwrap_or_else(|| ip.to_string()))
+        }
+        _ => None,
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    let host = host.trim();
+    if let Some(rest) = host.strip_prefix('[') {
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+        _ => host,
+    }
+}
+
+fn cidr_contains(network: IpAddr, len: u8, ip: IpAddr) -> bool {
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
+
+fn host_regex(pattern: &str) -> String {
+    pattern.strip_prefix("*.").map_or_else(
+        || pattern.replace('.', "\\."),
+        |suffix| format!("[^:/]+\\.{}", suffix.replace('.', "\\.")),
+    )
+}
+
+fn ipv4_regex(octets: [u8; 4], len: u8) -> String {
+    let mut regex = String::new();
+    let mut remaining = len;
+    for (index, octet) in octets.into_iter().enumerate() {
+        if index > 0 {
+            regex.push_str("\\.");
+        }
+        if remaining >= 8 {
+            let _ = write!(regex, "{octet}");
+            remaining -= 8;
+            continue;
+        }
+        if remaining == 0 {
+            regex.push_str("[0-9]{1,3}");
+            continue;
+        }
+        let span = 1u16 << (8 - remaining);
+        let base = u16::from(octet) & !(span - 1);
+        let values: Vec<String> = (base..base + span).map(|value| value.to_string()).collect();
+        let _ = write!(regex, "(?:{})", values.join("|"));
+        remaining = 0;
+    }
+    regex
+}
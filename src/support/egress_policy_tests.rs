@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use super::egress_policy::{EgressPolicy, HostPattern, PolicyMode};
+use crate::domain::traffic::{
+    Confidence, EntityId, FlowKey, FlowMetrics, FlowObservation, Observation, ObservationAttrs,
+    Peer, Socket, Transport, Visibility,
+};
+
+fn policy(mode: &str, patterns: &[&str]) -> Option<EgressPolicy> {
+    let patterns: Vec<String> = patterns.iter().map(ToString::to_string).collect();
+    EgressPolicy::parse(mode, &patterns).ok()
+}
+
+fn flow_to(ip: [u8; 4], dns_name: Option<&str>) -> Observation {
+    let socket = Socket {
+        ip: IpAddr::from(ip),
+        port: 443,
+    };
+    Observation::Flow(FlowObservation {
+        at_ms: 1,
+        flow: FlowKey {
+            src: socket.clone(),
+            dst: socket,
+            transport: Transport::Tcp,
+        },
+        metrics: FlowMetrics {
+            bytes_in: None,
+            bytes_out: None,
+            packets: None,
+            duration_ms: None,
+        },
+        peer: Peer {
+            src: None,
+            dst: Some(EntityId::External {
+                ip: IpAddr::from(ip),
+                dns_name: dns_name.map(ToString::to_string),
+            }),
+            raw: None,
+        },
+        attrs: ObservationAttrs {
+            visibility: Visibility::L4Flow,
+            confidence: Confidence::Likely,
+            tags: BTreeMap::new(),
+        },
+    })
+}
+
+fn policy_tag(obs: &Observation) -> Option<String> {
+    match obs {
+        Observation::Flow(flow) => flow.attrs.tags.get("policy").cloned(),
+        Observation::Http(http) => http.attrs.tags.get("policy").cloned(),
+        Observation::Db(db) => db.attrs.tags.get("policy").cloned(),
+    }
+}
+
+#[test]
+fn patterns_match_hosts_wildcards_and_ranges() {
+    let policy = policy(
+        "allow",
+        &["api.stripe.com", "*.internal.test", "10.0.0.0/8"],
+    );
+    let matches = |host: &str| policy.as_ref().is_some_and(|policy| policy.matches(host));
+    assert!(matches("api.stripe.com:443"));
+    assert!(matches("billing.internal.test"));
+    assert!(!matches("internal.test"));
+    assert!(matches("10.20.30.40"));
+    assert!(!matches("11.0.0.1"));
+    assert!(!matches("stripe.com"));
+}
+
+#[test]
+fn invalid_patterns_are_rejected() {
+    assert!(HostPattern::parse("10.0.0.0/33").is_err());
+    assert!(HostPattern::parse("api/v1").is_err());
+    assert!(HostPattern::parse("exa mple.com").is_err());
+    assert!(EgressPolicy::parse("block", &[]).is_err());
+}
+
+#[test]
+fn authority_regex_expands_ipv4_ranges() {
+    let regex = policy("deny", &["*.example.com", "192.168.4.0/23"])
+        .and_then(|policy| policy.authority_regex())
+        .unwrap_or_default();
+    assert_eq!(
+        regex,
+        "(?i)^(?:[^:/]+\\.example\\.com|192\\.168\\.(?:4|5)\\.[0-9]{1,3})(?::[0-9]+)?$"
+    );
+}
+
+#[test]
+fn report_mode_tags_unlisted_destinations() {
+    let Some(policy) = policy("report", &["api.stripe.com"]) else {
+        return;
+    };
+    assert_eq!(policy.mode, PolicyMode::Report);
+    let listed = policy.tag(flow_to([1, 2, 3, 4], Some("api.stripe.com")));
+    assert_eq!(policy_tag(&listed), None);
+    let unlisted = policy.tag(flow_to([5, 6, 7, 8], None));
+    assert_eq!(policy_tag(&unlisted).as_deref(), Some("reported"));
+}
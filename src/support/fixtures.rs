@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+use crate::domain::traffic::{
+    BodyEncodings, Confidence, Correlation, EntityId, HttpObservation, Observation,
+    ObservationAttrs, Peer, TrafficCall, Visibility,
+};
+
+pub fn workload(name: &str) -> EntityId {
+    EntityId::Workload {
+        name: name.to_string(),
+        instance: None,
+    }
+}
+
+// An HTTP exchange with empty defaults: `GET /` answered with 200, no peers, headers or bodies.
+pub struct HttpCall(HttpObservation);
+
+impl HttpCall {
+    pub fn new(method: &str, path: &str) -> Self {
+        Self(HttpObservation {
+            at_ms: 0,
+            peer: Peer {
+                src: None,
+                dst: None,
+                raw: None,
+            },
+            method: Some(method.to_string()),
+            path: Some(path.to_string()),
+            route: None,
+            status: Some(200),
+            duration_ms: None,
+            bytes_in: None,
+            bytes_out: None,
+            request_headers: BTreeMap::new(),
+            response_headers: BTreeMap::new(),
+            request_body: None,
+            response_body: None,
+            body_encoding: BodyEncodings::default(),
+            correlation: Correlation::default(),
+            attrs: ObservationAttrs {
+                visibility: Visibility::L7Semantics,
+                confidence: Confidence::Exact,
+                tags: BTreeMap::new(),
+            },
+        })
+    }
+
+    pub fn get(path: &str) -> Self {
+        Self::new("GET", path)
+    }
+
+    pub fn src(mut self, src: EntityId) -> Self {
+        self.0.peer.src = Some(src);
+        self
+    }
+
+    pub fn dst(mut self, dst: EntityId) -> Self {
+        self.0.peer.dst = Some(dst);
+        self
+    }
+
+    pub const fn at(mut self, at_ms: u64) -> Self {
+        self.0.at_ms = at_ms;
+        self
+    }
+
+    pub const fn status(mut self, status: u16) -> Self {
+        self.0.status = Some(status);
+        self
+    }
+
+    pub const fn duration(mut self, duration_ms: u64) -> Self {
+        self.0.duration_ms = Some(duration_ms);
+        self
+    }
+
+    pub fn route(mut self, route: &str) -> Self {
+        self.0.route = Some(route.to_string());
+        self
+    }
+
+    pub fn request_header(mut self, name: &str, value: &str) -> Self {
+        self.0
+            .request_headers
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn response_header(mut self, name: &str, value: &str) -> Self {
+        self.0
+            .response_headers
+            .insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn request_body(mut self, body: &str) -> Self {
+        self.0.request_body = Some(body.to_string());
+        self
+    }
+
+    pub fn response_body(mut self, body: &str) -> Self {
+        self.0.response_body = Some(body.to_string());
+        self
+    }
+
+    pub fn correlation(mut self, correlation: Correlation) -> Self {
+        self.0.correlation = correlation;
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.0.attrs.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn observation(self) -> Observation {
+        Observation::Http(Box::new(self.0))
+    }
+
+    pub fn call(self, seq: u64) -> TrafficCall {
+        let http = self.0;
+        TrafficCall {
+            seq,
+            at_ms: http.at_ms,
+            peer: http.peer,
+            method: http.method,
+            path: http.path,
+            route: http.route,
+            status: http.status,
+            duration_ms: http.duration_ms,
+            bytes_in: http.bytes_in,
+            bytes_out: http.bytes_out,
+            request_headers: http.request_headers,
+            response_headers: http.response_headers,
+            request_body: http.request_body,
+            response_body: http.response_body,
+            body_encoding: http.body_encoding,
+            correlation: http.correlation,
+            attrs: http.attrs,
+            violations: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::domain::traffic::{
+    BodyEncoding, BodyEncodings, Confidence, Correlation, EntityId, ObservationAttrs, Peer,
+    TrafficCall, Visibility,
+};
+use crate::support::constants::BIN_NAME;
+
+const HAR_VERSION: &str = "1.2";
+const HTTP_VERSION: &str = "HTTP/1.1";
+
+// Sanelens fields that HAR has no place for travel in `_`-prefixed entry fields, which HAR
+// readers ignore and `import` restores.
+pub fn export(calls: &[TrafficCall]) -> Value {
+    let entries: Vec<Value> = calls.iter().map(entry).collect();
+    json!({
+        "log": {
+            "version": HAR_VERSION,
+            "creator": { "name": BIN_NAME, "version": env!("CARGO_PKG_VERSION") },
+            "entries": entries,
+        }
+    })
+}
+
+fn entry(call: &TrafficCall) -> Value {
+    let duration = call.duration_ms.unwrap_or_default();
+    let mut entry = json!({
+        "startedDateTime": started_at(call.at_ms),
+        "time": duration,
+        "request": request(call),
+        "response": response(call),
+        "cache": {},
+        "timings": { "send": 0, "wait": duration, "receive": 0 },
+        "_seq": call.seq,
+        "_from": call.peer.src,
+        "_to": call.peer.dst,
+        "_correlation": call.correlation,
+        "_attrs": call.attrs,
+        "_bodyEncoding": call.body_encoding,
+    });
+    if let Some(route) = &call.route {
+        set(&mut entry, "_route", json!(route));
+    }
+    if !call.violations.is_empty() {
+        set(&mut entry, "_violations", json!(call.violations));
+    }
+    entry
+}
+
+fn request(call: &TrafficCall) -> Value {
+    let path = call.path.as_deref().unwrap_or("/");
+    let query: Vec<Value> = path
+        .split_once('?')
+        .map(|(_, query)| query)
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            json!({ "name": name, "value": value })
+        })
+        .collect();
+    let mut request = json!({
+        "method": call.method.as_deref().unwrap_or("GET"),
+        "url": format!("http://{}{path}", host(call)),
+        "httpVersion": HTTP_VERSION,
+        "cookies": [],
+        "headers": headers(&call.request_headers),
+        "queryString": query,
+        "headersSize": -1,
+        "bodySize": size(call.bytes_in),
+    });
+    if let Some(body) = &call.request_body {
+        set(
+            &mut request,
+            "postData",
+            json!({
+                "mimeType": content_type(&call.request_headers),
+                "text": body,
+            }),
+        );
+    }
+    request
+}
+
+fn response(call: &TrafficCall) -> Value {
+    let mut content = json!({
+        "size": call
+            .bytes_out
+            .or_else(|| call.response_body.as_ref().map(|body| body.len() as u64))
+            .unwrap_or_default(),
+        "mimeType": content_type(&call.response_headers),
+    });
+    if let Some(body) = &call.response_body {
+        set(&mut content, "text", json!(body));
+    }
+    if call.body_encoding.response == Some(BodyEncoding::Base64) {
+        set(&mut content, "encoding", json!("base64"));
+    }
+    json!({
+        "status": call.status.unwrap_or_default(),
+        "statusText": "",
+        "httpVersion": HTTP_VERSION,
+        "cookies": [],
+        "headers": headers(&call.response_headers),
+        "content": content,
+        "redirectURL": call.response_headers.get("location").cloned().unwrap_or_default(),
+        "headersSize": -1,
+        "bodySize": size(call.bytes_out),
+    })
+}
+
+// Prefer what the client asked for; fall back to the service the call reached.
+fn host(call: &TrafficCall) -> String {
+    if let Some(host) = call
+        .request_headers
+        .get(":authority")
+        .or_else(|| call.request_headers.get("host"))
+    {
+        return host.clone();
+    }
+    match &call.peer.dst {
+        Some(EntityId::Workload { name, .. } | EntityId::Host { name }) => name.clone(),
+        Some(EntityId::External { ip, dns_name }) => {
+            dns_name.clone().unwrap_or_else(|| ip.to_string())
+        }
+        Some(EntityId::Unknown) | None => "unknown".to_string(),
+    }
+}
+
+fn headers(headers: &BTreeMap<String, String>) -> Vec<Value> {
+    headers
+        .iter()
+        .map(|(name, value)| json!({ "name": name, "value": value }))
+        .collect()
+}
+
+fn content_type(headers: &BTreeMap<String, String>) -> String {
+    headers.get("content-type").cloned().unwrap_or_default()
+}
+
+fn size(bytes: Option<u64>) -> Value {
+    bytes.map_or_else(|| json!(-1), |bytes| json!(bytes))
+}
+
+fn started_at(at_ms: u64) -> String {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(at_ms) * 1_000_000)
+        .ok()
+        .and_then(|ts| ts.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+// Entries from browsers and other tools lack the `_` fields: their calls have no known
+// source and are attributed to the host in their URL.
+pub fn import(raw: &str) -> Result<Vec<TrafficCall>, String> {
+    let doc: Value = serde_json::from_str(raw).map_err(|err| format!("invalid HAR: {err}"))?;
+    let entries = doc
+        .pointer("/log/entries")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "invalid HAR: missing log.entries".to_string())?;
+    Ok(entries.iter().map(import_entry).collect())
+}
+
+fn import_entry(entry: &Value) -> TrafficCall {
+    let empty = Value::Null;
+    let request = entry.get("request").unwrap_or(&empty);
+    let response = entry.get("response").unwrap_or(&empty);
+    let url = text(request, "url").unwrap_or_default();
+    let (url_host, path) = split_url(&url);
+    let response_body = response.pointer("/content/text").and_then(Value::as_str);
+    let body_encoding = custom(entry, "_bodyEncoding").unwrap_or_else(|| BodyEncodings {
+        request: None,
+        response: (response
+            .pointer("/content/encoding")
+            .and_then(Value::as_str)
+            == Some("base64"))
+        .then_some(BodyEncoding::Base64),
+    });
+    TrafficCall {
+        seq: 0,
+        at_ms: text(entry, "startedDateTime")
+            .and_then(|ts| OffsetDateTime::parse(&ts, &Rfc3339).ok())
+            .and_then(|ts| u64::try_from(ts.unix_timestamp_nanos() / 1_000_000).ok())
+            .unwrap_or_default(),
+        peer: import_peer(entry, url_host),
+        method: text(request, "method"),
+        path: Some(path.to_string()),
+        route: text(entry, "_route"),
+        status: response
+            .get("status")
+            .and_then(Value::as_u64)
+            .and_then(|status| u16::try_from(status).ok())
+            .filter(|status| *status > 0),
+        duration_ms: entry.get("time").and_then(Value::as_f64).and_then(millis),
+        bytes_in: byte_count(request.get("bodySize")),
+        bytes_out: byte_count(response.get("bodySize"))
+            .or_else(|| byte_count(response.pointer("/content/size"))),
+        request_headers: import_headers(request.get("headers")),
+        response_headers: import_headers(response.get("headers")),
+        request_body: request
+            .pointer("/postData/text")
+            .and_then(Value::as_str)
+            .map(ToString::to_string),
+        response_body: response_body.map(ToString::to_string),
+        body_encoding,
+        correlation: custom::<Correlation>(entry, "_correlation").unwrap_or_default(),
+        attrs: custom(entry, "_attrs").unwrap_or_else(|| ObservationAttrs {
+            visibility: Visibility::L7Semantics,
+            confidence: Confidence::Exact,
+            tags: BTreeMap::new(),
+        }),
+        violations: custom(entry, "_violations").unwrap_or_default(),
+    }
+}
+
+fn import_peer(entry: &Value, url_host: &str) -> Peer {
+    Peer {
+        src: custom(entry, "_from"),
+        dst: custom(entry, "_to").or_else(|| {
+            (!url_host.is_empty()).then(|| EntityId::Host {
+                name: url_host.to_string(),
+            })
+        }),
+        raw: None,
+    }
+}
+
+fn split_url(url: &str) -> (&str, &str) {
+    let Some((_, rest)) = url.split_once("://") else {
+        return ("", if url.is_empty() { "/" } else { url });
+    };
+    rest.find('/').map_or((rest, "/"), |at| {
+        (
+            rest.get(..at).unwrap_or_default(),
+            rest.get(at..).unwrap_or("/"),
+        )
+    })
+}
+
+// HAR times are fractional milliseconds; -1 marks an unknown time.
+fn millis(time: f64) -> Option<u64> {
+    Duration::try_from_secs_f64((time + 0.5) / 1000.0)
+        .ok()
+        .and_then(|duration| u64::try_from(duration.as_millis()).ok())
+}
+
+// Header names are lowercased to match what the sidecars capture; repeated headers are
+// joined as HTTP allows.
+fn import_headers(headers: Option<&Value>) -> BTreeMap<String, String> {
+    let mut out: BTreeMap<String, String> = BTreeMap::new();
+    for header in headers.and_then(Value::as_array).into_iter().flatten() {
+        let (Some(name), Some(value)) = (text(header, "name"), text(header, "value")) else {
+            continue;
+        };
+        out.entry(name.to_lowercase())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    out
+}
+
+fn byte_count(value: Option<&Value>) -> Option<u64> {
+    value.and_then(Value::as_u64)
+}
+
+fn text(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .map(ToString::to_string)
+}
+
+fn custom<T: DeserializeOwned>(entry: &Value, key: &str) -> Option<T> {
+    entry
+        .get(key)
+        .filter(|value| !value.is_null())
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+}
+
+fn set(object: &mut Value, key: &str, value: Value) {
+    if let Value::Object(map) = object {
+        map.insert(key.to_string(), value);
+    }
+}
@@ -0,0 +1,106 @@
+use serde_json::Value;
+
+use super::fixtures::{workload, HttpCall};
+use super::har::{export, import};
+use crate::domain::traffic::{
+    BodyEncoding, ContractViolation, Correlation, EntityId, TrafficCall, ViolationKind,
+};
+
+fn exported_call() -> TrafficCall {
+    let mut call = HttpCall::new("POST", "/users?team=a&dry")
+        .src(workload("web"))
+        .dst(workload("api"))
+        .at(1_700_000_000_123)
+        .status(201)
+        .duration(12)
+        .route("/users")
+        .request_header("content-type", "application/json")
+        .request_header("host", "api:8080")
+        .response_header("content-type", "application/json")
+        .request_body(r#"{"name":"a"}"#)
+        .response_body(r#"{"id":1}"#)
+        .correlation(Correlation {
+            request_id: Some("req-1".to_string()),
+            ..Correlation::default()
+        })
+        .tag("redacted", "body")
+        .call(7);
+    call.bytes_in = Some(13);
+    call.bytes_out = Some(9);
+    call.violations = vec![ContractViolation {
+        kind: ViolationKind::UndocumentedStatus,
+        message: "status 201 is not documented for POST /users".to_string(),
+    }];
+    call
+}
+
+fn at<'a>(value: &'a Value, pointer: &str) -> &'a Value {
+    value.pointer(pointer).unwrap_or(&Value::Null)
+}
+
+#[test]
+fn export_is_har_with_custom_fields() {
+    let har = export(&[exported_call()]);
+    assert_eq!(at(&har, "/log/version"), "1.2");
+    let entry = at(&har, "/log/entries/0");
+    assert_eq!(at(entry, "/startedDateTime"), "2023-11-14T22:13:20.123Z");
+    assert_eq!(at(entry, "/time"), 12);
+    assert_eq!(
+        at(entry, "/request/url"),
+        "http://api:8080/users?team=a&dry"
+    );
+    assert_eq!(at(entry, "/request/queryString/1/name"), "dry");
+    assert_eq!(at(entry, "/request/postData/text"), r#"{"name":"a"}"#);
+    assert_eq!(at(entry, "/response/status"), 201);
+    assert_eq!(at(entry, "/response/content/text"), r#"{"id":1}"#);
+    assert_eq!(at(entry, "/timings/wait"), 12);
+    assert_eq!(at(entry, "/_route"), "/users");
+    assert_eq!(at(entry, "/_from/name"), "web");
+    assert_eq!(at(entry, "/_violations/0/kind"), "undocumented_status");
+}
+
+#[test]
+fn exported_calls_import_unchanged() {
+    let raw = export(&[exported_call()]).to_string();
+    let calls = import(&raw).unwrap_or_default();
+    assert_eq!(calls.len(), 1);
+    let original = serde_json::to_value(exported_call()).unwrap_or_default();
+    for imported in &calls {
+        let mut imported = serde_json::to_value(imported).unwrap_or_default();
+        if let Value::Object(map) = &mut imported {
+            map.insert("seq".to_string(), Value::from(7));
+        }
+        assert_eq!(imported, original);
+    }
+}
+
+#[test]
+fn foreign_har_entries_are_attributed_to_their_host() {
+    let raw = r#"{"log":{"version":"1.2","entries":[{
+        "startedDateTime":"2024-01-02T03:04:05.000+00:00","time":3.6,
+        "request":{"method":"GET","url":"https://example.com/img.png","headers":[
+            {"name":"Accept","value":"image/png"},{"name":"Accept","value":"*/*"}],"bodySize":0},
+        "response":{"status":200,"headers":[],"bodySize":-1,
+            "content":{"size":4,"mimeType":"image/png","text":"iVBO","encoding":"base64"}}}]}}"#;
+    let calls = import(raw).unwrap_or_default();
+    assert_eq!(calls.len(), 1);
+    for call in &calls {
+        assert_eq!(call.at_ms, 1_704_164_645_000);
+        assert_eq!(call.duration_ms, Some(4));
+        assert_eq!(call.path.as_deref(), Some("/img.png"));
+        assert!(call.peer.src.is_none());
+        assert_eq!(
+            call.peer.dst,
+            Some(EntityId::Host {
+                name: "example.com".to_string()
+            })
+        );
+        assert_eq!(
+            call.request_headers.get("accept").map(String::as_str),
+            Some("image/png, */*")
+        );
+        assert_eq!(call.bytes_out, Some(4));
+        assert_eq!(call.body_encoding.response, Some(BodyEncoding::Base64));
+    }
+    assert!(import(r#"{"entries":[]}"#).is_err());
+}
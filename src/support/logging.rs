@@ -36,6 +36,17 @@ impl LogHub {
     }
 
     pub fn publish(&self, service: &str, line: &str, container_ts: Option<&str>) {
+        self.publish_traced(service, line, container_ts, None);
+    }
+
+    // Log records received over OTLP carry the trace they were written in.
+    pub fn publish_traced(
+        &self,
+        service: &str,
+        line: &str,
+        container_ts: Option<&str>,
+        trace_id: Option<&str>,
+    ) {
         let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
         let event = LogEvent {
             seq,
@@ -46,6 +57,7 @@ impl LogHub {
             },
             container_ts: container_ts.map(ToString::to_string),
             line: line.to_string(),
+            trace_id: trace_id.map(ToString::to_string),
         };
         let clients = {
             let mut state = self.state();
@@ -70,6 +82,15 @@ impl LogHub {
         }
     }
 
+    pub fn trace_events(&self, trace_id: &str) -> Vec<LogEvent> {
+        self.state()
+            .history
+            .iter()
+            .filter(|event| event.trace_id.as_deref() == Some(trace_id))
+            .cloned()
+            .collect()
+    }
+
     pub fn register_client(&self) -> (Receiver<LogEvent>, Vec<LogEvent>) {
         let (sender, receiver) = bounded(CLIENT_QUEUE_SIZE);
         let mut state = self.state();
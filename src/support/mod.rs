@@ -1,12 +1,66 @@
 pub mod args;
+pub mod bench;
+pub mod body;
+pub mod capture;
+pub mod chaos;
 pub mod constants;
+pub mod contract;
+pub mod db;
+pub mod egress_policy;
+pub mod har;
 pub mod logging;
 pub mod multiline;
+pub mod openapi;
+pub mod otlp;
+pub mod otlp_export;
+pub mod replay;
+pub mod routes;
 pub mod run;
 pub mod services;
+pub mod timeline;
+pub mod trace;
 pub mod traffic;
+pub mod vcr;
 
+#[cfg(test)]
+mod args_tests;
+#[cfg(test)]
+mod bench_tests;
+#[cfg(test)]
+mod body_tests;
+#[cfg(test)]
+mod capture_tests;
+#[cfg(test)]
+mod chaos_tests;
+#[cfg(test)]
+mod contract_tests;
+#[cfg(test)]
+mod db_tests;
+#[cfg(test)]
+mod egress_policy_tests;
+#[cfg(test)]
+mod fixtures;
+#[cfg(test)]
+mod har_tests;
 #[cfg(test)]
 mod logging_tests;
 #[cfg(test)]
 mod multiline_tests;
+#[cfg(test)]
+mod openapi_tests;
+#[cfg(test)]
+mod otlp_export_tests;
+#[cfg(test)]
+mod otlp_tests;
+#[cfg(test)]
+mod replay_tests;
+#[cfg(test)]
+mod routes_tests;
+#[cfg(test)]
+mod services_tests;
+#[cfg(test)]
+mod trace_tests;
+#[cfg(test)]
+mod traffic_tests;
+#[cfg(test)]
+mod vcr_tests;
@@ -0,0 +1,455 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{json, Map, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::domain::traffic::{BodyEncoding, EntityId, TrafficCall};
+use crate::support::body::media_type;
+use crate::support::routes::normalize_route;
+
+const OPENAPI_VERSION: &str = "3.0.3";
+
+// Schemas are widened as samples come in: integers seen next to floats become numbers,
+// object keys missing from any sample become optional and conflicting types become `{}`.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Schema {
+    nullable: bool,
+    kind: Kind,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+enum Kind {
+    #[default]
+    Unknown,
+    Boolean,
+    Integer,
+    Number,
+    String(Option<&'static str>),
+    Array(Box<Schema>),
+    Object {
+        properties: BTreeMap<String, Schema>,
+        required: BTreeSet<String>,
+    },
+    Any,
+}
+
+impl Schema {
+    fn of(value: &Value) -> Self {
+        let kind = match value {
+            Value::Null => {
+                return Self {
+                    nullable: true,
+                    kind: Kind::Unknown,
+                }
+            }
+            Value::Bool(_) => Kind::Boolean,
+            Value::Number(number) if number.is_i64() || number.is_u64() => Kind::Integer,
+            Value::Number(_) => Kind::Number,
+            Value::String(text) => Kind::String(string_format(text)),
+            Value::Array(items) => Kind::Array(Box::new(
+                items
+                    .iter()
+                    .map(Self::of)
+                    .reduce(Self::merge)
+                    .unwrap_or_default(),
+            )),
+            Value::Object(fields) => Kind::Object {
+                properties: fields
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Self::of(value)))
+                    .collect(),
+                required: fields.keys().cloned().collect(),
+            },
+        };
+        Self {
+            nullable: false,
+            kind,
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        let kind = match (self.kind, other.kind) {
+            (Kind::Unknown, kind) | (kind, Kind::Unknown) => kind,
+            (Kind::Integer, Kind::Number) | (Kind::Number, Kind::Integer) => Kind::Number,
+            (Kind::String(left), Kind::String(right)) => {
+                Kind::String(if left == right { left } else { None })
+            }
+            (Kind::Array(left), Kind::Array(right)) => Kind::Array(Box::new(left.merge(*right))),
+            (
+                Kind::Object {
+                    properties: mut left,
+                    required: left_required,
+                },
+                Kind::Object {
+                    properties: right,
+                    required: right_required,
+                },
+            ) => {
+                for (key, schema) in right {
+                    let merged = match left.remove(&key) {
+                        Some(existing) => existing.merge(schema),
+                        None => schema,
+                    };
+                    left.insert(key, merged);
+                }
+                Kind::Object {
+                    properties: left,
+                    required: left_required
+                        .intersection(&right_required)
+                        .cloned()
+                        .collect(),
+                }
+            }
+            (left, right) if left == right => left,
+            _ => Kind::Any,
+        };
+        Self {
+            nullable: self.nullable || other.nullable,
+            kind,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut out = Map::new();
+        match &self.kind {
+            Kind::Unknown | Kind::Any => {}
+            Kind::Boolean => {
+                out.insert("type".to_string(), json!("boolean"));
+            }
+            Kind::Integer => {
+                out.insert("type".to_string(), json!("integer"));
+            }
+            Kind::Number => {
+                out.insert("type".to_string(), json!("number"));
+            }
+            Kind::String(format) => {
+                out.insert("type".to_string(), json!("string"));
+                if let Some(format) = format {
+                    out.insert("format".to_string(), json!(format));
+                }
+            }
+            Kind::Array(items) => {
+                out.insert("type".to_string(), json!("array"));
+                out.insert("items".to_string(), items.to_value());
+            }
+            Kind::Object {
+                properties,
+                required,
+            } => {
+                out.insert("type".to_string(), json!("object"));
+                let properties: Map<String, Value> = properties
+                    .iter()
+                    .map(|(key, schema)| (key.clone(), schema.to_value()))
+                    .collect();
+                out.insert("properties".to_string(), Value::Object(properties));
+                if !required.is_empty() {
+                    out.insert("required".to_string(), json!(required));
+                }
+            }
+        }
+        if self.nullable {
+            out.insert("nullable".to_string(), json!(true));
+        }
+        Value::Object(out)
+    }
+}
+
+#[derive(Default)]
+struct Operation {
+    calls: usize,
+    path_params: Vec<(String, Schema)>,
+    query: BTreeMap<String, (usize, Schema)>,
+    request: BTreeMap<String, Option<Schema>>,
+    responses: BTreeMap<u16, BTreeMap<String, Option<Schema>>>,
+}
+
+// Builds an OpenAPI document for the calls a service received, one operation per templated
+// route and method. Schemas only cover what was observed, so they are a starting point.
+pub fn infer(service: &str, calls: &[TrafficCall]) -> Value {
+    let mut operations: BTreeMap<(String, String), Operation> = BTreeMap::new();
+    let mut observed = 0;
+    for call in calls.iter().filter(|call| receives(call, service)) {
+        let Some(path) = call.path.as_deref() else {
+            continue;
+        };
+        let route = call.route.clone().unwrap_or_else(|| normalize_route(path));
+        let (template, names) = openapi_template(&route);
+        let method = call.method.as_deref().unwrap_or("GET").to_ascii_lowercase();
+        let operation = operations.entry((template, method)).or_default();
+        operation.record(call, &route, &names);
+        observed += 1;
+    }
+    let mut paths = Map::new();
+    for ((template, method), operation) in operations {
+        let item = paths
+            .entry(template)
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(item) = item {
+            item.insert(method, operation.to_value());
+        }
+    }
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": service,
+            "version": "observed",
+            "description": format!("Inferred by sanelens from {observed} captured calls."),
+        },
+        "paths": paths,
+    })
+}
+
+impl Operation {
+    fn record(&mut self, call: &TrafficCall, route: &str, names: &[String]) {
+        let path = call.path.as_deref().unwrap_or_default();
+        let (raw_path, query) = path.split_once('?').unwrap_or((path, ""));
+        self.calls += 1;
+        let values = path_values(route, raw_path);
+        for (index, name) in names.iter().enumerate() {
+            let schema = values
+                .get(index)
+                .map(|value| scalar_schema(value))
+                .unwrap_or_default();
+            match self.path_params.get_mut(index) {
+                Some((_, existing)) => *existing = existing.clone().merge(schema),
+                None => self.path_params.push((name.clone(), schema)),
+            }
+        }
+        let mut seen = BTreeSet::new();
+        for (name, value) in query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        {
+            let schema = scalar_schema(value);
+            let entry = self.query.entry(name.to_string()).or_default();
+            if seen.insert(name.to_string()) {
+                entry.0 += 1;
+            }
+            entry.1 = entry.1.clone().merge(schema);
+        }
+        if let Some((content_type, schema)) = body_schema(
+            &call.request_headers,
+            call.request_body.as_deref(),
+            call.body_encoding.request,
+        ) {
+            merge_content(&mut self.request, content_type, schema);
+        }
+        let Some(status) = call.status else {
+            return;
+        };
+        let content = self.responses.entry(status).or_default();
+        if let Some((content_type, schema)) = body_schema(
+            &call.response_headers,
+            call.response_body.as_deref(),
+            call.body_encoding.response,
+        ) {
+            merge_content(content, content_type, schema);
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut parameters: Vec<Value> = self
+            .path_params
+            .iter()
+            .map(|(name, schema)| {
+                json!({ "name": name, "in": "path", "required": true, "schema": schema.to_value() })
+            })
+            .collect();
+        parameters.extend(self.query.iter().map(|(name, (count, schema))| {
+            json!({
+                "name": name,
+                "in": "query",
+                "required": *count == self.calls,
+                "schema": schema.to_value(),
+            })
+        }));
+        let responses: Map<String, Value> = self
+            .responses
+            .iter()
+            .map(|(status, content)| {
+                let mut response = json!({ "description": status_description(*status) });
+                if !content.is_empty() {
+                    set(&mut response, "content", content_value(content));
+                }
+                (status.to_string(), response)
+            })
+            .collect();
+        let mut operation = json!({ "responses": responses });
+        if !parameters.is_empty() {
+            set(&mut operation, "parameters", Value::Array(parameters));
+        }
+        if !self.request.is_empty() {
+            set(
+                &mut operation,
+                "requestBody",
+                json!({ "content": content_value(&self.request) }),
+            );
+        }
+        operation
+    }
+}
+
+fn receives(call: &TrafficCall, service: &str) -> bool {
+    matches!(&call.peer.dst, Some(EntityId::Workload { name, .. }) if name == service)
+}
+
+// Route placeholders are renamed to be unique within a path and `*` becomes a trailing
+// `{path}` parameter, as OpenAPI requires named, distinct path parameters.
+fn openapi_template(route: &str) -> (String, Vec<String>) {
+    let mut names = Vec::new();
+    let segments: Vec<String> = route
+        .split('/')
+        .map(|segment| {
+            let name = if segment == "*" {
+                "path"
+            } else if let Some(name) = segment
+                .strip_prefix('{')
+                .and_then(|rest| rest.strip_suffix('}'))
+            {
+                name
+            } else {
+                return segment.to_string();
+            };
+            let mut unique = name.to_string();
+            let mut suffix = 2;
+            while names.contains(&unique) {
+                unique = format!("{name}{suffix}");
+                suffix += 1;
+            }
+            names.push(unique.clone());
+            format!("{{{unique}}}")
+        })
+        .collect();
+    (segments.join("/"), names)
+}
+
+fn path_values(route: &str, raw_path: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut segments = raw_path.split('/');
+    for expected in route.split('/') {
+        if expected == "*" {
+            values.push(segments.collect::<Vec<_>>().join("/"));
+            break;
+        }
+        let Some(segment) = segments.next() else {
+            break;
+        };
+        if expected.starts_with('{') && expected.ends_with('}') {
+            values.push(segment.to_string());
+        }
+    }
+    values
+}
+
+fn scalar_schema(value: &str) -> Schema {
+    let kind = if value.parse::<i64>().is_ok() {
+        Kind::Integer
+    } else if value.parse::<f64>().is_ok_and(f64::is_finite) {
+        Kind::Number
+    } else if value == "true" || value == "false" {
+        Kind::Boolean
+    } else {
+        Kind::String(string_format(value))
+    };
+    Schema {
+        nullable: false,
+        kind,
+    }
+}
+
+fn string_format(value: &str) -> Option<&'static str> {
+    let groups: Vec<usize> = value.split('-').map(str::len).collect();
+    if groups == [8, 4, 4, 4, 12]
+        && value
+            .bytes()
+            .all(|byte| byte == b'-' || byte.is_ascii_hexdigit())
+    {
+        return Some("uuid");
+    }
+    if value.len() > 10 && OffsetDateTime::parse(value, &Rfc3339).is_ok() {
+        return Some("date-time");
+    }
+    None
+}
+
+// JSON and form bodies get a schema; other captured bodies only contribute a content type.
+fn body_schema(
+    headers: &BTreeMap<String, String>,
+    body: Option<&str>,
+    encoding: Option<BodyEncoding>,
+) -> Option<(String, Option<Schema>)> {
+    let body = body.filter(|body| !body.is_empty())?;
+    let content_type = media_type(headers.get("content-type").map(String::as_str));
+    let content_type = if content_type.is_empty() {
+        "application/octet-stream".to_string()
+    } else {
+        content_type
+    };
+    let schema = match encoding {
+        Some(BodyEncoding::Base64) => Some(Schema {
+            nullable: false,
+            kind: Kind::String(Some("binary")),
+        }),
+        Some(BodyEncoding::Multipart) => None,
+        Some(BodyEncoding::Form) | None => serde_json::from_str::<Value>(body)
+            .ok()
+            .filter(|_| encoding.is_some() || content_type.contains("json"))
+            .map(|value| Schema::of(&value)),
+    };
+    Some((content_type, schema))
+}
+
+fn merge_content(
+    content: &mut BTreeMap<String, Option<Schema>>,
+    content_type: String,
+    schema: Option<Schema>,
+) {
+    let entry = content.entry(content_type).or_insert(None);
+    *entry = match (entry.take(), schema) {
+        (Some(existing), Some(schema)) => Some(existing.merge(schema)),
+        (existing, schema) => existing.or(schema),
+    };
+}
+
+fn content_value(content: &BTreeMap<String, Option<Schema>>) -> Value {
+    let content: Map<String, Value> = content
+        .iter()
+        .map(|(content_type, schema)| {
+            let schema = schema.as_ref().map_or_else(|| json!({}), Schema::to_value);
+            (content_type.clone(), json!({ "schema": schema }))
+        })
+        .collect();
+    Value::Object(content)
+}
+
+const fn status_description(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        409 => "Conflict",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Observed response",
+    }
+}
+
+fn set(object: &mut Value, key: &str, value: Value) {
+    if let Value::Object(map) = object {
+        map.insert(key.to_string(), value);
+    }
+}
@@ -0,0 +1,80 @@
+use serde_json::{json, Value};
+
+use super::fixtures::{workload, HttpCall};
+use super::openapi::infer;
+
+const JSON: &str = "application/json; charset=utf-8";
+
+fn api_call(path: &str) -> HttpCall {
+    HttpCall::get(path)
+        .src(workload("web"))
+        .dst(workload("api"))
+}
+
+fn at<'a>(value: &'a Value, pointer: &str) -> &'a Value {
+    value.pointer(pointer).unwrap_or(&Value::Null)
+}
+
+#[test]
+fn operations_are_grouped_by_templated_route() {
+    let calls = [
+        api_call("/users/42?expand=orders&limit=5")
+            .response_header("content-type", JSON)
+            .response_body(r#"{"id":42,"name":"Ada","score":1}"#)
+            .call(1),
+        api_call("/users/7?limit=10")
+            .response_header("content-type", JSON)
+            .response_body(r#"{"id":7,"name":"Lin","score":2.5,"email":null}"#)
+            .call(2),
+        api_call("/users/9").status(404).call(3),
+        HttpCall::get("/invoices")
+            .src(workload("web"))
+            .dst(workload("billing"))
+            .call(4),
+    ];
+    let document = infer("api", &calls);
+    assert_eq!(at(&document, "/openapi"), "3.0.3");
+    let paths = at(&document, "/paths")
+        .as_object()
+        .cloned()
+        .unwrap_or_default();
+    assert_eq!(paths.keys().collect::<Vec<_>>(), ["/users/{id}"]);
+
+    let operation = at(&document, "/paths/~1users~1{id}/get");
+    assert_eq!(
+        at(operation, "/parameters"),
+        &json!([
+            { "name": "id", "in": "path", "required": true, "schema": { "type": "integer" } },
+            { "name": "expand", "in": "query", "required": false, "schema": { "type": "string" } },
+            { "name": "limit", "in": "query", "required": false, "schema": { "type": "integer" } },
+        ])
+    );
+    assert_eq!(at(operation, "/responses/404/description"), "Not Found");
+    assert_eq!(at(operation, "/responses/404/content"), &Value::Null);
+    let schema = at(operation, "/responses/200/content/application~1json/schema");
+    assert_eq!(at(schema, "/properties/score/type"), "number");
+    assert_eq!(at(schema, "/properties/email/nullable"), true);
+    assert_eq!(at(schema, "/required"), &json!(["id", "name", "score"]));
+}
+
+#[test]
+fn request_bodies_and_repeated_placeholders_are_described() {
+    let create = HttpCall::new(
+        "POST",
+        "/teams/a1b2c3d4e5/members/550e8400-e29b-41d4-a716-446655440000",
+    )
+    .src(workload("web"))
+    .dst(workload("api"))
+    .status(201)
+    .route("/teams/{id}/members/{id}")
+    .request_header("content-type", JSON)
+    .request_body(r#"{"role":"admin","tags":["a","b"]}"#)
+    .call(1);
+    let document = infer("api", &[create]);
+    let operation = at(&document, "/paths/~1teams~1{id}~1members~1{id2}/post");
+    assert_eq!(at(operation, "/parameters/1/name"), "id2");
+    assert_eq!(at(operation, "/parameters/1/schema/format"), "uuid");
+    let schema = at(operation, "/requestBody/content/application~1json/schema");
+    assert_eq!(at(schema, "/properties/tags/items/type"), "string");
+    assert_eq!(at(operation, "/responses/201/description"), "Created");
+}
@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use serde_json::Value;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::domain::traffic::{AppSpan, SpanKind};
+
+const MALFORMED: &str = "malformed OTLP protobuf payload";
+const UNKNOWN_SERVICE: &str = "unknown";
+const STATUS_ERROR: u64 = 2;
+const SPAN_KIND_NAMES: [&str; 6] = [
+    "SPAN_KIND_UNSPECIFIED",
+    "SPAN_KIND_INTERNAL",
+    "SPAN_KIND_SERVER",
+    "SPAN_KIND_CLIENT",
+    "SPAN_KIND_PRODUCER",
+    "SPAN_KIND_CONSUMER",
+];
+const STATUS_NAMES: [&str; 3] = ["STATUS_CODE_UNSET", "STATUS_CODE_OK", "STATUS_CODE_ERROR"];
+const SEVERITY_NAMES: [&str; 6] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR", "FATAL"];
+
+pub struct LogRecord {
+    pub service: String,
+    pub time_unix_nano: u64,
+    pub severity: Option<String>,
+    pub body: String,
+    pub trace_id: Option<String>,
+}
+
+impl LogRecord {
+    pub fn line(&self) -> String {
+        self.severity.as_ref().map_or_else(
+            || self.body.clone(),
+            |severity| format!("{severity} {}", self.body),
+        )
+    }
+
+    pub fn timestamp(&self) -> Option<String> {
+        if self.time_unix_nano == 0 {
+            return None;
+        }
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(self.time_unix_nano))
+            .ok()?
+            .format(&Rfc3339)
+            .ok()
+    }
+}
+
+// Both encodings are decoded into this shape before being tied to the reporting service.
+#[derive(Default)]
+struct SpanFields {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    kind: u64,
+    start_ns: u64,
+    end_ns: u64,
+    status_code: u64,
+    status_message: Option<String>,
+    attributes: BTreeMap<String, String>,
+}
+
+impl SpanFields {
+    fn into_span(self, service: &str) -> Option<AppSpan> {
+        if self.trace_id.is_empty() || self.span_id.is_empty() {
+            return None;
+        }
+        Some(AppSpan {
+            service: service.to_string(),
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            parent_span_id: self.parent_span_id,
+            name: self.name,
+            kind: span_kind(self.kind),
+            start_ms: self.start_ns / 1_000_000,
+            duration_ms: self.end_ns.saturating_sub(self.start_ns) / 1_000_000,
+            error: self.status_code == STATUS_ERROR,
+            status_message: self.status_message,
+            attributes: self.attributes,
+        })
+    }
+}
+
+#[derive(Default)]
+struct LogFields {
+    time_unix_nano: u64,
+    observed_unix_nano: u64,
+    severity_number: u64,
+    severity_text: Option<String>,
+    body: Option<String>,
+    trace_id: Option<String>,
+}
+
+impl LogFields {
+    fn into_record(self, service: &str) -> LogRecord {
+        let severity = self.severity_text.or_else(|| {
+            let index = usize::try_from(self.severity_number.checked_sub(1)? / 4).ok()?;
+            SEVERITY_NAMES.get(index).map(ToString::to_string)
+        });
+        LogRecord {
+            service: service.to_string(),
+            time_unix_nano: if self.time_unix_nano == 0 {
+                self.observed_unix_nano
+            } else {
+                self.time_unix_nano
+            },
+            severity,
+            body: self.body.unwrap_or_default(),
+            trace_id: self.trace_id,
+        }
+    }
+}
+
+pub fn spans_from_json(body: &[u8]) -> Result<Vec<AppSpan>, String> {
+    let doc = parse_json(body)?;
+    let mut spans = Vec::new();
+    for resource in json_list(&doc, "resourceSpans") {
+        let service = json_service(resource);
+        let resource_spans = json_list(resource, "scopeSpans")
+            .flat_map(|scope| json_list(scope, "spans"))
+            .filter_map(|span| json_span(span).into_span(&service));
+        spans.extend(resource_spans);
+    }
+    Ok(spans)
+}
+
+pub fn logs_from_json(body: &[u8]) -> Result<Vec<LogRecord>, String> {
+    let doc = parse_json(body)?;
+    let mut logs = Vec::new();
+    for resource in json_list(&doc, "resourceLogs") {
+        let service = json_service(resource);
+        let records = json_list(resource, "scopeLogs")
+            .flat_map(|scope| json_list(scope, "logRecords"))
+            .map(|record| json_log(record).into_record(&service));
+        logs.extend(records);
+    }
+    Ok(logs)
+}
+
+pub fn spans_from_protobuf(body: &[u8]) -> Result<Vec<AppSpan>, String> {
+    let mut spans = Vec::new();
+    for resource in messages(body, 1)? {
+        let service = proto_service(resource)?;
+        for scope in messages(resource, 2)? {
+            for span in messages(scope, 2)? {
+                spans.extend(proto_span(span)?.into_span(&service));
+            }
+        }
+    }
+    Ok(spans)
+}
+
+pub fn logs_from_protobuf(body: &[u8]) -> Result<Vec<LogRecord>, String> {
+    let mut logs = Vec::new();
+    for resource in messages(body, 1)? {
+        let service = proto_service(resource)?;
+        for scope in messages(resource, 2)? {
+            for record in messages(scope, 2)? {
+                logs.push(proto_log(record)?.into_record(&service));
+            }
+        }
+    }
+    Ok(logs)
+}
+
+const fn span_kind(kind: u64) -> Option<SpanKind> {
+    match kind {
+        1 => Some(SpanKind::Internal),
+        2 => Some(SpanKind::Server),
+        3 => Some(SpanKind::Client),
+        4 => Some(SpanKind::Producer),
+        5 => Some(SpanKind::Consumer),
+        _ => None,
+    }
+}
+
+fn parse_json(body: &[u8]) -> Result<Value, String> {
+    serde_json::from_slice(body).map_err(|err| format!("invalid OTLP JSON payload: {err}"))
+}
+
+fn json_list<'a>(value: &'a Value, key: &str) -> impl Iterator<Item = &'a Value> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+fn json_service(resource: &Value) -> String {
+    resource
+        .get("resource")
+        .map(json_attributes)
+        .and_then(|mut attributes| attributes.remove("service.name"))
+        .unwrap_or_else(|| UNKNOWN_SERVICE.to_string())
+}
+
+fn json_span(span: &Value) -> SpanFields {
+    let status = span.get("status");
+    SpanFields {
+        trace_id: json_id(span, "traceId").unwrap_or_default(),
+        span_id: json_id(span, "spanId").unwrap_or_default(),
+        parent_span_id: json_id(span, "parentSpanId"),
+        name: json_string(span, "name").unwrap_or_default(),
+        kind: json_enum(span.get("kind"), &SPAN_KIND_NAMES),
+        start_ns: json_u64(span.get("startTimeUnixNano")),
+        end_ns: json_u64(span.get("endTimeUnixNano")),
+        status_code: json_enum(status.and_then(|status| status.get("code")), &STATUS_NAMES),
+        status_message: status.and_then(|status| json_string(status, "message")),
+        attributes: json_attributes(span),
+    }
+}
+
+fn json_log(record: &Value) -> LogFields {
+    LogFields {
+        time_unix_nano: json_u64(record.get("timeUnixNano")),
+        observed_unix_nano: json_u64(record.get("observedTimeUnixNano")),
+        severity_number: json_u64(record.get("severityNumber")),
+        severity_text: json_string(record, "severityText"),
+        body: record.get("body").and_then(json_any),
+        trace_id: json_id(record, "traceId"),
+    }
+}
+
+fn json_string(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+        .map(ToString::to_string)
+}
+
+fn json_id(value: &Value, key: &str) -> Option<String> {
+    json_string(value, key).map(|id| id.to_ascii_lowercase())
+}
+
+// 64-bit integers are strings in OTLP JSON, but plain numbers are accepted too.
+fn json_u64(value: Option<&Value>) -> u64 {
+    match value {
+        Some(Value::Number(number)) => number.as_u64().unwrap_or_default(),
+        Some(Value::String(text)) => text.parse().unwrap_or_default(),
+        _ => 0,
+    }
+}
+
+fn json_enum(value: Option<&Value>, names: &[&str]) -> u64 {
+    match value {
+        Some(Value::String(name)) => names
+            .iter()
+            .position(|candidate| candidate == name)
+            .and_then(|index| u64::try_from(index).ok())
+            .unwrap_or_default(),
+        other => json_u64(other),
+    }
+}
+
+fn json_attributes(value: &Value) -> BTreeMap<String, String> {
+    json_list(value, "attributes")
+        .filter_map(|attribute| {
+            let key = json_string(attribute, "key")?;
+            Some((key, attribute.get("value").and_then(json_any)?))
+        })
+        .collect()
+}
+
+fn json_any(value: &Value) -> Option<String> {
+    if let Some(values) = value
+        .get("arrayValue")
+        .and_then(|array| array.get("values"))
+        .and_then(Value::as_array)
+    {
+        let items: Vec<String> = values.iter().filter_map(json_any).collect();
+        return Some(items.join(","));
+    }
+    [
+        "stringValue",
+        "boolValue",
+        "intValue",
+        "doubleValue",
+        "bytesValue",
+    ]
+    .iter()
+    .find_map(|key| match value.get(key)? {
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    })
+}
+
+enum Wire<'a> {
+    Int(u64),
+    Bytes(&'a [u8]),
+}
+
+// Only the protobuf wire format is needed: each message is read as (field number, value)
+// pairs, with fixed-width values widened to integers.
+fn fields(buf: &[u8]) -> Result<Vec<(u64, Wire<'_>)>, String> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let key = varint(buf, &mut pos)?;
+        let value = match key & 7 {
+            0 => Wire::Int(varint(buf, &mut pos)?),
+            1 => Wire::Int(u64::from_le_bytes(fixed::<8>(buf, &mut pos)?)),
+            2 => {
+                let len = usize::try_from(varint(buf, &mut pos)?).map_err(|_| MALFORMED)?;
+                let bytes = buf.get(pos..pos + len).ok_or(MALFORMED)?;
+                pos += len;
+                Wire::Bytes(bytes)
+            }
+            5 => Wire::Int(u64::from(u32::from_le_bytes(fixed::<4>(buf, &mut pos)?))),
+            _ => return Err(MALFORMED.to_string()),
+        };
+        out.push((key >> 3, value));
+    }
+    Ok(out)
+}
+
+fn varint(buf: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos).ok_or(MALFORMED)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(MALFORMED.to_string())
+}
+
+fn fixed<const N: usize>(buf: &[u8], pos: &mut usize) -> Result<[u8; N], String> {
+    let bytes = buf
+        .get(*pos..*pos + N)
+        .and_then(|bytes| <[u8; N]>::try_from(bytes).ok())
+        .ok_or(MALFORMED)?;
+    *pos += N;
+    Ok(bytes)
+}
+
+fn messages(buf: &[u8], field: u64) -> Result<Vec<&[u8]>, String> {
+    Ok(fields(buf)?
+        .into_iter()
+        .filter_map(|(number, value)| match value {
+            Wire::Bytes(bytes) if number == field => Some(bytes),
+            _ => None,
+        })
+        .collect())
+}
+
+fn proto_service(resource_spans: &[u8]) -> Result<String, String> {
+    let mut service = None;
+    for resource in messages(resource_spans, 1)? {
+        for attribute in messages(resource, 1)? {
+            service = proto_key_value(attribute)?
+                .filter(|(key, _)| key == "service.name")
+                .map(|(_, value)| value)
+                .or(service);
+        }
+    }
+    Ok(service.unwrap_or_else(|| UNKNOWN_SERVICE.to_string()))
+}
+
+fn proto_span(buf: &[u8]) -> Result<SpanFields, String> {
+    let mut span = SpanFields::default();
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (1, Wire::Bytes(id)) => span.trace_id = hex(id),
+            (2, Wire::Bytes(id)) => span.span_id = hex(id),
+            (4, Wire::Bytes(id)) if !id.is_empty() => span.parent_span_id = Some(hex(id)),
+            (5, Wire::Bytes(name)) => span.name = String::from_utf8_lossy(name).into_owned(),
+            (6, Wire::Int(kind)) => span.kind = kind,
+            (7, Wire::Int(nanos)) => span.start_ns = nanos,
+            (8, Wire::Int(nanos)) => span.end_ns = nanos,
+            (9, Wire::Bytes(attribute)) => {
+                span.attributes.extend(proto_key_value(attribute)?);
+            }
+            (15, Wire::Bytes(status)) => proto_status(status, &mut span)?,
+            _ => {}
+        }
+    }
+    Ok(span)
+}
+
+fn proto_status(buf: &[u8], span: &mut SpanFields) -> Result<(), String> {
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (2, Wire::Bytes(message)) if !message.is_empty() => {
+                span.status_message = Some(String::from_utf8_lossy(message).into_owned());
+            }
+            (3, Wire::Int(code)) => span.status_code = code,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn proto_log(buf: &[u8]) -> Result<LogFields, String> {
+    let mut record = LogFields::default();
+    for (field, value) in fields(buf)? {
+        match (field, value) {
+            (1, Wire::Int(nanos)) => record.time_unix_nano = nanos,
+            (2, Wire::Int(number)) => record.severity_number = number,
+            (3, Wire::Bytes(text)) if !text.is_empty() => {
+                record.severity_text = Some(String::from_utf8_lossy(text).into_owned());
+            }
+            (5, Wire::Bytes(body)) => record.body = proto_any(body)?,
+            (9, Wire::Bytes(id)) if !id.is_empty() => record.trace_id = Some(hex(id)),
+            (11, Wire::Int(nanos)) => record.observed_unix_nano = nanos,
+            _ => {}
+        }
+    }
+    Ok(record)
+}
+
+fn proto_key_value(buf: &[u8]) -> Result<Option<(String, String)>, String> {
+    let mut key = None;
+    let mut value = None;
+    for (field, wire) in fields(buf)? {
+        match (field, wire) {
+            (1, Wire::Bytes(bytes)) => key = Some(String::from_utf8_lossy(bytes).into_owned()),
+            (2, Wire::Bytes(bytes)) => value = proto_any(bytes)?,
+            _ => {}
+        }
+    }
+    Ok(key.zip(value))
+}
+
+#[allow(clippy::cast_possible_wrap)]
+fn proto_any(buf: &[u8]) -> Result<Option<String>, String> {
+    let mut out = None;
+    for (field, value) in fields(buf)? {
+        out = match (field, value) {
+            (1, Wire::Bytes(text)) => Some(String::from_utf8_lossy(text).into_owned()),
+            (2, Wire::Int(flag)) => Some((flag != 0).to_string()),
+            (3, Wire::Int(number)) => Some((number as i64).to_string()),
+            (4, Wire::Int(bits)) => Some(f64::from_bits(bits).to_string()),
+            (5, Wire::Bytes(array)) => Some(proto_array(array)?),
+            (7, Wire::Bytes(bytes)) => Some(hex(bytes)),
+            _ => continue,
+        };
+    }
+    Ok(out)
+}
+
+fn proto_array(buf: &[u8]) -> Result<String, String> {
+    let mut items = Vec::new();
+    for value in messages(buf, 1)? {
+        items.extend(proto_any(value)?);
+    }
+    Ok(items.join(","))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
@@ -0,0 +1,344 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use serde_json::{json, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::domain::traffic::{
+    DbObservation, DbSystem, EntityId, FlowObservation, HttpObservation, Observation, Peer,
+};
+use crate::domain::LogEvent;
+use crate::support::constants::{PROJECT_NAME_LABEL, RUN_ID_LABEL, STARTED_AT_LABEL};
+
+const SCOPE_NAME: &str = "sanelens";
+const SPAN_KIND_SERVER: u8 = 2;
+const SPAN_KIND_CLIENT: u8 = 3;
+const STATUS_ERROR: u8 = 2;
+
+// Run labels become resource attributes on everything exported for the run.
+#[derive(Clone)]
+pub struct RunResource {
+    pub run_id: String,
+    pub project_name: String,
+    pub started_at: String,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ServiceKey {
+    name: String,
+    instance: Option<String>,
+}
+
+// An observation is exported from the point of view of the workload that saw it: the
+// receiving service for ingress (a server span), the caller for egress (a client span).
+struct SpanSide {
+    service: ServiceKey,
+    kind: u8,
+    peer: Option<String>,
+}
+
+pub fn traces_payload(
+    observations: &[Observation],
+    run: &RunResource,
+    mut new_id: impl FnMut(usize) -> String,
+) -> Value {
+    let mut grouped: BTreeMap<ServiceKey, Vec<Value>> = BTreeMap::new();
+    for obs in observations {
+        let (side, span) = match obs {
+            Observation::Http(http) => {
+                let side = span_side(&http.peer);
+                let span = http_span(http, &side, &mut new_id);
+                (side, span)
+            }
+            Observation::Flow(flow) => {
+                let side = span_side(&flow.peer);
+                let span = flow_span(flow, &side, &mut new_id);
+                (side, span)
+            }
+            Observation::Db(db) => {
+                let side = span_side(&db.peer);
+                let span = db_span(db, &side, &mut new_id);
+                (side, span)
+            }
+        };
+        grouped.entry(side.service).or_default().push(span);
+    }
+    let resource_spans: Vec<Value> = grouped
+        .into_iter()
+        .map(|(service, spans)| {
+            json!({
+                "resource": { "attributes": resource_attributes(&service, run) },
+                "scopeSpans": [{ "scope": { "name": SCOPE_NAME }, "spans": spans }],
+            })
+        })
+        .collect();
+    json!({ "resourceSpans": resource_spans })
+}
+
+pub fn logs_payload(events: &[LogEvent], run: &RunResource, now_ms: u64) -> Value {
+    let mut grouped: BTreeMap<ServiceKey, Vec<Value>> = BTreeMap::new();
+    for event in events {
+        let observed = nanos(now_ms);
+        let time = event
+            .container_ts
+            .as_deref()
+            .and_then(|ts| OffsetDateTime::parse(ts, &Rfc3339).ok())
+            .and_then(|ts| u64::try_from(ts.unix_timestamp_nanos()).ok())
+            .map_or_else(|| observed.clone(), |ts| ts.to_string());
+        let mut record = json!({
+            "timeUnixNano": time,
+            "observedTimeUnixNano": observed,
+            "body": { "stringValue": event.line },
+        });
+        if let Some(trace_id) = event.trace_id.as_deref().and_then(trace_id) {
+            set(&mut record, "traceId", Value::String(trace_id));
+        }
+        let service = ServiceKey {
+            name: event.service.clone(),
+            instance: None,
+        };
+        grouped.entry(service).or_default().push(record);
+    }
+    let resource_logs: Vec<Value> = grouped
+        .into_iter()
+        .map(|(service, records)| {
+            json!({
+                "resource": { "attributes": resource_attributes(&service, run) },
+                "scopeLogs": [{ "scope": { "name": SCOPE_NAME }, "logRecords": records }],
+            })
+        })
+        .collect();
+    json!({ "resourceLogs": resource_logs })
+}
+
+fn span_side(peer: &Peer) -> SpanSide {
+    let workload = |entity: Option<&EntityId>| match entity {
+        Some(EntityId::Workload { name, instance }) => Some(ServiceKey {
+            name: name.clone(),
+            instance: instance.clone(),
+        }),
+        _ => None,
+    };
+    if let Some(service) = workload(peer.dst.as_ref()) {
+        return SpanSide {
+            service,
+            kind: SPAN_KIND_SERVER,
+            peer: peer.src.as_ref().and_then(entity_label),
+        };
+    }
+    SpanSide {
+        service: workload(peer.src.as_ref()).unwrap_or_else(|| ServiceKey {
+            name: "unknown".to_string(),
+            instance: None,
+        }),
+        kind: SPAN_KIND_CLIENT,
+        peer: peer.dst.as_ref().and_then(entity_label),
+    }
+}
+
+// The propagated span id names the caller's span, so it becomes the parent of the span
+// exported for the call. Calls without a trace get one of their own.
+fn http_span(
+    http: &HttpObservation,
+    side: &SpanSide,
+    new_id: &mut impl FnMut(usize) -> String,
+) -> Value {
+    let method = http.method.clone().unwrap_or_else(|| "HTTP".to_string());
+    let target = http
+        .route
+        .as_deref()
+        .or(http.path.as_deref())
+        .unwrap_or("/");
+    let mut attributes = vec![attribute("http.request.method", &method)];
+    if let Some(path) = &http.path {
+        attributes.push(attribute("url.path", path));
+    }
+    if let Some(route) = &http.route {
+        attributes.push(attribute("http.route", route));
+    }
+    if let Some(status) = http.status {
+        attributes.push(int_attribute(
+            "http.response.status_code",
+            u64::from(status),
+        ));
+    }
+    push_common_attributes(
+        &mut attributes,
+        side,
+        http.bytes_in,
+        http.bytes_out,
+        &http.attrs.tags,
+    );
+    let error_from = if side.kind == SPAN_KIND_SERVER {
+        500
+    } else {
+        400
+    };
+    let failed = http.status.is_some_and(|status| status >= error_from);
+    let mut span = json!({
+        "traceId": http
+            .correlation
+            .trace_id
+            .as_deref()
+            .and_then(trace_id)
+            .unwrap_or_else(|| new_id(16)),
+        "spanId": new_id(8),
+        "name": format!("{method} {target}"),
+        "kind": side.kind,
+        "startTimeUnixNano": nanos(http.at_ms),
+        "endTimeUnixNano": nanos(http.at_ms + http.duration_ms.unwrap_or_default()),
+        "attributes": attributes,
+    });
+    if let Some(parent) = &http.correlation.span_id {
+        set(&mut span, "parentSpanId", Value::String(parent.clone()));
+    }
+    if failed {
+        set(&mut span, "status", json!({ "code": STATUS_ERROR }));
+    }
+    span
+}
+
+fn flow_span(
+    flow: &FlowObservation,
+    side: &SpanSide,
+    new_id: &mut impl FnMut(usize) -> String,
+) -> Value {
+    let port = flow.flow.dst.port;
+    let mut attributes = vec![
+        attribute("network.transport", "tcp"),
+        int_attribute("server.port", u64::from(port)),
+    ];
+    push_common_attributes(
+        &mut attributes,
+        side,
+        flow.metrics.bytes_in,
+        flow.metrics.bytes_out,
+        &flow.attrs.tags,
+    );
+    json!({
+        "traceId": new_id(16),
+        "spanId": new_id(8),
+        "name": format!("tcp :{port}"),
+        "kind": side.kind,
+        "startTimeUnixNano": nanos(flow.at_ms),
+        "endTimeUnixNano": nanos(flow.at_ms + flow.metrics.duration_ms.unwrap_or_default()),
+        "attributes": attributes,
+    })
+}
+
+fn db_span(db: &DbObservation, side: &SpanSide, new_id: &mut impl FnMut(usize) -> String) -> Value {
+    let system = match db.system {
+        DbSystem::Postgres => "postgresql",
+        DbSystem::Mysql => "mysql",
+        DbSystem::Redis => "redis",
+    };
+    let mut attributes = vec![
+        attribute("db.system.name", system),
+        attribute("db.operation.name", &db.operation),
+    ];
+    if let Some(table) = &db.table {
+        attributes.push(attribute("db.collection.name", table));
+    }
+    if let Some(error) = &db.error {
+        attributes.push(attribute("db.response.status_code", error));
+    }
+    push_common_attributes(&mut attributes, side, None, None, &db.attrs.tags);
+    let name = db.table.as_ref().map_or_else(
+        || db.operation.clone(),
+        |table| format!("{} {table}", db.operation),
+    );
+    let mut span = json!({
+        "traceId": new_id(16),
+        "spanId": new_id(8),
+        "name": name,
+        "kind": side.kind,
+        "startTimeUnixNano": nanos(db.at_ms),
+        "endTimeUnixNano": nanos(db.at_ms + db.duration_ms.unwrap_or_default()),
+        "attributes": attributes,
+    });
+    if db.error.is_some() {
+        set(&mut span, "status", json!({ "code": STATUS_ERROR }));
+    }
+    span
+}
+
+fn push_common_attributes(
+    attributes: &mut Vec<Value>,
+    side: &SpanSide,
+    bytes_in: Option<u64>,
+    bytes_out: Option<u64>,
+    tags: &BTreeMap<String, String>,
+) {
+    if let Some(peer) = &side.peer {
+        attributes.push(attribute("sanelens.peer", peer));
+    }
+    if let Some(bytes) = bytes_in {
+        attributes.push(int_attribute("sanelens.bytes_in", bytes));
+    }
+    if let Some(bytes) = bytes_out {
+        attributes.push(int_attribute("sanelens.bytes_out", bytes));
+    }
+    for (key, value) in tags {
+        attributes.push(attribute(&format!("sanelens.{key}"), value));
+    }
+}
+
+fn resource_attributes(service: &ServiceKey, run: &RunResource) -> Vec<Value> {
+    let mut attributes = vec![attribute("service.name", &service.name)];
+    if let Some(instance) = &service.instance {
+        attributes.push(attribute("service.instance.id", instance));
+    }
+    attributes.push(attribute(RUN_ID_LABEL, &run.run_id));
+    attributes.push(attribute(PROJECT_NAME_LABEL, &run.project_name));
+    attributes.push(attribute(STARTED_AT_LABEL, &run.started_at));
+    attributes
+}
+
+fn entity_label(entity: &EntityId) -> Option<String> {
+    match entity {
+        EntityId::Workload { name, .. } | EntityId::Host { name } => Some(name.clone()),
+        EntityId::External { ip, dns_name } => {
+            Some(dns_name.clone().unwrap_or_else(|| ip.to_string()))
+        }
+        EntityId::Unknown => None,
+    }
+}
+
+// OTLP trace ids are 128-bit; 64-bit B3 ids are left-padded as Zipkin does.
+fn trace_id(value: &str) -> Option<String> {
+    match value.len() {
+        32 => Some(value.to_string()),
+        16 => Some(format!("{value:0>32}")),
+        _ => None,
+    }
+}
+
+fn nanos(ms: u64) -> String {
+    (u128::from(ms) * 1_000_000).to_string()
+}
+
+fn set(object: &mut Value, key: &str, value: Value) {
+    if let Value::Object(map) = object {
+        map.insert(key.to_string(), value);
+    }
+}
+
+fn attribute(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int_attribute(key: &str, value: u64) -> Value {
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+pub fn random_id(bytes: usize) -> String {
+    let mut buf = vec![0u8; bytes];
+    if getrandom::getrandom(&mut buf).is_err() || buf.iter().all(|byte| *byte == 0) {
+        buf.fill(1);
+    }
+    buf.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
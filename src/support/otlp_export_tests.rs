@@ -0,0 +1,155 @@
+use serde_json::Value;
+
+use super::fixtures::{workload, HttpCall};
+use super::otlp_export::{logs_payload, traces_payload, RunResource};
+use crate::domain::traffic::{Correlation, EntityId};
+use crate::domain::LogEvent;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+fn run() -> RunResource {
+    RunResource {
+        run_id: "run_abc123".to_string(),
+        project_name: "sanelens-run_abc123".to_string(),
+        started_at: "2026-01-01T00:00:00Z".to_string(),
+    }
+}
+
+fn fixed_id(bytes: usize) -> String {
+    "ab".repeat(bytes)
+}
+
+fn user_lookup(src: EntityId, dst: EntityId) -> HttpCall {
+    HttpCall::get("/users/42")
+        .src(src)
+        .dst(dst)
+        .route("/users/{id}")
+        .at(1_000)
+        .duration(25)
+        .tag("fault", "delay")
+}
+
+fn at<'a>(value: &'a Value, pointer: &str) -> &'a Value {
+    value.pointer(pointer).unwrap_or(&Value::Null)
+}
+
+fn attribute<'a>(attributes: &'a Value, key: &str) -> Option<&'a Value> {
+    attributes
+        .as_array()?
+        .iter()
+        .find(|attribute| attribute.get("key").and_then(Value::as_str) == Some(key))
+        .and_then(|attribute| attribute.get("value"))
+}
+
+#[test]
+fn ingress_calls_become_server_spans_of_the_receiving_service() {
+    let api = EntityId::Workload {
+        name: "api".to_string(),
+        instance: Some("111111111111".to_string()),
+    };
+    let correlation = Correlation {
+        trace_id: Some(TRACE_ID.to_string()),
+        span_id: Some("00f067aa0ba902b7".to_string()),
+        ..Correlation::default()
+    };
+    let payload = traces_payload(
+        &[user_lookup(workload("web"), api)
+            .status(503)
+            .correlation(correlation)
+            .observation()],
+        &run(),
+        fixed_id,
+    );
+    let resource = at(&payload, "/resourceSpans/0");
+    let resource_attributes = at(resource, "/resource/attributes");
+    assert_eq!(
+        attribute(resource_attributes, "service.name"),
+        Some(&serde_json::json!({ "stringValue": "api" }))
+    );
+    assert_eq!(
+        attribute(resource_attributes, "service.instance.id"),
+        Some(&serde_json::json!({ "stringValue": "111111111111" }))
+    );
+    assert_eq!(
+        attribute(resource_attributes, "sanelens.run_id"),
+        Some(&serde_json::json!({ "stringValue": "run_abc123" }))
+    );
+    let span = at(resource, "/scopeSpans/0/spans/0");
+    assert_eq!(at(span, "/traceId"), TRACE_ID);
+    assert_eq!(at(span, "/spanId"), "abababababababab");
+    assert_eq!(at(span, "/parentSpanId"), "00f067aa0ba902b7");
+    assert_eq!(at(span, "/name"), "GET /users/{id}");
+    assert_eq!(at(span, "/kind"), 2);
+    assert_eq!(at(span, "/startTimeUnixNano"), "1000000000");
+    assert_eq!(at(span, "/endTimeUnixNano"), "1025000000");
+    assert_eq!(at(span, "/status/code"), 2);
+    assert_eq!(
+        attribute(at(span, "/attributes"), "sanelens.peer"),
+        Some(&serde_json::json!({ "stringValue": "web" }))
+    );
+    assert_eq!(
+        attribute(at(span, "/attributes"), "sanelens.fault"),
+        Some(&serde_json::json!({ "stringValue": "delay" }))
+    );
+}
+
+#[test]
+fn egress_calls_become_client_spans_of_the_caller() {
+    let external = EntityId::External {
+        ip: [93, 184, 216, 34].into(),
+        dns_name: Some("api.example.com".to_string()),
+    };
+    let correlation = Correlation {
+        trace_id: Some("a3ce929d0e0e4736".to_string()),
+        ..Correlation::default()
+    };
+    let payload = traces_payload(
+        &[user_lookup(workload("worker"), external)
+            .status(404)
+            .correlation(correlation)
+            .observation()],
+        &run(),
+        fixed_id,
+    );
+    let resource = at(&payload, "/resourceSpans/0");
+    assert_eq!(
+        attribute(at(resource, "/resource/attributes"), "service.name"),
+        Some(&serde_json::json!({ "stringValue": "worker" }))
+    );
+    let span = at(resource, "/scopeSpans/0/spans/0");
+    assert_eq!(at(span, "/kind"), 3);
+    assert_eq!(at(span, "/traceId"), "0000000000000000a3ce929d0e0e4736");
+    assert_eq!(span.get("parentSpanId"), None);
+    assert_eq!(at(span, "/status/code"), 2);
+    assert_eq!(
+        attribute(at(span, "/attributes"), "sanelens.peer"),
+        Some(&serde_json::json!({ "stringValue": "api.example.com" }))
+    );
+}
+
+#[test]
+fn log_events_keep_container_time_and_trace() {
+    let events = [
+        LogEvent {
+            seq: 1,
+            service: "api".to_string(),
+            container_ts: Some("2023-11-14T22:13:20Z".to_string()),
+            line: "listening".to_string(),
+            trace_id: None,
+        },
+        LogEvent {
+            seq: 2,
+            service: "api".to_string(),
+            container_ts: None,
+            line: "lookup failed".to_string(),
+            trace_id: Some(TRACE_ID.to_string()),
+        },
+    ];
+    let payload = logs_payload(&events, &run(), 5);
+    let records = at(&payload, "/resourceLogs/0/scopeLogs/0/logRecords");
+    assert_eq!(at(records, "/0/timeUnixNano"), "1700000000000000000");
+    assert_eq!(at(records, "/0/body/stringValue"), "listening");
+    assert_eq!(at(records, "/0").get("traceId"), None);
+    assert_eq!(at(records, "/1/timeUnixNano"), "5000000");
+    assert_eq!(at(records, "/1/traceId"), TRACE_ID);
+}
@@ -0,0 +1,149 @@
+use super::otlp::{logs_from_json, logs_from_protobuf, spans_from_json, spans_from_protobuf};
+use crate::domain::traffic::SpanKind;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+fn varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(u8::try_from(value & 0x7f).unwrap_or_default() | 0x80);
+        value >>= 7;
+    }
+    out.push(u8::try_from(value).unwrap_or_default());
+}
+
+fn bytes_field(field: u64, bytes: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint(field << 3 | 2, &mut out);
+    varint(bytes.len() as u64, &mut out);
+    out.extend_from_slice(bytes);
+    out
+}
+
+fn int_field(field: u64, value: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint(field << 3, &mut out);
+    varint(value, &mut out);
+    out
+}
+
+fn fixed64_field(field: u64, value: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    varint(field << 3 | 1, &mut out);
+    out.extend_from_slice(&value.to_le_bytes());
+    out
+}
+
+fn string_attribute(key: &str, value: &str) -> Vec<u8> {
+    let any = bytes_field(1, value.as_bytes());
+    [bytes_field(1, key.as_bytes()), bytes_field(2, &any)].concat()
+}
+
+fn resource(service: &str) -> Vec<u8> {
+    bytes_field(
+        1,
+        &bytes_field(1, &string_attribute("service.name", service)),
+    )
+}
+
+fn trace_id_bytes() -> Vec<u8> {
+    (0..16)
+        .filter_map(|index| u8::from_str_radix(TRACE_ID.get(index * 2..index * 2 + 2)?, 16).ok())
+        .collect()
+}
+
+#[test]
+fn json_spans_are_decoded() {
+    let spans = spans_from_json(
+        br#"{"resourceSpans":[{"resource":{"attributes":[{"key":"service.name","value":{"stringValue":"api"}}]},
+        "scopeSpans":[{"spans":[{"traceId":"4BF92F3577B34DA6A3CE929D0E0E4736","spanId":"00f067aa0ba902b7",
+        "name":"GET /users","kind":2,"startTimeUnixNano":"1700000000000000000","endTimeUnixNano":"1700000000025000000",
+        "status":{"code":"STATUS_CODE_ERROR","message":"boom"},
+        "attributes":[{"key":"http.status_code","value":{"intValue":"500"}}]}]}]}]}"#,
+    )
+    .unwrap_or_default();
+    assert_eq!(spans.len(), 1);
+    for span in &spans {
+        assert_eq!(span.service, "api");
+        assert_eq!(span.trace_id, TRACE_ID);
+        assert_eq!(span.kind, Some(SpanKind::Server));
+        assert_eq!(span.start_ms, 1_700_000_000_000);
+        assert_eq!(span.duration_ms, 25);
+        assert!(span.error);
+        assert_eq!(span.status_message.as_deref(), Some("boom"));
+        assert_eq!(
+            span.attributes.get("http.status_code").map(String::as_str),
+            Some("500")
+        );
+    }
+    assert!(spans_from_json(b"not json").is_err());
+}
+
+#[test]
+fn json_logs_fall_back_to_observed_time_and_severity_number() {
+    let logs = logs_from_json(
+        br#"{"resourceLogs":[{"scopeLogs":[{"logRecords":[{"timeUnixNano":"0","observedTimeUnixNano":"1700000000000000000",
+        "severityNumber":17,"body":{"stringValue":"lookup failed"},"traceId":"4bf92f3577b34da6a3ce929d0e0e4736"}]}]}]}"#,
+    )
+    .unwrap_or_default();
+    assert_eq!(logs.len(), 1);
+    for record in &logs {
+        assert_eq!(record.service, "unknown");
+        assert_eq!(record.line(), "ERROR lookup failed");
+        assert_eq!(record.trace_id.as_deref(), Some(TRACE_ID));
+        assert_eq!(record.timestamp().as_deref(), Some("2023-11-14T22:13:20Z"));
+    }
+}
+
+#[test]
+fn protobuf_spans_are_decoded() {
+    let span = [
+        bytes_field(1, &trace_id_bytes()),
+        bytes_field(2, &[0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]),
+        bytes_field(4, &[0xb0, 0, 0, 0, 0, 0, 0, 0x01]),
+        bytes_field(5, b"SELECT users"),
+        int_field(6, 3),
+        fixed64_field(7, 1_700_000_000_000_000_000),
+        fixed64_field(8, 1_700_000_000_007_000_000),
+        bytes_field(9, &string_attribute("db.system", "postgresql")),
+    ]
+    .concat();
+    let scope = bytes_field(2, &span);
+    let request = bytes_field(1, &[resource("api"), bytes_field(2, &scope)].concat());
+    let spans = spans_from_protobuf(&request).unwrap_or_default();
+    assert_eq!(spans.len(), 1);
+    for span in &spans {
+        assert_eq!(span.service, "api");
+        assert_eq!(span.trace_id, TRACE_ID);
+        assert_eq!(span.span_id, "00f067aa0ba902b7");
+        assert_eq!(span.parent_span_id.as_deref(), Some("b000000000000001"));
+        assert_eq!(span.name, "SELECT users");
+        assert_eq!(span.kind, Some(SpanKind::Client));
+        assert_eq!(span.duration_ms, 7);
+        assert!(!span.error);
+        assert_eq!(
+            span.attributes.get("db.system").map(String::as_str),
+            Some("postgresql")
+        );
+    }
+    assert!(spans_from_protobuf(&[0x0a, 0x05, 0x01]).is_err());
+}
+
+#[test]
+fn protobuf_logs_are_decoded() {
+    let record = [
+        fixed64_field(1, 1_700_000_000_000_000_000),
+        bytes_field(3, b"WARN"),
+        bytes_field(5, &bytes_field(1, b"slow query")),
+        bytes_field(9, &trace_id_bytes()),
+    ]
+    .concat();
+    let scope = bytes_field(2, &record);
+    let request = bytes_field(1, &[resource("worker"), bytes_field(2, &scope)].concat());
+    let logs = logs_from_protobuf(&request).unwrap_or_default();
+    assert_eq!(logs.len(), 1);
+    for record in &logs {
+        assert_eq!(record.service, "worker");
+        assert_eq!(record.line(), "WARN slow query");
+        assert_eq!(record.trace_id.as_deref(), Some(TRACE_ID));
+    }
+}
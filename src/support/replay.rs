@@ -0,0 +1,420 @@
+use std::collections::BTreeMap;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::domain::traffic::{BodyEncoding, EntityId, TrafficCall};
+use crate::support::body::{decode_base64, decompress, DECOMPRESS_LIMIT};
+use crate::support::constants::{BENCH_HEADER, REDACTED_TAG, REDACTED_VALUE, REPLAY_HEADER};
+
+// Headers that describe the captured connection or a body encoding that was undone at
+// capture time; the replay recomputes them.
+const DROPPED_HEADERS: [&str; 10] = [
+    "connection",
+    "content-encoding",
+    "content-length",
+    "expect",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+    "x-request-id",
+    BENCH_HEADER,
+    REPLAY_HEADER,
+];
+const CROP_MARKERS: [&str; 2] = ["\n... (truncated by tap)", "\n... (cropped)"];
+
+// Edits applied on top of the captured request. A `null` header value removes the header.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReplayEdits {
+    pub method: Option<String>,
+    pub path: Option<String>,
+    pub headers: BTreeMap<String, Option<String>>,
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayRequest {
+    pub service: String,
+    pub port: Option<u16>,
+    pub method: String,
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ReplayResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl ReplayRequest {
+    // Only calls into a proxied service can be replayed: the request goes back through that
+    // service's sidecar, on the port the original call reached.
+    pub fn from_call(call: &TrafficCall, edits: &ReplayEdits) -> Result<Self, String> {
+        let Some(EntityId::Workload { name, .. }) = &call.peer.dst else {
+            return Err(format!(
+                "call {} did not reach a service and cannot be replayed",
+                call.seq
+            ));
+        };
+        let method = edits
+            .method
+            .clone()
+            .or_else(|| call.method.clone())
+            .ok_or_else(|| format!("call {} has no HTTP method", call.seq))?
+            .to_uppercase();
+        let path = edits
+            .path
+            .clone()
+            .or_else(|| call.path.clone())
+            .filter(|path| path.starts_with('/'))
+            .ok_or_else(|| format!("call {} has no request path", call.seq))?;
+        let mut headers: BTreeMap<String, String> = call
+            .request_headers
+            .iter()
+            .filter(|(key, _)| !key.starts_with(':') && !DROPPED_HEADERS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if let Some(authority) = call.request_headers.get(":authority") {
+            headers
+                .entry("host".to_string())
+                .or_insert_with(|| authority.clone());
+        }
+        for (key, value) in &edits.headers {
+            let key = key.trim().to_ascii_lowercase();
+            match value {
+                Some(value) => headers.insert(key, value.clone()),
+                None => headers.remove(&key),
+            };
+        }
+        check_request_line(&method, &path)?;
+        for (key, value) in &headers {
+            check_header(key, value)?;
+        }
+        let body = match &edits.body {
+            Some(body) => body.as_bytes().to_vec(),
+            None => captured_body(call)?,
+        };
+        let warnings = headers
+            .iter()
+            .filter(|(_, value)| value.as_str() == REDACTED_VALUE)
+            .map(|(key, _)| format!("header {key} was redacted at capture time"))
+            .collect();
+        Ok(Self {
+            service: name.clone(),
+            port: target_port(call),
+            method,
+            path,
+            headers,
+            body,
+            warnings,
+        })
+    }
+
+    // Requests are sent with `connection: close` so the response ends with the stream. The
+    // marker header tags the resulting call, e.g. as a replay.
+    pub fn to_http(&self, marker: &str, value: &str) -> Vec<u8> {
+        let mut lines = vec![format!("{} {} HTTP/1.1", self.method, self.path)];
+        if !self.headers.contains_key("host") {
+            lines.push(format!("host: {}", self.host()));
+        }
+        lines.extend(
+            self.headers
+                .iter()
+                .map(|(key, value)| format!("{key}: {value}")),
+        );
+        if !self.body.is_empty() || matches!(self.method.as_str(), "POST" | "PUT" | "PATCH") {
+            lines.push(format!("content-length: {}", self.body.len()));
+        }
+        lines.push(format!("{marker}: {value}"));
+        lines.push("connection: close".to_string());
+        let mut raw = format!("{}\r\n\r\n", lines.join("\r\n")).into_bytes();
+        raw.extend_from_slice(&self.body);
+        raw
+    }
+
+    // Binary bodies are piped through `base64 -d` so the command stays copy-pasteable.
+    pub fn curl(&self) -> String {
+        let url = format!("http://{}{}", self.host(), self.path);
+        let mut parts = vec!["curl".to_string()];
+        if self.method != "GET" || !self.body.is_empty() {
+            parts.push(format!("-X {}", self.method));
+        }
+        parts.push(shell_quote(&url));
+        for (key, value) in &self.headers {
+            parts.push(format!("-H {}", shell_quote(&format!("{key}: {value}"))));
+        }
+        if self.body.is_empty() {
+            return parts.join(" ");
+        }
+        if let Ok(text) = std::str::from_utf8(&self.body) {
+            parts.push(format!("--data-raw {}", shell_quote(text)));
+            return parts.join(" ");
+        }
+        parts.push("--data-binary @-".to_string());
+        format!(
+            "printf %s {} | base64 -d | {}",
+            shell_quote(&STANDARD.encode(&self.body)),
+            parts.join(" ")
+        )
+    }
+
+    fn host(&self) -> String {
+        self.headers.get("host").cloned().unwrap_or_else(|| {
+            self.port.map_or_else(
+                || self.service.clone(),
+                |port| format!("{}:{port}", self.service),
+            )
+        })
+    }
+}
+
+// The sidecar listens on the port the app serves, which is the upstream port of the flow;
+// the client's host header is the fallback.
+// Everything below ends up verbatim in the request head, so a stray CR or LF would let an edit
+// split it into extra headers or a second request.
+fn check_request_line(method: &str, path: &str) -> Result<(), String> {
+    if method.is_empty() || !method.bytes().all(is_token_byte) {
+        return Err(format!("invalid HTTP method {method:?}"));
+    }
+    if path
+        .bytes()
+        .any(|byte| byte == b' ' || byte.is_ascii_control())
+    {
+        return Err(format!("invalid request path {path:?}"));
+    }
+    Ok(())
+}
+
+fn check_header(key: &str, value: &str) -> Result<(), String> {
+    if key.is_empty() || !key.bytes().all(is_token_byte) {
+        return Err(format!("invalid header name {key:?}"));
+    }
+    if value
+        .bytes()
+        .any(|byte| byte != b'\t' && byte.is_ascii_control())
+    {
+        return Err(format!("invalid value for header {key}"));
+    }
+    Ok(())
+}
+
+fn is_token_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+fn target_port(call: &TrafficCall) -> Option<u16> {
+    call.peer.raw.as_ref().map(|raw| raw.dst.port).or_else(|| {
+        call.request_headers
+            .get(":authority")
+            .or_else(|| call.request_headers.get("host"))
+            .and_then(|host| host.rsplit_once(':'))
+            .and_then(|(_, port)| port.parse().ok())
+    })
+}
+
+fn captured_body(call: &TrafficCall) -> Result<Vec<u8>, String> {
+    let incomplete = || {
+        format!(
+            "the request body of call {} was not captured in full; pass the body as an edit",
+            call.seq
+        )
+    };
+    let redacted = call
+        .attrs
+        .tags
+        .get(REDACTED_TAG)
+        .is_some_and(|parts| parts.split(',').any(|part| part == "request_body"));
+    if redacted {
+        return Err(incomplete());
+    }
+    let Some(text) = call.request_body.as_deref() else {
+        return if call.bytes_in.unwrap_or_default() > 0 {
+            Err(incomplete())
+        } else {
+            Ok(Vec::new())
+        };
+    };
+    let body = match call.body_encoding.request {
+        None if CROP_MARKERS.iter().any(|marker| text.ends_with(marker)) => {
+            return Err(incomplete())
+        }
+        None => text.as_bytes().to_vec(),
+        Some(BodyEncoding::Base64) => decode_base64(text).ok_or_else(incomplete)?,
+        Some(BodyEncoding::Form) => return form_encode(text).ok_or_else(incomplete),
+        Some(BodyEncoding::Multipart) => return Err(incomplete()),
+    };
+    // Compressed bodies were inflated at capture time, so only plain ones can be compared
+    // with the byte count on the wire.
+    let plain = !call.request_headers.contains_key("content-encoding");
+    if plain
+        && call
+            .bytes_in
+            .is_some_and(|bytes| bytes != body.len() as u64)
+    {
+        return Err(incomplete());
+    }
+    Ok(body)
+}
+
+// Form bodies are captured as a JSON object; repeated fields were collected into arrays.
+fn form_encode(text: &str) -> Option<Vec<u8>> {
+    let Value::Object(fields) = serde_json::from_str(text).ok()? else {
+        return None;
+    };
+    let mut pairs = Vec::new();
+    for (key, value) in &fields {
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            pairs.push(format!(
+                "{}={}",
+                percent_encode(key),
+                percent_encode(value.as_str()?)
+            ));
+        }
+    }
+    Some(pairs.join("&").into_bytes())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(byte).to_string()
+            }
+            b' ' => "+".to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+// Sequence numbers depend on how much of the capture is kept; the `x-request-id` Envoy gives
+// every request names the same call in every command.
+pub fn find_call(calls: Vec<TrafficCall>, key: &str) -> Option<TrafficCall> {
+    let seq = key.parse::<u64>().ok();
+    calls
+        .into_iter()
+        .find(|call| Some(call.seq) == seq || call.correlation.request_id.as_deref() == Some(key))
+}
+
+// Parses the raw HTTP/1.1 response read back from the sidecar.
+pub fn parse_response(raw: &[u8]) -> Result<ReplayResponse, String> {
+    let split = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| "the service closed the connection without a response".to_string())?;
+    let head = String::from_utf8_lossy(raw.get(..split).unwrap_or_default());
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| "the service sent a malformed status line".to_string())?;
+    let mut headers: BTreeMap<String, String> = BTreeMap::new();
+    for (key, value) in lines.filter_map(|line| line.split_once(':')) {
+        headers
+            .entry(key.trim().to_ascii_lowercase())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value.trim());
+            })
+            .or_insert_with(|| value.trim().to_string());
+    }
+    let mut body = raw.get(split + 4..).unwrap_or_default().to_vec();
+    if headers
+        .get("transfer-encoding")
+        .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+    {
+        body = dechunk(&body);
+    }
+    let mut warnings = Vec::new();
+    if let Some(coding) = headers.get("content-encoding") {
+        let (decoded, truncated) = decompress(&body, coding, DECOMPRESS_LIMIT);
+        if truncated {
+            warnings.push(format!(
+                "response body cut at {DECOMPRESS_LIMIT} bytes after decompression"
+            ));
+        }
+        body = decoded;
+    }
+    Ok(ReplayResponse {
+        status,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+        warnings,
+    })
+}
+
+fn dechunk(mut rest: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    while let Some(line_end) = rest.windows(2).position(|window| window == b"\r\n") {
+        let size_line = String::from_utf8_lossy(rest.get(..line_end).unwrap_or_default());
+        let size = size_line
+            .split(';')
+            .next()
+            .and_then(|size| usize::from_str_radix(size.trim(), 16).ok())
+            .unwrap_or_default();
+        let start = line_end + 2;
+        let Some(chunk) = rest.get(start..start + size).filter(|_| size > 0) else {
+            break;
+        };
+        body.extend_from_slice(chunk);
+        rest = rest.get(start + size + 2..).unwrap_or_default();
+    }
+    body
+}
+
+// `sanelens replay <run_id> <seq> [--method M] [--path P] [-H 'name: value'] [--data BODY]
+// [--curl]`; a header given without a value removes it.
+pub fn parse_replay_args(args: &[String]) -> Result<(ReplayEdits, bool), String> {
+    let mut edits = ReplayEdits::default();
+    let mut curl = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--curl" {
+            curl = true;
+            continue;
+        }
+        let (flag, inline) = arg
+            .split_once('=')
+            .filter(|_| arg.starts_with("--"))
+            .map_or((arg.as_str(), None), |(flag, value)| (flag, Some(value)));
+        if !matches!(flag, "--method" | "--path" | "-H" | "--header" | "--data") {
+            return Err(format!("unknown replay option '{arg}'"));
+        }
+        let value = inline
+            .map(str::to_string)
+            .or_else(|| iter.next().cloned())
+            .ok_or_else(|| format!("{flag} needs a value"))?;
+        match flag {
+            "--method" => edits.method = Some(value),
+            "--path" => edits.path = Some(value),
+            "--data" => edits.body = Some(value),
+            _ => {
+                let (key, value) = value.split_once(':').unwrap_or((&value, ""));
+                let value = value.trim();
+                edits.headers.insert(
+                    key.trim().to_ascii_lowercase(),
+                    (!value.is_empty()).then(|| value.to_string()),
+                );
+            }
+        }
+    }
+    Ok((edits, curl))
+}
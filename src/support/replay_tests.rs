@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+use super::fixtures::{workload, HttpCall};
+use super::replay::{find_call, parse_replay_args, parse_response, ReplayEdits, ReplayRequest};
+use crate::domain::traffic::{BodyEncoding, EntityId, FlowKey, Socket, TrafficCall, Transport};
+use crate::support::constants::REPLAY_HEADER;
+
+fn socket(last: u8, port: u16) -> Socket {
+    Socket {
+        ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, last)),
+        port,
+    }
+}
+
+fn captured_post(body: &str) -> TrafficCall {
+    let mut call = HttpCall::new("post", "/users?team=a")
+        .dst(workload("api"))
+        .status(201)
+        .duration(4)
+        .request_header(":authority", "api:8080")
+        .request_header("content-length", &body.len().to_string())
+        .request_header("content-type", "application/json")
+        .request_header("x-request-id", "req-1")
+        .request_body(body)
+        .call(3);
+    call.peer.raw = Some(FlowKey {
+        src: socket(2, 41_000),
+        dst: socket(3, 8080),
+        transport: Transport::Tcp,
+    });
+    call.bytes_in = Some(body.len() as u64);
+    call
+}
+
+fn request(call: &TrafficCall, edits: &ReplayEdits) -> ReplayRequest {
+    let request = ReplayRequest::from_call(call, edits);
+    assert!(request.is_ok(), "{request:?}");
+    let Ok(request) = request else {
+        return ReplayRequest {
+            service: String::new(),
+            port: None,
+            method: String::new(),
+            path: String::new(),
+            headers: BTreeMap::new(),
+            body: Vec::new(),
+            warnings: Vec::new(),
+        };
+    };
+    request
+}
+
+#[test]
+fn replays_go_to_the_captured_port_with_edits_and_a_replay_header() {
+    let original = captured_post(r#"{"name":"ada"}"#);
+    let replay = request(&original, &ReplayEdits::default());
+    assert_eq!(replay.port, Some(8080));
+    assert_eq!(
+        String::from_utf8_lossy(&replay.to_http(REPLAY_HEADER, "true")),
+        "POST /users?team=a HTTP/1.1\r\ncontent-type: application/json\r\nhost: api:8080\r\n\
+         content-length: 14\r\nx-sanelens-replay: true\r\nconnection: close\r\n\r\n{\"name\":\"ada\"}"
+    );
+
+    let edits = ReplayEdits {
+        method: Some("put".to_string()),
+        path: Some("/users/1".to_string()),
+        headers: BTreeMap::from([
+            ("Content-Type".to_string(), None),
+            ("x-debug".to_string(), Some("1".to_string())),
+        ]),
+        body: Some("name=bob".to_string()),
+    };
+    let edited = request(&original, &edits);
+    assert_eq!(edited.method, "PUT");
+    assert_eq!(edited.path, "/users/1");
+    assert_eq!(
+        edited
+            .headers
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>(),
+        vec!["host", "x-debug"]
+    );
+    assert_eq!(edited.body, b"name=bob");
+}
+
+#[test]
+fn incomplete_bodies_need_an_edit() {
+    let mut cropped = captured_post("{\"name\":\"a\"}\n... (cropped)");
+    cropped.bytes_in = Some(4096);
+    assert!(ReplayRequest::from_call(&cropped, &ReplayEdits::default()).is_err());
+    let edits = ReplayEdits {
+        body: Some("{}".to_string()),
+        ..ReplayEdits::default()
+    };
+    assert!(ReplayRequest::from_call(&cropped, &edits).is_ok());
+
+    let mut form = captured_post(r#"{"q":"a b","tag":["x","y&z"]}"#);
+    form.bytes_in = None;
+    form.body_encoding.request = Some(BodyEncoding::Form);
+    assert_eq!(
+        request(&form, &ReplayEdits::default()).body,
+        b"q=a+b&tag=x&tag=y%26z"
+    );
+
+    let mut egress = captured_post("");
+    egress.peer.dst = Some(EntityId::Host {
+        name: "example.com".to_string(),
+    });
+    assert!(ReplayRequest::from_call(&egress, &ReplayEdits::default()).is_err());
+}
+
+#[test]
+fn edits_cannot_split_the_request_head() {
+    let call = captured_post("{}");
+    let edited = |edits: ReplayEdits| ReplayRequest::from_call(&call, &edits);
+    assert!(edited(ReplayEdits {
+        method: Some("GET /admin HTTP/1.1\r\nx:".to_string()),
+        ..ReplayEdits::default()
+    })
+    .is_err());
+    assert!(edited(ReplayEdits {
+        path: Some("/users\r\nhost: evil".to_string()),
+        ..ReplayEdits::default()
+    })
+    .is_err());
+    assert!(edited(ReplayEdits {
+        headers: BTreeMap::from([("x-a\nx-b".to_string(), Some("1".to_string()))]),
+        ..ReplayEdits::default()
+    })
+    .is_err());
+    assert!(edited(ReplayEdits {
+        headers: BTreeMap::from([("x-a".to_string(), Some("1\r\n\r\nGET /".to_string()))]),
+        ..ReplayEdits::default()
+    })
+    .is_err());
+    assert!(edited(ReplayEdits {
+        headers: BTreeMap::from([("x-a".to_string(), Some("a\tb".to_string()))]),
+        ..ReplayEdits::default()
+    })
+    .is_ok());
+}
+
+#[test]
+fn curl_commands_quote_and_pipe_binary_bodies() {
+    let text = request(
+        &captured_post(r#"{"name":"o'hara"}"#),
+        &ReplayEdits::default(),
+    );
+    assert_eq!(
+        text.curl(),
+        r#"curl -X POST 'http://api:8080/users?team=a' -H 'content-type: application/json' -H 'host: api:8080' --data-raw '{"name":"o'\''hara"}'"#
+    );
+
+    let mut binary = captured_post("//79");
+    binary.bytes_in = Some(3);
+    binary.body_encoding.request = Some(BodyEncoding::Base64);
+    let binary = request(&binary, &ReplayEdits::default());
+    assert_eq!(binary.body, vec![0xff, 0xfe, 0xfd]);
+    assert!(binary
+        .curl()
+        .starts_with("printf %s '//79' | base64 -d | curl -X POST"));
+}
+
+#[test]
+fn responses_and_args_are_parsed() {
+    let raw = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n\
+                3\r\nabc\r\n2;x=1\r\nde\r\n0\r\n\r\n";
+    let response = parse_response(raw);
+    assert!(response.is_ok());
+    let Ok(response) = response else {
+        return;
+    };
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.headers.get("content-type").map(String::as_str),
+        Some("text/plain")
+    );
+    assert_eq!(response.body, "abcde");
+    assert!(parse_response(b"").is_err());
+
+    let args: Vec<String> = [
+        "--method=delete",
+        "-H",
+        "Authorization: Bearer x",
+        "-H",
+        "cookie",
+    ]
+    .map(str::to_string)
+    .to_vec();
+    let parsed = parse_replay_args(&args);
+    assert!(parsed.is_ok());
+    let Ok((edits, curl)) = parsed else {
+        return;
+    };
+    assert!(!curl);
+    assert_eq!(edits.method.as_deref(), Some("delete"));
+    assert_eq!(
+        edits.headers,
+        BTreeMap::from([
+            ("authorization".to_string(), Some("Bearer x".to_string())),
+            ("cookie".to_string(), None),
+        ])
+    );
+    assert!(parse_replay_args(&["--data".to_string()]).is_err());
+}
+
+#[test]
+fn calls_are_found_by_sequence_number_or_request_id() {
+    let mut first = captured_post("");
+    first.correlation.request_id = Some("5f0c".to_string());
+    let mut second = captured_post("");
+    second.seq = 4;
+    let calls = vec![first, second];
+    assert_eq!(find_call(calls.clone(), "4").map(|call| call.seq), Some(4));
+    assert_eq!(
+        find_call(calls.clone(), "5f0c").map(|call| call.seq),
+        Some(3)
+    );
+    assert!(find_call(calls, "7").is_none());
+}
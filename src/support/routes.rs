@@ -0,0 +1,84 @@
+const ID_PLACEHOLDER: &str = "{id}";
+const MIN_HEX_ID_LEN: usize = 8;
+const MIN_TOKEN_LEN: usize = 20;
+
+// Edges are keyed on routes rather than raw paths. Templates such as `/users/{user}/orders`
+// or `/files/*` win; anything else has its query dropped and id-like segments collapsed.
+#[derive(Clone, Debug, Default)]
+pub struct RouteTemplates {
+    templates: Vec<String>,
+}
+
+impl RouteTemplates {
+    pub const fn new(templates: Vec<String>) -> Self {
+        Self { templates }
+    }
+
+    pub fn route(&self, path: &str) -> String {
+        let path = path.split(['?', '#']).next().unwrap_or(path);
+        self.templates
+            .iter()
+            .find(|template| template_matches(template, path))
+            .cloned()
+            .unwrap_or_else(|| normalize_path(path))
+    }
+}
+
+pub fn normalize_route(path: &str) -> String {
+    RouteTemplates::default().route(path)
+}
+
+fn template_matches(template: &str, path: &str) -> bool {
+    let mut segments = path.split('/');
+    for expected in template.split('/') {
+        if expected == "*" {
+            return true;
+        }
+        let Some(segment) = segments.next() else {
+            return false;
+        };
+        let placeholder = expected.starts_with('{') && expected.ends_with('}');
+        if placeholder && segment.is_empty() || !placeholder && segment != expected {
+            return false;
+        }
+    }
+    segments.next().is_none()
+}
+
+fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if is_id(segment) {
+                ID_PLACEHOLDER
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_id(segment: &str) -> bool {
+    if segment.is_empty() {
+        return false;
+    }
+    let has_digit = segment.bytes().any(|byte| byte.is_ascii_digit());
+    segment.bytes().all(|byte| byte.is_ascii_digit())
+        || is_uuid(segment)
+        || segment.len() >= MIN_HEX_ID_LEN
+            && has_digit
+            && segment.bytes().all(|byte| byte.is_ascii_hexdigit())
+        || segment.len() >= MIN_TOKEN_LEN
+            && has_digit
+            && segment
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|group| group.bytes().all(|byte| byte.is_ascii_hexdigit()))
+}
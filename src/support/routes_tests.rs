@@ -0,0 +1,28 @@
+use super::routes::{normalize_route, RouteTemplates};
+
+#[test]
+fn id_like_segments_are_collapsed() {
+    assert_eq!(normalize_route("/users/123?expand=1"), "/users/{id}");
+    assert_eq!(
+        normalize_route("/orders/3f2c9a1e-8b7d-4c6e-9f1a-2b3c4d5e6f70/items"),
+        "/orders/{id}/items"
+    );
+    assert_eq!(normalize_route("/blobs/9f86d081884c7d65"), "/blobs/{id}");
+    assert_eq!(
+        normalize_route("/v1/charges/ch_3MqBzL2eZvKYlo2C0XjKFd1M"),
+        "/v1/charges/{id}"
+    );
+    assert_eq!(normalize_route("/users/me/settings"), "/users/me/settings");
+    assert_eq!(normalize_route("/v2/health"), "/v2/health");
+}
+
+#[test]
+fn templates_take_precedence() {
+    let routes = RouteTemplates::new(vec![
+        "/users/{name}/posts".to_string(),
+        "/static/*".to_string(),
+    ]);
+    assert_eq!(routes.route("/users/ada/posts"), "/users/{name}/posts");
+    assert_eq!(routes.route("/static/css/site.css"), "/static/*");
+    assert_eq!(routes.route("/users/ada/posts/7"), "/users/ada/posts/{id}");
+}
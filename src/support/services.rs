@@ -7,7 +7,7 @@ use crate::domain::ServiceInfo;
 pub fn build_service_info(compose_file: &str) -> Vec<ServiceInfo> {
     let (services, ports_by_service) = parse_compose_services_and_ports(compose_file);
     let mut info = Vec::new();
-    for name in services {
+    for (name, display_name) in services {
         let endpoints: Vec<String> = ports_by_service
             .get(&name)
             .map(|ports| {
@@ -19,6 +19,7 @@ pub fn build_service_info(compose_file: &str) -> Vec<ServiceInfo> {
             .unwrap_or_default();
         info.push(ServiceInfo {
             name: name.clone(),
+            display_name,
             endpoint: endpoints.first().cloned(),
             exposed: !endpoints.is_empty(),
             endpoints,
@@ -27,9 +28,45 @@ pub fn build_service_info(compose_file: &str) -> Vec<ServiceInfo> {
     info
 }
 
+// Host and container port of each published port of a service, in compose order. Ports
+// published on a random host port and port ranges are left out.
+pub fn published_ports(compose_file: &str, service: &str) -> Vec<(u16, u16)> {
+    let Ok(contents) = fs::read_to_string(compose_file) else {
+        return Vec::new();
+    };
+    let Ok(doc) = serde_yaml::from_str::<serde_yaml::Value>(&contents) else {
+        return Vec::new();
+    };
+    let Some(list) = doc
+        .get("services")
+        .and_then(|services| services.get(service))
+        .and_then(|service| service.get("ports"))
+        .and_then(serde_yaml::Value::as_sequence)
+    else {
+        return Vec::new();
+    };
+    list.iter()
+        .filter_map(|entry| {
+            let (host, container) = match entry {
+                serde_yaml::Value::String(value) => parse_port_mapping(value)?,
+                serde_yaml::Value::Mapping(_) => (
+                    entry.get("published").and_then(yaml_value_to_string)?,
+                    entry.get("target").and_then(yaml_value_to_string)?,
+                ),
+                _ => return None,
+            };
+            let host = resolve_host_port(&host)?.parse().ok()?;
+            let container = resolve_env_value(&container).parse().ok()?;
+            Some((host, container))
+        })
+        .collect()
+}
+
+type NamedService = (String, Option<String>);
+
 fn parse_compose_services_and_ports(
     compose_file: &str,
-) -> (Vec<String>, HashMap<String, Vec<String>>) {
+) -> (Vec<NamedService>, HashMap<String, Vec<String>>) {
     let Ok(contents) = fs::read_to_string(compose_file) else {
         return (Vec::new(), HashMap::new());
     };
@@ -50,12 +87,22 @@ fn parse_compose_services_and_ports(
         let ports = extract_service_ports(service_val);
         let unique = dedup_ports(ports);
         ports_by_service.insert(name.to_string(), unique);
-        services.push(name.to_string());
+        services.push((name.to_string(), extract_display_name(service_val)));
     }
 
     (services, ports_by_service)
 }
 
+fn extract_display_name(service_val: &serde_yaml::Value) -> Option<String> {
+    service_val
+        .get("x-sanelens")
+        .and_then(|extension| extension.get("name"))
+        .and_then(serde_yaml::Value::as_str)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
 fn extract_service_ports(service_val: &serde_yaml::Value) -> Vec<String> {
     let Some(service_map) = service_val.as_mapping() else {
         return Vec::new();
@@ -160,6 +207,17 @@ fn parse_port_short(value: &str) -> Option<String> {
     Some(first.to_string())
 }
 
+fn parse_port_mapping(value: &str) -> Option<(String, String)> {
+    let entry = strip_quotes(value.trim());
+    let entry = entry.split('/').next().unwrap_or(entry);
+    let (host, container) = entry.rsplit_once(':')?;
+    let host = match host.rsplit_once(':') {
+        Some((address, port)) if !address.contains("${") => port,
+        _ => host,
+    };
+    Some((host.to_string(), container.to_string()))
+}
+
 fn resolve_host_port(raw_port: &str) -> Option<String> {
     let value = resolve_env_value(raw_port).trim().to_string();
     if value.is_empty() || value == "0" {
@@ -0,0 +1,30 @@
+use std::env;
+use std::fs;
+
+use super::services::published_ports;
+
+#[test]
+fn published_ports_map_host_to_container_ports() {
+    let dir = env::temp_dir().join(format!("sanelens-services-{}", std::process::id()));
+    assert!(fs::create_dir_all(&dir).is_ok());
+    let path = dir.join("compose.yml");
+    let compose = r#"
+services:
+  api:
+    ports:
+      - "8081:8080"
+      - "127.0.0.1:9091:9090/tcp"
+      - "3000"
+      - "[::1]:7001:7000"
+      - target: 5000
+        published: 5001
+"#;
+    assert!(fs::write(&path, compose).is_ok());
+    let ports = published_ports(&path.to_string_lossy(), "api");
+    assert_eq!(
+        ports,
+        vec![(8081, 8080), (9091, 9090), (7001, 7000), (5001, 5000)]
+    );
+    assert!(published_ports(&path.to_string_lossy(), "web").is_empty());
+    let _ = fs::remove_dir_all(&dir);
+}
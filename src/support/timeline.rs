@@ -0,0 +1,38 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+const TIMELINE_FILE: &str = "timeline.jsonl";
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    pub at_ms: u64,
+    pub kind: String,
+    pub message: String,
+}
+
+// The timeline lives in the run directory so that `sanelens` commands run from other
+// terminals add to the same history as the attached `up`.
+pub fn append(run_dir: &Path, event: &TimelineEvent) -> io::Result<()> {
+    let mut line = serde_json::to_string(event).map_err(io::Error::other)?;
+    line.push('\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(run_dir.join(TIMELINE_FILE))?;
+    file.write_all(line.as_bytes())
+}
+
+pub fn read(run_dir: &Path) -> io::Result<Vec<TimelineEvent>> {
+    let payload = match fs::read_to_string(run_dir.join(TIMELINE_FILE)) {
+        Ok(payload) => payload,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(payload
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::domain::traffic::{
+    AppSpan, Correlation, EntityId, SpanNode, TraceNode, TraceSummary, TraceTree, TrafficCall,
+};
+
+pub const TRACE_HEADERS: [&str; 7] = [
+    "traceparent",
+    "tracestate",
+    "b3",
+    "x-b3-traceid",
+    "x-b3-spanid",
+    "x-b3-parentspanid",
+    "x-b3-sampled",
+];
+
+// Calls observed by different sidecars rarely agree to the millisecond.
+const CLOCK_SLACK_MS: u64 = 5;
+
+pub fn correlation(request_id: Option<String>, headers: &BTreeMap<String, String>) -> Correlation {
+    let context = headers
+        .get("traceparent")
+        .and_then(|value| parse_traceparent(value))
+        .or_else(|| headers.get("b3").and_then(|value| parse_b3(value)))
+        .or_else(|| parse_b3_multi(headers))
+        .unwrap_or_default();
+    Correlation {
+        request_id,
+        trace_id: context.trace_id,
+        span_id: context.span_id,
+        parent_span_id: context.parent_span_id,
+        trace_state: headers.get("tracestate").cloned(),
+        sampled: context.sampled,
+    }
+}
+
+#[derive(Default)]
+struct TraceContext {
+    trace_id: Option<String>,
+    span_id: Option<String>,
+    parent_span_id: Option<String>,
+    sampled: Option<bool>,
+}
+
+// `version-traceid-spanid-flags`, e.g. `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+fn parse_traceparent(value: &str) -> Option<TraceContext> {
+    let mut parts = value.trim().split('-');
+    let _version = parts.next().filter(|version| {
+        version.len() == 2 && version.bytes().all(|byte| byte.is_ascii_hexdigit())
+    })?;
+    let trace_id = parts.next().filter(|id| is_hex(id, 32))?;
+    let span_id = parts.next().filter(|id| is_hex(id, 16))?;
+    let flags = parts
+        .next()
+        .and_then(|flags| u8::from_str_radix(flags, 16).ok())?;
+    Some(TraceContext {
+        trace_id: Some(trace_id.to_ascii_lowercase()),
+        span_id: Some(span_id.to_ascii_lowercase()),
+        parent_span_id: None,
+        sampled: Some(flags & 1 == 1),
+    })
+}
+
+// `traceid-spanid[-sampled[-parentspanid]]`; a lone sampling flag carries no ids.
+fn parse_b3(value: &str) -> Option<TraceContext> {
+    let mut parts = value.trim().split('-');
+    let trace_id = parts.next().filter(|id| is_trace_id(id))?;
+    let span_id = parts.next().filter(|id| is_hex(id, 16))?;
+    let sampled = parts.next().and_then(parse_b3_sampled);
+    let parent_span_id = parts.next().filter(|id| is_hex(id, 16));
+    Some(TraceContext {
+        trace_id: Some(trace_id.to_ascii_lowercase()),
+        span_id: Some(span_id.to_ascii_lowercase()),
+        parent_span_id: parent_span_id.map(str::to_ascii_lowercase),
+        sampled,
+    })
+}
+
+fn parse_b3_multi(headers: &BTreeMap<String, String>) -> Option<TraceContext> {
+    let trace_id = headers
+        .get("x-b3-traceid")
+        .map(|id| id.trim())
+        .filter(|id| is_trace_id(id))?;
+    let span = |name: &str| {
+        headers
+            .get(name)
+            .map(|id| id.trim())
+            .filter(|id| is_hex(id, 16))
+            .map(str::to_ascii_lowercase)
+    };
+    Some(TraceContext {
+        trace_id: Some(trace_id.to_ascii_lowercase()),
+        span_id: span("x-b3-spanid"),
+        parent_span_id: span("x-b3-parentspanid"),
+        sampled: headers
+            .get("x-b3-sampled")
+            .and_then(|value| parse_b3_sampled(value)),
+    })
+}
+
+fn parse_b3_sampled(value: &str) -> Option<bool> {
+    match value.trim() {
+        "1" | "d" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn is_trace_id(value: &str) -> bool {
+    is_hex(value, 16) || is_hex(value, 32)
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+        && value.bytes().any(|byte| byte != b'0')
+}
+
+// Everything indexed under one trace id: calls seen by sidecars and spans reported by apps.
+#[derive(Clone, Default)]
+pub struct TraceRecord {
+    pub calls: Vec<TrafficCall>,
+    pub spans: Vec<AppSpan>,
+}
+
+pub fn summarize(trace_id: &str, record: &TraceRecord) -> TraceSummary {
+    let starts = record.calls.iter().map(|call| call.at_ms);
+    let ends = record.calls.iter().map(call_end);
+    let started_at_ms = starts
+        .chain(record.spans.iter().map(|span| span.start_ms))
+        .min()
+        .unwrap_or_default();
+    let ended_at_ms = ends
+        .chain(record.spans.iter().map(span_end))
+        .max()
+        .unwrap_or_default();
+    let services: BTreeSet<String> = record
+        .calls
+        .iter()
+        .flat_map(|call| [call.peer.src.as_ref(), call.peer.dst.as_ref()])
+        .flatten()
+        .filter_map(entity_name)
+        .chain(record.spans.iter().map(|span| span.service.clone()))
+        .collect();
+    let root_call = record
+        .calls
+        .iter()
+        .min_by_key(|call| (call.at_ms, call.seq))
+        .map(|call| {
+            format!(
+                "{} {}",
+                call.method.as_deref().unwrap_or("UNKNOWN"),
+                call.route
+                    .as_deref()
+                    .or(call.path.as_deref())
+                    .unwrap_or("/")
+            )
+        });
+    let root = root_call.or_else(|| {
+        record
+            .spans
+            .iter()
+            .min_by_key(|span| span.start_ms)
+            .map(|span| format!("{} {}", span.service, span.name))
+    });
+    let call_errors = record
+        .calls
+        .iter()
+        .filter(|call| call.status.is_some_and(|status| status >= 400))
+        .count();
+    TraceSummary {
+        trace_id: trace_id.to_string(),
+        started_at_ms,
+        duration_ms: ended_at_ms.saturating_sub(started_at_ms),
+        calls: record.calls.len(),
+        spans: record.spans.len(),
+        errors: call_errors + record.spans.iter().filter(|span| span.error).count(),
+        services: services.into_iter().collect(),
+        root,
+    }
+}
+
+// Only the receiving sidecar sees a call, so parents are inferred: an explicit B3 parent span
+// wins, otherwise the latest earlier call into the caller whose timing encloses this one. App
+// spans nest under their parent span, or under the call that carried their parent span id.
+pub fn build_tree(trace_id: &str, record: &TraceRecord) -> TraceTree {
+    let mut calls = record.calls.clone();
+    calls.sort_by_key(|call| (call.at_ms, call.seq));
+    let mut spans = record.spans.clone();
+    spans.sort_by_key(|span| span.start_ms);
+    let mut tree = TreeIndex {
+        children: vec![Vec::new(); calls.len()],
+        call_spans: vec![Vec::new(); calls.len()],
+        span_children: vec![Vec::new(); spans.len()],
+        started_at_ms: 0,
+        calls,
+        spans,
+    };
+    let mut roots = Vec::new();
+    for index in 0..tree.calls.len() {
+        match parent_of(&tree.calls, index) {
+            Some(parent) => push_child(&mut tree.children, parent, index),
+            None => roots.push(index),
+        }
+    }
+    let mut loose_spans = Vec::new();
+    for index in 0..tree.spans.len() {
+        match span_parent(&tree, index) {
+            SpanParent::Span(parent) => push_child(&mut tree.span_children, parent, index),
+            SpanParent::Call(call) => push_child(&mut tree.call_spans, call, index),
+            SpanParent::None => loose_spans.push(index),
+        }
+    }
+    let summary = summarize(
+        trace_id,
+        &TraceRecord {
+            calls: tree.calls.clone(),
+            spans: tree.spans.clone(),
+        },
+    );
+    tree.started_at_ms = summary.started_at_ms;
+    TraceTree {
+        summary,
+        roots: roots
+            .into_iter()
+            .filter_map(|index| tree.call_node(index))
+            .collect(),
+        spans: loose_spans
+            .into_iter()
+            .filter_map(|index| tree.span_node(index))
+            .collect(),
+        logs: Vec::new(),
+    }
+}
+
+struct TreeIndex {
+    calls: Vec<TrafficCall>,
+    spans: Vec<AppSpan>,
+    children: Vec<Vec<usize>>,
+    call_spans: Vec<Vec<usize>>,
+    span_children: Vec<Vec<usize>>,
+    started_at_ms: u64,
+}
+
+impl TreeIndex {
+    fn call_node(&self, index: usize) -> Option<TraceNode> {
+        let call = self.calls.get(index)?;
+        Some(TraceNode {
+            offset_ms: call.at_ms.saturating_sub(self.started_at_ms),
+            call: call.clone(),
+            spans: self.nodes(&self.call_spans, index, Self::span_node),
+            children: self.nodes(&self.children, index, Self::call_node),
+        })
+    }
+
+    fn span_node(&self, index: usize) -> Option<SpanNode> {
+        let span = self.spans.get(index)?;
+        Some(SpanNode {
+            offset_ms: span.start_ms.saturating_sub(self.started_at_ms),
+            span: span.clone(),
+            children: self.nodes(&self.span_children, index, Self::span_node),
+        })
+    }
+
+    fn nodes<T>(
+        &self,
+        edges: &[Vec<usize>],
+        index: usize,
+        node: impl Fn(&Self, usize) -> Option<T>,
+    ) -> Vec<T> {
+        edges
+            .get(index)
+            .map(|nested| {
+                nested
+                    .iter()
+                    .filter_map(|child| node(self, *child))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+enum SpanParent {
+    Span(usize),
+    Call(usize),
+    None,
+}
+
+fn span_parent(tree: &TreeIndex, index: usize) -> SpanParent {
+    let Some(parent) = tree
+        .spans
+        .get(index)
+        .and_then(|span| span.parent_span_id.as_ref())
+    else {
+        return SpanParent::None;
+    };
+    if let Some(span) = tree
+        .spans
+        .iter()
+        .position(|candidate| &candidate.span_id == parent)
+        .filter(|span| *span != index)
+    {
+        return SpanParent::Span(span);
+    }
+    tree.calls
+        .iter()
+        .position(|call| call.correlation.span_id.as_ref() == Some(parent))
+        .map_or(SpanParent::None, SpanParent::Call)
+}
+
+fn push_child(edges: &mut [Vec<usize>], parent: usize, child: usize) {
+    if let Some(siblings) = edges.get_mut(parent) {
+        siblings.push(child);
+    }
+}
+
+fn parent_of(calls: &[TrafficCall], index: usize) -> Option<usize> {
+    let call = calls.get(index)?;
+    let earlier = calls.get(..index)?;
+    if let Some(parent_span) = &call.correlation.parent_span_id {
+        let explicit = earlier
+            .iter()
+            .rposition(|candidate| candidate.correlation.span_id.as_ref() == Some(parent_span));
+        if explicit.is_some() {
+            return explicit;
+        }
+    }
+    let caller = call.peer.src.as_ref().and_then(entity_name)?;
+    let callers_of = |candidate: &TrafficCall| {
+        candidate.peer.dst.as_ref().and_then(entity_name).as_ref() == Some(&caller)
+    };
+    earlier
+        .iter()
+        .rposition(|candidate| {
+            callers_of(candidate) && call_end(call) <= call_end(candidate) + CLOCK_SLACK_MS
+        })
+        .or_else(|| earlier.iter().rposition(callers_of))
+}
+
+fn call_end(call: &TrafficCall) -> u64 {
+    call.at_ms + call.duration_ms.unwrap_or_default()
+}
+
+const fn span_end(span: &AppSpan) -> u64 {
+    span.start_ms + span.duration_ms
+}
+
+fn entity_name(entity: &EntityId) -> Option<String> {
+    match entity {
+        EntityId::Workload { name, .. } | EntityId::Host { name } => Some(name.clone()),
+        EntityId::External { ip, dns_name } => {
+            Some(dns_name.clone().unwrap_or_else(|| ip.to_string()))
+        }
+        EntityId::Unknown => None,
+    }
+}
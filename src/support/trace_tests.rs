@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use super::fixtures::{workload, HttpCall};
+use super::trace::{build_tree, correlation, TraceRecord};
+use crate::domain::traffic::{AppSpan, Correlation, SpanNode, TraceNode};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+fn headers(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| ((*name).to_string(), (*value).to_string()))
+        .collect()
+}
+
+fn traced(src: &str, dst: &str) -> HttpCall {
+    HttpCall::get(&format!("/{dst}"))
+        .src(workload(src))
+        .dst(workload(dst))
+        .correlation(Correlation {
+            trace_id: Some(TRACE_ID.to_string()),
+            ..Correlation::default()
+        })
+}
+
+fn span(span_id: &str, parent: Option<&str>, name: &str, start_ms: u64) -> AppSpan {
+    AppSpan {
+        service: "api".to_string(),
+        trace_id: TRACE_ID.to_string(),
+        span_id: span_id.to_string(),
+        parent_span_id: parent.map(ToString::to_string),
+        name: name.to_string(),
+        kind: None,
+        start_ms,
+        duration_ms: 5,
+        error: false,
+        status_message: None,
+        attributes: BTreeMap::new(),
+    }
+}
+
+fn span_shape(node: &SpanNode) -> String {
+    if node.children.is_empty() {
+        return node.span.name.clone();
+    }
+    let children: Vec<String> = node.children.iter().map(span_shape).collect();
+    format!("{}[{}]", node.span.name, children.join(","))
+}
+
+fn shape(node: &TraceNode) -> String {
+    let name = node.call.path.clone().unwrap_or_default();
+    if node.children.is_empty() {
+        return name;
+    }
+    let children: Vec<String> = node.children.iter().map(shape).collect();
+    format!("{name}[{}]", children.join(","))
+}
+
+#[test]
+fn trace_headers_are_parsed() {
+    let w3c = correlation(
+        Some("req-1".to_string()),
+        &headers(&[
+            (
+                "traceparent",
+                "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            ),
+            ("tracestate", "vendor=abc"),
+        ]),
+    );
+    assert_eq!(w3c.request_id.as_deref(), Some("req-1"));
+    assert_eq!(w3c.trace_id.as_deref(), Some(TRACE_ID));
+    assert_eq!(w3c.span_id.as_deref(), Some("00f067aa0ba902b7"));
+    assert_eq!(w3c.trace_state.as_deref(), Some("vendor=abc"));
+    assert_eq!(w3c.sampled, Some(true));
+
+    let single = correlation(
+        None,
+        &headers(&[(
+            "b3",
+            "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0-05e3ac9a4f6e3b90",
+        )]),
+    );
+    assert_eq!(single.span_id.as_deref(), Some("e457b5a2e4d86bd1"));
+    assert_eq!(single.parent_span_id.as_deref(), Some("05e3ac9a4f6e3b90"));
+    assert_eq!(single.sampled, Some(false));
+
+    let multi = correlation(
+        None,
+        &headers(&[
+            ("x-b3-traceid", "a3ce929d0e0e4736"),
+            ("x-b3-spanid", "00f067aa0ba902b7"),
+            ("x-b3-sampled", "1"),
+        ]),
+    );
+    assert_eq!(multi.trace_id.as_deref(), Some("a3ce929d0e0e4736"));
+    assert_eq!(multi.sampled, Some(true));
+
+    let invalid = correlation(
+        None,
+        &headers(&[(
+            "traceparent",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        )]),
+    );
+    assert_eq!(invalid.trace_id, None);
+}
+
+#[test]
+fn calls_nest_under_the_call_into_their_caller() {
+    let calls = vec![
+        traced("api", "cache").at(30).duration(2).call(4),
+        traced("gateway", "web").at(0).duration(100).call(1),
+        traced("web", "api").at(10).duration(50).call(2),
+        traced("api", "db").at(20).duration(5).call(3),
+        traced("web", "auth").at(70).duration(10).call(5),
+    ];
+    let tree = build_tree(
+        TRACE_ID,
+        &TraceRecord {
+            calls,
+            spans: Vec::new(),
+        },
+    );
+    assert_eq!(tree.summary.calls, 5);
+    assert_eq!(tree.summary.duration_ms, 100);
+    assert_eq!(tree.summary.root.as_deref(), Some("GET /web"));
+    assert_eq!(
+        tree.summary.services,
+        ["api", "auth", "cache", "db", "gateway", "web"]
+    );
+    let shapes: Vec<String> = tree.roots.iter().map(shape).collect();
+    assert_eq!(shapes, ["/web[/api[/db,/cache],/auth]"]);
+}
+
+#[test]
+fn app_spans_nest_under_the_call_carrying_their_parent() {
+    let mut inbound = traced("web", "api").at(10).duration(50).call(1);
+    inbound.correlation.span_id = Some("00f067aa0ba902b7".to_string());
+    let tree = build_tree(
+        TRACE_ID,
+        &TraceRecord {
+            calls: vec![inbound],
+            spans: vec![
+                span("b000000000000002", Some("b000000000000001"), "query", 15),
+                span("b000000000000001", Some("00f067aa0ba902b7"), "handler", 12),
+                span("c000000000000001", None, "startup", 0),
+            ],
+        },
+    );
+    assert_eq!(tree.summary.calls, 1);
+    assert_eq!(tree.summary.spans, 3);
+    assert_eq!(tree.summary.started_at_ms, 0);
+    let nested: Vec<String> = tree
+        .roots
+        .iter()
+        .flat_map(|root| root.spans.iter().map(span_shape))
+        .collect();
+    assert_eq!(nested, ["handler[query]"]);
+    let loose: Vec<String> = tree.spans.iter().map(span_shape).collect();
+    assert_eq!(loose, ["startup"]);
+}
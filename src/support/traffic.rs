@@ -1,22 +1,96 @@
-use std::collections::{HashMap, VecDeque};
-use std::sync::{Mutex, MutexGuard};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
 
 use crate::domain::traffic::{
-    EdgeKey, EdgeStats, EntityId, FlowObservation, HttpObservation, Observation, ObservationSink,
-    TrafficCall, TrafficEdge, Visibility,
+    AppSpan, ContractViolation, DbObservation, EdgeKey, EdgeStats, EntityId, FlowObservation,
+    HttpObservation, Observation, ObservationAttrs, ObservationSink, TraceSummary, TraceTree,
+    TrafficCall, TrafficEdge, ViolationKind, Visibility,
 };
-use crate::support::constants::{TRAFFIC_CALL_HISTORY_LIMIT, TRAFFIC_CLIENT_QUEUE_SIZE};
+use crate::support::constants::{
+    POLICY_TAG, TRACE_CALL_LIMIT, TRACE_HISTORY_LIMIT, TRAFFIC_CALL_HISTORY_LIMIT,
+    TRAFFIC_CLIENT_QUEUE_SIZE,
+};
+use crate::support::contract::Contract;
+use crate::support::routes::normalize_route;
+use crate::support::trace::{self, TraceRecord};
 
 const LATENCY_SAMPLE_LIMIT: usize = 256;
 
-struct EdgeState {
+struct SampleStats {
     stats: EdgeStats,
     latencies: VecDeque<u64>,
+}
+
+struct EdgeState {
+    totals: SampleStats,
+    instances: BTreeMap<String, SampleStats>,
     last_seen_ms: u64,
 }
 
+struct EdgeSample<'a> {
+    at_ms: u64,
+    bytes_in: Option<u64>,
+    bytes_out: Option<u64>,
+    error: bool,
+    violations: u64,
+    duration_ms: Option<u64>,
+    visibility: &'a Visibility,
+    instance: Option<&'a str>,
+}
+
+impl SampleStats {
+    fn new(visibility: &Visibility) -> Self {
+        Self {
+            stats: EdgeStats {
+                count: 0,
+                bytes_in: 0,
+                bytes_out: 0,
+                errors: 0,
+                violations: 0,
+                p50_ms: None,
+                p95_ms: None,
+                visibility: visibility.clone(),
+            },
+            latencies: VecDeque::new(),
+        }
+    }
+
+    fn apply(&mut self, sample: &EdgeSample<'_>) {
+        self.stats.count += 1;
+        self.stats.bytes_in += sample.bytes_in.unwrap_or(0);
+        self.stats.bytes_out += sample.bytes_out.unwrap_or(0);
+        if sample.error {
+            self.stats.errors += 1;
+        }
+        self.stats.violations += sample.violations;
+        self.stats.visibility = Visibility::merge(&self.stats.visibility, sample.visibility);
+        if let Some(duration) = sample.duration_ms {
+            self.latencies.push_back(duration);
+            while self.latencies.len() > LATENCY_SAMPLE_LIMIT {
+                self.latencies.pop_front();
+            }
+            update_latency_stats(&mut self.stats, &self.latencies);
+        }
+    }
+}
+
+impl EdgeState {
+    fn snapshot(&self, key: &EdgeKey) -> TrafficEdge {
+        TrafficEdge {
+            key: key.clone(),
+            stats: self.totals.stats.clone(),
+            instances: self
+                .instances
+                .iter()
+                .map(|(instance, stats)| (instance.clone(), stats.stats.clone()))
+                .collect(),
+            last_seen_ms: self.last_seen_ms,
+        }
+    }
+}
+
 struct TrafficHubState {
     edges: HashMap<EdgeKey, EdgeState>,
     clients: Vec<(usize, Sender<TrafficEdge>)>,
@@ -25,6 +99,66 @@ struct TrafficHubState {
     call_clients: Vec<(usize, Sender<TrafficCall>)>,
     next_call_client_id: usize,
     next_call_seq: u64,
+    policy_hits: BTreeMap<PolicyHitKey, u64>,
+    traces: HashMap<String, TraceRecord>,
+    trace_order: VecDeque<String>,
+    contracts: HashMap<String, Arc<Contract>>,
+    contract_hits: BTreeMap<ContractHitKey, u64>,
+}
+
+impl TrafficHubState {
+    fn index_trace(&mut self, call: &TrafficCall) {
+        let Some(trace_id) = call.correlation.trace_id.as_deref() else {
+            return;
+        };
+        let record = self.trace_record(trace_id);
+        if record.calls.len() < TRACE_CALL_LIMIT {
+            record.calls.push(call.clone());
+        }
+    }
+
+    fn trace_record(&mut self, trace_id: &str) -> &mut TraceRecord {
+        if !self.traces.contains_key(trace_id) {
+            self.trace_order.push_back(trace_id.to_string());
+            while self.trace_order.len() > TRACE_HISTORY_LIMIT {
+                let oldest = self.trace_order.pop_front().unwrap_or_default();
+                self.traces.remove(&oldest);
+            }
+        }
+        self.traces.entry(trace_id.to_string()).or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct PolicyHitKey {
+    verdict: String,
+    from: String,
+    to: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyHit {
+    pub verdict: String,
+    pub from: String,
+    pub to: String,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ContractHitKey {
+    service: String,
+    method: String,
+    route: String,
+    kind: ViolationKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContractHit {
+    pub service: String,
+    pub method: String,
+    pub route: String,
+    pub kind: ViolationKind,
+    pub count: u64,
 }
 
 pub struct TrafficHub {
@@ -42,6 +176,11 @@ impl TrafficHub {
                 call_clients: Vec::new(),
                 next_call_client_id: 1,
                 next_call_seq: 1,
+                policy_hits: BTreeMap::new(),
+                traces: HashMap::new(),
+                trace_order: VecDeque::new(),
+                contracts: HashMap::new(),
+                contract_hits: BTreeMap::new(),
             }),
         }
     }
@@ -55,11 +194,7 @@ impl TrafficHub {
         let snapshot = state
             .edges
             .iter()
-            .map(|(key, edge)| TrafficEdge {
-                key: key.clone(),
-                stats: edge.stats.clone(),
-                last_seen_ms: edge.last_seen_ms,
-            })
+            .map(|(key, edge)| edge.snapshot(key))
             .collect();
         drop(state);
         (receiver, snapshot)
@@ -76,24 +211,49 @@ impl TrafficHub {
         (receiver, snapshot)
     }
 
+    pub fn edges(&self) -> Vec<TrafficEdge> {
+        self.state()
+            .edges
+            .iter()
+            .map(|(key, edge)| edge.snapshot(key))
+            .collect()
+    }
+
+    pub fn calls(&self) -> Vec<TrafficCall> {
+        self.state().calls.iter().cloned().collect()
+    }
+
+    pub fn traces(&self) -> Vec<TraceSummary> {
+        let state = self.state();
+        state
+            .trace_order
+            .iter()
+            .rev()
+            .filter_map(|trace_id| {
+                state
+                    .traces
+                    .get(trace_id)
+                    .map(|record| trace::summarize(trace_id, record))
+            })
+            .collect()
+    }
+
+    pub fn trace(&self, trace_id: &str) -> Option<TraceTree> {
+        let record = self.state().traces.get(trace_id).cloned()?;
+        Some(trace::build_tree(trace_id, &record))
+    }
+
+    pub fn publish_span(&self, span: AppSpan) {
+        let mut state = self.state();
+        let record = state.trace_record(&span.trace_id);
+        if record.spans.len() < TRACE_CALL_LIMIT {
+            record.spans.push(span);
+        }
+        drop(state);
+    }
+
     fn publish(&self, edge: &TrafficEdge) {
-        let clients = {
-            let mut state = self.state();
-            if let Some(existing) = state.edges.get_mut(&edge.key) {
-                existing.stats = edge.stats.clone();
-                existing.last_seen_ms = edge.last_seen_ms;
-            } else {
-                state.edges.insert(
-                    edge.key.clone(),
-                    EdgeState {
-                        stats: edge.stats.clone(),
-                        latencies: VecDeque::new(),
-                        last_seen_ms: edge.last_seen_ms,
-                    },
-                );
-            }
-            state.clients.clone()
-        };
+        let clients = self.state().clients.clone();
         let mut disconnected = Vec::new();
         for (id, sender) in clients {
             match sender.try_send(edge.clone()) {
@@ -109,6 +269,101 @@ impl TrafficHub {
         }
     }
 
+    fn record(&self, key: &EdgeKey, sample: &EdgeSample<'_>) -> TrafficEdge {
+        let mut state = self.state();
+        let edge = state.edges.entry(key.clone()).or_insert_with(|| EdgeState {
+            totals: SampleStats::new(sample.visibility),
+            instances: BTreeMap::new(),
+            last_seen_ms: sample.at_ms,
+        });
+        edge.totals.apply(sample);
+        if let Some(instance) = sample.instance {
+            edge.instances
+                .entry(instance.to_string())
+                .or_insert_with(|| SampleStats::new(sample.visibility))
+                .apply(sample);
+        }
+        edge.last_seen_ms = sample.at_ms;
+        let snapshot = edge.snapshot(key);
+        drop(state);
+        snapshot
+    }
+
+    pub fn policy_summary(&self) -> Vec<PolicyHit> {
+        self.state()
+            .policy_hits
+            .iter()
+            .map(|(key, count)| PolicyHit {
+                verdict: key.verdict.clone(),
+                from: key.from.clone(),
+                to: key.to.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    // Calls into `service` are checked against `contract` from now on.
+    pub fn set_contract(&self, service: &str, contract: Contract) {
+        self.state()
+            .contracts
+            .insert(service.to_string(), Arc::new(contract));
+    }
+
+    pub fn contract_summary(&self) -> Vec<ContractHit> {
+        self.state()
+            .contract_hits
+            .iter()
+            .map(|(key, count)| ContractHit {
+                service: key.service.clone(),
+                method: key.method.clone(),
+                route: key.route.clone(),
+                kind: key.kind,
+                count: *count,
+            })
+            .collect()
+    }
+
+    fn check_contract(&self, key: &EdgeKey, call: &TrafficCall) -> Vec<ContractViolation> {
+        let EdgeKey::Http {
+            to: EntityId::Workload { name, .. },
+            method,
+            route,
+            ..
+        } = key
+        else {
+            return Vec::new();
+        };
+        let Some(contract) = self.state().contracts.get(name).cloned() else {
+            return Vec::new();
+        };
+        let violations = contract.check(call);
+        if !violations.is_empty() {
+            let mut state = self.state();
+            for violation in &violations {
+                let hit = ContractHitKey {
+                    service: name.clone(),
+                    method: method.clone(),
+                    route: route.clone(),
+                    kind: violation.kind,
+                };
+                *state.contract_hits.entry(hit).or_insert(0) += 1;
+            }
+        }
+        violations
+    }
+
+    fn record_policy(&self, attrs: &ObservationAttrs, from: &EntityId, to: &EntityId) {
+        let Some(verdict) = attrs.tags.get(POLICY_TAG) else {
+            return;
+        };
+        let key = PolicyHitKey {
+            verdict: verdict.clone(),
+            from: entity_label(from),
+            to: entity_label(to),
+        };
+        *self.state().policy_hits.entry(key).or_insert(0) += 1;
+    }
+
     fn state(&self) -> MutexGuard<'_, TrafficHubState> {
         self.state
             .lock()
@@ -116,122 +371,112 @@ impl TrafficHub {
     }
 
     fn emit_http(&self, http: &HttpObservation) {
-        let from = http.peer.src.clone().unwrap_or(EntityId::Unknown);
-        let to = http.peer.dst.clone().unwrap_or(EntityId::Unknown);
-        let method = http.method.as_deref().unwrap_or("UNKNOWN").to_uppercase();
-        let route = http.path.clone().unwrap_or_else(|| "/".to_string());
+        self.ingest_call(call_from(http));
+    }
+
+    // Adds a call captured elsewhere, e.g. read from a HAR file, as if it had been observed.
+    pub fn import_call(&self, call: TrafficCall) {
+        self.ingest_call(call);
+    }
+
+    fn ingest_call(&self, mut call: TrafficCall) {
+        let from = call.peer.src.clone().unwrap_or(EntityId::Unknown);
+        let to = call.peer.dst.clone().unwrap_or(EntityId::Unknown);
+        let method = call.method.as_deref().unwrap_or("UNKNOWN").to_uppercase();
+        let route = call
+            .route
+            .clone()
+            .or_else(|| call.path.as_deref().map(normalize_route))
+            .unwrap_or_else(|| "/".to_string());
+        self.record_policy(&call.attrs, &from, &to);
         let key = EdgeKey::Http {
-            from,
-            to,
+            from: from.without_instance(),
+            to: to.without_instance(),
             method,
             route,
         };
-        let mut state = self.state();
-        let edge = state.edges.entry(key.clone()).or_insert_with(|| EdgeState {
-            stats: EdgeStats {
-                count: 0,
-                bytes_in: 0,
-                bytes_out: 0,
-                errors: 0,
-                p50_ms: None,
-                p95_ms: None,
-                visibility: http.attrs.visibility.clone(),
+        let violations = self.check_contract(&key, &call);
+        call.violations.extend(violations);
+        let snapshot = self.record(
+            &key,
+            &EdgeSample {
+                at_ms: call.at_ms,
+                bytes_in: call.bytes_in,
+                bytes_out: call.bytes_out,
+                error: call.status.is_some_and(|status| status >= 400),
+                violations: call.violations.len() as u64,
+                duration_ms: call.duration_ms,
+                visibility: &call.attrs.visibility,
+                instance: to.instance(),
             },
-            latencies: VecDeque::new(),
-            last_seen_ms: http.at_ms,
-        });
-        edge.stats.count += 1;
-        edge.stats.bytes_in += http.bytes_in.unwrap_or(0);
-        edge.stats.bytes_out += http.bytes_out.unwrap_or(0);
-        if let Some(status) = http.status {
-            if status >= 400 {
-                edge.stats.errors += 1;
-            }
-        }
-        edge.stats.visibility = Visibility::merge(&edge.stats.visibility, &http.attrs.visibility);
-        edge.last_seen_ms = http.at_ms;
-        if let Some(duration) = http.duration_ms {
-            edge.latencies.push_back(duration);
-            while edge.latencies.len() > LATENCY_SAMPLE_LIMIT {
-                edge.latencies.pop_front();
-            }
-            update_latency_stats(&mut edge.stats, &edge.latencies);
-        }
-        let snapshot = TrafficEdge {
-            key,
-            stats: edge.stats.clone(),
-            last_seen_ms: edge.last_seen_ms,
-        };
-        drop(state);
+        );
         self.publish(&snapshot);
-        self.publish_call(http);
+        self.publish_call(call);
     }
 
-    fn emit_flow(&self, flow: FlowObservation) {
-        let from = flow.peer.src.unwrap_or(EntityId::Unknown);
-        let to = flow.peer.dst.unwrap_or(EntityId::Unknown);
-        let port = flow.flow.dst.port;
+    fn emit_flow(&self, flow: &FlowObservation) {
+        let from = flow.peer.src.clone().unwrap_or(EntityId::Unknown);
+        let to = flow.peer.dst.clone().unwrap_or(EntityId::Unknown);
+        self.record_policy(&flow.attrs, &from, &to);
         let key = EdgeKey::Flow {
-            from,
-            to,
+            from: from.without_instance(),
+            to: to.without_instance(),
             transport: flow.flow.transport.clone(),
-            port,
+            port: flow.flow.dst.port,
         };
-        let mut state = self.state();
-        let edge = state.edges.entry(key.clone()).or_insert_with(|| EdgeState {
-            stats: EdgeStats {
-                count: 0,
-                bytes_in: 0,
-                bytes_out: 0,
-                errors: 0,
-                p50_ms: None,
-                p95_ms: None,
-                visibility: flow.attrs.visibility.clone(),
+        let snapshot = self.record(
+            &key,
+            &EdgeSample {
+                at_ms: flow.at_ms,
+                bytes_in: flow.metrics.bytes_in,
+                bytes_out: flow.metrics.bytes_out,
+                error: false,
+                violations: 0,
+                duration_ms: None,
+                visibility: &flow.attrs.visibility,
+                instance: to.instance(),
             },
-            latencies: VecDeque::new(),
-            last_seen_ms: flow.at_ms,
-        });
-        edge.stats.count += 1;
-        edge.stats.bytes_in += flow.metrics.bytes_in.unwrap_or(0);
-        edge.stats.bytes_out += flow.metrics.bytes_out.unwrap_or(0);
-        edge.stats.visibility = Visibility::merge(&edge.stats.visibility, &flow.attrs.visibility);
-        edge.last_seen_ms = flow.at_ms;
-        let snapshot = TrafficEdge {
-            key,
-            stats: edge.stats.clone(),
-            last_seen_ms: edge.last_seen_ms,
+        );
+        self.publish(&snapshot);
+    }
+
+    fn emit_db(&self, db: &DbObservation) {
+        let from = db.peer.src.clone().unwrap_or(EntityId::Unknown);
+        let to = db.peer.dst.clone().unwrap_or(EntityId::Unknown);
+        let key = EdgeKey::Db {
+            from: from.without_instance(),
+            to: to.without_instance(),
+            system: db.system,
+            operation: db.operation.clone(),
+            table: db.table.clone(),
         };
-        drop(state);
+        let snapshot = self.record(
+            &key,
+            &EdgeSample {
+                at_ms: db.at_ms,
+                bytes_in: None,
+                bytes_out: None,
+                error: db.error.is_some(),
+                violations: 0,
+                duration_ms: db.duration_ms,
+                visibility: &db.attrs.visibility,
+                instance: to.instance(),
+            },
+        );
         self.publish(&snapshot);
     }
 
-    fn publish_call(&self, http: &HttpObservation) {
-        let (call, clients) = {
+    fn publish_call(&self, mut call: TrafficCall) {
+        let clients = {
             let mut state = self.state();
-            let seq = state.next_call_seq;
+            call.seq = state.next_call_seq;
             state.next_call_seq += 1;
-            let call = TrafficCall {
-                seq,
-                at_ms: http.at_ms,
-                peer: http.peer.clone(),
-                method: http.method.clone(),
-                path: http.path.clone(),
-                status: http.status,
-                duration_ms: http.duration_ms,
-                bytes_in: http.bytes_in,
-                bytes_out: http.bytes_out,
-                request_headers: http.request_headers.clone(),
-                response_headers: http.response_headers.clone(),
-                request_body: http.request_body.clone(),
-                response_body: http.response_body.clone(),
-                correlation: http.correlation.clone(),
-                attrs: http.attrs.clone(),
-            };
             state.calls.push_back(call.clone());
+            state.index_trace(&call);
             while state.calls.len() > TRAFFIC_CALL_HISTORY_LIMIT {
                 state.calls.pop_front();
             }
-            (call, state.call_clients.clone())
+            state.call_clients.clone()
         };
 
         let mut disconnected = Vec::new();
@@ -256,8 +501,115 @@ impl ObservationSink for TrafficHub {
     fn emit(&self, obs: Observation) {
         match obs {
             Observation::Http(http) => self.emit_http(&http),
-            Observation::Flow(flow) => self.emit_flow(flow),
+            Observation::Flow(flow) => self.emit_flow(&flow),
+            Observation::Db(db) => self.emit_db(&db),
+        }
+    }
+}
+
+// Hands every observation to each sink configured for the run.
+pub struct FanoutSink {
+    sinks: Vec<Arc<dyn ObservationSink>>,
+}
+
+impl FanoutSink {
+    pub fn new(sinks: Vec<Arc<dyn ObservationSink>>) -> Self {
+        Self { sinks }
+    }
+}
+
+impl ObservationSink for FanoutSink {
+    fn emit(&self, obs: Observation) {
+        let Some((last, rest)) = self.sinks.split_last() else {
+            return;
+        };
+        for sink in rest {
+            sink.emit(obs.clone());
         }
+        last.emit(obs);
+    }
+}
+
+// The sequence number is assigned when the call is published.
+fn call_from(http: &HttpObservation) -> TrafficCall {
+    TrafficCall {
+        seq: 0,
+        at_ms: http.at_ms,
+        peer: http.peer.clone(),
+        method: http.method.clone(),
+        path: http.path.clone(),
+        route: http.route.clone(),
+        status: http.status,
+        duration_ms: http.duration_ms,
+        bytes_in: http.bytes_in,
+        bytes_out: http.bytes_out,
+        request_headers: http.request_headers.clone(),
+        response_headers: http.response_headers.clone(),
+        request_body: http.request_body.clone(),
+        response_body: http.response_body.clone(),
+        body_encoding: http.body_encoding.clone(),
+        correlation: http.correlation.clone(),
+        attrs: http.attrs.clone(),
+        violations: Vec::new(),
+    }
+}
+
+pub fn format_policy_summary(hits: &[PolicyHit]) -> Vec<String> {
+    if hits.is_empty() {
+        return Vec::new();
+    }
+    let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+    for hit in hits {
+        *totals.entry(hit.verdict.as_str()).or_insert(0) += hit.count;
+    }
+    let totals = totals
+        .iter()
+        .map(|(verdict, count)| format!("{count} {verdict}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut lines = vec![format!("egress policy: {totals}")];
+    lines.extend(hits.iter().map(|hit| {
+        format!(
+            "  {} {} -> {} ({})",
+            hit.verdict, hit.from, hit.to, hit.count
+        )
+    }));
+    lines
+}
+
+pub fn format_contract_summary(hits: &[ContractHit]) -> Vec<String> {
+    if hits.is_empty() {
+        return Vec::new();
+    }
+    let mut totals: BTreeMap<ViolationKind, u64> = BTreeMap::new();
+    for hit in hits {
+        *totals.entry(hit.kind).or_insert(0) += hit.count;
+    }
+    let total: u64 = totals.values().sum();
+    let totals = totals
+        .iter()
+        .map(|(kind, count)| format!("{count} {}", kind.as_str()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let mut lines = vec![format!("contract: {total} violations ({totals})")];
+    lines.extend(hits.iter().map(|hit| {
+        format!(
+            "  {} {} {}: {} ({})",
+            hit.service,
+            hit.method,
+            hit.route,
+            hit.kind.as_str(),
+            hit.count
+        )
+    }));
+    lines
+}
+
+fn entity_label(entity: &EntityId) -> String {
+    match entity {
+        EntityId::Workload { name, .. } | EntityId::Host { name } => name.clone(),
+        EntityId::External { ip, dns_name } => dns_name.clone().unwrap_or_else(|| ip.to_string()),
+        EntityId::Unknown => "unknown".to_string(),
     }
 }
 
@@ -273,7 +625,7 @@ fn update_latency_stats(stats: &mut EdgeStats, samples: &VecDeque<u64>) {
     stats.p95_ms = Some(percentile(&sorted, 95));
 }
 
-fn percentile(sorted: &[u64], pct: usize) -> u64 {
+pub fn percentile(sorted: &[u64], pct: usize) -> u64 {
     if sorted.is_empty() {
         return 0;
     }
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use super::contract::Contract;
+use super::fixtures::HttpCall;
+use super::traffic::{format_contract_summary, format_policy_summary, FanoutSink, TrafficHub};
+use crate::domain::traffic::{EdgeKey, EntityId, ObservationSink, TrafficEdge, ViolationKind};
+
+fn workload(name: &str, instance: Option<&str>) -> EntityId {
+    EntityId::Workload {
+        name: name.to_string(),
+        instance: instance.map(ToString::to_string),
+    }
+}
+
+fn users_call(path: &str, dst_instance: &str) -> HttpCall {
+    HttpCall::get(path)
+        .src(workload("web", Some("aaaaaaaaaaaa")))
+        .dst(workload("api", Some(dst_instance)))
+        .at(1)
+        .duration(5)
+}
+
+fn single_edge(hub: &TrafficHub) -> Option<TrafficEdge> {
+    let (_, mut edges) = hub.register_client();
+    assert_eq!(edges.len(), 1);
+    edges.pop()
+}
+
+#[test]
+fn replicas_share_an_edge_with_per_instance_stats() {
+    let hub = TrafficHub::new();
+    hub.emit(users_call("/users", "111111111111").observation());
+    hub.emit(
+        users_call("/users", "222222222222")
+            .status(500)
+            .observation(),
+    );
+    hub.emit(
+        users_call("/users", "222222222222")
+            .status(502)
+            .observation(),
+    );
+
+    let edge = single_edge(&hub);
+    assert_eq!(edge.as_ref().map(|edge| edge.stats.count), Some(3));
+    assert_eq!(edge.as_ref().map(|edge| edge.stats.errors), Some(2));
+    let instances = edge.map(|edge| edge.instances).unwrap_or_default();
+    assert_eq!(instances.len(), 2);
+    assert_eq!(instances.get("111111111111").map(|s| s.errors), Some(0));
+    assert_eq!(instances.get("222222222222").map(|s| s.errors), Some(2));
+}
+
+#[test]
+fn edge_keys_drop_instances() {
+    let hub = TrafficHub::new();
+    hub.emit(users_call("/users", "111111111111").observation());
+    let key = single_edge(&hub).map(|edge| edge.key);
+    let expected = EdgeKey::Http {
+        from: workload("web", None),
+        to: workload("api", None),
+        method: "GET".to_string(),
+        route: "/users".to_string(),
+    };
+    assert_eq!(key, Some(expected));
+}
+
+#[test]
+fn policy_tags_are_summarized() {
+    let hub = TrafficHub::new();
+    hub.emit(users_call("/users", "111111111111").observation());
+    for _ in 0..2 {
+        hub.emit(
+            users_call("/users", "111111111111")
+                .status(403)
+                .tag("policy", "blocked")
+                .observation(),
+        );
+    }
+
+    let lines = format_policy_summary(&hub.policy_summary());
+    assert_eq!(
+        lines,
+        vec![
+            "egress policy: 2 blocked".to_string(),
+            "  blocked web -> api (2)".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn raw_paths_share_a_templated_edge() {
+    let hub = TrafficHub::new();
+    for path in ["/users/41", "/users/42?full=1"] {
+        hub.emit(users_call(path, "111111111111").observation());
+    }
+    let edge = single_edge(&hub);
+    assert_eq!(edge.as_ref().map(|edge| edge.stats.count), Some(2));
+    let route = edge.and_then(|edge| match edge.key {
+        EdgeKey::Http { route, .. } => Some(route),
+        _ => None,
+    });
+    assert_eq!(route.as_deref(), Some("/users/{id}"));
+    let (_, calls) = hub.register_call_client();
+    let paths: Vec<_> = calls
+        .iter()
+        .filter_map(|call| call.path.as_deref())
+        .collect();
+    assert_eq!(paths, vec!["/users/41", "/users/42?full=1"]);
+}
+
+#[test]
+fn fanout_sink_reaches_every_sink() {
+    let first = Arc::new(TrafficHub::new());
+    let second = Arc::new(TrafficHub::new());
+    let sink = FanoutSink::new(vec![first.clone(), second.clone()]);
+    sink.emit(users_call("/users", "111111111111").observation());
+    assert_eq!(single_edge(&first).map(|edge| edge.stats.count), Some(1));
+    assert_eq!(single_edge(&second).map(|edge| edge.stats.count), Some(1));
+}
+
+#[test]
+fn contract_violations_are_attached_counted_and_summarized() {
+    let hub = TrafficHub::new();
+    let contract = Contract::parse(
+        "openapi: 3.0.3\npaths:\n  /users:\n    get:\n      responses:\n        200:\n          description: ok\n",
+    );
+    assert!(contract.is_ok());
+    if let Ok(contract) = contract {
+        hub.set_contract("api", contract);
+    }
+    hub.emit(users_call("/users", "111111111111").observation());
+    hub.emit(
+        users_call("/users", "111111111111")
+            .status(500)
+            .observation(),
+    );
+
+    let (_, calls) = hub.register_call_client();
+    let kinds: Vec<Vec<ViolationKind>> = calls
+        .iter()
+        .map(|call| {
+            call.violations
+                .iter()
+                .map(|violation| violation.kind)
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![Vec::new(), vec![ViolationKind::UndocumentedStatus]]
+    );
+    assert_eq!(single_edge(&hub).map(|edge| edge.stats.violations), Some(1));
+    let lines = format_contract_summary(&hub.contract_summary());
+    assert_eq!(
+        lines,
+        vec![
+            "contract: 1 violations (1 undocumented_status)".to_string(),
+            "  api GET /users: undocumented_status (1)".to_string(),
+        ]
+    );
+}
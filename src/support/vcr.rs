@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::support::capture::is_sensitive_header;
+
+const DEFAULT_MATCH: [MatchField; 5] = [
+    MatchField::Method,
+    MatchField::Host,
+    MatchField::Path,
+    MatchField::Query,
+    MatchField::Body,
+];
+const CASSETTE_EXT: &str = "json";
+const UNMATCHED_FILE: &str = "unmatched.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VcrMode {
+    Record,
+    Replay,
+}
+
+impl VcrMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "record" => Some(Self::Record),
+            "replay" => Some(Self::Replay),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MatchField {
+    Method,
+    Host,
+    Path,
+    Query,
+    Body,
+}
+
+impl MatchField {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "method" => Some(Self::Method),
+            "host" => Some(Self::Host),
+            "path" => Some(Self::Path),
+            "query" => Some(Self::Query),
+            "body" => Some(Self::Body),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VcrConfig {
+    pub mode: VcrMode,
+    pub dir: PathBuf,
+    pub match_on: Vec<MatchField>,
+}
+
+impl VcrConfig {
+    pub fn parse(mode: &str, dir: PathBuf, match_on: &[String]) -> Result<Self, String> {
+        let mode = VcrMode::parse(mode)
+            .ok_or_else(|| format!("invalid VCR mode '{mode}' (record or replay)"))?;
+        let match_on = if match_on.is_empty() {
+            DEFAULT_MATCH.to_vec()
+        } else {
+            match_on
+                .iter()
+                .map(|raw| {
+                    MatchField::parse(raw).ok_or_else(|| format!("invalid VCR match field '{raw}'"))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        Ok(Self {
+            mode,
+            dir,
+            match_on,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub host: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl RecordedRequest {
+    pub fn new(
+        method: &str,
+        host: &str,
+        target: &str,
+        headers: BTreeMap<String, String>,
+        body: Option<String>,
+    ) -> Self {
+        let (path, query) = target
+            .split_once('?')
+            .map_or((target, None), |(path, query)| (path, Some(query)));
+        let body = body.filter(|body| !body.is_empty());
+        Self {
+            method: method.to_uppercase(),
+            host: host.to_lowercase(),
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+            query: query.filter(|query| !query.is_empty()).map(str::to_string),
+            body_hash: body.as_deref().map(body_hash),
+            headers,
+            body,
+        }
+    }
+
+    pub fn key(&self, fields: &[MatchField]) -> String {
+        fields
+            .iter()
+            .map(|field| match field {
+                MatchField::Method => self.method.as_str(),
+                MatchField::Host => self.host.as_str(),
+                MatchField::Path => self.path.as_str(),
+                MatchField::Query => self.query.as_deref().unwrap_or_default(),
+                MatchField::Body => self.body_hash.as_deref().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn target(&self) -> String {
+        self.query.as_ref().map_or_else(
+            || format!("{}{}", self.host, self.path),
+            |query| format!("{}{}?{query}", self.host, self.path),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Interaction {
+    pub recorded_at_ms: u64,
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+// FNV-1a keeps cassette keys stable across builds, unlike the std hasher.
+pub fn body_hash(body: &str) -> String {
+    let hash = body.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+pub struct Recorder {
+    dir: PathBuf,
+    next_index: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl Recorder {
+    // Recording into a directory that already holds cassettes would mix two sessions and
+    // interleave their replay order, so it is refused.
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut existing = Vec::new();
+        collect_cassettes(dir, &mut existing)?;
+        if !existing.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already holds {} recordings; remove it or set SANELENS_VCR_DIR",
+                    dir.display(),
+                    existing.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            next_index: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // Files are named after every match field so a recording can be replayed under any
+    // rule set; repeated requests get increasing suffixes and replay in that order. Request
+    // credentials are never matched on, so they are left out of the cassette.
+    pub fn record(&self, interaction: &Interaction) -> io::Result<PathBuf> {
+        let request = &interaction.request;
+        let host_dir = self.dir.join(sanitize(&request.host));
+        fs::create_dir_all(&host_dir)?;
+        let stem = host_dir.join(format!(
+            "{}-{}",
+            request.method.to_lowercase(),
+            body_hash(&request.key(&DEFAULT_MATCH))
+        ));
+        let index = self.next_index(&stem);
+        let mut name = stem.into_os_string();
+        name.push(format!("-{index:06}.{CASSETTE_EXT}"));
+        let path = PathBuf::from(name);
+        let mut interaction = interaction.clone();
+        interaction
+            .request
+            .headers
+            .retain(|name, _| !is_sensitive_header(name));
+        let payload = serde_json::to_vec_pretty(&interaction).map_err(io::Error::other)?;
+        fs::write(&path, payload)?;
+        Ok(path)
+    }
+
+    fn next_index(&self, stem: &Path) -> usize {
+        let mut next_index = self
+            .next_index
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let next = next_index.entry(stem.to_path_buf()).or_default();
+        let index = *next;
+        *next += 1;
+        drop(next_index);
+        index
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VcrMiss {
+    pub method: String,
+    pub target: String,
+    pub count: u64,
+}
+
+#[derive(Default)]
+struct PlayerState {
+    cursors: HashMap<String, usize>,
+    misses: BTreeMap<(String, String), u64>,
+}
+
+pub struct Player {
+    match_on: Vec<MatchField>,
+    entries: HashMap<String, Vec<RecordedResponse>>,
+    state: Mutex<PlayerState>,
+}
+
+impl Player {
+    pub fn new(match_on: Vec<MatchField>, interactions: Vec<Interaction>) -> Self {
+        let mut entries: HashMap<String, Vec<RecordedResponse>> = HashMap::new();
+        for interaction in interactions {
+            entries
+                .entry(interaction.request.key(&match_on))
+                .or_default()
+                .push(interaction.response);
+        }
+        Self {
+            match_on,
+            entries,
+            state: Mutex::new(PlayerState::default()),
+        }
+    }
+
+    pub fn load(config: &VcrConfig) -> Result<Self, String> {
+        let mut files = Vec::new();
+        collect_cassettes(&config.dir, &mut files).map_err(|err| {
+            format!(
+                "failed to read cassettes in {}: {err}",
+                config.dir.display()
+            )
+        })?;
+        files.sort();
+        let mut interactions = Vec::with_capacity(files.len());
+        for path in files {
+            let payload = fs::read_to_string(&path)
+                .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+            let interaction = serde_json::from_str(&payload)
+                .map_err(|err| format!("invalid cassette {}: {err}", path.display()))?;
+            interactions.push(interaction);
+        }
+        Ok(Self::new(config.match_on.clone(), interactions))
+    }
+
+    pub fn interaction_count(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    // Recorded responses for the same key are served in order; the last one repeats.
+    pub fn lookup(&self, request: &RecordedRequest) -> Option<RecordedResponse> {
+        let key = request.key(&self.match_on);
+        let Some(responses) = self.entries.get(&key) else {
+            *self
+                .state()
+                .misses
+                .entry((request.method.clone(), request.target()))
+                .or_default() += 1;
+            return None;
+        };
+        let mut state = self.state();
+        let cursor = state.cursors.entry(key).or_default();
+        let index = *cursor;
+        *cursor += 1;
+        drop(state);
+        responses.get(index).or_else(|| responses.last()).cloned()
+    }
+
+    pub fn misses(&self) -> Vec<VcrMiss> {
+        self.state()
+            .misses
+            .iter()
+            .map(|((method, target), count)| VcrMiss {
+                method: method.clone(),
+                target: target.clone(),
+                count: *count,
+            })
+            .collect()
+    }
+
+    fn state(&self) -> MutexGuard<'_, PlayerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+pub fn format_misses(misses: &[VcrMiss]) -> Vec<String> {
+    if misses.is_empty() {
+        return Vec::new();
+    }
+    let total: u64 = misses.iter().map(|miss| miss.count).sum();
+    let mut lines = vec![format!("vcr: {total} unmatched requests")];
+    lines.extend(
+        misses
+            .iter()
+            .map(|miss| format!("  {} {} ({})", miss.method, miss.target, miss.count)),
+    );
+    lines
+}
+
+// A clean replay removes the report left behind by an earlier run.
+pub fn write_unmatched(dir: &Path, misses: &[VcrMiss]) -> io::Result<()> {
+    let path = dir.join(UNMATCHED_FILE);
+    if misses.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+    let payload = serde_json::to_vec_pretty(misses).map_err(io::Error::other)?;
+    fs::write(path, payload)
+}
+
+fn collect_cassettes(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_cassettes(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == CASSETTE_EXT)
+            && path.file_name().is_some_and(|name| name != UNMATCHED_FILE)
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn sanitize(host: &str) -> String {
+    host.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '.' || ch == '-' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+
+use super::vcr::{
+    format_misses, Interaction, MatchField, Player, RecordedRequest, RecordedResponse, Recorder,
+    VcrConfig, VcrMiss,
+};
+
+fn request(method: &str, target: &str, body: Option<&str>) -> RecordedRequest {
+    RecordedRequest::new(
+        method,
+        "API.example.com",
+        target,
+        BTreeMap::new(),
+        body.map(ToString::to_string),
+    )
+}
+
+fn interaction(target: &str, body: Option<&str>, status: u16) -> Interaction {
+    Interaction {
+        recorded_at_ms: 1,
+        request: request("post", target, body),
+        response: RecordedResponse {
+            status,
+            headers: BTreeMap::new(),
+            body: None,
+        },
+    }
+}
+
+#[test]
+fn request_key_splits_target_and_hashes_body() {
+    let request = request("get", "/v1/items?page=2", Some("{}"));
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.host, "api.example.com");
+    assert_eq!(request.path, "/v1/items");
+    assert_eq!(request.query.as_deref(), Some("page=2"));
+    assert_eq!(
+        request.key(&[MatchField::Method, MatchField::Query]),
+        "GET page=2"
+    );
+    assert_eq!(request.body_hash.as_ref().map(String::len), Some(16));
+}
+
+#[test]
+fn player_matches_on_configured_fields_and_replays_in_order() {
+    let player = Player::new(
+        vec![MatchField::Method, MatchField::Host, MatchField::Path],
+        vec![
+            interaction("/charge?id=1", Some("a"), 200),
+            interaction("/charge?id=2", Some("b"), 201),
+        ],
+    );
+    let status = |target: &str| {
+        player
+            .lookup(&request("POST", target, Some("c")))
+            .map(|response| response.status)
+    };
+    assert_eq!(status("/charge"), Some(200));
+    assert_eq!(status("/charge?id=9"), Some(201));
+    assert_eq!(status("/charge"), Some(201));
+    assert_eq!(status("/refund"), None);
+    assert_eq!(status("/refund"), None);
+    assert_eq!(
+        player.misses(),
+        vec![VcrMiss {
+            method: "POST".to_string(),
+            target: "api.example.com/refund".to_string(),
+            count: 2,
+        }]
+    );
+}
+
+#[test]
+fn body_hash_is_part_of_the_default_match() {
+    let config = VcrConfig::parse("replay", "cassettes".into(), &[]).ok();
+    let match_on = config.map(|config| config.match_on).unwrap_or_default();
+    let player = Player::new(match_on, vec![interaction("/charge", Some("a"), 200)]);
+    assert!(player
+        .lookup(&request("POST", "/charge", Some("b")))
+        .is_none());
+    assert!(player
+        .lookup(&request("POST", "/charge", Some("a")))
+        .is_some());
+    assert!(VcrConfig::parse("replay", "cassettes".into(), &["headers".to_string()]).is_err());
+}
+
+#[test]
+fn format_misses_summarizes_unmatched_requests() {
+    let misses = vec![VcrMiss {
+        method: "GET".to_string(),
+        target: "api.example.com/v1".to_string(),
+        count: 3,
+    }];
+    assert_eq!(
+        format_misses(&misses),
+        vec![
+            "vcr: 3 unmatched requests".to_string(),
+            "  GET api.example.com/v1 (3)".to_string(),
+        ]
+    );
+    assert!(format_misses(&[]).is_empty());
+}
+
+#[test]
+fn recorder_numbers_repeats_and_refuses_a_used_directory() {
+    let dir = env::temp_dir().join(format!("sanelens-vcr-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let recorder = Recorder::new(&dir);
+    assert!(recorder.is_ok());
+    let Ok(recorder) = recorder else {
+        return;
+    };
+    let first = recorder.record(&interaction("/a", None, 200));
+    let second = recorder.record(&interaction("/a", None, 201));
+    let names: Vec<String> = [first, second]
+        .into_iter()
+        .flatten()
+        .filter_map(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .collect();
+    assert_eq!(names.len(), 2);
+    assert!(names
+        .first()
+        .is_some_and(|name| name.ends_with("-000000.json")));
+    assert!(names
+        .get(1)
+        .is_some_and(|name| name.ends_with("-000001.json")));
+    assert!(Recorder::new(&dir).is_err());
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn recorder_leaves_request_credentials_out_of_cassettes() {
+    let dir = env::temp_dir().join(format!("sanelens-vcr-creds-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let Ok(recorder) = Recorder::new(&dir) else {
+        return;
+    };
+    let mut call = interaction("/charge", None, 200);
+    call.request.headers = BTreeMap::from([
+        ("Authorization".to_string(), "Bearer sk_live".to_string()),
+        ("cookie".to_string(), "session=1".to_string()),
+        ("x-api-key".to_string(), "key".to_string()),
+        ("accept".to_string(), "application/json".to_string()),
+    ]);
+    let cassette = recorder
+        .record(&call)
+        .and_then(fs::read_to_string)
+        .unwrap_or_default();
+    let _ = fs::remove_dir_all(&dir);
+    let saved: Option<Interaction> = serde_json::from_str(&cassette).ok();
+    let headers = saved.map(|saved| saved.request.headers).unwrap_or_default();
+    assert_eq!(
+        headers.keys().collect::<Vec<_>>(),
+        vec![&"accept".to_string()]
+    );
+    assert!(!cassette.contains("sk_live"));
+}